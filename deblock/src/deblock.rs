@@ -27,21 +27,51 @@ mod scalar_impl {
     #[allow(non_snake_case)]
     #[inline]
     pub fn process(A: &mut u8, B: &mut u8, C: &mut u8, D: &mut u8, strength: u8) {
+        let (mut a16, mut b16, mut c16, mut d16) = (*A as i16, *B as i16, *C as i16, *D as i16);
+
+        process_i16(&mut a16, &mut b16, &mut c16, &mut d16, strength);
+
+        *A = a16 as u8;
+        *B = b16.clamp(0, 255) as u8;
+        *C = c16.clamp(0, 255) as u8;
+        *D = d16 as u8;
+    }
+
+    /// Same as `process`, but on wider, signed values, which are not clipped
+    /// to the 0..=255 range afterwards.
+    #[allow(non_snake_case)]
+    #[inline]
+    pub fn process_i16(A: &mut i16, B: &mut i16, C: &mut i16, D: &mut i16, strength: u8) {
         debug_assert!((1..=12).contains(&strength));
 
-        let (a16, b16, c16, d16) = (*A as i16, *B as i16, *C as i16, *D as i16);
+        let (a16, b16, c16, d16) = (*A, *B, *C, *D);
 
         let d: i16 = (a16 - 4 * b16 + 4 * c16 - d16) / 8;
         let d1: i16 = up_down_ramp(d, strength as i16);
         let d2: i16 = clipd1((a16 - d16) / 4, d1 / 2);
 
-        *A = (a16 - d2) as u8;
-        *B = (b16 + d1).clamp(0, 255) as u8;
-        *C = (c16 - d1).clamp(0, 255) as u8;
-        *D = (d16 + d2) as u8;
+        *A = a16 - d2;
+        *B = b16 + d1;
+        *C = c16 - d1;
+        *D = d16 + d2;
+    }
+
+    /// Pure-scalar stand-in for `simd_impl::process_simd`, used when the `simd`
+    /// feature is disabled. Performs `process` on 8 independent sets of values
+    /// one by one. All slice parameters must have a length of 8.
+    #[cfg(not(feature = "simd"))]
+    #[allow(non_snake_case)]
+    #[inline]
+    pub fn process_simd(A: &mut [u8], B: &mut [u8], C: &mut [u8], D: &mut [u8], strength: u8) {
+        debug_assert!(A.len() == 8 && B.len() == 8 && C.len() == 8 && D.len() == 8);
+
+        for i in 0..8 {
+            process(&mut A[i], &mut B[i], &mut C[i], &mut D[i], strength);
+        }
     }
 }
 
+#[cfg(feature = "simd")]
 mod simd_impl {
     use std::ops::Shr;
     use wide::{i16x8, CmpGt, CmpLt};
@@ -128,12 +158,19 @@ mod simd_impl {
 }
 
 use itertools::izip;
-use scalar_impl::process;
+#[cfg(not(feature = "simd"))]
+use scalar_impl::process_simd;
+use scalar_impl::{process, process_i16};
+#[cfg(feature = "simd")]
 use simd_impl::process_simd;
 
-/// Applies the deblocking with the given strength to the horizontal block edges.
+/// Applies the deblocking to the horizontal block edges.
+///
+/// The strength of each 8 sample wide segment of an edge is looked up from
+/// the position of it's first "B" sample. Segments with a strength of 0 are
+/// not filtered.
 #[allow(non_snake_case)]
-fn deblock_horiz(result: &mut [u8], width: usize, strength: u8) {
+fn deblock_horiz(result: &mut [u8], width: usize, strength_at: &impl Fn((usize, usize)) -> u8) {
     let height = result.len() / width;
 
     let mut edge_y = 8; // the vertical index of the row with the "C" samples
@@ -153,12 +190,16 @@ fn deblock_horiz(result: &mut [u8], width: usize, strength: u8) {
 
         // luckily the memory layout is advantageous here, no need for transposing
         // chunks into the SIMD lanes
-        for (((A, B), C), D) in row_a_chunks
+        for (i, (((A, B), C), D)) in row_a_chunks
             .zip(row_b_chunks)
             .zip(row_c_chunks)
             .zip(row_d_chunks)
+            .enumerate()
         {
-            process_simd(A, B, C, D, strength);
+            match strength_at((i * 8, edge_y - 1)) {
+                0 => {}
+                strength => process_simd(A, B, C, D, strength),
+            }
         }
 
         // the remaining <=7 columns are handled by the scalar implementation
@@ -167,22 +208,34 @@ fn deblock_horiz(result: &mut [u8], width: usize, strength: u8) {
         let row_c_rem = row_c.chunks_exact_mut(8).into_remainder();
         let row_d_rem = row_d.chunks_exact_mut(8).into_remainder();
 
-        for (((A, B), C), D) in row_a_rem
-            .iter_mut()
-            .zip(row_b_rem)
-            .zip(row_c_rem)
-            .zip(row_d_rem)
-        {
-            process(A, B, C, D, strength);
+        let strength = if row_a_rem.is_empty() {
+            0
+        } else {
+            strength_at((width - row_a_rem.len(), edge_y - 1))
+        };
+
+        if strength != 0 {
+            for (((A, B), C), D) in row_a_rem
+                .iter_mut()
+                .zip(row_b_rem)
+                .zip(row_c_rem)
+                .zip(row_d_rem)
+            {
+                process(A, B, C, D, strength);
+            }
         }
 
         edge_y += 8;
     }
 }
 
-/// Applies the deblocking with the given strength to the vertical block edges.
+/// Applies the deblocking to the vertical block edges.
+///
+/// The strength of each 8 sample tall segment of an edge is looked up from
+/// the position of it's first "B" sample. Segments with a strength of 0 are
+/// not filtered.
 #[allow(non_snake_case)]
-fn deblock_vert(result: &mut [u8], width: usize, strength: u8) {
+fn deblock_vert(result: &mut [u8], width: usize, strength_at: &impl Fn((usize, usize)) -> u8) {
     /// Holds a bundle of 8 mutable byte slice references.
     /// This is a tuple instead of an array due to `izip!` usage below.
     type ByteSliceMutRefOctet<'a> = (
@@ -228,7 +281,7 @@ fn deblock_vert(result: &mut [u8], width: usize, strength: u8) {
     if width >= 10 {
         // Handling the top N*8 rows with the SIMD implementation,
         // iterating on 8 (the SIMD width) rows worth of data at a time.
-        for rows in result.chunks_exact_mut(width * 8) {
+        for (j, rows) in result.chunks_exact_mut(width * 8).enumerate() {
             // Splitting into separate rows (doing it this way to satisfy the borrow checker),
             // each row will supply one SIMD lane.
             let (row_0, rows) = rows.split_at_mut(width);
@@ -260,7 +313,12 @@ fn deblock_vert(result: &mut [u8], width: usize, strength: u8) {
 
             // Transposing the (vertical) sample tuples into SIMD vectors, processing them,
             // then untransposing and storing.
-            for mut arrays in parallel_iter {
+            for (i, mut arrays) in parallel_iter.enumerate() {
+                let strength = strength_at((i * 8 + 7, j * 8));
+                if strength == 0 {
+                    continue;
+                }
+
                 let mut As = extract_column(&arrays, 4);
                 let mut Bs = extract_column(&arrays, 5);
                 let mut Cs = extract_column(&arrays, 6);
@@ -278,12 +336,19 @@ fn deblock_vert(result: &mut [u8], width: usize, strength: u8) {
         // The remaining <=7 rows at the bottom are handled by the scalar implementation,
         // with a similar iteration pattern as above, but with one row at a time, not in
         // parallel over an octet of rows.
-        for row in result
+        let remainder_y = result.len() / (width * 8) * 8;
+        for (j, row) in result
             .chunks_exact_mut(width * 8)
             .into_remainder()
             .chunks_exact_mut(width)
+            .enumerate()
         {
-            for chunk in row[2..].chunks_exact_mut(8) {
+            for (i, chunk) in row[2..].chunks_exact_mut(8).enumerate() {
+                let strength = strength_at((i * 8 + 7, remainder_y + j));
+                if strength == 0 {
+                    continue;
+                }
+
                 let mut A = chunk[4];
                 let mut B = chunk[5];
                 let mut C = chunk[6];
@@ -300,20 +365,235 @@ fn deblock_vert(result: &mut [u8], width: usize, strength: u8) {
 
 /// Applies the deblocking filter to the horizontal and vertical block edges
 /// of the given image data with the given strength, assuming 8x8 block size.
+///
+/// This is `deblock_with_mb_strengths` with the same strength for every
+/// macroblock.
 #[allow(non_snake_case)]
 #[allow(clippy::identity_op)]
 pub fn deblock(data: &[u8], width: usize, strength: u8) -> Vec<u8> {
-    debug_assert!(data.len() % width == 0);
+    let height = data.len().checked_div(width).unwrap_or(0);
+    let mb_count = width.div_ceil(16) * height.div_ceil(16);
 
-    let mut result = data.to_vec();
+    deblock_with_mb_strengths(data, width, &vec![strength; mb_count])
+}
+
+/// Applies the deblocking filter to the horizontal and vertical block edges
+/// of the given image data with the given strength, in place, assuming 8x8
+/// block size.
+///
+/// This is the same filter as `deblock`, for callers that already own a
+/// buffer they don't need to keep unfiltered.
+pub fn deblock_in_place(data: &mut [u8], width: usize, strength: u8) {
+    debug_assert!(data.len().is_multiple_of(width));
 
     // According to the spec, the horizontal deblocking filter is applied first.
-    deblock_horiz(result.as_mut(), width, strength);
-    deblock_vert(result.as_mut(), width, strength);
+    deblock_horiz(data, width, &|_| strength);
+    deblock_vert(data, width, &|_| strength);
+}
+
+/// Applies the deblocking filter to the horizontal and vertical block edges
+/// of the given image data with the given strength, assuming 8x8 block size,
+/// without any SIMD.
+///
+/// This gives the same results as `deblock`, one sample quartet at a time.
+/// It is much slower, but useful as a reference to check the SIMD
+/// implementation against, or to rule it out when debugging.
+pub fn deblock_scalar(data: &[u8], width: usize, strength: u8) -> Vec<u8> {
+    let mut result = data.to_vec();
+    deblock_scalar_with(&mut result, width, |_| strength);
 
     result
 }
 
+/// Applies the deblocking filter to the horizontal and vertical block edges
+/// of the given image data, with a strength for each macroblock, assuming
+/// 8x8 block size.
+///
+/// `strengths` holds the strength of each macroblock of the picture in
+/// raster order. Macroblocks are `mb_size` samples wide and tall: 16 for
+/// luma planes, 8 for chroma planes. Each quartet of samples across an edge
+/// is filtered with the strength of the macroblock containing it's "B"
+/// sample, that is, the one above or to the left of the edge. A strength of
+/// 0 leaves the edges of that macroblock unfiltered.
+///
+/// Returns `None` if there isn't exactly one strength for each macroblock.
+pub fn deblock_with_strength_map(
+    data: &[u8],
+    width: usize,
+    strengths: &[u8],
+    mb_size: usize,
+) -> Option<Vec<u8>> {
+    if mb_size == 0 {
+        return None;
+    }
+
+    if width == 0 {
+        return strengths.is_empty().then(Vec::new);
+    }
+
+    debug_assert!(data.len().is_multiple_of(width));
+
+    let height = data.len() / width;
+    let mb_width = width.div_ceil(mb_size);
+    if height.div_ceil(mb_size) * mb_width != strengths.len() {
+        return None;
+    }
+
+    let strength_at = |(x, y): (usize, usize)| strengths[x / mb_size + (y / mb_size) * mb_width];
+
+    let mut result = data.to_vec();
+
+    // According to the spec, the horizontal deblocking filter is applied first.
+    deblock_horiz(&mut result, width, &strength_at);
+    deblock_vert(&mut result, width, &strength_at);
+
+    Some(result)
+}
+
+/// Applies the deblocking filter to the horizontal and vertical block edges
+/// of the given image data, with a strength for each 16x16 macroblock,
+/// assuming 8x8 block size.
+///
+/// `mb_strengths` holds the strength of each macroblock in raster order,
+/// typically looked up in `QUANT_TO_STRENGTH` from the quantizer that
+/// macroblock was coded with. This is `deblock_with_strength_map` for a luma
+/// plane.
+///
+/// # Panics
+///
+/// Panics if there isn't exactly one strength for each macroblock.
+pub fn deblock_with_mb_strengths(data: &[u8], width: usize, mb_strengths: &[u8]) -> Vec<u8> {
+    deblock_with_strength_map(data, width, mb_strengths, 16)
+        .expect("one deblocking strength per macroblock")
+}
+
+/// Applies the deblocking filter to the horizontal and vertical block edges
+/// of the given image data, in place, one sample quartet at a time.
+///
+/// The strength of each quartet is looked up from the position of it's "B"
+/// sample. Quartets with a strength of 0 are not filtered.
+#[allow(non_snake_case)]
+fn deblock_scalar_with(data: &mut [u8], width: usize, strength_at: impl Fn((usize, usize)) -> u8) {
+    debug_assert!(data.len().is_multiple_of(width));
+
+    let height = data.len() / width;
+
+    // According to the spec, the horizontal deblocking filter is applied first.
+    // The `edge_y` and `edge_x` indices are those of the "C" samples.
+    for edge_y in (8..height.saturating_sub(1)).step_by(8) {
+        let (_, rest) = data.split_at_mut((edge_y - 2) * width);
+        let (row_a, rest) = rest.split_at_mut(width);
+        let (row_b, rest) = rest.split_at_mut(width);
+        let (row_c, rest) = rest.split_at_mut(width);
+        let row_d = &mut rest[..width];
+
+        for (x, (A, B, C, D)) in izip!(row_a, row_b, row_c, row_d).enumerate() {
+            match strength_at((x, edge_y - 1)) {
+                0 => {}
+                strength => process(A, B, C, D, strength),
+            }
+        }
+    }
+
+    for (y, row) in data.chunks_exact_mut(width).enumerate() {
+        for edge_x in (8..width.saturating_sub(1)).step_by(8) {
+            if let [A, B, C, D] = &mut row[edge_x - 2..edge_x + 2] {
+                match strength_at((edge_x - 1, y)) {
+                    0 => {}
+                    strength => process(A, B, C, D, strength),
+                }
+            }
+        }
+    }
+}
+
+/// Applies the deblocking filter to the horizontal and vertical block edges
+/// of the given signed sample data with the given strength, in place,
+/// assuming 8x8 block size.
+///
+/// This is the same filter as `deblock`, but for the wider intermediate
+/// values of picture reconstruction, which may be out of the 0..=255 range
+/// before they are finally clipped. Unlike `deblock`, no clipping is done on
+/// the filtered values. This is only implemented in scalar code.
+#[allow(non_snake_case)]
+pub fn deblock_i16(data: &mut [i16], width: usize, strength: u8) {
+    debug_assert!(data.len().is_multiple_of(width));
+
+    let height = data.len() / width;
+
+    // According to the spec, the horizontal deblocking filter is applied first.
+    // The `edge_y` and `edge_x` indices are those of the "C" samples.
+    for edge_y in (8..height.saturating_sub(1)).step_by(8) {
+        let (_, rest) = data.split_at_mut((edge_y - 2) * width);
+        let (row_a, rest) = rest.split_at_mut(width);
+        let (row_b, rest) = rest.split_at_mut(width);
+        let (row_c, rest) = rest.split_at_mut(width);
+        let row_d = &mut rest[..width];
+
+        for (A, B, C, D) in izip!(row_a, row_b, row_c, row_d) {
+            process_i16(A, B, C, D, strength);
+        }
+    }
+
+    for row in data.chunks_exact_mut(width) {
+        for edge_x in (8..width.saturating_sub(1)).step_by(8) {
+            if let [A, B, C, D] = &mut row[edge_x - 2..edge_x + 2] {
+                process_i16(A, B, C, D, strength);
+            }
+        }
+    }
+}
+
+/// Applies the deblocking filter to all three planes of a YUV 4:2:0 picture.
+///
+/// The chroma planes are assumed to be half the width of the luma plane,
+/// rounded up, which is the case for all pictures decoded from H.263
+/// bitstreams. Annex J usually applies the same strength to all planes, but
+/// the chroma planes may use a different one (e.g. one derived from the
+/// chroma quantizer of Annex T).
+///
+/// The filtered luma, chroma-B, and chroma-R planes are returned in that
+/// order.
+pub fn deblock_yuv420(
+    y: &[u8],
+    cb: &[u8],
+    cr: &[u8],
+    y_width: usize,
+    luma_strength: u8,
+    chroma_strength: u8,
+) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+    let chroma_width = y_width.div_ceil(2);
+
+    (
+        deblock(y, y_width, luma_strength),
+        deblock(cb, chroma_width, chroma_strength),
+        deblock(cr, chroma_width, chroma_strength),
+    )
+}
+
+/// Applies the deblocking filter to all three planes of a YUV 4:2:0 picture,
+/// with the strength Annex J specifies for a given `quant`.
+///
+/// This is the same as `deblock_yuv420`, with both strengths looked up from
+/// `QUANT_TO_STRENGTH`. The filtered luma, chroma-B, and chroma-R planes are
+/// returned in that order, or `None` if `quant` is not a valid quantizer
+/// (1 to 31).
+pub fn deblock_frame(
+    y: &[u8],
+    cb: &[u8],
+    cr: &[u8],
+    y_width: usize,
+    quant: u8,
+) -> Option<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    if !(1..=31).contains(&quant) {
+        return None;
+    }
+
+    let strength = QUANT_TO_STRENGTH[quant as usize];
+
+    Some(deblock_yuv420(y, cb, cr, y_width, strength, strength))
+}
+
 /// These tests serve more as an explanation/demonstration/checking of how all of the above works,
 /// and regression testing, rather than requiring conformance to any externally prescribed results.
 #[cfg(test)]
@@ -498,6 +778,7 @@ mod test {
         ];
         let result_4 = deblock(data, 11, 4);
         assert_eq!(result_4, expected_4);
+        assert_eq!(deblock_scalar(data, 11, 4), result_4);
 
         // A deblocking filter of strength 8 should nicely smooth
         // the vertical 0-10 edge at the top, should also smooth the
@@ -528,6 +809,7 @@ mod test {
         ];
         let result_8 = deblock(data, 11, 8);
         assert_eq!(result_8, expected_8);
+        assert_eq!(deblock_scalar(data, 11, 8), result_8);
 
         // A deblocking filter of strength 12 should nicely smooth almost
         // all edges, with only 10-50 and 80-30 being a bit less affected.
@@ -555,5 +837,195 @@ mod test {
         ];
         let result_12 = deblock(data, 11, 12);
         assert_eq!(result_12, expected_12);
+        assert_eq!(deblock_scalar(data, 11, 12), result_12);
+    }
+
+    #[test]
+    fn test_deblock_yuv420() {
+        // A 20x10 picture with an edge down the middle of every plane, so the
+        // chroma planes are 10x5 with an edge at the 8th column.
+        let y: Vec<u8> = (0..200).map(|i| if i % 20 < 8 { 0 } else { 4 }).collect();
+        let cb: Vec<u8> = (0..50).map(|i| if i % 10 < 8 { 0 } else { 4 }).collect();
+        let cr: Vec<u8> = cb.iter().map(|v| 255 - v).collect();
+
+        let (y_out, cb_out, cr_out) = deblock_yuv420(&y, &cb, &cr, 20, 1, 2);
+        assert_eq!(y_out, deblock(&y, 20, 1));
+        assert_eq!(cb_out, deblock(&cb, 10, 2));
+        assert_eq!(cr_out, deblock(&cr, 10, 2));
+
+        // The chroma edge was actually found and smoothed
+        assert_ne!(cb_out, cb);
+        assert_ne!(cr_out, cr);
+    }
+
+    #[test]
+    fn test_deblock_frame() {
+        let y: Vec<u8> = (0..200).map(|i| if i % 20 < 8 { 0 } else { 40 }).collect();
+        let cb: Vec<u8> = (0..50).map(|i| if i % 10 < 8 { 0 } else { 40 }).collect();
+        let cr: Vec<u8> = cb.iter().map(|v| 255 - v).collect();
+
+        // QUANT 16 is strength 7, for all three planes
+        assert_eq!(
+            deblock_frame(&y, &cb, &cr, 20, 16),
+            Some(deblock_yuv420(&y, &cb, &cr, 20, 7, 7))
+        );
+
+        assert_eq!(deblock_frame(&y, &cb, &cr, 20, 0), None);
+        assert_eq!(deblock_frame(&y, &cb, &cr, 20, 32), None);
+    }
+
+    #[test]
+    fn test_deblock_with_strength_map() {
+        // A 32x32 picture of four flat macroblocks, with edges between all of them
+        let data: Vec<u8> = (0..32 * 32)
+            .map(|i| match (i % 32 < 16, i / 32 < 16) {
+                (true, true) => 0,
+                (false, true) => 20,
+                (true, false) => 40,
+                (false, false) => 60,
+            })
+            .collect();
+
+        // The same strength everywhere is the same as a single strength
+        assert_eq!(
+            deblock_with_strength_map(&data, 32, &[5; 4], 16),
+            Some(deblock(&data, 32, 5))
+        );
+
+        // Only the edges to the right of and below the top-left macroblock
+        // are filtered
+        let result = deblock_with_strength_map(&data, 32, &[5, 0, 0, 0], 16).unwrap();
+        let filtered = deblock(&data, 32, 5);
+        assert_ne!(result, data);
+        for y in 0..32 {
+            for x in 0..32 {
+                let i = x + y * 32;
+                if x >= 18 || y >= 18 {
+                    assert_eq!(result[i], data[i], "at ({}, {})", x, y);
+                } else if y < 13 {
+                    assert_eq!(result[i], filtered[i], "at ({}, {})", x, y);
+                }
+            }
+        }
+
+        // A 16x16 chroma plane of the same picture has 8x8 macroblocks
+        let chroma: Vec<u8> = (0..16 * 16)
+            .map(|i| if i % 16 < 8 { 0 } else { 20 })
+            .collect();
+        assert_eq!(
+            deblock_with_strength_map(&chroma, 16, &[5; 4], 8),
+            Some(deblock(&chroma, 16, 5))
+        );
+
+        assert_eq!(deblock_with_strength_map(&data, 32, &[5; 3], 16), None);
+        assert_eq!(deblock_with_strength_map(&data, 32, &[5; 4], 8), None);
+        assert_eq!(deblock_with_strength_map(&data, 32, &[5; 4], 0), None);
+
+        // An empty picture has no macroblocks to take a strength for
+        assert_eq!(deblock_with_strength_map(&[], 0, &[], 16), Some(vec![]));
+        assert_eq!(deblock_with_strength_map(&[], 0, &[5], 16), None);
+    }
+
+    #[test]
+    fn test_deblock_with_strength_map_simd() {
+        // A 43x35 picture of blocky noise, which is not a whole number of
+        // macroblocks or blocks across or down, with every strength between
+        // 0 and 12 somewhere among it's 3x3 macroblocks.
+        let data: Vec<u8> = (0..43 * 35)
+            .map(|i| ((i % 43 / 4 * 37 + i / 43 / 4 * 91) % 256) as u8)
+            .collect();
+        let mb_strengths = [0, 1, 3, 5, 7, 9, 12, 4, 0];
+
+        // The SIMD implementation agrees with the scalar one
+        let result = deblock_with_strength_map(&data, 43, &mb_strengths, 16).unwrap();
+        let mut scalar = data.clone();
+        deblock_scalar_with(&mut scalar, 43, |(x, y)| {
+            mb_strengths[x / 16 + (y / 16) * 3]
+        });
+        assert_ne!(result, data);
+        assert_eq!(result, scalar);
+
+        // The same strength everywhere is the same as a single strength
+        assert_eq!(
+            deblock_with_strength_map(&data, 43, &[5; 9], 16),
+            Some(deblock(&data, 43, 5))
+        );
+        assert_eq!(
+            deblock_with_strength_map(&data, 43, &[0; 9], 16),
+            Some(data.clone())
+        );
+
+        // A short strength map is rejected instead of indexed out of bounds
+        assert_eq!(deblock_with_strength_map(&data, 43, &[5; 8], 16), None);
+    }
+
+    #[test]
+    fn test_deblock_with_mb_strengths() {
+        let data: Vec<u8> = (0..43 * 35)
+            .map(|i| ((i % 43 / 4 * 37 + i / 43 / 4 * 91) % 256) as u8)
+            .collect();
+        let mb_strengths = [0, 1, 3, 5, 7, 9, 12, 4, 0];
+
+        assert_eq!(
+            Some(deblock_with_mb_strengths(&data, 43, &mb_strengths)),
+            deblock_with_strength_map(&data, 43, &mb_strengths, 16)
+        );
+        assert_eq!(
+            deblock_with_mb_strengths(&data, 43, &[5; 9]),
+            deblock(&data, 43, 5)
+        );
+        assert_eq!(deblock_with_mb_strengths(&data, 43, &[0; 9]), data);
+
+        // `deblock` still filters every edge with the one strength
+        let mut in_place = data.clone();
+        deblock_in_place(&mut in_place, 43, 5);
+        assert_eq!(deblock(&data, 43, 5), in_place);
+    }
+
+    #[test]
+    fn test_deblock_in_place() {
+        // The same edges as in `test_deblock_yuv420`, filtered in place.
+        let data: Vec<u8> = (0..200).map(|i| if i % 20 < 8 { 0 } else { 4 }).collect();
+        let mut in_place = data.clone();
+
+        deblock_in_place(&mut in_place, 20, 3);
+        assert_eq!(in_place, deblock(&data, 20, 3));
+        assert_ne!(in_place, data);
+    }
+
+    #[test]
+    fn test_deblock_i16() {
+        // The same 11x17 image as in `test_deblock`, where nothing is clipped,
+        // gives the same results as the `u8` filter.
+        let data: Vec<u8> = (0..11 * 17)
+            .map(|i| match (i % 11 < 8, i / 11) {
+                (true, 0..=7) => 0,
+                (false, 0..=7) => 10,
+                (true, 8..=15) => 20,
+                (false, 8..=15) => 50,
+                (true, _) => 80,
+                (false, _) => 30,
+            })
+            .collect();
+
+        for strength in 1..=12 {
+            let mut wide: Vec<i16> = data.iter().map(|v| *v as i16).collect();
+            deblock_i16(&mut wide, 11, strength);
+
+            let narrow: Vec<i16> = deblock(&data, 11, strength)
+                .iter()
+                .map(|v| *v as i16)
+                .collect();
+            assert_eq!(wide, narrow);
+        }
+
+        // Out-of-range values are filtered, but not clipped.
+        let mut row: Vec<i16> = [300; 8].iter().chain([304; 8].iter()).copied().collect();
+        deblock_i16(&mut row, 16, 1);
+        assert_eq!(&row[6..10], &[300, 301, 303, 304]);
+
+        let mut row: Vec<i16> = [-4; 8].iter().chain([0; 8].iter()).copied().collect();
+        deblock_i16(&mut row, 16, 1);
+        assert_eq!(&row[6..10], &[-4, -3, -1, 0]);
     }
 }
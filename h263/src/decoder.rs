@@ -3,7 +3,11 @@
 mod cpu;
 mod picture;
 mod state;
+mod stats;
 mod types;
 
+pub use cpu::{dequantize, dequantize_advanced_intra};
+pub use picture::{DecodedPicture, YuvPlane};
 pub use state::H263State;
-pub use types::DecoderOption;
+pub use stats::{DecodeCost, FrameStats};
+pub use types::{DecodeStatus, DecoderOption};
@@ -2,10 +2,14 @@
 
 mod gather;
 mod idct;
+mod intra_pred;
 mod mvd_pred;
 mod rle;
 
-pub use gather::gather;
-pub use idct::idct_channel;
-pub use mvd_pred::{mv_decode, predict_candidate};
-pub use rle::inverse_rle;
+pub use gather::{
+    average_b_picture, average_bidirectional, gather, gather_range, gather_range_overlapped,
+};
+pub use idct::{idct_block, idct_channel};
+pub use intra_pred::IntraPredictionContext;
+pub use mvd_pred::{mv_decode, pb_vectors, predict_candidate};
+pub use rle::{chroma_quantizer, dequantize, dequantize_advanced_intra, inverse_rle};
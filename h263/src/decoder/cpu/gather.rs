@@ -2,7 +2,8 @@
 
 use crate::decoder::picture::DecodedPicture;
 use crate::error::Error;
-use crate::types::{MacroblockType, MotionVector};
+use crate::types::{BPredictionMode, MacroblockType, MotionVector};
+use std::ops::Range;
 
 /// Read a sample from the pixel array at a given position.
 ///
@@ -33,25 +34,72 @@ fn read_sample(
 /// Linear interpolation between two values by 0 or 50%.
 fn lerp(sample_a: u8, sample_b: u8, middle: bool) -> u8 {
     if middle {
-        ((sample_a as u16 + sample_b as u16 + 1) / 2) as u8
+        (sample_a as u16 + sample_b as u16).div_ceil(2) as u8
     } else {
         sample_a
     }
 }
 
+/// Read a single sample at a half-pixel position from a pixel array.
+///
+/// `pos` is the full-pixel part of the position, and `interp` flags which
+/// directions it is half a pixel further along in.
+fn interpolate_sample(
+    pixel_array: &[u8],
+    samples_per_row: usize,
+    array_height: usize,
+    pos: (isize, isize),
+    interp: (bool, bool),
+) -> u8 {
+    let (u, v) = pos;
+    let (x_interp, y_interp) = interp;
+    let sample_0_0 = read_sample(pixel_array, samples_per_row, array_height, (u, v));
+
+    if !x_interp && !y_interp {
+        return sample_0_0;
+    }
+
+    let sample_1_0 = read_sample(pixel_array, samples_per_row, array_height, (u + 1, v));
+    let sample_0_1 = read_sample(pixel_array, samples_per_row, array_height, (u, v + 1));
+    let sample_1_1 = read_sample(pixel_array, samples_per_row, array_height, (u + 1, v + 1));
+
+    if x_interp && y_interp {
+        // Special case: Only round once at the end when interpolating in both directions.
+
+        ((sample_0_0 as u16
+            + sample_1_0 as u16
+            + sample_0_1 as u16
+            + sample_1_1 as u16
+            + 2) // for proper rounding
+            / 4) as u8
+    } else {
+        // Interpolating in exactly one of the directions.
+
+        let sample_mid_0 = lerp(sample_0_0, sample_1_0, x_interp);
+        let sample_mid_1 = lerp(sample_0_1, sample_1_1, x_interp);
+
+        lerp(sample_mid_0, sample_mid_1, y_interp)
+    }
+}
+
 /// Copy pixel data from a pixel array, motion-compensate it, and fill a block
 /// with the given data.
 ///
 /// Target block and source pixel array are written to in row-major (x + y*8)
 /// order.
+///
+/// Motion vectors too large to have come from a valid bitstream are rejected
+/// with `InvalidSemantics`, and the block is left untouched.
 fn gather_block(
     pixel_array: &[u8],
     samples_per_row: usize,
     pos: (usize, usize),
     mv: MotionVector,
     target: &mut [u8],
-) {
-    let ((x_delta, x_interp), (y_delta, y_interp)) = mv.into_lerp_parameters();
+) -> Result<(), Error> {
+    let ((x_delta, x_interp), (y_delta, y_interp)) = mv
+        .checked_into_lerp_parameters()
+        .ok_or(Error::InvalidSemantics)?;
 
     let src_x = pos.0 as isize + x_delta as isize;
     let src_y = pos.1 as isize + y_delta as isize;
@@ -60,8 +108,9 @@ fn gather_block(
     let block_cols = (samples_per_row as isize - pos.0 as isize).clamp(0, 8);
     let block_rows = (array_height as isize - pos.1 as isize).clamp(0, 8);
 
-    if !x_interp && !y_interp {
-        // Fast path: No interpolation needed in either direction.
+    if mv.is_full_pixel() {
+        // Fast path: Full-pixel vectors need no interpolation in either
+        // direction, so motion compensation is a plain copy.
 
         if block_cols == 8
             && block_rows == 8
@@ -92,37 +141,167 @@ fn gather_block(
 
         for (j, v) in (src_y..src_y + block_rows).enumerate() {
             for (i, u) in (src_x..src_x + block_cols).enumerate() {
-                let sample_0_0 = read_sample(pixel_array, samples_per_row, array_height, (u, v));
-                let sample_1_0 =
-                    read_sample(pixel_array, samples_per_row, array_height, (u + 1, v));
-                let sample_0_1 =
-                    read_sample(pixel_array, samples_per_row, array_height, (u, v + 1));
-                let sample_1_1 =
-                    read_sample(pixel_array, samples_per_row, array_height, (u + 1, v + 1));
-
-                if x_interp && y_interp {
-                    // Special case: Only round once at the end when interpolating in both directions.
-
-                    let sample = ((sample_0_0 as u16
-                        + sample_1_0 as u16
-                        + sample_0_1 as u16
-                        + sample_1_1 as u16
-                        + 2) // for proper rounding
-                        / 4) as u8;
-
-                    target[pos.0 + i + ((pos.1 + j) * samples_per_row)] = sample;
-                } else {
-                    // Interpolating in exactly one of the directions.
-
-                    let sample_mid_0 = lerp(sample_0_0, sample_1_0, x_interp);
-                    let sample_mid_1 = lerp(sample_0_1, sample_1_1, x_interp);
-
-                    target[pos.0 + i + ((pos.1 + j) * samples_per_row)] =
-                        lerp(sample_mid_0, sample_mid_1, y_interp);
-                }
+                target[pos.0 + i + ((pos.1 + j) * samples_per_row)] = interpolate_sample(
+                    pixel_array,
+                    samples_per_row,
+                    array_height,
+                    (u, v),
+                    (x_interp, y_interp),
+                );
             }
         }
     }
+
+    Ok(())
+}
+
+/// Weighting of the prediction from a block's own motion vector, in
+/// overlapped block motion compensation.
+///
+/// ITU-T Recommendation H.263 (01/2005) Figure F.2.
+const OBMC_CURRENT_WEIGHTS: [[u16; 8]; 8] = [
+    [4, 5, 5, 5, 5, 5, 5, 4],
+    [5, 5, 5, 5, 5, 5, 5, 5],
+    [5, 5, 6, 6, 6, 6, 5, 5],
+    [5, 5, 6, 6, 6, 6, 5, 5],
+    [5, 5, 6, 6, 6, 6, 5, 5],
+    [5, 5, 6, 6, 6, 6, 5, 5],
+    [5, 5, 5, 5, 5, 5, 5, 5],
+    [4, 5, 5, 5, 5, 5, 5, 4],
+];
+
+/// Weighting of the prediction from the motion vector of the block above or
+/// below, in overlapped block motion compensation.
+///
+/// ITU-T Recommendation H.263 (01/2005) Figure F.3.
+const OBMC_VERTICAL_WEIGHTS: [[u16; 8]; 8] = [
+    [2, 2, 2, 2, 2, 2, 2, 2],
+    [1, 1, 2, 2, 2, 2, 1, 1],
+    [1, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 2, 2, 2, 2, 1, 1],
+    [2, 2, 2, 2, 2, 2, 2, 2],
+];
+
+/// Weighting of the prediction from the motion vector of the block to the
+/// left or right, in overlapped block motion compensation.
+///
+/// ITU-T Recommendation H.263 (01/2005) Figure F.4.
+const OBMC_HORIZONTAL_WEIGHTS: [[u16; 8]; 8] = [
+    [2, 1, 1, 1, 1, 1, 1, 2],
+    [2, 2, 1, 1, 1, 1, 2, 2],
+    [2, 2, 1, 1, 1, 1, 2, 2],
+    [2, 2, 1, 1, 1, 1, 2, 2],
+    [2, 2, 1, 1, 1, 1, 2, 2],
+    [2, 2, 1, 1, 1, 1, 2, 2],
+    [2, 2, 1, 1, 1, 1, 2, 2],
+    [2, 1, 1, 1, 1, 1, 1, 2],
+];
+
+/// The motion vectors an 8x8 luma block is predicted from in overlapped block
+/// motion compensation: it's own, then those of the blocks above, below, to
+/// the left of, and to the right of it.
+type ObmcVectors = [MotionVector; 5];
+
+/// Motion-compensate one 8x8 luma block from a pixel array with overlapped
+/// block motion compensation, and fill a block with the result.
+///
+/// This implements ITU-T Recommendation H.263 (01/2005) F.3. Each sample is
+/// a weighted sum of the predictions from the block's own motion vector, and
+/// those of the nearest vertical and horizontal neighbours. Target block and
+/// source pixel array are written to in row-major (x + y*8) order.
+fn gather_block_obmc(
+    pixel_array: &[u8],
+    samples_per_row: usize,
+    pos: (usize, usize),
+    mvs: ObmcVectors,
+    target: &mut [u8],
+) -> Result<(), Error> {
+    let mut lerps = [((0, false), (0, false)); 5];
+    for (lerp, mv) in lerps.iter_mut().zip(mvs.iter()) {
+        *lerp = mv
+            .checked_into_lerp_parameters()
+            .ok_or(Error::InvalidSemantics)?;
+    }
+
+    let array_height = pixel_array.len() / samples_per_row;
+    let block_cols = samples_per_row.saturating_sub(pos.0).min(8);
+    let block_rows = array_height.saturating_sub(pos.1).min(8);
+    let sample = |(x, y): (usize, usize), ((x_delta, x_interp), (y_delta, y_interp))| {
+        interpolate_sample(
+            pixel_array,
+            samples_per_row,
+            array_height,
+            (
+                (pos.0 + x) as isize + x_delta as isize,
+                (pos.1 + y) as isize + y_delta as isize,
+            ),
+            (x_interp, y_interp),
+        ) as u16
+    };
+
+    for y in 0..block_rows {
+        for x in 0..block_cols {
+            let [current, above, below, left, right] = lerps;
+            let vertical = if y < 4 { above } else { below };
+            let horizontal = if x < 4 { left } else { right };
+            let weighted = sample((x, y), current) * OBMC_CURRENT_WEIGHTS[y][x]
+                + sample((x, y), vertical) * OBMC_VERTICAL_WEIGHTS[y][x]
+                + sample((x, y), horizontal) * OBMC_HORIZONTAL_WEIGHTS[y][x];
+
+            target[pos.0 + x + (pos.1 + y) * samples_per_row] = ((weighted + 4) / 8) as u8;
+        }
+    }
+
+    Ok(())
+}
+
+/// Determine the motion vectors an 8x8 luma block is predicted from, for
+/// overlapped block motion compensation.
+///
+/// `block` is the position of the block within the picture, in blocks.
+/// Neighbouring blocks that are intra coded, outside of the picture, or not
+/// yet decoded are replaced with the block's own vector, as is the block
+/// below for the lower blocks of each macroblock.
+fn obmc_vectors(
+    mb_types: &[MacroblockType],
+    mvs: &[[MotionVector; 4]],
+    mb_per_line: usize,
+    block: (usize, usize),
+) -> ObmcVectors {
+    let vector_at = |x: Option<usize>, y: Option<usize>| {
+        let (x, y) = (x?, y?);
+        if x >= mb_per_line * 2 {
+            return None;
+        }
+
+        let mb = (x / 2) + (y / 2) * mb_per_line;
+        match (mb_types.get(mb), mvs.get(mb)) {
+            (Some(mb_type), Some(mv)) if !mb_type.is_intra() => Some(mv[x % 2 + (y % 2) * 2]),
+            _ => None,
+        }
+    };
+
+    let (x, y) = block;
+    let current = vector_at(Some(x), Some(y)).unwrap_or(MotionVector::ZERO);
+    let above = vector_at(Some(x), y.checked_sub(1));
+    let below = if y % 2 == 0 {
+        vector_at(Some(x), Some(y + 1))
+    } else {
+        None
+    };
+    let left = vector_at(x.checked_sub(1), Some(y));
+    let right = vector_at(Some(x + 1), Some(y));
+
+    [
+        current,
+        above.unwrap_or(current),
+        below.unwrap_or(current),
+        left.unwrap_or(current),
+        right.unwrap_or(current),
+    ]
 }
 
 /// Copy pixels from a previously decoded reference picture into a new picture.
@@ -144,40 +323,138 @@ pub fn gather(
     mb_per_line: usize,
     new_picture: &mut DecodedPicture,
 ) -> Result<(), Error> {
-    for (i, (mb_type, mv)) in mb_types.iter().zip(mvs.iter()).enumerate() {
+    gather_range(
+        mb_types,
+        reference_picture,
+        mvs,
+        mb_per_line,
+        0..mb_types.len(),
+        new_picture,
+    )
+}
+
+/// Copy pixels from a previously decoded reference picture into a new
+/// picture, for only the macroblocks in `mb_range`.
+///
+/// This works the same as `gather`, except that all macroblocks outside of the
+/// range are left untouched.
+pub fn gather_range(
+    mb_types: &[MacroblockType],
+    reference_picture: Option<&DecodedPicture>,
+    mvs: &[[MotionVector; 4]],
+    mb_per_line: usize,
+    mb_range: Range<usize>,
+    new_picture: &mut DecodedPicture,
+) -> Result<(), Error> {
+    gather_macroblocks(
+        mb_types,
+        reference_picture,
+        mvs,
+        mb_per_line,
+        mb_range,
+        false,
+        new_picture,
+    )
+}
+
+/// Copy pixels from a previously decoded reference picture into a new
+/// picture with overlapped block motion compensation, for only the
+/// macroblocks in `mb_range`.
+///
+/// This is the motion compensation used by Advanced Prediction (Annex F).
+/// Luma blocks are predicted from their own motion vectors, as well as those
+/// of their neighbours, so the vectors of the macroblock to the right of each
+/// macroblock in the range must already be in `mvs`. Chroma blocks are
+/// predicted the same way as in `gather_range`.
+pub fn gather_range_overlapped(
+    mb_types: &[MacroblockType],
+    reference_picture: Option<&DecodedPicture>,
+    mvs: &[[MotionVector; 4]],
+    mb_per_line: usize,
+    mb_range: Range<usize>,
+    new_picture: &mut DecodedPicture,
+) -> Result<(), Error> {
+    gather_macroblocks(
+        mb_types,
+        reference_picture,
+        mvs,
+        mb_per_line,
+        mb_range,
+        true,
+        new_picture,
+    )
+}
+
+/// Motion-compensate the macroblocks in `mb_range`, with or without
+/// overlapped block motion compensation of the luma blocks.
+fn gather_macroblocks(
+    mb_types: &[MacroblockType],
+    reference_picture: Option<&DecodedPicture>,
+    mvs: &[[MotionVector; 4]],
+    mb_per_line: usize,
+    mb_range: Range<usize>,
+    overlapped: bool,
+    new_picture: &mut DecodedPicture,
+) -> Result<(), Error> {
+    for (i, (mb_type, mv)) in mb_types
+        .iter()
+        .zip(mvs.iter())
+        .enumerate()
+        .take(mb_range.end)
+        .skip(mb_range.start)
+    {
         if mb_type.is_inter() {
             let reference_picture = reference_picture.ok_or(Error::UncodedIFrameBlocks)?;
             let luma_samples_per_row = reference_picture.luma_samples_per_row();
             let pos = ((i % mb_per_line) * 16, (i / mb_per_line) * 16);
 
-            gather_block(
-                reference_picture.as_luma(),
-                luma_samples_per_row,
-                pos,
-                mv[0],
-                new_picture.as_luma_mut(),
-            );
-            gather_block(
-                reference_picture.as_luma(),
-                luma_samples_per_row,
-                (pos.0 + 8, pos.1),
-                mv[1],
-                new_picture.as_luma_mut(),
-            );
-            gather_block(
-                reference_picture.as_luma(),
-                luma_samples_per_row,
-                (pos.0, pos.1 + 8),
-                mv[2],
-                new_picture.as_luma_mut(),
-            );
-            gather_block(
-                reference_picture.as_luma(),
-                luma_samples_per_row,
-                (pos.0 + 8, pos.1 + 8),
-                mv[3],
-                new_picture.as_luma_mut(),
-            );
+            if overlapped {
+                for block in 0..4 {
+                    let block_pos = (pos.0 + (block % 2) * 8, pos.1 + (block / 2) * 8);
+
+                    gather_block_obmc(
+                        reference_picture.as_luma(),
+                        luma_samples_per_row,
+                        block_pos,
+                        obmc_vectors(
+                            mb_types,
+                            mvs,
+                            mb_per_line,
+                            (block_pos.0 / 8, block_pos.1 / 8),
+                        ),
+                        new_picture.as_luma_mut(),
+                    )?;
+                }
+            } else {
+                gather_block(
+                    reference_picture.as_luma(),
+                    luma_samples_per_row,
+                    pos,
+                    mv[0],
+                    new_picture.as_luma_mut(),
+                )?;
+                gather_block(
+                    reference_picture.as_luma(),
+                    luma_samples_per_row,
+                    (pos.0 + 8, pos.1),
+                    mv[1],
+                    new_picture.as_luma_mut(),
+                )?;
+                gather_block(
+                    reference_picture.as_luma(),
+                    luma_samples_per_row,
+                    (pos.0, pos.1 + 8),
+                    mv[2],
+                    new_picture.as_luma_mut(),
+                )?;
+                gather_block(
+                    reference_picture.as_luma(),
+                    luma_samples_per_row,
+                    (pos.0 + 8, pos.1 + 8),
+                    mv[3],
+                    new_picture.as_luma_mut(),
+                )?;
+            }
 
             let mv_chr = (mv[0] + mv[1] + mv[2] + mv[3]).average_sum_of_mvs();
             let chroma_samples_per_row = reference_picture.chroma_samples_per_row();
@@ -189,16 +466,394 @@ pub fn gather(
                 (chroma_pos.0, chroma_pos.1),
                 mv_chr,
                 new_picture.as_chroma_b_mut(),
-            );
+            )?;
             gather_block(
                 reference_picture.as_chroma_r(),
                 chroma_samples_per_row,
                 (chroma_pos.0, chroma_pos.1),
                 mv_chr,
                 new_picture.as_chroma_r_mut(),
-            );
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Average a backward prediction into a forward prediction, as is done for
+/// the B blocks of PB frames.
+///
+/// Per H.263 (01/2005) G.5, only the parts of each macroblock whose backward
+/// motion vector points inside the co-located macroblock of the P picture are
+/// predicted bidirectionally. The rest of the macroblock keeps it's forward
+/// prediction. `forward` should contain the result of gathering the previous
+/// reference picture with the forward motion vectors, and `backward` the
+/// result of gathering the P picture with the backward motion vectors in
+/// `mvs_b`.
+///
+/// Improved PB frames (Annex M) can also select forward-only or backward-only
+/// prediction for each macroblock in `b_modes`. Forward predicted macroblocks
+/// are left as-is, and backward predicted ones are replaced entirely with the
+/// backward prediction.
+pub fn average_bidirectional(
+    forward: &mut DecodedPicture,
+    backward: &DecodedPicture,
+    mvs_b: &[[MotionVector; 4]],
+    b_modes: &[BPredictionMode],
+    mb_per_line: usize,
+) -> Result<(), Error> {
+    let luma_samples_per_row = backward.luma_samples_per_row();
+    let chroma_samples_per_row = backward.chroma_samples_per_row();
+
+    for (i, (mv, b_mode)) in mvs_b.iter().zip(b_modes.iter()).enumerate() {
+        let pos = ((i % mb_per_line) * 16, (i / mb_per_line) * 16);
+        let chroma_pos = (pos.0 / 2, pos.1 / 2);
+
+        match b_mode {
+            BPredictionMode::Forward => continue,
+            BPredictionMode::Backward => {
+                copy_block(
+                    forward.as_luma_mut(),
+                    backward.as_luma(),
+                    luma_samples_per_row,
+                    pos,
+                    16,
+                );
+                copy_block(
+                    forward.as_chroma_b_mut(),
+                    backward.as_chroma_b(),
+                    chroma_samples_per_row,
+                    chroma_pos,
+                    8,
+                );
+                copy_block(
+                    forward.as_chroma_r_mut(),
+                    backward.as_chroma_r(),
+                    chroma_samples_per_row,
+                    chroma_pos,
+                    8,
+                );
+                continue;
+            }
+            BPredictionMode::Bidirectional | BPredictionMode::Direct => {}
+        }
+
+        for (block, block_mv) in mv.iter().enumerate() {
+            average_block(
+                forward.as_luma_mut(),
+                backward.as_luma(),
+                luma_samples_per_row,
+                (pos.0 + (block % 2) * 8, pos.1 + (block / 2) * 8),
+                *block_mv,
+                (pos, 16),
+            )?;
+        }
+
+        let mv_chr = (mv[0] + mv[1] + mv[2] + mv[3]).average_sum_of_mvs();
+
+        average_block(
+            forward.as_chroma_b_mut(),
+            backward.as_chroma_b(),
+            chroma_samples_per_row,
+            chroma_pos,
+            mv_chr,
+            (chroma_pos, 8),
+        )?;
+        average_block(
+            forward.as_chroma_r_mut(),
+            backward.as_chroma_r(),
+            chroma_samples_per_row,
+            chroma_pos,
+            mv_chr,
+            (chroma_pos, 8),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Combine the forward and backward predictions of a B picture of temporal
+/// scalability (Annex O).
+///
+/// Unlike the B blocks of PB frames, bidirectionally predicted macroblocks
+/// of B pictures are the average of the whole of both predictions, whether
+/// their motion vectors were coded or derived in direct mode. `forward`
+/// should contain the result of gathering the forward reference picture, and
+/// `backward` the result of gathering the backward reference picture.
+/// Forward predicted macroblocks are left as-is, backward predicted ones are
+/// replaced entirely with the backward prediction, and intra macroblocks are
+/// skipped.
+pub fn average_b_picture(
+    forward: &mut DecodedPicture,
+    backward: &DecodedPicture,
+    mb_types: &[MacroblockType],
+    b_modes: &[BPredictionMode],
+    mb_per_line: usize,
+) {
+    let luma_samples_per_row = backward.luma_samples_per_row();
+    let chroma_samples_per_row = backward.chroma_samples_per_row();
+
+    for (i, (mb_type, b_mode)) in mb_types.iter().zip(b_modes.iter()).enumerate() {
+        if !mb_type.is_inter() {
+            continue;
+        }
+
+        let pos = ((i % mb_per_line) * 16, (i / mb_per_line) * 16);
+        let chroma_pos = (pos.0 / 2, pos.1 / 2);
+        let merge = match b_mode {
+            BPredictionMode::Forward => continue,
+            BPredictionMode::Backward => copy_block,
+            BPredictionMode::Bidirectional | BPredictionMode::Direct => average_whole_block,
+        };
+
+        merge(
+            forward.as_luma_mut(),
+            backward.as_luma(),
+            luma_samples_per_row,
+            pos,
+            16,
+        );
+        merge(
+            forward.as_chroma_b_mut(),
+            backward.as_chroma_b(),
+            chroma_samples_per_row,
+            chroma_pos,
+            8,
+        );
+        merge(
+            forward.as_chroma_r_mut(),
+            backward.as_chroma_r(),
+            chroma_samples_per_row,
+            chroma_pos,
+            8,
+        );
+    }
+}
+
+/// Copy a square block of `size` samples from one plane into another.
+fn copy_block(
+    target: &mut [u8],
+    source: &[u8],
+    samples_per_row: usize,
+    pos: (usize, usize),
+    size: usize,
+) {
+    let array_height = source.len() / samples_per_row;
+    let cols = pos.0..(pos.0 + size).min(samples_per_row);
+
+    for y in pos.1..(pos.1 + size).min(array_height) {
+        let row = y * samples_per_row;
+        target[row + cols.start..row + cols.end]
+            .copy_from_slice(&source[row + cols.start..row + cols.end]);
+    }
+}
+
+/// Average a square block of `size` samples of one plane into another.
+fn average_whole_block(
+    target: &mut [u8],
+    source: &[u8],
+    samples_per_row: usize,
+    pos: (usize, usize),
+    size: usize,
+) {
+    let array_height = source.len() / samples_per_row;
+    let cols = pos.0..(pos.0 + size).min(samples_per_row);
+
+    for y in pos.1..(pos.1 + size).min(array_height) {
+        let row = y * samples_per_row;
+        for (target, source) in target[row + cols.start..row + cols.end]
+            .iter_mut()
+            .zip(&source[row + cols.start..row + cols.end])
+        {
+            *target = lerp(*target, *source, true);
+        }
+    }
+}
+
+/// Average one 8x8 block of a backward prediction into a forward one, for
+/// every sample whose motion vector points inside of the square `bounds`
+/// (given as it's top-left corner and size).
+fn average_block(
+    forward: &mut [u8],
+    backward: &[u8],
+    samples_per_row: usize,
+    pos: (usize, usize),
+    mv: MotionVector,
+    bounds: ((usize, usize), usize),
+) -> Result<(), Error> {
+    let ((x_delta, _), (y_delta, _)) = mv
+        .checked_into_lerp_parameters()
+        .ok_or(Error::InvalidSemantics)?;
+    let array_height = backward.len() / samples_per_row;
+    let ((min_x, min_y), size) = bounds;
+    let x_range = min_x as isize..(min_x + size) as isize;
+    let y_range = min_y as isize..(min_y + size) as isize;
+
+    for y in pos.1..(pos.1 + 8).min(array_height) {
+        if !y_range.contains(&(y as isize + y_delta as isize)) {
+            continue;
+        }
+
+        for x in pos.0..(pos.0 + 8).min(samples_per_row) {
+            if x_range.contains(&(x as isize + x_delta as isize)) {
+                let i = x + y * samples_per_row;
+                forward[i] = lerp(forward[i], backward[i], true);
+            }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::decoder::cpu::gather::{
+        gather_block, gather_range, gather_range_overlapped, OBMC_CURRENT_WEIGHTS,
+        OBMC_HORIZONTAL_WEIGHTS, OBMC_VERTICAL_WEIGHTS,
+    };
+    use crate::decoder::DecodedPicture;
+    use crate::types::{
+        HalfPel, MacroblockType, MotionVector, Picture, PictureTypeCode, SourceFormat,
+    };
+
+    #[test]
+    fn gather_outside_picture() {
+        // 16x16 gradient, so that every sample is unique
+        let reference: Vec<u8> = (0..=255).collect();
+        let mut target = vec![0; 256];
+
+        // Two pixels up and to the left of the top-left block
+        let mv = MotionVector::from((HalfPel::from_unit(-4), HalfPel::from_unit(-4)));
+        gather_block(&reference, 16, (0, 0), mv, &mut target).unwrap();
+
+        for y in 0..8 {
+            for x in 0..8 {
+                let src_x = (x as isize - 2).max(0) as usize;
+                let src_y = (y as isize - 2).max(0) as usize;
+                assert_eq!(target[x + y * 16], reference[src_x + src_y * 16]);
+            }
+        }
+
+        // Three and a half pixels right of the bottom-right block
+        let mv = MotionVector::from((HalfPel::from_unit(7), HalfPel::ZERO));
+        gather_block(&reference, 16, (8, 8), mv, &mut target).unwrap();
+
+        for y in 8..16 {
+            for x in 8..16 {
+                let left = reference[(x + 3).min(15) + y * 16] as u16;
+                let right = reference[(x + 4).min(15) + y * 16] as u16;
+                assert_eq!(target[x + y * 16] as u16, (left + right).div_ceil(2));
+            }
+        }
+    }
+
+    #[test]
+    fn gather_full_pixel() {
+        let reference: Vec<u8> = (0..32 * 32).map(|i| (i * 7 % 251) as u8).collect();
+
+        for (x, y) in [(0, 0), (4, -6), (-8, 2), (16, 16), (-40, 30)] {
+            let mv = MotionVector::from((HalfPel::from_unit(x), HalfPel::from_unit(y)));
+            assert!(mv.is_full_pixel());
+
+            let mut target = vec![0; 32 * 32];
+            gather_block(&reference, 32, (8, 8), mv, &mut target).unwrap();
+
+            // The copy must match sampling one pixel at a time
+            for j in 8..16 {
+                for i in 8..16 {
+                    let src_x = (i as isize + x as isize / 2).clamp(0, 31) as usize;
+                    let src_y = (j as isize + y as isize / 2).clamp(0, 31) as usize;
+                    assert_eq!(target[i + j * 32], reference[src_x + src_y * 32]);
+                }
+            }
+        }
+    }
+
+    fn subqcif_picture(picture_type: PictureTypeCode, temporal_reference: u16) -> DecodedPicture {
+        let header = Picture {
+            temporal_reference,
+            format: Some(SourceFormat::SubQcif),
+            quantizer: 8,
+            ..Picture::for_test(picture_type)
+        };
+
+        DecodedPicture::new(header, SourceFormat::SubQcif).unwrap()
+    }
+
+    #[test]
+    fn obmc_weights() {
+        for y in 0..8 {
+            for x in 0..8 {
+                assert_eq!(
+                    OBMC_CURRENT_WEIGHTS[y][x]
+                        + OBMC_VERTICAL_WEIGHTS[y][x]
+                        + OBMC_HORIZONTAL_WEIGHTS[y][x],
+                    8
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn gather_overlapped() {
+        // Sub-QCIF reference picture, with the luma of each column equal to
+        // it's index
+        let mut reference = subqcif_picture(PictureTypeCode::IFrame, 0);
+        for (i, luma) in reference.as_luma_mut().iter_mut().enumerate() {
+            *luma = (i % 128) as u8;
+        }
+        let mb_count = 8 * 6;
+        let right = MotionVector::from((HalfPel::from_unit(4), HalfPel::ZERO));
+        let mut mb_types = vec![MacroblockType::Inter; mb_count];
+        let mut mvs = vec![[right; 4]; mb_count];
+
+        // With the same vector everywhere, the overlapped prediction is the
+        // same as the plain one
+        let mut plain = subqcif_picture(PictureTypeCode::PFrame, 1);
+        let mut overlapped = subqcif_picture(PictureTypeCode::PFrame, 1);
+        gather_range(
+            &mb_types,
+            Some(&reference),
+            &mvs,
+            8,
+            0..mb_count,
+            &mut plain,
+        )
+        .unwrap();
+        gather_range_overlapped(
+            &mb_types,
+            Some(&reference),
+            &mvs,
+            8,
+            0..mb_count,
+            &mut overlapped,
+        )
+        .unwrap();
+        assert_eq!(plain.as_luma(), overlapped.as_luma());
+
+        // The macroblock to the right has no motion, and the one below is
+        // intra coded
+        mvs[1] = [MotionVector::zero(); 4];
+        mb_types[8] = MacroblockType::Intra;
+        gather_range_overlapped(&mb_types, Some(&reference), &mvs, 8, 0..1, &mut overlapped)
+            .unwrap();
+
+        let luma = overlapped.as_luma();
+        for y in 0..16 {
+            for x in 0..16 {
+                // Only the right half of the right blocks is predicted from
+                // the neighbouring vector, the rest all use the same one
+                let current = x as u16 + 2;
+                let horizontal = if x >= 12 { x as u16 } else { current };
+                let (bx, by) = (x % 8, y % 8);
+                let expected = (current * OBMC_CURRENT_WEIGHTS[by][bx]
+                    + current * OBMC_VERTICAL_WEIGHTS[by][bx]
+                    + horizontal * OBMC_HORIZONTAL_WEIGHTS[by][bx]
+                    + 4)
+                    / 8;
+
+                assert_eq!(luma[x + y * 128] as u16, expected, "at ({}, {})", x, y);
+            }
+        }
+    }
+}
@@ -85,14 +85,8 @@ pub fn idct_channel(
     blk_per_line: usize,
     output_samples_per_line: usize,
 ) {
-    let output_height = output.len() / output_samples_per_line;
     let blk_height = block_levels.len() / blk_per_line;
 
-    // Taking advantage of the separability of the 2D IDCT, and
-    // decomposing it into two subsequent orthogonal series of 1D IDCTs.
-    let mut idct_intermediate: [[f32; 8]; 8] = [[0.0; 8]; 8];
-    let mut idct_output: [[f32; 8]; 8] = [[0.0; 8]; 8];
-
     for y_base in 0..blk_height {
         for x_base in 0..blk_per_line {
             let block_id = x_base + (y_base * blk_per_line);
@@ -100,100 +94,125 @@ pub fn idct_channel(
                 continue;
             }
 
-            // It appears that sometimes there are blocks encoded that are entirely outside the frame bounds, so
-            // `x_base * 8` (and `y_base * 8`) can be greater than `output_samples_per_line` (and `output_height`),
-            // hence the need for signed subtraction.
-            let xs = (output_samples_per_line as isize - x_base as isize * 8).clamp(0, 8) as usize;
-            let ys = (output_height as isize - y_base as isize * 8).clamp(0, 8) as usize;
+            idct_block(
+                &block_levels[block_id],
+                output,
+                output_samples_per_line,
+                (x_base, y_base),
+            );
+        }
+    }
+}
+
+/// Transform a single block of reconstructed IDCT levels out of the frequency
+/// domain.
+///
+/// This works the same as `idct_channel`, but only for the one block at
+/// `blk_pos` (given in units of blocks, not samples) within the `output`.
+pub fn idct_block(
+    block: &DecodedDctBlock,
+    output: &mut [u8],
+    output_samples_per_line: usize,
+    blk_pos: (usize, usize),
+) {
+    let output_height = output.len() / output_samples_per_line;
+    let (x_base, y_base) = blk_pos;
+
+    // Taking advantage of the separability of the 2D IDCT, and
+    // decomposing it into two subsequent orthogonal series of 1D IDCTs.
+    let mut idct_intermediate: [[f32; 8]; 8] = [[0.0; 8]; 8];
+    let mut idct_output: [[f32; 8]; 8] = [[0.0; 8]; 8];
+
+    // It appears that sometimes there are blocks encoded that are entirely outside the frame bounds, so
+    // `x_base * 8` (and `y_base * 8`) can be greater than `output_samples_per_line` (and `output_height`),
+    // hence the need for signed subtraction.
+    let xs = (output_samples_per_line as isize - x_base as isize * 8).clamp(0, 8) as usize;
+    let ys = (output_height as isize - y_base as isize * 8).clamp(0, 8) as usize;
 
-            match &block_levels[block_id] {
-                DecodedDctBlock::Zero => {
-                    // Nothing to do here, this block contributes nothing to the output.
+    match block {
+        DecodedDctBlock::Zero => {
+            // Nothing to do here, this block contributes nothing to the output.
+        }
+        DecodedDctBlock::Dc(dc) => {
+            // This is a DC block, so we can skip the IDCT entirely, and just use the
+            // DC coefficient. Note the additional 0.5 factor here compared to the
+            // `Full` case: this is `BASIS_TABLE[0][0] * BASIS_TABLE[0][0]`, and is
+            // needed because the 1D IDCTs in both dimensions would apply the `1/sqrt(2)`
+            // scaling twice, which we have to do here manually.
+            let clipped_idct = ((dc * 0.5 / 4.0 + dc.signum() * 0.5) as i16).clamp(-256, 255);
+
+            for y_offset in 0..ys {
+                for x_offset in 0..xs {
+                    let x = x_base * 8 + x_offset;
+                    let y = y_base * 8 + y_offset;
+
+                    let mocomp_pixel = output[x + (y * output_samples_per_line)] as i16;
+
+                    output[x + (y * output_samples_per_line)] =
+                        (clipped_idct + mocomp_pixel).clamp(0, 255) as u8;
                 }
-                DecodedDctBlock::Dc(dc) => {
-                    // This is a DC block, so we can skip the IDCT entirely, and just use the
-                    // DC coefficient. Note the additional 0.5 factor here compared to the
-                    // `Full` case: this is `BASIS_TABLE[0][0] * BASIS_TABLE[0][0]`, and is
-                    // needed because the 1D IDCTs in both dimensions would apply the `1/sqrt(2)`
-                    // scaling twice, which we have to do here manually.
-                    let clipped_idct =
-                        ((dc * 0.5 / 4.0 + dc.signum() * 0.5) as i16).clamp(-256, 255);
-
-                    for y_offset in 0..ys {
-                        for x_offset in 0..xs {
-                            let x = x_base * 8 + x_offset;
-                            let y = y_base * 8 + y_offset;
-
-                            let mocomp_pixel = output[x + (y * output_samples_per_line)] as i16;
-
-                            output[x + (y * output_samples_per_line)] =
-                                (clipped_idct + mocomp_pixel).clamp(0, 255) as u8;
-                        }
-                    }
+            }
+        }
+        DecodedDctBlock::Horiz(first_row) => {
+            idct_1d(first_row, &mut idct_intermediate[0]);
+
+            for y_offset in 0..ys {
+                for (x_offset, idct) in idct_intermediate[0].iter().take(xs).enumerate() {
+                    let x = x_base * 8 + x_offset;
+                    let y = y_base * 8 + y_offset;
+
+                    let clipped_idct = ((idct * BASIS_TABLE[0][0] / 4.0 + idct.signum() * 0.5)
+                        as i16)
+                        .clamp(-256, 255);
+                    let mocomp_pixel = output[x + (y * output_samples_per_line)] as i16;
+
+                    output[x + (y * output_samples_per_line)] =
+                        (clipped_idct + mocomp_pixel).clamp(0, 255) as u8;
                 }
-                DecodedDctBlock::Horiz(first_row) => {
-                    idct_1d(first_row, &mut idct_intermediate[0]);
-
-                    for y_offset in 0..ys {
-                        for (x_offset, idct) in idct_intermediate[0].iter().take(xs).enumerate() {
-                            let x = x_base * 8 + x_offset;
-                            let y = y_base * 8 + y_offset;
-
-                            let clipped_idct =
-                                ((idct * BASIS_TABLE[0][0] / 4.0 + idct.signum() * 0.5) as i16)
-                                    .clamp(-256, 255);
-                            let mocomp_pixel = output[x + (y * output_samples_per_line)] as i16;
-
-                            output[x + (y * output_samples_per_line)] =
-                                (clipped_idct + mocomp_pixel).clamp(0, 255) as u8;
-                        }
-                    }
+            }
+        }
+        DecodedDctBlock::Vert(first_col) => {
+            idct_1d(first_col, &mut idct_intermediate[0]);
+
+            for (y_offset, idct) in idct_intermediate[0].iter().take(ys).enumerate() {
+                for x_offset in 0..xs {
+                    let x = x_base * 8 + x_offset;
+                    let y = y_base * 8 + y_offset;
+
+                    let clipped_idct = ((idct * BASIS_TABLE[0][0] / 4.0 + idct.signum() * 0.5)
+                        as i16)
+                        .clamp(-256, 255);
+                    let mocomp_pixel = output[x + (y * output_samples_per_line)] as i16;
+
+                    output[x + (y * output_samples_per_line)] =
+                        (clipped_idct + mocomp_pixel).clamp(0, 255) as u8;
                 }
-                DecodedDctBlock::Vert(first_col) => {
-                    idct_1d(first_col, &mut idct_intermediate[0]);
-
-                    for (y_offset, idct) in idct_intermediate[0].iter().take(ys).enumerate() {
-                        for x_offset in 0..xs {
-                            let x = x_base * 8 + x_offset;
-                            let y = y_base * 8 + y_offset;
-
-                            let clipped_idct =
-                                ((idct * BASIS_TABLE[0][0] / 4.0 + idct.signum() * 0.5) as i16)
-                                    .clamp(-256, 255);
-                            let mocomp_pixel = output[x + (y * output_samples_per_line)] as i16;
-
-                            output[x + (y * output_samples_per_line)] =
-                                (clipped_idct + mocomp_pixel).clamp(0, 255) as u8;
-                        }
-                    }
+            }
+        }
+        DecodedDctBlock::Full(block_data) => {
+            for row in 0..8 {
+                idct_1d(&block_data[row], &mut idct_output[row]);
+                for (i, interim_row) in idct_intermediate.iter_mut().enumerate() {
+                    // There is a transposition here!
+                    interim_row[row] = idct_output[row][i];
                 }
-                DecodedDctBlock::Full(block_data) => {
-                    for row in 0..8 {
-                        idct_1d(&block_data[row], &mut idct_output[row]);
-                        for (i, interim_row) in idct_intermediate.iter_mut().enumerate() {
-                            // There is a transposition here!
-                            interim_row[row] = idct_output[row][i];
-                        }
-                    }
-
-                    for row in 0..8 {
-                        idct_1d(&idct_intermediate[row], &mut idct_output[row]);
-                    }
-
-                    // The swapped use of `x_offset` and `y_offset` loops is to undo the above transposition.
-                    for (x_offset, idct_row) in idct_output.iter().take(xs).enumerate() {
-                        for (y_offset, idct) in idct_row.iter().take(ys).enumerate() {
-                            let x = x_base * 8 + x_offset;
-                            let y = y_base * 8 + y_offset;
-
-                            let clipped_idct =
-                                ((idct / 4.0 + idct.signum() * 0.5) as i16).clamp(-256, 255);
-                            let mocomp_pixel = output[x + (y * output_samples_per_line)] as i16;
-
-                            output[x + (y * output_samples_per_line)] =
-                                (clipped_idct + mocomp_pixel).clamp(0, 255) as u8;
-                        }
-                    }
+            }
+
+            for row in 0..8 {
+                idct_1d(&idct_intermediate[row], &mut idct_output[row]);
+            }
+
+            // The swapped use of `x_offset` and `y_offset` loops is to undo the above transposition.
+            for (x_offset, idct_row) in idct_output.iter().take(xs).enumerate() {
+                for (y_offset, idct) in idct_row.iter().take(ys).enumerate() {
+                    let x = x_base * 8 + x_offset;
+                    let y = y_base * 8 + y_offset;
+
+                    let clipped_idct = ((idct / 4.0 + idct.signum() * 0.5) as i16).clamp(-256, 255);
+                    let mocomp_pixel = output[x + (y * output_samples_per_line)] as i16;
+
+                    output[x + (y * output_samples_per_line)] =
+                        (clipped_idct + mocomp_pixel).clamp(0, 255) as u8;
                 }
             }
         }
@@ -0,0 +1,263 @@
+//! Intra coefficient prediction
+//!
+//! Under Advanced Intra Coding (Annex I), the DC and some of the AC
+//! coefficients of intra blocks are coded as a difference from those of the
+//! blocks above or to the left of them. This module covers adding those
+//! predictions back to the dequantized coefficients of each block, before
+//! the IDCT.
+
+use crate::types::{DecodedDctBlock, IntraPredictionMode};
+
+/// The prediction of the DC coefficient when no neighbouring block is
+/// available, and of the AC coefficients when the block they would be
+/// predicted from is not.
+///
+/// ITU-T Recommendation H.263 (01/2005) I.3.
+const UNAVAILABLE_DC: i16 = 1024;
+
+/// The coefficients of already-reconstructed blocks that later blocks of
+/// the same plane may be predicted from.
+///
+/// Only the first row and column of each block can ever be used in
+/// prediction. Predicting in raster order within each macroblock, the first
+/// row of the last block reconstructed in each column of blocks is that of
+/// the block above the next one, and the first column of the last block
+/// reconstructed in each row of blocks is that of the block to the left of
+/// the next one.
+#[derive(Clone, Debug)]
+pub struct IntraPredictionContext {
+    /// The first row of the last block reconstructed in each column.
+    above: Vec<Option<[i16; 8]>>,
+
+    /// The first column of the last block reconstructed in each row.
+    left: Vec<Option<[i16; 8]>>,
+}
+
+impl IntraPredictionContext {
+    /// Construct a prediction context for a plane of a given size, in blocks.
+    pub fn new(blk_per_line: usize, blk_per_column: usize) -> Self {
+        Self {
+            above: vec![None; blk_per_line],
+            left: vec![None; blk_per_column],
+        }
+    }
+
+    /// Mark a block as unavailable for prediction.
+    ///
+    /// This must be done for every block of a non-intra macroblock, so that
+    /// the blocks below and to the right of it aren't predicted from stale
+    /// coefficients.
+    pub fn skip_block(&mut self, pos: (usize, usize)) {
+        self.above[pos.0] = None;
+        self.left[pos.1] = None;
+    }
+
+    /// Mark every block reconstructed so far as unavailable.
+    ///
+    /// Blocks are never predicted across the start of a group of blocks or
+    /// slice, so this must be done at each of them.
+    pub fn start_segment(&mut self) {
+        self.above.fill(None);
+        self.left.fill(None);
+    }
+
+    /// Add the prediction of an intra block to it's dequantized `coefficients`,
+    /// and keep the result for predicting later blocks.
+    ///
+    /// `pos` is the position of the block in the plane, in blocks, and
+    /// `coefficients` are stored in row-major (x + y*8) order. Predicted
+    /// coefficients are clipped to the range of the IDCT input.
+    pub fn predict(
+        &mut self,
+        pos: (usize, usize),
+        mode: IntraPredictionMode,
+        coefficients: &mut [i16; 64],
+    ) {
+        let above = if pos.1 > 0 { self.above[pos.0] } else { None };
+        let left = if pos.0 > 0 { self.left[pos.1] } else { None };
+        let unavailable = {
+            let mut unavailable = [0; 8];
+            unavailable[0] = UNAVAILABLE_DC;
+            unavailable
+        };
+
+        match mode {
+            IntraPredictionMode::Dc => {
+                coefficients[0] += match (above, left) {
+                    (Some(above), Some(left)) => (above[0] + left[0]) / 2,
+                    (Some(only), None) | (None, Some(only)) => only[0],
+                    (None, None) => UNAVAILABLE_DC,
+                };
+                coefficients[0] = coefficients[0].clamp(-2048, 2047);
+            }
+            IntraPredictionMode::Vertical => {
+                let above = above.unwrap_or(unavailable);
+                for (coefficient, prediction) in coefficients[..8].iter_mut().zip(above) {
+                    *coefficient = (*coefficient + prediction).clamp(-2048, 2047);
+                }
+            }
+            IntraPredictionMode::Horizontal => {
+                let left = left.unwrap_or(unavailable);
+                for (coefficient, prediction) in coefficients.iter_mut().step_by(8).zip(left) {
+                    *coefficient = (*coefficient + prediction).clamp(-2048, 2047);
+                }
+            }
+        }
+
+        let mut first_row = [0; 8];
+        let mut first_column = [0; 8];
+        for i in 0..8 {
+            first_row[i] = coefficients[i];
+            first_column[i] = coefficients[i * 8];
+        }
+
+        self.above[pos.0] = Some(first_row);
+        self.left[pos.1] = Some(first_column);
+    }
+
+    /// Add the prediction of an intra block to it's dequantized coefficients,
+    /// as `predict` does, for a `block` that has already been prepared for
+    /// the IDCT.
+    pub fn predict_block(
+        &mut self,
+        pos: (usize, usize),
+        mode: IntraPredictionMode,
+        block: &mut DecodedDctBlock,
+    ) {
+        let mut coefficients = [0; 64];
+        match block {
+            DecodedDctBlock::Zero => {}
+            DecodedDctBlock::Dc(dc) => coefficients[0] = *dc as i16,
+            DecodedDctBlock::Horiz(row) => {
+                for (coefficient, value) in coefficients[..8].iter_mut().zip(row.iter()) {
+                    *coefficient = *value as i16;
+                }
+            }
+            DecodedDctBlock::Vert(column) => {
+                for (coefficient, value) in coefficients.iter_mut().step_by(8).zip(column.iter()) {
+                    *coefficient = *value as i16;
+                }
+            }
+            DecodedDctBlock::Full(rows) => {
+                for (coefficient, value) in coefficients.iter_mut().zip(rows.iter().flatten()) {
+                    *coefficient = *value as i16;
+                }
+            }
+        }
+
+        self.predict(pos, mode, &mut coefficients);
+
+        let is_horiz = coefficients[8..].iter().all(|c| *c == 0);
+        let is_vert = coefficients
+            .iter()
+            .enumerate()
+            .all(|(i, c)| i % 8 == 0 || *c == 0);
+
+        *block = match (is_horiz, is_vert) {
+            (true, true) if coefficients[0] == 0 => DecodedDctBlock::Zero,
+            (true, true) => DecodedDctBlock::Dc(coefficients[0].into()),
+            (true, false) => {
+                DecodedDctBlock::Horiz(std::array::from_fn(|x| coefficients[x].into()))
+            }
+            (false, true) => {
+                DecodedDctBlock::Vert(std::array::from_fn(|y| coefficients[y * 8].into()))
+            }
+            (false, false) => DecodedDctBlock::Full(std::array::from_fn(|y| {
+                std::array::from_fn(|x| coefficients[x + y * 8].into())
+            })),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decoder::cpu::intra_pred::IntraPredictionContext;
+    use crate::types::{DecodedDctBlock, IntraPredictionMode};
+
+    /// A block with only a DC coefficient.
+    fn dc(level: i16) -> [i16; 64] {
+        let mut block = [0; 64];
+        block[0] = level;
+        block
+    }
+
+    #[test]
+    fn dc_prediction() {
+        // One macroblock's worth of luma, in the order it is coded
+        let mut context = IntraPredictionContext::new(2, 2);
+
+        // No neighbours: predicted from the mid-gray default
+        let mut block0 = dc(-24);
+        context.predict((0, 0), IntraPredictionMode::Dc, &mut block0);
+        assert_eq!(block0[0], 1000);
+
+        // Left neighbour only
+        let mut block1 = dc(16);
+        context.predict((1, 0), IntraPredictionMode::Dc, &mut block1);
+        assert_eq!(block1[0], 1016);
+
+        // Above neighbour only
+        let mut block2 = dc(-200);
+        context.predict((0, 1), IntraPredictionMode::Dc, &mut block2);
+        assert_eq!(block2[0], 800);
+
+        // Average of both, rounded down
+        let mut block3 = dc(5);
+        context.predict((1, 1), IntraPredictionMode::Dc, &mut block3);
+        assert_eq!(block3[0], (1016 + 800) / 2 + 5);
+
+        // Non-intra blocks break the chain
+        let mut context = IntraPredictionContext::new(3, 1);
+        let mut block0 = dc(-24);
+        context.predict((0, 0), IntraPredictionMode::Dc, &mut block0);
+        context.skip_block((1, 0));
+        let mut block2 = dc(0);
+        context.predict((2, 0), IntraPredictionMode::Dc, &mut block2);
+        assert_eq!(block2[0], 1024);
+    }
+
+    #[test]
+    fn ac_prediction() {
+        let mut context = IntraPredictionContext::new(2, 2);
+
+        let mut block0 = [0; 64];
+        for (i, coefficient) in block0.iter_mut().enumerate() {
+            *coefficient = i as i16;
+        }
+        context.predict((0, 0), IntraPredictionMode::Dc, &mut block0);
+        assert_eq!(block0[0], 1024);
+
+        // The first column is predicted from the block to the left
+        let mut block1 = [0; 64];
+        context.predict((1, 0), IntraPredictionMode::Horizontal, &mut block1);
+        for y in 0..8 {
+            assert_eq!(block1[y * 8], block0[y * 8]);
+        }
+        assert_eq!(block1[1..8], [0; 7]);
+
+        // The first row is predicted from the block above
+        let mut block2 = [0; 64];
+        block2[3] = 2047;
+        context.predict((0, 1), IntraPredictionMode::Vertical, &mut block2);
+        assert_eq!(block2[..8], [1024, 1, 2, 2047, 4, 5, 6, 7]);
+        assert_eq!(block2[8], 0);
+    }
+
+    #[test]
+    fn predict_block() {
+        let mut context = IntraPredictionContext::new(2, 1);
+
+        // An uncoded block with no neighbours is mid-gray
+        let mut block0 = DecodedDctBlock::Zero;
+        context.predict_block((0, 0), IntraPredictionMode::Dc, &mut block0);
+        assert!(matches!(block0, DecodedDctBlock::Dc(dc) if dc == 1024.0));
+
+        // Predicting from the left adds to the first column
+        let mut block1 = DecodedDctBlock::Horiz([8.0, 16.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        context.predict_block((1, 0), IntraPredictionMode::Horizontal, &mut block1);
+        assert!(matches!(
+            block1,
+            DecodedDctBlock::Horiz(row) if row == [1032.0, 16.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]
+        ));
+    }
+}
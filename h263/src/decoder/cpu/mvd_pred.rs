@@ -33,7 +33,7 @@ pub fn predict_candidate(
     let current_mb = predictor_vectors.len();
     let col_index = current_mb % mb_per_line;
     let mv1_pred = match index {
-        0 | 2 if col_index == 0 => MotionVector::zero(),
+        0 | 2 if col_index == 0 => MotionVector::ZERO,
         0 | 2 => predictor_vectors[current_mb - 1][index + 1],
         1 | 3 => current_predictors[index - 1],
         _ => unreachable!(),
@@ -53,7 +53,7 @@ pub fn predict_candidate(
 
     let is_end_of_line = col_index == mb_per_line.saturating_sub(1);
     let mv3_pred = match index {
-        0 | 1 if is_end_of_line => MotionVector::zero(),
+        0 | 1 if is_end_of_line => MotionVector::ZERO,
         0 | 1 if line_index == 0 => mv1_pred,
         0 | 1 => predictor_vectors
             .get(last_line_mb + 1)
@@ -67,6 +67,13 @@ pub fn predict_candidate(
 }
 
 /// Decode a single component of a motion vector.
+///
+/// Advanced Prediction (Annex F) lets motion vectors point outside of the
+/// picture just like Unrestricted Motion Vectors (Annex D) does, but it does
+/// not extend their range: without UMV, vectors still wrap around to stay
+/// within [-16, 15.5]. Pointing outside of the picture needs no special
+/// handling here, as the gather step always extends the edges of the
+/// reference picture.
 pub fn halfpel_decode(
     current_picture: &DecodedPicture,
     running_options: PictureOption,
@@ -91,22 +98,8 @@ pub fn halfpel_decode(
             Some(MotionVectorRange::Extended)
         )
     {
-        if is_x {
-            range = match current_picture.format().into_width_and_height() {
-                Some((0..=352, _)) => HalfPel::EXTENDED_RANGE,
-                Some((356..=704, _)) => HalfPel::EXTENDED_RANGE_QUADCIF,
-                Some((708..=1408, _)) => HalfPel::EXTENDED_RANGE_SIXTEENCIF,
-                Some((1412..=u16::MAX, _)) => HalfPel::EXTENDED_RANGE_BEYONDCIF,
-                _ => HalfPel::EXTENDED_RANGE, // this is actually an error condition.
-            };
-        } else {
-            range = match current_picture.format().into_width_and_height() {
-                Some((_, 0..=288)) => HalfPel::EXTENDED_RANGE,
-                Some((_, 292..=576)) => HalfPel::EXTENDED_RANGE_QUADCIF,
-                Some((_, 580..=u16::MAX)) => HalfPel::EXTENDED_RANGE_SIXTEENCIF,
-                _ => HalfPel::EXTENDED_RANGE, // this is actually an error condition.
-            };
-        }
+        let (x_range, y_range) = current_picture.format().extended_mv_range();
+        range = if is_x { x_range } else { y_range };
     }
 
     if !out.is_mv_within_range(range) {
@@ -132,3 +125,115 @@ pub fn mv_decode(
 
     (out_x, out_y).into()
 }
+
+/// Derive the forward and backward motion vectors of the B block of a PB
+/// frame macroblock.
+///
+/// `mv` is the decoded motion vector of the corresponding P block, `mvdb` is
+/// the B block's delta vector (if one was coded), `trb` is the temporal
+/// distance from the previous reference picture to the B picture, and `trd`
+/// is the distance from the previous reference picture to the P picture.
+/// Divisions truncate towards zero, per H.263 (01/2005) G.4. A `trd` of zero
+/// is treated as one, so that malformed streams don't divide by zero.
+///
+/// The returned vectors are `(forward, backward)`.
+pub fn pb_vectors(
+    mv: MotionVector,
+    mvdb: Option<MotionVector>,
+    trb: i16,
+    trd: i16,
+) -> (MotionVector, MotionVector) {
+    //Avoid dividing by zero on malformed streams.
+    let trd = trd.max(1);
+    let mvdb = mvdb.unwrap_or(MotionVector::ZERO);
+    let forward = mv.scale(trb, trd) + mvdb;
+    let backward = if mvdb == MotionVector::ZERO {
+        mv.scale(trb - trd, trd)
+    } else {
+        forward - mv
+    };
+
+    (forward, backward)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decoder::cpu::mvd_pred::{halfpel_decode, predict_candidate};
+    use crate::decoder::picture::DecodedPicture;
+    use crate::types::{
+        HalfPel, MotionVector, Picture, PictureOption, PictureTypeCode, SourceFormat,
+    };
+
+    fn mv(x: i16) -> MotionVector {
+        (HalfPel::from_unit(x), HalfPel::from_unit(0)).into()
+    }
+
+    #[test]
+    fn four_vector_candidates() {
+        //Three macroblocks per line; the current macroblock is the second one
+        //on the second line, so it has neighbors to the left, above, and
+        //above-right.
+        let predictor_vectors = [
+            [mv(0), mv(0), mv(0), mv(0)],
+            [mv(20), mv(21), mv(22), mv(23)],
+            [mv(30), mv(31), mv(32), mv(33)],
+            [mv(10), mv(11), mv(12), mv(13)],
+        ];
+        let current = [mv(1), mv(40), mv(3), mv(4)];
+
+        assert_eq!(
+            predict_candidate(&predictor_vectors, &current, 3, 0),
+            mv(22)
+        );
+        assert_eq!(
+            predict_candidate(&predictor_vectors, &current, 3, 1),
+            mv(23)
+        );
+        assert_eq!(
+            predict_candidate(&predictor_vectors, &current, 3, 2),
+            mv(13)
+        );
+        assert_eq!(predict_candidate(&predictor_vectors, &current, 3, 3), mv(3));
+    }
+
+    #[test]
+    fn advanced_prediction_range() {
+        let header = Picture {
+            temporal_reference: 1,
+            format: Some(SourceFormat::SubQcif),
+            options: PictureOption::ADVANCED_PREDICTION,
+            quantizer: 8,
+            ..Picture::for_test(PictureTypeCode::PFrame)
+        };
+        let picture = DecodedPicture::new(header, SourceFormat::SubQcif).unwrap();
+        let decode = |options, predictor, mvd| {
+            halfpel_decode(
+                &picture,
+                options,
+                HalfPel::from_unit(predictor),
+                HalfPel::from_unit(mvd),
+                true,
+            )
+        };
+
+        // 15 + 2 pixels is out of the standard range, so it wraps around
+        assert_eq!(
+            decode(PictureOption::ADVANCED_PREDICTION, 30, 4),
+            HalfPel::from_unit(-30)
+        );
+        assert_eq!(
+            decode(PictureOption::ADVANCED_PREDICTION, -30, -4),
+            HalfPel::from_unit(30)
+        );
+
+        // Only unrestricted motion vectors extend the range
+        assert_eq!(
+            decode(
+                PictureOption::ADVANCED_PREDICTION | PictureOption::UNRESTRICTED_MOTION_VECTORS,
+                30,
+                4
+            ),
+            HalfPel::from_unit(34)
+        );
+    }
+}
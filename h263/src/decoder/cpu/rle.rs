@@ -1,75 +1,66 @@
 //! Block run decompression
 
-use crate::{types::Block, types::DecodedDctBlock};
-
-// These are (x, y) coords, not (row, col).
-const DEZIGZAG_MAPPING: [(u8, u8); 64] = [
-    (0, 0),
-    (1, 0),
-    (0, 1),
-    (0, 2),
-    (1, 1),
-    (2, 0),
-    (3, 0),
-    (2, 1),
-    (1, 2),
-    (0, 3),
-    (0, 4),
-    (1, 3),
-    (2, 2),
-    (3, 1),
-    (4, 0),
-    (5, 0),
-    (4, 1),
-    (3, 2),
-    (2, 3),
-    (1, 4),
-    (0, 5),
-    (0, 6),
-    (1, 5),
-    (2, 4),
-    (3, 3),
-    (4, 2),
-    (5, 1),
-    (6, 0),
-    (7, 0),
-    (6, 1),
-    (5, 2),
-    (4, 3),
-    (3, 4),
-    (2, 5),
-    (1, 6),
-    (0, 7),
-    (1, 7),
-    (2, 6),
-    (3, 5),
-    (4, 4),
-    (5, 3),
-    (6, 2),
-    (7, 1),
-    (7, 2),
-    (6, 3),
-    (5, 4),
-    (4, 5),
-    (3, 6),
-    (2, 7),
-    (3, 7),
-    (4, 6),
-    (5, 5),
-    (6, 4),
-    (7, 3),
-    (7, 4),
-    (6, 5),
-    (5, 6),
-    (4, 7),
-    (5, 7),
-    (6, 6),
-    (7, 5),
-    (7, 6),
-    (6, 7),
-    (7, 7),
+use crate::types::{Block, BlockScan, DecodedDctBlock, IntraPredictionMode};
+
+/// The chroma quantizer for each luma quantizer under Modified Quantization.
+///
+/// ITU-T Recommendation H.263 (01/2005) Table T.1.
+const MODIFIED_CHROMA_QUANTIZER: [u8; 32] = [
+    0, 1, 2, 3, 4, 5, 6, 6, 7, 8, 9, 9, 10, 10, 11, 11, 12, 12, 12, 13, 13, 13, 14, 14, 14, 14, 14,
+    15, 15, 15, 15, 15,
 ];
 
+/// Determine the quantizer of the chroma blocks of a macroblock, given the
+/// quantizer in force for it's luma blocks.
+///
+/// Normally both are the same, but Modified Quantization (Annex T) reduces
+/// the step size of chroma at higher quantizers, so the chroma quantizer must
+/// be looked up from the luma one.
+pub fn chroma_quantizer(quant: u8, is_modified_quantization: bool) -> u8 {
+    if is_modified_quantization {
+        MODIFIED_CHROMA_QUANTIZER[quant.min(31) as usize]
+    } else {
+        quant
+    }
+}
+
+/// Reconstruct a single coefficient from it's quantized level.
+///
+/// This implements ITU-T Recommendation H.263 (01/2005) 6.2.1. The DC
+/// coefficient of an intra block is always reconstructed as eight times it's
+/// `level`, regardless of `quantizer`; `level` here is the `INTRADC` value,
+/// with 255 standing in for 128. All other coefficients are reconstructed
+/// as `quantizer * (2 * |level| + 1)`, less one if `quantizer` is even, with
+/// the sign of `level`. The result is clipped to the range of the IDCT input.
+pub fn dequantize(level: i16, quantizer: u8, is_intra_dc: bool) -> i16 {
+    if is_intra_dc {
+        return match level.clamp(0, 255) {
+            255 => 1024,
+            level => level * 8,
+        };
+    }
+
+    if level == 0 {
+        return 0;
+    }
+
+    let quantizer = quantizer as i32;
+    let magnitude = quantizer * (2 * (level as i32).abs() + 1) - (1 - quantizer % 2);
+
+    (level.signum() as i32 * magnitude).clamp(-2048, 2047) as i16
+}
+
+/// Reconstruct a single coefficient of an intra block coded with Advanced
+/// Intra Coding (Annex I) from it's quantized level.
+///
+/// This implements ITU-T Recommendation H.263 (01/2005) I.3, where every
+/// coefficient, the DC coefficient included, is reconstructed as
+/// `2 * quantizer * level` with no rounding offset. The result is clipped to
+/// the range of the IDCT input.
+pub fn dequantize_advanced_intra(level: i16, quantizer: u8) -> i16 {
+    (2 * quantizer as i32 * level as i32).clamp(-2048, 2047) as i16
+}
+
 /// Inverse RLE, dezigzag, and dequantize encoded block coefficient data.
 ///
 /// `encoded_block` should be the block data as returned from `decode_block`.
@@ -77,6 +68,10 @@ const DEZIGZAG_MAPPING: [(u8, u8); 64] = [
 /// coefficients at the position `pos` (assuming a stride of
 /// `samples_per_line`.)
 ///
+/// Intra blocks of pictures using Advanced Intra Coding (Annex I) must pass
+/// the `intra_mode` of their macroblock, which selects the scan order and
+/// dequantization of their coefficients; all other blocks pass `None`.
+///
 /// This function assumes `levels` has already been initialized to zero. If the
 /// levels array is reused, you must reinitialize it again.
 pub fn inverse_rle(
@@ -85,6 +80,7 @@ pub fn inverse_rle(
     pos: (usize, usize),
     blk_per_line: usize,
     quant: u8,
+    intra_mode: Option<IntraPredictionMode>,
 ) {
     let block_id = pos.0 / 8 + (pos.1 / 8 * blk_per_line);
     let block = &mut levels[block_id];
@@ -114,6 +110,9 @@ pub fn inverse_rle(
         let mut is_horiz = true;
         let mut is_vert = true;
 
+        let zigzag = intra_mode
+            .map_or(BlockScan::Zigzag, IntraPredictionMode::scan)
+            .order();
         let mut zigzag_index = 0;
         if let Some(dc) = encoded_block.intradc {
             block_data[0][0] = dc.into_level().into();
@@ -122,17 +121,18 @@ pub fn inverse_rle(
         for tcoef in encoded_block.tcoef.iter() {
             zigzag_index += tcoef.run as usize;
 
-            if zigzag_index >= DEZIGZAG_MAPPING.len() {
+            if zigzag_index >= zigzag.len() {
                 return;
             }
 
-            let (zig_x, zig_y) = DEZIGZAG_MAPPING[zigzag_index];
-            let dequantized_level = quant as i16 * ((2 * tcoef.level.abs()) + 1);
-            let parity = if quant % 2 == 1 { 0 } else { -1 };
-
-            let value = (tcoef.level.signum() * (dequantized_level + parity)).clamp(-2048, 2047);
-            let val = value.into();
-            block_data[zig_y as usize][zig_x as usize] = val;
+            let zig_x = zigzag[zigzag_index] % 8;
+            let zig_y = zigzag[zigzag_index] / 8;
+            let val = match intra_mode {
+                Some(_) => dequantize_advanced_intra(tcoef.level, quant),
+                None => dequantize(tcoef.level, quant, false),
+            }
+            .into();
+            block_data[zig_y][zig_x] = val;
             zigzag_index += 1;
 
             if val != 0.0 {
@@ -170,3 +170,173 @@ pub fn inverse_rle(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::decoder::cpu::rle::{
+        chroma_quantizer, dequantize, dequantize_advanced_intra, inverse_rle,
+    };
+    use crate::types::{Block, DecodedDctBlock, IntraPredictionMode, TCoefficient};
+
+    #[test]
+    fn dequantize_levels() {
+        // Odd quantizers: |REC| = QUANT * (2 * |LEVEL| + 1)
+        assert_eq!(dequantize(1, 5, false), 15);
+        assert_eq!(dequantize(-1, 5, false), -15);
+        assert_eq!(dequantize(3, 7, false), 49);
+
+        // Even quantizers: |REC| = QUANT * (2 * |LEVEL| + 1) - 1
+        assert_eq!(dequantize(1, 4, false), 11);
+        assert_eq!(dequantize(-2, 4, false), -19);
+        assert_eq!(dequantize(3, 8, false), 55);
+
+        // Zero levels stay zero
+        assert_eq!(dequantize(0, 31, false), 0);
+
+        // Clipping
+        assert_eq!(dequantize(127, 31, false), 2047);
+        assert_eq!(dequantize(-127, 31, false), -2048);
+
+        // Extended levels of Modified Quantization (Annex T)
+        assert_eq!(dequantize(300, 1, false), 601);
+        assert_eq!(dequantize(-1023, 1, false), -2047);
+        assert_eq!(dequantize(-2048, 31, false), -2048);
+
+        // Intra DC ignores the quantizer
+        assert_eq!(dequantize(16, 31, true), 128);
+        assert_eq!(dequantize(128, 1, true), 1024);
+        assert_eq!(dequantize(254, 2, true), 2032);
+        assert_eq!(dequantize(255, 2, true), 1024);
+    }
+
+    #[test]
+    fn advanced_intra_levels() {
+        // |REC| = 2 * QUANT * |LEVEL|, for even and odd quantizers alike
+        assert_eq!(dequantize_advanced_intra(1, 5), 10);
+        assert_eq!(dequantize_advanced_intra(-3, 4), -24);
+        assert_eq!(dequantize_advanced_intra(0, 31), 0);
+
+        // Clipping
+        assert_eq!(dequantize_advanced_intra(127, 31), 2047);
+        assert_eq!(dequantize_advanced_intra(-127, 31), -2048);
+
+        // An intra block with a DC level of 4, and a level of -1 at the
+        // second scan position
+        let block = Block {
+            intradc: None,
+            tcoef: vec![
+                TCoefficient {
+                    is_short: true,
+                    run: 0,
+                    level: 4,
+                },
+                TCoefficient {
+                    is_short: true,
+                    run: 0,
+                    level: -1,
+                },
+            ],
+        };
+
+        // Vertical prediction scans along the first row, and horizontal
+        // prediction down the first column
+        let mut levels = vec![DecodedDctBlock::Zero; 1];
+        inverse_rle(
+            &block,
+            &mut levels,
+            (0, 0),
+            1,
+            3,
+            Some(IntraPredictionMode::Vertical),
+        );
+        match &levels[0] {
+            DecodedDctBlock::Horiz(data) => {
+                assert_eq!(data, &[24.0, -6.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0])
+            }
+            _ => panic!("Expected a horizontal block"),
+        }
+
+        let mut levels = vec![DecodedDctBlock::Zero; 1];
+        inverse_rle(
+            &block,
+            &mut levels,
+            (0, 0),
+            1,
+            3,
+            Some(IntraPredictionMode::Horizontal),
+        );
+        match &levels[0] {
+            DecodedDctBlock::Vert(data) => {
+                assert_eq!(data, &[24.0, -6.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0])
+            }
+            _ => panic!("Expected a vertical block"),
+        }
+    }
+
+    #[test]
+    fn modified_quantization_chroma() {
+        assert_eq!(chroma_quantizer(17, false), 17);
+        assert_eq!(chroma_quantizer(1, true), 1);
+        assert_eq!(chroma_quantizer(6, true), 6);
+        assert_eq!(chroma_quantizer(7, true), 6);
+        assert_eq!(chroma_quantizer(17, true), 12);
+        assert_eq!(chroma_quantizer(31, true), 15);
+
+        // A chroma block with a level of 2 at the first AC position, and -1
+        // at the second
+        let block = Block {
+            intradc: None,
+            tcoef: vec![
+                TCoefficient {
+                    is_short: true,
+                    run: 1,
+                    level: 2,
+                },
+                TCoefficient {
+                    is_short: true,
+                    run: 0,
+                    level: -1,
+                },
+            ],
+        };
+
+        // With a luma quantizer of 20, the chroma step is 13 (odd): 13 * 5
+        // and -(13 * 3)
+        let mut levels = vec![DecodedDctBlock::Zero; 1];
+        inverse_rle(
+            &block,
+            &mut levels,
+            (0, 0),
+            1,
+            chroma_quantizer(20, true),
+            None,
+        );
+
+        let mut expected = [[0.0; 8]; 8];
+        expected[0][1] = 65.0;
+        expected[1][0] = -39.0;
+        match &levels[0] {
+            DecodedDctBlock::Full(data) => assert_eq!(data, &expected),
+            _ => panic!("Expected a full block"),
+        }
+
+        // With a luma quantizer of 12, the chroma step is 10 (even): 10 * 5 - 1
+        // and -(10 * 3 - 1)
+        let mut levels = vec![DecodedDctBlock::Zero; 1];
+        inverse_rle(
+            &block,
+            &mut levels,
+            (0, 0),
+            1,
+            chroma_quantizer(12, true),
+            None,
+        );
+
+        expected[0][1] = 49.0;
+        expected[1][0] = -29.0;
+        match &levels[0] {
+            DecodedDctBlock::Full(data) => assert_eq!(data, &expected),
+            _ => panic!("Expected a full block"),
+        }
+    }
+}
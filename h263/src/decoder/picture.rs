@@ -2,9 +2,26 @@
 
 use std::vec;
 
-use crate::types::{Picture, SourceFormat};
+use crate::types::{MotionVector, Picture, SourceFormat};
+
+/// A borrowed plane of samples from a decoded picture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct YuvPlane<'a> {
+    /// The samples of the plane, in row-major order with 8 bits per sample.
+    pub data: &'a [u8],
+
+    /// The number of samples in each row of the plane.
+    pub width: usize,
+
+    /// The number of rows in the plane.
+    pub height: usize,
+
+    /// The distance between the starts of two consecutive rows in `data`.
+    pub stride: usize,
+}
 
 /// A decoded picture.
+#[derive(Clone)]
 pub struct DecodedPicture {
     /// The header of the picture that was decoded.
     picture_header: Picture,
@@ -25,6 +42,17 @@ pub struct DecodedPicture {
     ///
     /// May be rounded up to the nearest pixel.
     chroma_samples_per_row: usize,
+
+    /// Whether or not the chroma data was lost to bitstream damage.
+    chroma_degraded: bool,
+
+    /// The temporal reference of the picture this one was predicted from.
+    reference_temporal_reference: Option<u16>,
+
+    /// The motion vectors of each 8x8 luma block, four per macroblock.
+    ///
+    /// Only collected with `DecoderOption::COLLECT_MACROBLOCK_STATS`.
+    motion_vectors: Option<Vec<[MotionVector; 4]>>,
 }
 
 impl DecodedPicture {
@@ -54,6 +82,9 @@ impl DecodedPicture {
             chroma_b,
             chroma_r,
             chroma_samples_per_row: chroma_w,
+            chroma_degraded: false,
+            reference_temporal_reference: None,
+            motion_vectors: None,
         })
     }
 
@@ -67,6 +98,115 @@ impl DecodedPicture {
         self.format
     }
 
+    /// Get the temporal reference of the picture this one was predicted
+    /// from.
+    ///
+    /// This is `None` for pictures that were not predicted from any other
+    /// picture, such as I-frames. The B pictures of PB frames yield their
+    /// forward reference; they are also predicted from the P picture decoded
+    /// alongside them.
+    pub fn reference_temporal_reference(&self) -> Option<u16> {
+        self.reference_temporal_reference
+    }
+
+    /// Record the temporal reference of the picture this one was predicted
+    /// from.
+    pub(crate) fn set_reference_temporal_reference(&mut self, reference: Option<u16>) {
+        self.reference_temporal_reference = reference;
+    }
+
+    /// Get the motion vectors each macroblock of this picture was predicted
+    /// with.
+    ///
+    /// Macroblocks are listed in row-major order, each with the vectors of
+    /// it's four luma blocks, in the order top-left, top-right, bottom-left,
+    /// bottom-right. Macroblocks that were not predicted from another picture
+    /// have zero vectors.
+    ///
+    /// This is `None` unless the decoder was constructed with
+    /// `DecoderOption::COLLECT_MACROBLOCK_STATS`.
+    pub fn motion_vectors(&self) -> Option<&[[MotionVector; 4]]> {
+        self.motion_vectors.as_deref()
+    }
+
+    /// Record the motion vectors of each macroblock of this picture.
+    pub(crate) fn set_motion_vectors(&mut self, motion_vectors: Vec<[MotionVector; 4]>) {
+        self.motion_vectors = Some(motion_vectors);
+    }
+
+    /// Render the motion vectors of this picture as an RGBA overlay.
+    ///
+    /// The overlay has the same dimensions as the luma plane, with each 8x8
+    /// block colored according to it's motion vector: the hue gives the
+    /// direction of motion, and the saturation it's magnitude relative to the
+    /// largest vector in the picture. Blocks without motion are fully
+    /// transparent, everything else is opaque.
+    ///
+    /// If no motion vectors were collected (see `motion_vectors`), the
+    /// overlay is entirely transparent.
+    pub fn motion_overlay(&self) -> Vec<u8> {
+        let width = self.luma_samples_per_row();
+        let height = self.luma.len() / width;
+        let mut overlay = vec![0; width * height * 4];
+
+        let motion_vectors = match &self.motion_vectors {
+            Some(motion_vectors) => motion_vectors,
+            None => return overlay,
+        };
+
+        let mb_per_line = width.div_ceil(16);
+        let components = |mv: MotionVector| {
+            let (x, y) = mv.into();
+            (x.to_f32(), y.to_f32())
+        };
+        let max_magnitude = motion_vectors
+            .iter()
+            .flatten()
+            .map(|mv| {
+                let (x, y) = components(*mv);
+                x.hypot(y)
+            })
+            .fold(0.0, f32::max);
+
+        for (y, row) in overlay.chunks_exact_mut(width * 4).enumerate() {
+            for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                let mb = (y / 16) * mb_per_line + x / 16;
+                let block = ((y % 16) / 8) * 2 + (x % 16) / 8;
+                let (mv_x, mv_y) = match motion_vectors.get(mb) {
+                    Some(mvs) => components(mvs[block]),
+                    None => continue,
+                };
+
+                if mv_x == 0.0 && mv_y == 0.0 {
+                    continue;
+                }
+
+                let hue = mv_y.atan2(mv_x).to_degrees().rem_euclid(360.0);
+                let saturation = mv_x.hypot(mv_y) / max_magnitude;
+                let (r, g, b) = hsv_to_rgb(hue, saturation);
+
+                pixel.copy_from_slice(&[r, g, b, 255]);
+            }
+        }
+
+        overlay
+    }
+
+    /// Convert this picture to an RGBA image.
+    ///
+    /// The picture is converted from BT.601 YUV as-is, without deblocking,
+    /// and cropped to the dimensions of it's source format.
+    #[cfg(feature = "image")]
+    pub fn to_image(&self) -> image::RgbaImage {
+        let width = self.luma_samples_per_row();
+        let height = self.luma.len() / width;
+        let rgba =
+            h263_rs_yuv::bt601::yuv420_to_rgba(&self.luma, &self.chroma_b, &self.chroma_r, width);
+
+        image::RgbaImage::from_raw(width as u32, height as u32, rgba)
+            .expect("conversion yields one RGBA pixel per luma sample")
+    }
+
     /// Get the luma data for this picture.
     ///
     /// Raw luma data is stored in row-major (x + y*samples_per_row) order with
@@ -136,8 +276,249 @@ impl DecodedPicture {
         &mut self.chroma_r
     }
 
+    /// Determine if the chroma data of this picture was lost.
+    ///
+    /// In lenient mode, pictures with damaged chroma blocks are still decoded,
+    /// with both chroma planes replaced with neutral gray. Pictures predicted
+    /// from a degraded picture are degraded as well, up until the next
+    /// I-frame. Such pictures should be displayed as-is, but are probably not
+    /// worth keeping around as a reference for e.g. thumbnails.
+    pub fn is_chroma_degraded(&self) -> bool {
+        self.chroma_degraded
+    }
+
+    /// Replace both chroma planes with neutral gray, and mark this picture as
+    /// having lost it's chroma data.
+    pub(crate) fn degrade_chroma(&mut self) {
+        self.chroma_b.fill(128);
+        self.chroma_r.fill(128);
+        self.chroma_degraded = true;
+    }
+
     /// Borrow the YUV data in this picture.
     pub fn as_yuv(&self) -> (&[u8], &[u8], &[u8]) {
         (&self.luma, &self.chroma_b, &self.chroma_r)
     }
+
+    /// Borrow the luma, chroma-B, and chroma-R planes of this picture, along
+    /// with their dimensions.
+    ///
+    /// This is intended for consumers that do their own color conversion,
+    /// such as GPU shaders. Chroma planes are half the width and height of the
+    /// luma plane, rounded up.
+    pub fn planes(&self) -> (YuvPlane<'_>, YuvPlane<'_>, YuvPlane<'_>) {
+        let luma_width = self.luma_samples_per_row();
+        let luma_height = self.luma.len() / luma_width;
+        let chroma_width = self.chroma_samples_per_row;
+        let chroma_height = self.chroma_b.len() / chroma_width;
+
+        (
+            YuvPlane {
+                data: &self.luma,
+                width: luma_width,
+                height: luma_height,
+                stride: luma_width,
+            },
+            YuvPlane {
+                data: &self.chroma_b,
+                width: chroma_width,
+                height: chroma_height,
+                stride: chroma_width,
+            },
+            YuvPlane {
+                data: &self.chroma_r,
+                width: chroma_width,
+                height: chroma_height,
+                stride: chroma_width,
+            },
+        )
+    }
+
+    /// Compute the peak signal-to-noise ratio of each plane of this picture,
+    /// in decibels, against another picture.
+    ///
+    /// This is intended for testing decoder changes that are not expected to
+    /// be bit-exact, such as IDCT approximations within the tolerance allowed
+    /// by Annex A. The result is given in luma, chroma-B, chroma-R order.
+    /// Identical planes yield infinity.
+    ///
+    /// Both pictures must have the same dimensions, otherwise this will
+    /// panic.
+    pub fn psnr(&self, other: &DecodedPicture) -> [f64; 3] {
+        let (luma, chroma_b, chroma_r) = self.planes();
+        let (other_luma, other_chroma_b, other_chroma_r) = other.planes();
+
+        [
+            plane_psnr(luma, other_luma),
+            plane_psnr(chroma_b, other_chroma_b),
+            plane_psnr(chroma_r, other_chroma_r),
+        ]
+    }
+}
+
+/// Convert a fully bright color, with a hue in degrees and a saturation in
+/// the range 0 to 1, into 8-bit RGB.
+fn hsv_to_rgb(hue: f32, saturation: f32) -> (u8, u8, u8) {
+    let chroma = saturation;
+    let sector = hue / 60.0;
+    let secondary = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u8 {
+        0 => (chroma, secondary, 0.0),
+        1 => (secondary, chroma, 0.0),
+        2 => (0.0, chroma, secondary),
+        3 => (0.0, secondary, chroma),
+        4 => (secondary, 0.0, chroma),
+        _ => (chroma, 0.0, secondary),
+    };
+    let lightness = 1.0 - chroma;
+    let to_u8 = |c: f32| ((c + lightness) * 255.0).round() as u8;
+
+    (to_u8(r), to_u8(g), to_u8(b))
+}
+
+/// Compute the PSNR of two 8-bit planes of equal size.
+fn plane_psnr(lhs: YuvPlane<'_>, rhs: YuvPlane<'_>) -> f64 {
+    assert_eq!(
+        (lhs.width, lhs.height),
+        (rhs.width, rhs.height),
+        "Cannot compare planes of different sizes"
+    );
+
+    let mut sum_squared_error = 0u64;
+    for y in 0..lhs.height {
+        let lhs_row = &lhs.data[y * lhs.stride..y * lhs.stride + lhs.width];
+        let rhs_row = &rhs.data[y * rhs.stride..y * rhs.stride + rhs.width];
+
+        for (l, r) in lhs_row.iter().zip(rhs_row.iter()) {
+            let error = (*l as i32 - *r as i32).unsigned_abs() as u64;
+            sum_squared_error += error * error;
+        }
+    }
+
+    if sum_squared_error == 0 {
+        return f64::INFINITY;
+    }
+
+    let mse = sum_squared_error as f64 / (lhs.width * lhs.height) as f64;
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decoder::picture::{DecodedPicture, YuvPlane};
+    use crate::types::{
+        CustomPictureFormat, HalfPel, MotionVector, Picture, PictureTypeCode, PixelAspectRatio,
+        SourceFormat,
+    };
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn to_image() {
+        let mut picture = DecodedPicture::new(
+            Picture::for_test(PictureTypeCode::IFrame),
+            SourceFormat::Extended(CustomPictureFormat {
+                pixel_aspect_ratio: PixelAspectRatio::Square,
+                picture_width_indication: 5,
+                picture_height_indication: 3,
+            }),
+        )
+        .unwrap();
+        picture.as_luma_mut().fill(235);
+        picture.as_chroma_b_mut().fill(128);
+        picture.as_chroma_r_mut().fill(128);
+
+        let image = picture.to_image();
+
+        assert_eq!(image.dimensions(), (5, 3));
+        assert!(image.pixels().all(|p| p.0 == [255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn planes() {
+        let format = SourceFormat::Extended(CustomPictureFormat {
+            pixel_aspect_ratio: PixelAspectRatio::Square,
+            picture_width_indication: 34,
+            picture_height_indication: 17,
+        });
+        let mut picture =
+            DecodedPicture::new(Picture::for_test(PictureTypeCode::IFrame), format).unwrap();
+        picture.as_chroma_r_mut()[0] = 7;
+
+        let (luma, chroma_b, chroma_r) = picture.planes();
+
+        assert_eq!(
+            luma,
+            YuvPlane {
+                data: &[0; 34 * 17],
+                width: 34,
+                height: 17,
+                stride: 34,
+            }
+        );
+        assert_eq!(
+            chroma_b,
+            YuvPlane {
+                data: &[0; 17 * 9],
+                width: 17,
+                height: 9,
+                stride: 17,
+            }
+        );
+        assert_eq!(
+            (chroma_r.width, chroma_r.height, chroma_r.stride),
+            (17, 9, 17)
+        );
+        assert_eq!(chroma_r.data[0], 7);
+    }
+
+    #[test]
+    fn psnr() {
+        let mut lhs = DecodedPicture::new(
+            Picture::for_test(PictureTypeCode::IFrame),
+            SourceFormat::SubQcif,
+        )
+        .unwrap();
+        let mut rhs = lhs.clone();
+        assert_eq!(lhs.psnr(&rhs), [f64::INFINITY; 3]);
+
+        // Off by one everywhere: MSE of 1
+        lhs.as_luma_mut().fill(128);
+        rhs.as_luma_mut().fill(129);
+        // Off by 255 in a quarter of the samples: MSE of 255^2 / 4
+        let quarter = rhs.as_chroma_b().len() / 4;
+        rhs.as_chroma_b_mut()[..quarter].fill(255);
+
+        let [luma, chroma_b, chroma_r] = lhs.psnr(&rhs);
+        assert!((luma - 48.1308).abs() < 0.0001);
+        assert!((chroma_b - 6.0206).abs() < 0.0001);
+        assert_eq!(chroma_r, f64::INFINITY);
+        assert_eq!(rhs.psnr(&lhs), [luma, chroma_b, chroma_r]);
+    }
+
+    #[test]
+    fn motion_overlay() {
+        let mut picture = DecodedPicture::new(
+            Picture::for_test(PictureTypeCode::IFrame),
+            SourceFormat::SubQcif,
+        )
+        .unwrap();
+        assert_eq!(picture.motion_vectors(), None);
+        assert!(picture.motion_overlay().iter().all(|c| *c == 0));
+
+        let mv = |x, y| MotionVector::from((HalfPel::from_unit(x), HalfPel::from_unit(y)));
+        let mut motion_vectors = vec![[MotionVector::ZERO; 4]; 8 * 6];
+        // Second macroblock: right at full speed, then down at half speed
+        motion_vectors[1] = [mv(4, 0), mv(4, 0), mv(0, 2), mv(0, 2)];
+        picture.set_motion_vectors(motion_vectors);
+
+        let overlay = picture.motion_overlay();
+        let pixel = |x: usize, y: usize| &overlay[(y * 128 + x) * 4..(y * 128 + x) * 4 + 4];
+
+        assert_eq!(overlay.len(), 128 * 96 * 4);
+        assert_eq!(pixel(0, 0), &[0, 0, 0, 0]);
+        assert_eq!(pixel(16, 0), &[255, 0, 0, 255]);
+        assert_eq!(pixel(31, 7), &[255, 0, 0, 255]);
+        assert_eq!(pixel(16, 8), &[191, 255, 128, 255]);
+        assert_eq!(pixel(32, 0), &[0, 0, 0, 0]);
+    }
 }
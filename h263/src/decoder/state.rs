@@ -1,18 +1,43 @@
 //! H.263 decoder core
 
-use crate::decoder::cpu::{gather, idct_channel, inverse_rle, mv_decode, predict_candidate};
+use crate::decoder::cpu::{
+    average_b_picture, average_bidirectional, chroma_quantizer, gather, gather_range,
+    gather_range_overlapped, idct_block, idct_channel, inverse_rle, mv_decode, pb_vectors,
+    predict_candidate, IntraPredictionContext,
+};
 use crate::decoder::picture::DecodedPicture;
-use crate::decoder::types::DecoderOption;
+use crate::decoder::stats::{DecodeCost, FrameStats};
+use crate::decoder::types::{DecodeStatus, DecoderOption};
 use crate::error::{Error, Result};
-use crate::parser::{decode_block, decode_gob, decode_macroblock, decode_picture, H263Reader};
+use crate::parser::{
+    decode_block, decode_end_of_sequence, decode_gob, decode_macroblock,
+    decode_picture_with_previous_format, dump_picture_data, BitstreamIndex, H263Reader,
+};
 use crate::types::{
-    DecodedDctBlock, GroupOfBlocks, Macroblock, MacroblockType, MotionVector, Picture,
-    PictureOption, PictureTypeCode, MPPTYPE_OPTIONS, OPPTYPE_OPTIONS,
+    BPictureQuantizer, BPredictionMode, CodedBlockPattern, DecodedDctBlock, GroupOfBlocks,
+    IntraPredictionMode, Macroblock, MacroblockType, MotionVector, Picture, PictureOption,
+    PictureTypeCode,
 };
+#[cfg(feature = "pipeline")]
+use h263_rs_deblock::deblock::{deblock_in_place, QUANT_TO_STRENGTH};
+#[cfg(feature = "pipeline")]
+use h263_rs_yuv::bt601::yuv420_to_rgba_into;
 use std::collections::HashMap;
-use std::io::Read;
+use std::io::{Read, Seek};
+use std::ops::Range;
+
+/// A callback that is given a picture being decoded, and the range of luma
+/// rows within it that have just finished reconstruction.
+type RowCallback<'a> = &'a mut dyn FnMut(&DecodedPicture, Range<usize>);
 
 /// All state necessary to decode a successive series of H.263 pictures.
+///
+/// Cloning a state yields an independent decoder that continues from the same
+/// point, which allows speculatively decoding ahead and then discarding the
+/// result. This copies every reference picture held by the state, so it
+/// costs about as much memory and time as a few full pictures' worth of
+/// planes.
+#[derive(Clone)]
 pub struct H263State {
     /// External decoder options enabled on this decoder.
     decoder_options: DecoderOption,
@@ -30,25 +55,80 @@ pub struct H263State {
     /// `None` indicates that no reference picture has been decoded yet.
     reference_picture: Option<u16>,
 
+    /// The temporal reference of the reference picture decoded before the
+    /// current one.
+    ///
+    /// B pictures of temporal scalability (Annex O) are predicted from both
+    /// this picture (forwards) and the current reference picture (backwards),
+    /// as they are coded after both of the pictures they sit between.
+    previous_reference_picture: Option<u16>,
+
+    /// The motion vectors of each macroblock of the reference picture.
+    ///
+    /// Direct prediction in B pictures of temporal scalability (Annex O)
+    /// scales the vectors of the co-located macroblock of the reference
+    /// picture. Empty if the reference picture was not decoded by this state,
+    /// in which case all of it's vectors are taken to be zero.
+    reference_motion_vectors: Vec<[MotionVector; 4]>,
+
     /// All currently in-force picture options as of the last decoded frame.
     running_options: PictureOption,
 
     /// All previously-encoded reference pictures.
     reference_states: HashMap<u16, DecodedPicture>,
+
+    /// The B picture of the last decoded PB frame.
+    ///
+    /// B pictures are never used as references, so they are kept apart from
+    /// all other decoded pictures. `None` indicates that the last decoded
+    /// picture was not a PB frame.
+    last_b_picture: Option<DecodedPicture>,
+
+    /// Macroblock statistics for the last decoded picture.
+    ///
+    /// Only collected with `DecoderOption::COLLECT_MACROBLOCK_STATS`.
+    last_frame_stats: Option<FrameStats>,
+
+    /// Scratch buffers for the deblocked luma, chroma-B, and chroma-R planes
+    /// of the last picture run through the decoding pipeline.
+    #[cfg(feature = "pipeline")]
+    deblocked_planes: [Vec<u8>; 3],
 }
 
 impl H263State {
     /// Construct a new `H263State`.
     pub fn new(decoder_options: DecoderOption) -> Self {
+        Self::with_capacity(decoder_options, 0)
+    }
+
+    /// Construct a new `H263State`, with room for a given number of reference
+    /// pictures.
+    ///
+    /// This is purely a hint, to avoid reallocating as pictures are decoded.
+    /// It doesn't limit how many reference pictures can be kept around, nor
+    /// change how anything is decoded. Without reference picture selection
+    /// (Annex N), two reference pictures are enough for most bitstreams.
+    pub fn with_capacity(decoder_options: DecoderOption, expected_refs: usize) -> Self {
         Self {
             decoder_options,
             last_picture: None,
             reference_picture: None,
+            previous_reference_picture: None,
+            reference_motion_vectors: Vec::new(),
             running_options: PictureOption::empty(),
-            reference_states: HashMap::new(),
+            reference_states: HashMap::with_capacity(expected_refs),
+            last_b_picture: None,
+            last_frame_stats: None,
+            #[cfg(feature = "pipeline")]
+            deblocked_planes: [Vec::new(), Vec::new(), Vec::new()],
         }
     }
 
+    /// Get the external decoder options this decoder was constructed with.
+    pub fn decoder_options(&self) -> DecoderOption {
+        self.decoder_options
+    }
+
     /// Determine if this decoder is in "Sorenson" H.263 mode.
     pub fn is_sorenson(&self) -> bool {
         self.decoder_options
@@ -59,46 +139,207 @@ impl H263State {
     ///
     /// If `None`, then no pictures have yet to be decoded.
     pub fn get_last_picture(&self) -> Option<&DecodedPicture> {
-        if self.last_picture.is_none() {
-            None
-        } else {
-            self.reference_states.get(&self.last_picture.unwrap())
-        }
+        self.last_picture
+            .and_then(|last_picture| self.reference_states.get(&last_picture))
     }
 
     /// Get the implicit reference picture decoded in the bitstream.
     ///
     /// If `None`, then no pictures have yet to be decoded.
     pub fn get_reference_picture(&self) -> Option<&DecodedPicture> {
-        if self.reference_picture.is_none() {
-            None
-        } else {
-            self.reference_states.get(&self.last_picture.unwrap())
-        }
+        self.reference_picture
+            .and_then(|reference_picture| self.reference_states.get(&reference_picture))
+    }
+
+    /// Get the pictures that a B picture of temporal scalability (Annex O)
+    /// decoded next would be predicted from.
+    ///
+    /// These are the last two reference pictures, in forward, backward order:
+    /// B pictures are coded after the pictures on either side of them, and
+    /// are never used as a reference themselves. Either picture is `None` if
+    /// not enough reference pictures have been decoded yet.
+    pub fn get_b_picture_references(&self) -> (Option<&DecodedPicture>, Option<&DecodedPicture>) {
+        (
+            self.previous_reference_picture
+                .and_then(|tr| self.reference_states.get(&tr)),
+            self.reference_picture
+                .and_then(|tr| self.reference_states.get(&tr)),
+        )
+    }
+
+    /// Get the B picture decoded alongside the last picture, if it was a PB
+    /// frame.
+    ///
+    /// The B picture's header is that of the PB frame, except for it's
+    /// temporal reference. It is meant to be displayed before the last
+    /// picture.
+    pub fn get_last_b_picture(&self) -> Option<&DecodedPicture> {
+        self.last_b_picture.as_ref()
+    }
+
+    /// Get the macroblock statistics of the last decoded picture.
+    ///
+    /// If `None`, then either no pictures have yet to be decoded, or the
+    /// decoder was not asked to collect statistics with
+    /// `DecoderOption::COLLECT_MACROBLOCK_STATS`.
+    pub fn get_last_frame_stats(&self) -> Option<&FrameStats> {
+        self.last_frame_stats.as_ref()
+    }
+
+    /// Use an externally-obtained picture as the reference picture for the
+    /// next decode operation.
+    ///
+    /// This is intended for recovering from lost or corrupted pictures, where
+    /// an equivalent of the reference picture has been obtained out-of-band
+    /// (e.g. through reference picture selection, or a separately-decoded
+    /// intra refresh). The picture replaces any previous reference and last
+    /// picture, and is stored under `temporal_reference` regardless of the
+    /// temporal reference in it's own header.
+    ///
+    /// No validation of the picture is performed. It is the caller's
+    /// responsibility to ensure that the picture's source format is the same
+    /// as that of the pictures that will be predicted from it: P-frames that
+    /// omit their source format will inherit the one given here, and motion
+    /// compensation from a reference of different dimensions will yield
+    /// garbage. The picture's header also provides the option set that
+    /// following pictures are parsed relative to.
+    pub fn set_reference_picture(&mut self, temporal_reference: u16, picture: DecodedPicture) {
+        self.last_picture = Some(temporal_reference);
+        self.reference_picture = Some(temporal_reference);
+        self.previous_reference_picture = None;
+        self.reference_motion_vectors = Vec::new();
+        self.reference_states.insert(temporal_reference, picture);
+        self.cleanup_buffers();
+    }
+
+    /// Discard all decoded pictures and picture options, as if this decoder
+    /// had just been constructed.
+    ///
+    /// The next picture decoded must be an I-frame. This is done
+    /// automatically at the start of each new sequence in the bitstream (see
+    /// `decode_next_picture`), so you only need to call this when switching to
+    /// an unrelated bitstream that cannot be detected as such.
+    pub fn reset(&mut self) {
+        self.last_picture = None;
+        self.reference_picture = None;
+        self.previous_reference_picture = None;
+        self.reference_motion_vectors = Vec::new();
+        self.running_options = PictureOption::empty();
+        self.reference_states = HashMap::new();
+        self.last_b_picture = None;
+        self.last_frame_stats = None;
     }
 
     /// Remove all disposable pictures from the reference states list.
     pub fn cleanup_buffers(&mut self) {
-        let last_picture = self
-            .last_picture
-            .and_then(|lp| self.reference_states.remove_entry(&lp));
-        let reference_picture = self
-            .reference_picture
-            .and_then(|rp| self.reference_states.remove_entry(&rp));
+        let kept = [
+            self.last_picture,
+            self.reference_picture,
+            self.previous_reference_picture,
+        ];
 
-        self.reference_states = HashMap::new();
+        self.reference_states
+            .retain(|tr, _| kept.contains(&Some(*tr)));
+    }
 
-        if let Some((k, lp)) = last_picture {
-            self.reference_states.insert(k, lp);
-        }
+    /// Determine if a picture starts a new sequence, in which case nothing
+    /// from before it may carry into it.
+    ///
+    /// Sequences start after an end of sequence code, or with an I-frame
+    /// that changes the picture format.
+    fn starts_sequence(&self, after_end_of_sequence: bool, picture: &Picture) -> bool {
+        after_end_of_sequence
+            || (matches!(picture.picture_type, PictureTypeCode::IFrame)
+                && picture.format.is_some()
+                && self
+                    .get_last_picture()
+                    .map(|p| Some(p.format()) != picture.format)
+                    .unwrap_or(false))
+    }
 
-        if let Some((k, rp)) = reference_picture {
-            self.reference_states.insert(k, rp);
-        }
+    /// Estimate how expensive the next picture in the bitstream will be to
+    /// decode, without decoding it.
+    ///
+    /// The picture header and all of it's macroblocks and blocks are parsed,
+    /// but nothing is reconstructed. Afterwards, the reader is left where it
+    /// was and the decoder state is unchanged, so the picture can still be
+    /// decoded (or skipped) as usual. Parsing typically takes a small fraction
+    /// of the time that reconstruction does.
+    ///
+    /// Unlike decoding, no attempt is made to recover from bitstream errors.
+    pub fn estimate_next_cost<R>(&self, reader: &mut H263Reader<R>) -> Result<DecodeCost>
+    where
+        R: Read,
+    {
+        reader.with_lookahead(|reader| {
+            let after_end_of_sequence = !self.is_sorenson() && decode_end_of_sequence(reader)?;
+            let previous_picture = if after_end_of_sequence {
+                None
+            } else {
+                self.get_last_picture().map(|p| p.as_header())
+            };
+
+            let picture = self
+                .parse_picture(reader, previous_picture)?
+                .ok_or(Error::MiddleOfBitstream)?;
+
+            let running_options = if self.starts_sequence(after_end_of_sequence, &picture) {
+                PictureOption::empty()
+            } else {
+                self.running_options
+            };
+            let format = picture
+                .format
+                .or_else(|| {
+                    previous_picture
+                        .and(self.get_last_picture())
+                        .map(|p| p.format())
+                })
+                .ok_or(Error::PictureFormatMissing)?;
+            let dimensions = format
+                .into_width_and_height()
+                .ok_or(Error::PictureFormatInvalid)?;
+
+            let gobs = dump_picture_data(
+                reader,
+                self.decoder_options,
+                &picture,
+                picture.running_options(running_options),
+                format,
+            )?;
+
+            let mut cost = DecodeCost {
+                picture_type: picture.picture_type,
+                dimensions,
+                coded_macroblocks: 0,
+                uncoded_macroblocks: 0,
+                coded_blocks: 0,
+            };
+            for node in gobs.iter().flat_map(|gob| &gob.macroblocks) {
+                match node.macroblock {
+                    Macroblock::Coded { .. } => cost.coded_macroblocks += 1,
+                    Macroblock::Uncoded => cost.uncoded_macroblocks += 1,
+                    Macroblock::Stuffing => {}
+                }
+
+                cost.coded_blocks += node
+                    .blocks
+                    .iter()
+                    .chain(&node.b_blocks)
+                    .filter(|block| !block.tcoef.is_empty())
+                    .count();
+            }
+
+            Ok(cost)
+        })
     }
 
     /// Parse a picture from the reader using the current state's decoder
     /// options.
+    ///
+    /// `previous_picture` should be the header of the last decoded picture.
+    /// It's format is what the new picture is compared against; if the header
+    /// doesn't restate it, the format of the last decoded picture is used.
     pub fn parse_picture<R>(
         &self,
         reader: &mut H263Reader<R>,
@@ -107,7 +348,73 @@ impl H263State {
     where
         R: Read,
     {
-        decode_picture(reader, self.decoder_options, previous_picture)
+        //Only the decoded picture knows the format it's header may omit.
+        let previous_format = previous_picture.and_then(|picture| {
+            picture
+                .format
+                .or(self.get_last_picture().map(|p| p.format()))
+        });
+
+        decode_picture_with_previous_format(
+            reader,
+            self.decoder_options,
+            previous_picture,
+            previous_format,
+        )
+    }
+
+    /// Skip over any data preceding the next picture start code.
+    ///
+    /// This is intended for bitstreams that are prefixed with a few bytes of
+    /// container framing or other garbage, which would otherwise cause
+    /// `decode_next_picture` to fail with `MiddleOfBitstream`. Group of blocks
+    /// and end of sequence start codes are skipped over as well.
+    ///
+    /// At most `max_bytes` of data will be skipped, so that data which isn't
+    /// H.263 at all won't be read in its entirety looking for a picture that
+    /// isn't there. If no picture start code is found in time, this yields
+    /// `MiddleOfBitstream` and the reader is left where it was. Otherwise,
+    /// the reader is left pointing to the picture start code, and the number
+    /// of bits skipped is returned.
+    pub fn skip_to_next_picture<R>(
+        &self,
+        reader: &mut H263Reader<R>,
+        max_bytes: usize,
+    ) -> Result<u64>
+    where
+        R: Read,
+    {
+        reader.with_transaction(|reader| {
+            let max_skip_bits = (max_bytes as u64 * 8).min(u32::MAX as u64) as u32;
+            let mut skipped_bits = 0;
+
+            loop {
+                let garbage_bits = reader
+                    .recognize_start_code_within(max_skip_bits - skipped_bits)?
+                    .ok_or(Error::MiddleOfBitstream)?;
+                reader.skip_bits(garbage_bits)?;
+                skipped_bits += garbage_bits;
+
+                //Sorenson abuses the GOB ID as a version field.
+                let group_number = reader.peek_bits::<u32>(22)? & 0x1F;
+                let is_picture = if self.is_sorenson() {
+                    group_number <= 1
+                } else {
+                    group_number == 0 && reader.recognize_picture_start_code(false)? == Some(0)
+                };
+
+                if is_picture {
+                    return Ok(skipped_bits as u64);
+                }
+
+                if skipped_bits >= max_skip_bits {
+                    return Err(Error::MiddleOfBitstream);
+                }
+
+                reader.skip_bits(1)?;
+                skipped_bits += 1;
+            }
+        })
     }
 
     /// Decode the next picture in the bitstream.
@@ -135,332 +442,184 @@ impl H263State {
     /// is OK, but seeking the reader to a new position is not. In order to
     /// seek to a new position, you must discard all existing decoder state,
     /// then seek to the position of a valid I frame and begin decoding anew.
-    pub fn decode_next_picture<R>(&mut self, reader: &mut H263Reader<R>) -> Result<()>
+    ///
+    /// Bitstreams made of several independent sequences concatenated together
+    /// are supported. A new sequence starts after an end of sequence code
+    /// (`EOS`), or at an I-frame with a different source format than the last
+    /// picture. All existing decoder state is discarded at the start of each
+    /// new sequence, once it's first picture has been decoded successfully.
+    ///
+    /// Each macroblock is committed as soon as it has been parsed, so the
+    /// reader never has to keep a whole picture buffered. A picture that runs
+    /// out of data partway through can therefore only be retried with more
+    /// data streamed in if it did so within it's first macroblock; otherwise,
+    /// the error is yielded with the reader left where the data ran out.
+    ///
+    /// Yields `DecodeStatus::EndOfStream` if the bitstream ended normally
+    /// instead, with no further pictures in it. Readers that are still being
+    /// streamed into report this as well when they run out of data between
+    /// pictures; it's safe to call this again once more data has arrived.
+    pub fn decode_next_picture<R>(&mut self, reader: &mut H263Reader<R>) -> Result<DecodeStatus>
     where
         R: Read,
     {
-        reader.with_transaction(|reader| {
+        self.decode_picture_rows(reader, None)
+    }
+
+    /// Decode the next picture in the bitstream, reporting each row of
+    /// macroblocks as soon as it has been reconstructed.
+    ///
+    /// This works the same as `decode_next_picture`, except that pixel
+    /// reconstruction is interleaved with parsing one row of macroblocks at a
+    /// time, rather than done for the whole picture at once. After each row is
+    /// reconstructed, `on_row` is called with the picture being decoded and
+    /// the range of luma rows that are now complete (the corresponding chroma
+    /// rows are half of that range). Rows are reported in order, exactly once
+    /// each. This allows low-latency display of pictures that are still being
+    /// decoded, at the cost of some throughput.
+    ///
+    /// Reported rows have not been postprocessed (e.g. deblocked) in any way.
+    /// If decoding fails partway through a picture, rows will have been
+    /// reported for a picture that will never be finished. For PB frames, only
+    /// the P picture is reported: the B picture can only be reconstructed
+    /// once the P picture is complete.
+    pub fn decode_next_picture_progressive<R, F>(
+        &mut self,
+        reader: &mut H263Reader<R>,
+        mut on_row: F,
+    ) -> Result<DecodeStatus>
+    where
+        R: Read,
+        F: FnMut(&DecodedPicture, Range<usize>),
+    {
+        self.decode_picture_rows(reader, Some(&mut on_row))
+    }
+
+    /// Decode the next picture in the bitstream, optionally reconstructing and
+    /// reporting it one row of macroblocks at a time.
+    fn decode_picture_rows<R>(
+        &mut self,
+        reader: &mut H263Reader<R>,
+        mut on_row: Option<RowCallback<'_>>,
+    ) -> Result<DecodeStatus>
+    where
+        R: Read,
+    {
+        let result = reader.with_transaction(|reader| {
+            if is_end_of_stream(reader)? {
+                return Ok(DecodeStatus::EndOfStream);
+            }
+
+            let after_end_of_sequence = !self.is_sorenson() && decode_end_of_sequence(reader)?;
+            if after_end_of_sequence && is_end_of_stream(reader)? {
+                return Ok(DecodeStatus::EndOfStream);
+            }
+
+            let previous_picture = if after_end_of_sequence {
+                None
+            } else {
+                self.get_last_picture().map(|p| p.as_header())
+            };
+
             let next_picture = self
-                .parse_picture(reader, self.get_last_picture().map(|p| p.as_header()))?
+                .parse_picture(reader, previous_picture)?
                 .ok_or(Error::MiddleOfBitstream)?;
 
-            let next_running_options = if next_picture.has_plusptype && next_picture.has_opptype {
-                next_picture.options
-            } else if next_picture.has_plusptype {
-                (next_picture.options & !*OPPTYPE_OPTIONS)
-                    | (self.running_options & *OPPTYPE_OPTIONS)
+            let starts_sequence = self.starts_sequence(after_end_of_sequence, &next_picture);
+            let running_options = if starts_sequence {
+                PictureOption::empty()
             } else {
-                (next_picture.options & !*OPPTYPE_OPTIONS & !*MPPTYPE_OPTIONS)
-                    | (self.running_options & (*OPPTYPE_OPTIONS | *MPPTYPE_OPTIONS))
+                self.running_options
             };
 
+            let next_running_options = next_picture.running_options(running_options);
+
+            debug!(
+                "Decoding {:?} picture, TR {}, options {:?}",
+                next_picture.picture_type, next_picture.temporal_reference, next_running_options
+            );
+
             let format = if let Some(format) = next_picture.format {
                 format
             } else if matches!(next_picture.picture_type, PictureTypeCode::IFrame) {
                 return Err(Error::PictureFormatMissing);
-            } else if let Some(ref_format) = self.get_last_picture().map(|rp| rp.format()) {
+            } else if let Some(ref_format) = previous_picture
+                .and(self.get_last_picture())
+                .map(|rp| rp.format())
+            {
                 ref_format
             } else {
                 return Err(Error::PictureFormatMissing);
             };
 
-            let reference_picture = self.get_reference_picture();
-
-            let output_dimensions = format
-                .into_width_and_height()
-                .ok_or(Error::PictureFormatInvalid)?;
-
-            let mb_per_line = (output_dimensions.0 as f64 / 16.0).ceil() as usize;
-            let mb_height = (output_dimensions.1 as f64 / 16.0).ceil() as usize;
+            //TODO: Reference Picture Resampling (Annex P)
+            if next_picture.reference_picture_resampling.is_some()
+                && !matches!(next_picture.picture_type, PictureTypeCode::IFrame)
+            {
+                return Err(Error::UnimplementedDecoding);
+            }
 
-            let level_dimensions = (mb_per_line * 16, mb_height * 16);
+            let reference_picture = if starts_sequence {
+                None
+            } else {
+                self.get_reference_picture()
+            };
+            let reference_tr = match (reference_picture, next_picture.picture_type) {
+                (_, PictureTypeCode::IFrame) | (None, _) => None,
+                (Some(_), PictureTypeCode::BFrame) => self.previous_reference_picture,
+                (Some(_), _) => self.reference_picture,
+            };
+            let forward_reference = if starts_sequence {
+                None
+            } else {
+                self.get_b_picture_references().0
+            };
 
-            let mut in_force_quantizer = next_picture.quantizer;
-            let mut predictor_vectors = Vec::with_capacity(mb_per_line * mb_height); // all previously decoded MVDs
-            let mut macroblock_types = Vec::with_capacity(mb_per_line * mb_height);
-            let mut macroblocks_after_gob = 0; //reset after every GOB header
+            let pb_frame = self.pb_frame_timing(&next_picture);
+            let b_frame = self.b_frame_timing(&next_picture);
 
             let mut next_decoded_picture =
                 DecodedPicture::new(next_picture, format).ok_or(Error::PictureFormatInvalid)?;
+            next_decoded_picture.set_reference_temporal_reference(reference_tr);
 
-            let mut luma_levels =
-                vec![DecodedDctBlock::Zero; level_dimensions.0 * level_dimensions.1 / 64];
-            let mut chroma_b_levels =
-                vec![DecodedDctBlock::Zero; level_dimensions.0 * level_dimensions.1 / 4 / 64];
-            let mut chroma_r_levels =
-                vec![DecodedDctBlock::Zero; level_dimensions.0 * level_dimensions.1 / 4 / 64];
+            let mut context = PictureContext::new(
+                next_decoded_picture,
+                next_running_options,
+                self.decoder_options,
+                [forward_reference, reference_picture],
+                &self.reference_motion_vectors,
+                pb_frame,
+                b_frame,
+            )?;
 
             loop {
-                let mb = decode_macroblock(
-                    reader,
-                    next_decoded_picture.as_header(),
-                    next_running_options,
-                );
-                let pos = (
-                    (macroblock_types.len() % mb_per_line) * 16,
-                    (macroblock_types.len() / mb_per_line) * 16,
-                );
-                let mut motion_vectors = [MotionVector::zero(); 4];
-
-                let mb_type = match mb {
-                    Ok(Macroblock::Stuffing) => continue,
-                    Ok(Macroblock::Uncoded) => {
-                        if matches!(
-                            next_decoded_picture.as_header().picture_type,
-                            PictureTypeCode::IFrame
-                        ) {
-                            return Err(Error::UncodedIFrameBlocks);
-                        }
-
-                        MacroblockType::Inter
-                    }
-                    Ok(Macroblock::Coded {
-                        mb_type,
-                        coded_block_pattern,
-                        coded_block_pattern_b: _coded_block_pattern_b,
-                        d_quantizer,
-                        motion_vector,
-                        addl_motion_vectors,
-                        motion_vectors_b: _motion_vectors_b,
-                    }) => {
-                        let quantizer = in_force_quantizer as i8 + d_quantizer.unwrap_or(0);
-                        in_force_quantizer = quantizer.clamp(1, 31) as u8;
-
-                        if mb_type.is_inter() {
-                            let mv1 = motion_vector.unwrap_or_else(MotionVector::zero);
-                            let mpred1 = predict_candidate(
-                                &predictor_vectors[macroblocks_after_gob..],
-                                &motion_vectors,
-                                mb_per_line,
-                                0,
-                            );
-
-                            motion_vectors[0] =
-                                mv_decode(&next_decoded_picture, next_running_options, mpred1, mv1);
-
-                            if let Some([mv2, mv3, mv4]) = addl_motion_vectors {
-                                let mpred2 = predict_candidate(
-                                    &predictor_vectors[macroblocks_after_gob..],
-                                    &motion_vectors,
-                                    mb_per_line,
-                                    1,
-                                );
-                                motion_vectors[1] = mv_decode(
-                                    &next_decoded_picture,
-                                    next_running_options,
-                                    mpred2,
-                                    mv2,
-                                );
-
-                                let mpred3 = predict_candidate(
-                                    &predictor_vectors[macroblocks_after_gob..],
-                                    &motion_vectors,
-                                    mb_per_line,
-                                    2,
-                                );
-                                motion_vectors[2] = mv_decode(
-                                    &next_decoded_picture,
-                                    next_running_options,
-                                    mpred3,
-                                    mv3,
-                                );
-
-                                let mpred4 = predict_candidate(
-                                    &predictor_vectors[macroblocks_after_gob..],
-                                    &motion_vectors,
-                                    mb_per_line,
-                                    3,
-                                );
-                                motion_vectors[3] = mv_decode(
-                                    &next_decoded_picture,
-                                    next_running_options,
-                                    mpred4,
-                                    mv4,
-                                );
-                            } else {
-                                motion_vectors[1] = motion_vectors[0];
-                                motion_vectors[2] = motion_vectors[0];
-                                motion_vectors[3] = motion_vectors[0];
-                            };
-                        };
-
-                        let luma0 = decode_block(
-                            reader,
-                            self.decoder_options,
-                            next_decoded_picture.as_header(),
-                            next_running_options,
-                            mb_type,
-                            coded_block_pattern.codes_luma[0],
-                        )?;
-                        inverse_rle(
-                            &luma0,
-                            &mut luma_levels,
-                            pos,
-                            level_dimensions.0 / 8,
-                            in_force_quantizer,
-                        );
-
-                        let luma1 = decode_block(
-                            reader,
-                            self.decoder_options,
-                            next_decoded_picture.as_header(),
-                            next_running_options,
-                            mb_type,
-                            coded_block_pattern.codes_luma[1],
-                        )?;
-                        inverse_rle(
-                            &luma1,
-                            &mut luma_levels,
-                            (pos.0 + 8, pos.1),
-                            level_dimensions.0 / 8,
-                            in_force_quantizer,
-                        );
-
-                        let luma2 = decode_block(
-                            reader,
-                            self.decoder_options,
-                            next_decoded_picture.as_header(),
-                            next_running_options,
-                            mb_type,
-                            coded_block_pattern.codes_luma[2],
-                        )?;
-                        inverse_rle(
-                            &luma2,
-                            &mut luma_levels,
-                            (pos.0, pos.1 + 8),
-                            level_dimensions.0 / 8,
-                            in_force_quantizer,
-                        );
-
-                        let luma3 = decode_block(
-                            reader,
-                            self.decoder_options,
-                            next_decoded_picture.as_header(),
-                            next_running_options,
-                            mb_type,
-                            coded_block_pattern.codes_luma[3],
-                        )?;
-                        inverse_rle(
-                            &luma3,
-                            &mut luma_levels,
-                            (pos.0 + 8, pos.1 + 8),
-                            level_dimensions.0 / 8,
-                            in_force_quantizer,
-                        );
-
-                        let chroma_b = decode_block(
-                            reader,
-                            self.decoder_options,
-                            next_decoded_picture.as_header(),
-                            next_running_options,
-                            mb_type,
-                            coded_block_pattern.codes_chroma_b,
-                        )?;
-                        inverse_rle(
-                            &chroma_b,
-                            &mut chroma_b_levels,
-                            (pos.0 / 2, pos.1 / 2),
-                            mb_per_line,
-                            in_force_quantizer,
-                        );
-
-                        let chroma_r = decode_block(
-                            reader,
-                            self.decoder_options,
-                            next_decoded_picture.as_header(),
-                            next_running_options,
-                            mb_type,
-                            coded_block_pattern.codes_chroma_r,
-                        )?;
-                        inverse_rle(
-                            &chroma_r,
-                            &mut chroma_r_levels,
-                            (pos.0 / 2, pos.1 / 2),
-                            mb_per_line,
-                            in_force_quantizer,
-                        );
-
-                        mb_type
-                    }
-
-                    //Attempt to recover from macroblock errors if possible
-                    Err(ref e) if e.is_macroblock_error() && !self.is_sorenson() => {
-                        match decode_gob(reader, self.decoder_options) {
-                            //Resynchronized to end of picture.
-                            Ok(None) => break,
-
-                            //Resynchronized to end of GOB.
-                            Ok(Some(GroupOfBlocks {
-                                group_number: _group_number,
-                                multiplex_bitstream: _multiplex_bitstream,
-                                frame_id: _frame_id,
-                                quantizer,
-                            })) => {
-                                in_force_quantizer = quantizer;
-                                macroblocks_after_gob = macroblock_types.len();
-                                continue;
-                            }
-
-                            // Treat EOF/GOB errors as end of picture
-                            Err(ref e) if e.is_eof_error() || e.is_gob_error() => break,
-                            Err(e) => return Err(e),
-                        }
-                    }
-
-                    //Treat EOF errors as end of picture
-                    Err(ref e) if e.is_eof_error() => break,
-                    Err(e) => return Err(e),
-                };
+                match context.parse_macroblock(reader)? {
+                    MacroblockStep::Stored => context.reconstruct_stored(&mut on_row)?,
+                    MacroblockStep::Skipped => {}
+                    MacroblockStep::EndOfPicture => break,
+                }
 
-                predictor_vectors.push(motion_vectors);
-                macroblock_types.push(mb_type);
+                //Parsed macroblocks are never read again, so there's no need
+                //to keep them buffered.
+                reader.commit();
             }
 
-            //If the picture ended early, assume all the remaining blocks are
-            //empty INTER blocks with motion vector (0,0)
-            if predictor_vectors.len() < predictor_vectors.capacity() {
-                predictor_vectors.resize(predictor_vectors.capacity(), [MotionVector::zero(); 4]);
-            }
-
-            if macroblock_types.len() < macroblock_types.capacity() {
-                macroblock_types.resize(macroblock_types.capacity(), MacroblockType::Inter);
-            }
-
-            //We have now read out all of the macroblock and block data and
-            //queued it up into the various internal buffers we allocated for
-            //this purpose. Time to decode it all in one go.
-            gather(
-                &macroblock_types,
-                reference_picture,
-                &predictor_vectors,
-                mb_per_line,
-                &mut next_decoded_picture,
-            )?;
-            idct_channel(
-                &luma_levels,
-                next_decoded_picture.as_luma_mut(),
-                mb_per_line * 2,
-                (output_dimensions.0).into(),
-            );
-
-            let chroma_samples_per_row = next_decoded_picture.chroma_samples_per_row();
-            idct_channel(
-                &chroma_b_levels,
-                next_decoded_picture.as_chroma_b_mut(),
-                mb_per_line,
-                chroma_samples_per_row,
-            );
-            idct_channel(
-                &chroma_r_levels,
-                next_decoded_picture.as_chroma_r_mut(),
-                mb_per_line,
-                chroma_samples_per_row,
-            );
+            context.reconstruct_remaining(&mut on_row)?;
+            let b_picture = context.reconstruct_pb_b_picture(reference_tr)?;
+            let PictureContext {
+                picture: next_decoded_picture,
+                predictor_vectors,
+                frame_stats,
+                ..
+            } = context;
 
             //At this point, all decoding should be complete, and we should
             //have a fresh picture to put into the reference pile. We treat YUV
             //encoded pictures as "decoded" since the referencing scheme used
             //in H.263 demands it. Ask a GPU for help.
+            //B pictures may still be predicted from both sides of an iframe.
+            let previous_reference_picture = self.reference_picture;
+
             if matches!(
                 next_decoded_picture.as_header().picture_type,
                 PictureTypeCode::IFrame
@@ -469,6 +628,10 @@ impl H263State {
                 self.reference_picture = None;
             }
 
+            if starts_sequence {
+                self.reset();
+            }
+
             let this_tr = next_decoded_picture.as_header().temporal_reference;
             self.last_picture = Some(this_tr);
             if !next_decoded_picture
@@ -476,15 +639,3067 @@ impl H263State {
                 .picture_type
                 .is_disposable()
             {
+                self.previous_reference_picture = if starts_sequence {
+                    None
+                } else {
+                    previous_reference_picture
+                };
                 self.reference_picture = Some(this_tr);
+                self.reference_motion_vectors = predictor_vectors;
             }
 
             self.reference_states.insert(this_tr, next_decoded_picture);
+            self.last_b_picture = b_picture;
+            self.last_frame_stats = frame_stats;
             self.cleanup_buffers();
 
             reader.commit();
 
-            Ok(())
-        })
+            Ok(DecodeStatus::Decoded)
+        });
+
+        match &result {
+            Err(Error::UnimplementedDecoding) => {
+                error!("Picture uses a bitstream feature that is not implemented")
+            }
+            Err(e) if !e.is_eof_error() => error!("Could not decode picture: {}", e),
+            _ => {}
+        }
+
+        result
+    }
+
+    /// Determine the temporal distances of the B picture of a PB frame.
+    ///
+    /// H.263 Annex G: PB frames carry a B picture, predicted from both the
+    /// reference picture and the P picture, in the same macroblocks. Yields
+    /// `None` if the picture is not a PB frame.
+    fn pb_frame_timing(&self, picture: &Picture) -> Option<PbFrameTiming> {
+        match (
+            picture.picture_type,
+            picture.pb_reference,
+            &picture.pb_quantizer,
+        ) {
+            (
+                PictureTypeCode::PbFrame | PictureTypeCode::ImprovedPbFrame,
+                Some(trb),
+                Some(dbquant),
+            ) => {
+                //Temporal references wrap at 8 bits, or 10 with a custom
+                //picture clock.
+                let tr_modulus = if picture.temporal_reference > 0xFF
+                    || self.reference_picture.unwrap_or(0) > 0xFF
+                {
+                    1024
+                } else {
+                    256
+                };
+                let trd = match self.reference_picture {
+                    Some(ref_tr) => (picture.temporal_reference + tr_modulus - ref_tr) % tr_modulus,
+                    None => 0,
+                };
+
+                //A P picture coincident with it's reference can only come
+                //from a malformed or spliced stream. Place the B picture on
+                //top of the P picture, rather than before the reference, so
+                //that the vector scaling stays sensible. `pb_vectors`
+                //separately guards the TRB = TRD = 0 case.
+                let trd = if trd == 0 { trb as u16 } else { trd };
+                let b_tr =
+                    (picture.temporal_reference + tr_modulus - trd + trb as u16) % tr_modulus;
+
+                Some(PbFrameTiming {
+                    trb: trb as i16,
+                    trd: trd as i16,
+                    temporal_reference: b_tr,
+                    quantizer: dbquant.clone(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Determine the temporal distances of a B picture of temporal
+    /// scalability, as TRB and TRD.
+    ///
+    /// H.263 Annex O: B pictures are predicted from the reference pictures on
+    /// either side of them. Direct prediction scales the backward reference's
+    /// motion vectors by how far the B picture is from the forward reference.
+    /// Yields `None` if the picture is not a B picture.
+    fn b_frame_timing(&self, picture: &Picture) -> Option<(i16, i16)> {
+        match (
+            picture.picture_type,
+            self.previous_reference_picture,
+            self.reference_picture,
+        ) {
+            (PictureTypeCode::BFrame, Some(forward_tr), Some(backward_tr)) => {
+                let tr_modulus =
+                    if picture.temporal_reference > 0xFF || backward_tr > 0xFF || forward_tr > 0xFF
+                    {
+                        1024
+                    } else {
+                        256
+                    };
+                let trb = (picture.temporal_reference + tr_modulus - forward_tr) % tr_modulus;
+                let trd = (backward_tr + tr_modulus - forward_tr) % tr_modulus;
+
+                Some((trb as i16, trd as i16))
+            }
+            //Without both references, there is nothing to predict from and
+            //gathering will fail later on.
+            (PictureTypeCode::BFrame, _, _) => Some((0, 0)),
+            _ => None,
+        }
+    }
+
+    /// Decode the next picture in the bitstream, postprocess it, and convert
+    /// it to RGBA.
+    ///
+    /// This runs the usual sequence of steps a player needs to display a
+    /// picture: `decode_next_picture`, then the deblocking filter on all
+    /// three planes (if the picture requests it, with the strength given by
+    /// the picture quantizer), then BT.601 YUV to RGBA conversion. The
+    /// resulting pixels are written into `rgba`, which is resized to hold
+    /// exactly four bytes per pixel of the picture.
+    ///
+    /// The deblocking filter is only applied to the output, never to the
+    /// picture stored for future reference, unless
+    /// `DecoderOption::IN_LOOP_DEBLOCK` is set: such pictures have already
+    /// been filtered and are converted as-is. Deblocked planes are kept in
+    /// scratch buffers within this state, which are reused across calls.
+    ///
+    /// Yields the width and height of the decoded picture, or `None` if the
+    /// bitstream has ended and there is no picture to convert, in which case
+    /// `rgba` is left untouched.
+    #[cfg(feature = "pipeline")]
+    pub fn decode_and_deblock_and_convert<R>(
+        &mut self,
+        reader: &mut H263Reader<R>,
+        rgba: &mut Vec<u8>,
+    ) -> Result<Option<(u16, u16)>>
+    where
+        R: Read,
+    {
+        if self.decode_next_picture(reader)? == DecodeStatus::EndOfStream {
+            return Ok(None);
+        }
+
+        let reference_states = &self.reference_states;
+        let picture = self
+            .last_picture
+            .and_then(|last_picture| reference_states.get(&last_picture))
+            .ok_or(Error::InternalDecoderError)?;
+        let (width, height) = picture
+            .format()
+            .into_width_and_height()
+            .ok_or(Error::PictureFormatInvalid)?;
+        let luma_width = width as usize;
+        let header = picture.as_header();
+
+        let (luma, chroma_b, chroma_r) = if requests_deblocking(header)
+            && !self
+                .decoder_options
+                .contains(DecoderOption::IN_LOOP_DEBLOCK)
+        {
+            let strength = QUANT_TO_STRENGTH[header.quantizer.clamp(1, 31) as usize];
+            let (luma, chroma_b, chroma_r) = picture.as_yuv();
+
+            for (plane, source, plane_width) in [
+                (0, luma, luma_width),
+                (1, chroma_b, luma_width.div_ceil(2)),
+                (2, chroma_r, luma_width.div_ceil(2)),
+            ] {
+                let deblocked = &mut self.deblocked_planes[plane];
+                deblocked.clear();
+                deblocked.extend_from_slice(source);
+                deblock_in_place(deblocked, plane_width, strength);
+            }
+
+            (
+                &self.deblocked_planes[0][..],
+                &self.deblocked_planes[1][..],
+                &self.deblocked_planes[2][..],
+            )
+        } else {
+            picture.as_yuv()
+        };
+
+        rgba.resize(luma.len() * 4, 0);
+        yuv420_to_rgba_into(luma, chroma_b, chroma_r, luma_width, rgba)
+            .map_err(|_| Error::InternalDecoderError)?;
+
+        Ok(Some((width, height)))
+    }
+
+    /// Decode the picture with a given temporal reference out of an indexed
+    /// bitstream.
+    ///
+    /// The `index` must have been built from the same bitstream the `reader`
+    /// reads from. All existing decoder state is discarded; decoding restarts
+    /// from the closest I-frame at or before the target picture, then proceeds
+    /// forward through every non-disposable picture until the target picture
+    /// has been decoded. Afterwards, the `reader` points to the picture after
+    /// the target.
+    ///
+    /// The target picture is also left as this decoder's last picture, so
+    /// that decoding can continue from it.
+    ///
+    /// If the same temporal reference occurs more than once in the index, the
+    /// first picture with it is decoded.
+    pub fn decode_picture_at_tr<R>(
+        &mut self,
+        reader: &mut H263Reader<R>,
+        target_tr: u16,
+        index: &BitstreamIndex,
+    ) -> Result<DecodedPicture>
+    where
+        R: Read + Seek,
+    {
+        let target = index
+            .position_of(target_tr)
+            .ok_or(Error::PictureNotIndexed)?;
+        let keyframe = index
+            .keyframe_before(target)
+            .ok_or(Error::PictureNotIndexed)?;
+
+        self.reset();
+
+        for (position, entry) in index.entries()[keyframe..=target].iter().enumerate() {
+            //Nothing references disposable pictures, so only the target needs
+            //to be decoded if it is one.
+            if entry.picture_type.is_disposable() && keyframe + position != target {
+                continue;
+            }
+
+            //Each picture gets a reader of it's own, so that the end of the
+            //picture's data ends the picture.
+            reader.seek_to_byte(entry.byte_offset)?;
+            let mut picture_reader =
+                H263Reader::from_source(reader.source_mut().take(entry.byte_length));
+            if self.decode_next_picture(&mut picture_reader)? == DecodeStatus::EndOfStream {
+                return Err(Error::PictureNotIndexed);
+            }
+        }
+
+        let target = &index.entries()[target];
+        reader.seek_to_byte(target.byte_offset + target.byte_length)?;
+
+        self.get_last_picture()
+            .cloned()
+            .ok_or(Error::InternalDecoderError)
+    }
+}
+
+/// The temporal position and quantizer of the B picture of a PB frame.
+#[derive(Clone, Debug)]
+struct PbFrameTiming {
+    /// The temporal distance from the reference picture to the B picture.
+    trb: i16,
+
+    /// The temporal distance from the reference picture to the P picture.
+    trd: i16,
+
+    /// The temporal reference of the B picture.
+    temporal_reference: u16,
+
+    /// The quantizer of the B picture, relative to that of the P picture.
+    quantizer: BPictureQuantizer,
+}
+
+/// What parsing a single macroblock did to the picture being decoded.
+enum MacroblockStep {
+    /// A macroblock was stored, and can be reconstructed.
+    Stored,
+
+    /// Something other than a macroblock was read, such as stuffing or a GOB
+    /// header.
+    Skipped,
+
+    /// The picture has ended.
+    EndOfPicture,
+}
+
+/// The motion vectors and prediction modes of a single macroblock.
+struct MacroblockPrediction {
+    mb_type: MacroblockType,
+
+    /// The motion vectors of each luma block, or the forward vectors of a B
+    /// picture macroblock.
+    motion_vectors: [MotionVector; 4],
+
+    /// The backward vectors of a B picture macroblock.
+    backward_motion_vectors: [MotionVector; 4],
+
+    /// The forward and backward vectors actually used to predict the B
+    /// picture or B block, after any direct prediction.
+    b_vectors: ([MotionVector; 4], [MotionVector; 4]),
+    b_mode: BPredictionMode,
+    intra_mode: Option<IntraPredictionMode>,
+}
+
+impl MacroblockPrediction {
+    /// A macroblock with no motion.
+    fn new(mb_type: MacroblockType) -> Self {
+        Self {
+            mb_type,
+            motion_vectors: [MotionVector::ZERO; 4],
+            backward_motion_vectors: [MotionVector::ZERO; 4],
+            b_vectors: ([MotionVector::ZERO; 4], [MotionVector::ZERO; 4]),
+            b_mode: BPredictionMode::Bidirectional,
+            intra_mode: None,
+        }
+    }
+}
+
+/// Everything needed to decode the macroblocks of a single picture.
+///
+/// Decoding a picture alternates between parsing one macroblock at a time
+/// into the buffers held here, and reconstructing whatever pixels those
+/// macroblocks completed.
+struct PictureContext<'a> {
+    /// The picture being decoded.
+    picture: DecodedPicture,
+
+    /// All in-force picture options of the picture being decoded.
+    running_options: PictureOption,
+
+    decoder_options: DecoderOption,
+
+    /// The forward and backward reference pictures.
+    ///
+    /// Only B pictures are predicted from the forward reference; all other
+    /// pictures are predicted from the backward one.
+    references: [Option<&'a DecodedPicture>; 2],
+
+    /// The motion vectors of each macroblock of the backward reference.
+    reference_motion_vectors: &'a [[MotionVector; 4]],
+
+    /// The B picture of a PB frame, if this is one.
+    pb_frame: Option<PbFrameTiming>,
+
+    /// The TRB and TRD of a B picture, if this is one.
+    b_frame: Option<(i16, i16)>,
+
+    mb_per_line: usize,
+    mb_height: usize,
+
+    /// The height of the picture, in luma rows.
+    height: usize,
+
+    /// How many rows of macroblocks are in each GOB.
+    mb_rows_per_gob: usize,
+
+    /// Whether each macroblock is reconstructed as soon as it is parsed, in
+    /// which case only one macroblock of levels is kept.
+    streaming_idct: bool,
+    luma_blk_per_line: usize,
+    chroma_blk_per_line: usize,
+
+    in_force_quantizer: u8,
+
+    /// All previously decoded motion vectors.
+    predictor_vectors: Vec<[MotionVector; 4]>,
+
+    /// The forward vectors of each B picture or B block.
+    forward_vectors: Vec<[MotionVector; 4]>,
+
+    /// The backward vectors of each B picture or B block.
+    backward_vectors: Vec<[MotionVector; 4]>,
+
+    /// All previously decoded B picture MVDBWs.
+    backward_predictor_vectors: Vec<[MotionVector; 4]>,
+
+    /// The prediction of each B picture or B block.
+    b_modes: Vec<BPredictionMode>,
+    macroblock_types: Vec<MacroblockType>,
+
+    /// The number of the first macroblock after the last GOB header.
+    macroblocks_after_gob: usize,
+
+    /// How many macroblocks have been reconstructed, when streaming.
+    reconstructed_macroblocks: usize,
+
+    /// How many rows of macroblocks have been reported.
+    reconstructed_rows: usize,
+
+    /// Whether the chroma of this picture has been lost.
+    chroma_degraded: bool,
+
+    /// An error that is deferred until the current macroblock is stored.
+    pending_error: Option<Error>,
+    frame_stats: Option<FrameStats>,
+
+    /// H.263 Annex I: intra blocks are predicted from the blocks above or to
+    /// the left of them, within each plane.
+    intra_prediction: Option<[IntraPredictionContext; 3]>,
+
+    /// The IDCT levels of the picture, in luma, chroma-B, chroma-R order.
+    levels: [Vec<DecodedDctBlock>; 3],
+
+    /// The IDCT levels of the B picture of a PB frame.
+    b_levels: [Vec<DecodedDctBlock>; 3],
+}
+
+impl<'a> PictureContext<'a> {
+    /// Start decoding a picture, predicted from the given forward and
+    /// backward references.
+    fn new(
+        picture: DecodedPicture,
+        running_options: PictureOption,
+        decoder_options: DecoderOption,
+        references: [Option<&'a DecodedPicture>; 2],
+        reference_motion_vectors: &'a [[MotionVector; 4]],
+        pb_frame: Option<PbFrameTiming>,
+        b_frame: Option<(i16, i16)>,
+    ) -> Result<Self> {
+        let output_dimensions = picture
+            .format()
+            .into_width_and_height()
+            .ok_or(Error::PictureFormatInvalid)?;
+
+        let mb_per_line = (output_dimensions.0 as f64 / 16.0).ceil() as usize;
+        let mb_height = (output_dimensions.1 as f64 / 16.0).ceil() as usize;
+
+        //H.263 5.2.1: larger pictures use GOBs that span multiple rows of
+        //macroblocks.
+        let mb_rows_per_gob = match output_dimensions.1 {
+            0..=400 => 1,
+            401..=800 => 2,
+            _ => 4,
+        };
+
+        //Pictures predicted from one that lost it's chroma can't get it back,
+        //so they are degraded too, until the next I-frame.
+        let [forward_reference, reference_picture] = references;
+        let chroma_degraded = match picture.as_header().picture_type {
+            PictureTypeCode::IFrame => false,
+            PictureTypeCode::BFrame => [forward_reference, reference_picture]
+                .iter()
+                .flatten()
+                .any(|picture| picture.is_chroma_degraded()),
+            _ => reference_picture.is_some_and(|picture| picture.is_chroma_degraded()),
+        };
+
+        let frame_stats = if decoder_options.contains(DecoderOption::COLLECT_MACROBLOCK_STATS) {
+            Some(FrameStats::default())
+        } else {
+            None
+        };
+
+        let intra_prediction = running_options
+            .contains(PictureOption::ADVANCED_INTRA_CODING)
+            .then(|| {
+                [
+                    IntraPredictionContext::new(mb_per_line * 2, mb_height * 2),
+                    IntraPredictionContext::new(mb_per_line, mb_height),
+                    IntraPredictionContext::new(mb_per_line, mb_height),
+                ]
+            });
+
+        //When streaming the IDCT, each macroblock is reconstructed as soon as
+        //it is decoded, so we only ever need one macroblock of levels.
+        //Overlapped motion compensation needs the vectors of the next
+        //macroblock, and B pictures need both of their predictions before any
+        //macroblock can be reconstructed, so neither can be streamed.
+        let streaming_idct = decoder_options.contains(DecoderOption::STREAMING_IDCT)
+            && !running_options.contains(PictureOption::ADVANCED_PREDICTION)
+            && b_frame.is_none();
+        let luma_blocks = mb_per_line * mb_height * 4;
+        let chroma_blocks = mb_per_line * mb_height;
+        let (luma_blk_per_line, chroma_blk_per_line) = if streaming_idct {
+            (2, 1)
+        } else {
+            (mb_per_line * 2, mb_per_line)
+        };
+
+        let chroma_levels =
+            vec![DecodedDctBlock::Zero; if streaming_idct { 1 } else { chroma_blocks }];
+        let levels = [
+            vec![DecodedDctBlock::Zero; if streaming_idct { 4 } else { luma_blocks }],
+            chroma_levels.clone(),
+            chroma_levels,
+        ];
+
+        let b_levels = if pb_frame.is_some() {
+            [
+                vec![DecodedDctBlock::Zero; luma_blocks],
+                vec![DecodedDctBlock::Zero; chroma_blocks],
+                vec![DecodedDctBlock::Zero; chroma_blocks],
+            ]
+        } else {
+            [Vec::new(), Vec::new(), Vec::new()]
+        };
+
+        let mb_count = mb_per_line * mb_height;
+
+        Ok(Self {
+            in_force_quantizer: picture.as_header().quantizer,
+            picture,
+            running_options,
+            decoder_options,
+            references,
+            reference_motion_vectors,
+            pb_frame,
+            b_frame,
+            mb_per_line,
+            mb_height,
+            height: output_dimensions.1 as usize,
+            mb_rows_per_gob,
+            streaming_idct,
+            luma_blk_per_line,
+            chroma_blk_per_line,
+            predictor_vectors: Vec::with_capacity(mb_count),
+            forward_vectors: Vec::with_capacity(mb_count),
+            backward_vectors: Vec::with_capacity(mb_count),
+            backward_predictor_vectors: Vec::with_capacity(mb_count),
+            b_modes: Vec::with_capacity(mb_count),
+            macroblock_types: Vec::with_capacity(mb_count),
+            macroblocks_after_gob: 0,
+            reconstructed_macroblocks: 0,
+            reconstructed_rows: 0,
+            chroma_degraded,
+            pending_error: None,
+            frame_stats,
+            intra_prediction,
+            levels,
+            b_levels,
+        })
+    }
+
+    fn is_sorenson(&self) -> bool {
+        self.decoder_options
+            .contains(DecoderOption::SORENSON_SPARK_BITSTREAM)
+    }
+
+    fn is_lenient(&self) -> bool {
+        self.decoder_options.contains(DecoderOption::LENIENT)
+    }
+
+    /// The reference picture that P pictures, and the backward prediction of
+    /// B pictures, are predicted from.
+    fn reference_picture(&self) -> Option<&'a DecodedPicture> {
+        self.references[1]
+    }
+
+    /// The range of luma rows covered by a row of macroblocks.
+    fn luma_rows(&self, mb_row: usize) -> Range<usize> {
+        mb_row * 16..((mb_row + 1) * 16).min(self.height)
+    }
+
+    /// Parse the next macroblock of the picture, and store it's motion
+    /// vectors and levels for reconstruction.
+    fn parse_macroblock<R>(&mut self, reader: &mut H263Reader<R>) -> Result<MacroblockStep>
+    where
+        R: Read,
+    {
+        let mb_start = reader.bit_position();
+        let mb = match self.pending_error.take() {
+            Some(e) => Err(e),
+            None => decode_macroblock(reader, self.picture.as_header(), self.running_options),
+        };
+        let current_mb = self.macroblock_types.len();
+        let pos = (
+            (current_mb % self.mb_per_line) * 16,
+            (current_mb / self.mb_per_line) * 16,
+        );
+        let level_pos = if self.streaming_idct { (0, 0) } else { pos };
+
+        let prediction = match mb {
+            Ok(Macroblock::Stuffing) => {
+                trace!("Macroblock stuffing");
+
+                if let Some(frame_stats) = self.frame_stats.as_mut() {
+                    frame_stats.stuffing_macroblocks += 1;
+                }
+
+                return Ok(MacroblockStep::Skipped);
+            }
+            Ok(Macroblock::Uncoded) => {
+                if matches!(
+                    self.picture.as_header().picture_type,
+                    PictureTypeCode::IFrame
+                ) {
+                    return Err(Error::UncodedIFrameBlocks);
+                }
+
+                if let Some(frame_stats) = self.frame_stats.as_mut() {
+                    frame_stats.uncoded_macroblocks += 1;
+                }
+
+                MacroblockPrediction::new(MacroblockType::Inter)
+            }
+            Ok(Macroblock::Coded {
+                mb_type,
+                coded_block_pattern,
+                coded_block_pattern_b,
+                d_quantizer,
+                motion_vector,
+                addl_motion_vectors,
+                motion_vector_b,
+                b_prediction_mode,
+                intra_prediction_mode,
+            }) => {
+                if let Some(d_quantizer) = d_quantizer {
+                    self.in_force_quantizer = d_quantizer.apply(self.in_force_quantizer);
+                }
+
+                let mut prediction = self.decode_motion_vectors(
+                    mb_type,
+                    motion_vector,
+                    addl_motion_vectors,
+                    motion_vector_b,
+                    b_prediction_mode,
+                );
+                prediction.intra_mode = intra_prediction_mode;
+
+                self.parse_blocks(reader, &prediction, &coded_block_pattern, level_pos)?;
+
+                //Any B blocks come after the chroma blocks, so they are lost
+                //along with them.
+                if self.pb_frame.is_some() && self.pending_error.is_none() {
+                    self.parse_pb_b_blocks(
+                        reader,
+                        &mut prediction,
+                        motion_vector,
+                        motion_vector_b,
+                        b_prediction_mode,
+                        coded_block_pattern_b.unwrap_or_default(),
+                        pos,
+                    )?;
+                }
+
+                if let Some(frame_stats) = self.frame_stats.as_mut() {
+                    *frame_stats.macroblock_types.entry(mb_type).or_insert(0) += 1;
+                }
+
+                prediction
+            }
+            Err(e) => return self.recover_from_error(reader, e),
+        };
+
+        self.predict_intra(pos, level_pos, prediction.intra_mode);
+
+        trace!(
+            "Macroblock {}: {:?}, {} bits",
+            current_mb,
+            prediction.mb_type,
+            reader.bit_position() - mb_start
+        );
+
+        if let Some(frame_stats) = self.frame_stats.as_mut() {
+            frame_stats
+                .macroblock_bits
+                .push(reader.bit_position() - mb_start);
+        }
+
+        self.predictor_vectors.push(prediction.motion_vectors);
+        self.backward_predictor_vectors
+            .push(prediction.backward_motion_vectors);
+        self.forward_vectors.push(prediction.b_vectors.0);
+        self.backward_vectors.push(prediction.b_vectors.1);
+        self.b_modes.push(prediction.b_mode);
+        self.macroblock_types.push(prediction.mb_type);
+
+        Ok(MacroblockStep::Stored)
+    }
+
+    /// Decode the motion vectors of a coded macroblock from their
+    /// differences, and those of it's B prediction if this is a B picture.
+    fn decode_motion_vectors(
+        &self,
+        mb_type: MacroblockType,
+        motion_vector: Option<MotionVector>,
+        addl_motion_vectors: Option<[MotionVector; 3]>,
+        motion_vector_b: Option<MotionVector>,
+        b_prediction_mode: Option<BPredictionMode>,
+    ) -> MacroblockPrediction {
+        let mut prediction = MacroblockPrediction::new(mb_type);
+        let predictor_vectors = &self.predictor_vectors[self.macroblocks_after_gob..];
+
+        if let Some((trb, trd)) = self.b_frame.filter(|_| mb_type.is_inter()) {
+            //Forward and backward vectors are each predicted from the vectors
+            //of neighbouring macroblocks in the same direction, which are
+            //zero for those not predicted in that direction.
+            prediction.b_mode = b_prediction_mode.unwrap_or(BPredictionMode::Direct);
+
+            if let Some(mvdfw) = motion_vector {
+                let mpred = predict_candidate(
+                    predictor_vectors,
+                    &prediction.motion_vectors,
+                    self.mb_per_line,
+                    0,
+                );
+
+                prediction.motion_vectors =
+                    [mv_decode(&self.picture, self.running_options, mpred, mvdfw); 4];
+                prediction.b_vectors.0 = prediction.motion_vectors;
+            }
+
+            if let Some(mvdbw) = motion_vector_b {
+                let mpred = predict_candidate(
+                    &self.backward_predictor_vectors[self.macroblocks_after_gob..],
+                    &prediction.backward_motion_vectors,
+                    self.mb_per_line,
+                    0,
+                );
+
+                prediction.backward_motion_vectors =
+                    [mv_decode(&self.picture, self.running_options, mpred, mvdbw); 4];
+                prediction.b_vectors.1 = prediction.backward_motion_vectors;
+            }
+
+            if prediction.b_mode == BPredictionMode::Direct {
+                let colocated = self
+                    .reference_motion_vectors
+                    .get(self.macroblock_types.len())
+                    .copied()
+                    .unwrap_or([MotionVector::ZERO; 4]);
+
+                for (i, colocated_vector) in colocated.iter().enumerate() {
+                    let (forward, backward) = pb_vectors(*colocated_vector, None, trb, trd);
+                    prediction.b_vectors.0[i] = forward;
+                    prediction.b_vectors.1[i] = backward;
+                }
+            }
+        } else if mb_type.is_inter() {
+            let motion_vectors = &mut prediction.motion_vectors;
+            let mv1 = motion_vector.unwrap_or(MotionVector::ZERO);
+            let mpred1 = predict_candidate(predictor_vectors, motion_vectors, self.mb_per_line, 0);
+
+            motion_vectors[0] = mv_decode(&self.picture, self.running_options, mpred1, mv1);
+
+            if let Some([mv2, mv3, mv4]) = addl_motion_vectors {
+                let mpred2 =
+                    predict_candidate(predictor_vectors, motion_vectors, self.mb_per_line, 1);
+                motion_vectors[1] = mv_decode(&self.picture, self.running_options, mpred2, mv2);
+
+                let mpred3 =
+                    predict_candidate(predictor_vectors, motion_vectors, self.mb_per_line, 2);
+                motion_vectors[2] = mv_decode(&self.picture, self.running_options, mpred3, mv3);
+
+                let mpred4 =
+                    predict_candidate(predictor_vectors, motion_vectors, self.mb_per_line, 3);
+                motion_vectors[3] = mv_decode(&self.picture, self.running_options, mpred4, mv4);
+            } else {
+                motion_vectors[1] = motion_vectors[0];
+                motion_vectors[2] = motion_vectors[0];
+                motion_vectors[3] = motion_vectors[0];
+            };
+        };
+
+        prediction
+    }
+
+    /// Parse the luma and chroma blocks of a coded macroblock into the IDCT
+    /// levels of the picture.
+    ///
+    /// In lenient mode, a corrupt chroma block degrades the chroma of the
+    /// whole picture instead, and the error is deferred until the macroblock
+    /// has been stored.
+    fn parse_blocks<R>(
+        &mut self,
+        reader: &mut H263Reader<R>,
+        prediction: &MacroblockPrediction,
+        coded_block_pattern: &CodedBlockPattern,
+        level_pos: (usize, usize),
+    ) -> Result<()>
+    where
+        R: Read,
+    {
+        let mb_type = prediction.mb_type;
+        let intra_mode = prediction.intra_mode;
+
+        for (i, codes_luma) in coded_block_pattern.codes_luma.iter().enumerate() {
+            let luma = decode_block(
+                reader,
+                self.decoder_options,
+                self.picture.as_header(),
+                self.running_options,
+                mb_type,
+                *codes_luma,
+            )?;
+            inverse_rle(
+                &luma,
+                &mut self.levels[0],
+                (level_pos.0 + (i % 2) * 8, level_pos.1 + (i / 2) * 8),
+                self.luma_blk_per_line,
+                self.in_force_quantizer,
+                intra_mode,
+            );
+        }
+
+        //H.263 Annex T: chroma uses a finer quantizer.
+        let chroma_quant = chroma_quantizer(
+            self.in_force_quantizer,
+            self.running_options
+                .contains(PictureOption::MODIFIED_QUANTIZATION),
+        );
+
+        let chroma = decode_block(
+            reader,
+            self.decoder_options,
+            self.picture.as_header(),
+            self.running_options,
+            mb_type,
+            coded_block_pattern.codes_chroma_b,
+        )
+        .and_then(|chroma_b| {
+            let chroma_r = decode_block(
+                reader,
+                self.decoder_options,
+                self.picture.as_header(),
+                self.running_options,
+                mb_type,
+                coded_block_pattern.codes_chroma_r,
+            )?;
+
+            Ok((chroma_b, chroma_r))
+        });
+
+        match chroma {
+            Ok((chroma_b, chroma_r)) => {
+                for (chroma, levels) in [(chroma_b, 1), (chroma_r, 2)] {
+                    inverse_rle(
+                        &chroma,
+                        &mut self.levels[levels],
+                        (level_pos.0 / 2, level_pos.1 / 2),
+                        self.chroma_blk_per_line,
+                        chroma_quant,
+                        intra_mode,
+                    );
+                }
+            }
+
+            //Keep the luma we already have, and resynchronize once this
+            //macroblock has been stored.
+            Err(e) if self.is_lenient() && e.is_block_error() => {
+                warn!(
+                    "Dropping chroma of macroblock {} after error: {}",
+                    self.macroblock_types.len(),
+                    e
+                );
+                self.chroma_degraded = true;
+                self.pending_error = Some(e);
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(())
+    }
+
+    /// Parse the B blocks of a PB frame macroblock, and decode the vectors
+    /// they are predicted with.
+    #[allow(clippy::too_many_arguments)]
+    fn parse_pb_b_blocks<R>(
+        &mut self,
+        reader: &mut H263Reader<R>,
+        prediction: &mut MacroblockPrediction,
+        motion_vector: Option<MotionVector>,
+        motion_vector_b: Option<MotionVector>,
+        b_prediction_mode: Option<BPredictionMode>,
+        coded_block_pattern_b: CodedBlockPattern,
+        pos: (usize, usize),
+    ) -> Result<()>
+    where
+        R: Read,
+    {
+        let PbFrameTiming {
+            trb,
+            trd,
+            quantizer,
+            ..
+        } = match &self.pb_frame {
+            Some(pb_frame) => pb_frame.clone(),
+            None => return Ok(()),
+        };
+
+        //The B block of a PB frame macroblock is predicted from the P block's
+        //motion vectors. Intra P blocks still send a motion vector for this
+        //purpose, but it does not take part in predicting any other P block's
+        //vectors.
+        let p_vectors = if prediction.mb_type.is_intra() {
+            let mpred = predict_candidate(
+                &self.predictor_vectors[self.macroblocks_after_gob..],
+                &prediction.motion_vectors,
+                self.mb_per_line,
+                0,
+            );
+
+            [mv_decode(
+                &self.picture,
+                self.running_options,
+                mpred,
+                motion_vector.unwrap_or(MotionVector::ZERO),
+            ); 4]
+        } else {
+            prediction.motion_vectors
+        };
+
+        prediction.b_mode = b_prediction_mode.unwrap_or(BPredictionMode::Bidirectional);
+
+        match prediction.b_mode {
+            BPredictionMode::Bidirectional | BPredictionMode::Direct => {
+                for (i, p_vector) in p_vectors.iter().enumerate() {
+                    let (forward, backward) = pb_vectors(*p_vector, motion_vector_b, trb, trd);
+                    prediction.b_vectors.0[i] = forward;
+                    prediction.b_vectors.1[i] = backward;
+                }
+            }
+
+            //H.263 Annex M: forward-only B blocks carry their own vector,
+            //predicted from the macroblock to the left if it was also forward
+            //predicted.
+            BPredictionMode::Forward => {
+                let current_mb = self.macroblock_types.len();
+                let mpred = if current_mb > self.macroblocks_after_gob
+                    && !current_mb.is_multiple_of(self.mb_per_line)
+                    && self.b_modes[current_mb - 1] == BPredictionMode::Forward
+                {
+                    self.forward_vectors[current_mb - 1][0]
+                } else {
+                    MotionVector::ZERO
+                };
+
+                prediction.b_vectors.0 = [mv_decode(
+                    &self.picture,
+                    self.running_options,
+                    mpred,
+                    motion_vector_b.unwrap_or(MotionVector::ZERO),
+                ); 4];
+            }
+
+            //Backward-only B blocks are predicted from the co-located P
+            //macroblock, with no vector at all.
+            BPredictionMode::Backward => {}
+        }
+
+        let b_quantizer = quantizer.b_quantizer(self.in_force_quantizer);
+        let b_chroma_quant = chroma_quantizer(
+            b_quantizer,
+            self.running_options
+                .contains(PictureOption::MODIFIED_QUANTIZATION),
+        );
+
+        for (i, codes_luma) in coded_block_pattern_b.codes_luma.iter().enumerate() {
+            let luma = decode_block(
+                reader,
+                self.decoder_options,
+                self.picture.as_header(),
+                self.running_options,
+                MacroblockType::Inter,
+                *codes_luma,
+            )?;
+            inverse_rle(
+                &luma,
+                &mut self.b_levels[0],
+                (pos.0 + (i % 2) * 8, pos.1 + (i / 2) * 8),
+                self.mb_per_line * 2,
+                b_quantizer,
+                None,
+            );
+        }
+
+        for (codes_chroma, levels) in [
+            (coded_block_pattern_b.codes_chroma_b, 1),
+            (coded_block_pattern_b.codes_chroma_r, 2),
+        ] {
+            let chroma = decode_block(
+                reader,
+                self.decoder_options,
+                self.picture.as_header(),
+                self.running_options,
+                MacroblockType::Inter,
+                codes_chroma,
+            )?;
+            inverse_rle(
+                &chroma,
+                &mut self.b_levels[levels],
+                (pos.0 / 2, pos.1 / 2),
+                self.mb_per_line,
+                b_chroma_quant,
+                None,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Apply Advanced Intra Coding (Annex I) prediction to the levels of the
+    /// macroblock that was just parsed.
+    fn predict_intra(
+        &mut self,
+        pos: (usize, usize),
+        level_pos: (usize, usize),
+        intra_mode: Option<IntraPredictionMode>,
+    ) {
+        let [luma_context, chroma_b_context, chroma_r_context] =
+            match self.intra_prediction.as_mut() {
+                Some(contexts) => contexts,
+                None => return,
+            };
+        let [luma_levels, chroma_b_levels, chroma_r_levels] = &mut self.levels;
+
+        for i in 0..4 {
+            let pos = (pos.0 / 8 + i % 2, pos.1 / 8 + i / 2);
+            let level = &mut luma_levels
+                [level_pos.0 / 8 + i % 2 + (level_pos.1 / 8 + i / 2) * self.luma_blk_per_line];
+
+            match intra_mode {
+                Some(mode) => luma_context.predict_block(pos, mode, level),
+                None => luma_context.skip_block(pos),
+            }
+        }
+
+        for (context, levels) in [
+            (chroma_b_context, chroma_b_levels),
+            (chroma_r_context, chroma_r_levels),
+        ] {
+            let pos = (pos.0 / 16, pos.1 / 16);
+            let level = &mut levels[level_pos.0 / 16 + level_pos.1 / 16 * self.chroma_blk_per_line];
+
+            match intra_mode {
+                Some(mode) => context.predict_block(pos, mode, level),
+                None => context.skip_block(pos),
+            }
+        }
+    }
+
+    /// Recover from an error while parsing a macroblock, by resynchronizing
+    /// to the next GOB or picture, or treating it as the end of the picture.
+    fn recover_from_error<R>(
+        &mut self,
+        reader: &mut H263Reader<R>,
+        e: Error,
+    ) -> Result<MacroblockStep>
+    where
+        R: Read,
+    {
+        let lenient = self.is_lenient();
+
+        if self.is_sorenson() {
+            //Sorenson pictures are a single group of blocks, so any start
+            //code we run into belongs to the next picture.
+            if matches!(e, Error::InvalidMacroblockHeader) && is_at_start_code(reader)? {
+                return Ok(MacroblockStep::EndOfPicture);
+            }
+
+            //Sorenson pictures have no GOBs to resynchronize to, so the rest
+            //of the picture is lost, but the next one can still be found. A
+            //long run of zeroes usually means we are lost in corrupt data just
+            //before it.
+            if lenient && e.is_macroblock_error() {
+                if matches!(e, Error::InvalidMacroblockHeader) && is_long_zero_run(reader)? {
+                    warn!(
+                        "Skipping zero run to next start code at macroblock {}",
+                        self.macroblock_types.len()
+                    );
+                } else {
+                    warn!(
+                        "Skipping to next start code after {} at macroblock {}",
+                        e,
+                        self.macroblock_types.len()
+                    );
+                }
+
+                //The next picture has to be parsed from it's start code.
+                if let Some(skipped_bits) =
+                    reader.with_lookahead(|reader| reader.find_next_start_code())?
+                {
+                    reader.skip_bits(skipped_bits)?;
+                }
+
+                return Ok(MacroblockStep::EndOfPicture);
+            }
+        } else if e.is_macroblock_error() || (lenient && e.is_block_error()) {
+            //Attempt to recover from macroblock errors if possible
+            if lenient && matches!(e, Error::InvalidMacroblockHeader) && is_long_zero_run(reader)? {
+                warn!(
+                    "Skipping zero run to next start code at macroblock {}",
+                    self.macroblock_types.len()
+                );
+
+                //The GOB header has to be parsed from it's start code.
+                if let Some(skipped_bits) =
+                    reader.with_lookahead(|reader| reader.find_next_start_code())?
+                {
+                    reader.skip_bits(skipped_bits)?;
+                }
+            }
+
+            return match decode_gob(reader, self.decoder_options, self.picture.as_header()) {
+                //Resynchronized to end of picture.
+                Ok(None) => Ok(MacroblockStep::EndOfPicture),
+
+                //Resynchronized to end of GOB.
+                Ok(Some(GroupOfBlocks {
+                    group_number,
+                    multiplex_bitstream: _multiplex_bitstream,
+                    frame_id: _frame_id,
+                    quantizer,
+                })) => {
+                    self.start_gob(group_number, quantizer, &e);
+                    Ok(MacroblockStep::Skipped)
+                }
+
+                // Treat EOF/GOB errors as end of picture
+                Err(ref e) if e.is_eof_error() || e.is_gob_error() => {
+                    Ok(MacroblockStep::EndOfPicture)
+                }
+                Err(e) => Err(e),
+            };
+        }
+
+        //Treat EOF errors as end of picture, as well as block errors we can't
+        //resynchronize from in lenient mode
+        if e.is_eof_error() || (lenient && e.is_block_error()) {
+            Ok(MacroblockStep::EndOfPicture)
+        } else {
+            Err(e)
+        }
+    }
+
+    /// Continue decoding from the start of a GOB that was resynchronized to
+    /// after an error.
+    fn start_gob(&mut self, group_number: u8, quantizer: u8, e: &Error) {
+        //Any macroblocks skipped over by the GOB are treated the same as a
+        //picture that ended early.
+        let gob_start = (group_number as usize * self.mb_rows_per_gob * self.mb_per_line)
+            .min(self.mb_per_line * self.mb_height);
+        if self.macroblock_types.len() < gob_start {
+            warn!(
+                "Resynchronized to GOB {} after {}, skipping {} macroblocks",
+                group_number,
+                e,
+                gob_start - self.macroblock_types.len()
+            );
+            self.predictor_vectors
+                .resize(gob_start, [MotionVector::ZERO; 4]);
+            self.forward_vectors
+                .resize(gob_start, [MotionVector::ZERO; 4]);
+            self.backward_vectors
+                .resize(gob_start, [MotionVector::ZERO; 4]);
+            self.backward_predictor_vectors
+                .resize(gob_start, [MotionVector::ZERO; 4]);
+            self.b_modes
+                .resize(gob_start, BPredictionMode::Bidirectional);
+            self.macroblock_types
+                .resize(gob_start, MacroblockType::Inter);
+        } else {
+            trace!("GOB {}", group_number);
+        }
+
+        self.in_force_quantizer = quantizer;
+        self.macroblocks_after_gob = self.macroblock_types.len();
+        for context in self.intra_prediction.iter_mut().flatten() {
+            context.start_segment();
+        }
+    }
+
+    /// Reconstruct whatever the macroblock that was just stored completed.
+    ///
+    /// When streaming the IDCT, that is the macroblock itself. Any rows of
+    /// macroblocks that are now complete are then reported to `on_row`.
+    fn reconstruct_stored(&mut self, on_row: &mut Option<RowCallback<'_>>) -> Result<()> {
+        if self.streaming_idct {
+            //Macroblocks skipped over by a GOB resync have no levels.
+            let this_mb = self.macroblock_types.len() - 1;
+            gather_range(
+                &self.macroblock_types,
+                self.reference_picture(),
+                &self.predictor_vectors,
+                self.mb_per_line,
+                self.reconstructed_macroblocks..this_mb,
+                &mut self.picture,
+            )?;
+            reconstruct_macroblock(
+                this_mb,
+                self.mb_per_line,
+                &self.macroblock_types,
+                &self.predictor_vectors,
+                self.reference_picture(),
+                level_slices(&self.levels),
+                &mut self.picture,
+            )?;
+            for levels in self.levels.iter_mut() {
+                levels.fill(DecodedDctBlock::Zero);
+            }
+            self.reconstructed_macroblocks = self.macroblock_types.len();
+        }
+
+        if let Some(on_row) = on_row.as_mut().filter(|_| self.b_frame.is_none()) {
+            while self.reconstructed_rows < self.mb_height
+                && (self.reconstructed_rows + 1) * self.mb_per_line <= self.macroblock_types.len()
+            {
+                let row = self.reconstructed_rows;
+                if !self.streaming_idct {
+                    self.reconstruct_row_range(row..row + 1)?;
+                }
+                on_row(&self.picture, self.luma_rows(row));
+                self.reconstructed_rows += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reconstruct the rest of the picture once it has been parsed.
+    ///
+    /// If the picture ended early, all the remaining blocks are assumed to be
+    /// empty INTER blocks with motion vector (0,0). Any rows of macroblocks
+    /// not yet reported to `on_row` are reported, and the picture is then
+    /// postprocessed.
+    fn reconstruct_remaining(&mut self, on_row: &mut Option<RowCallback<'_>>) -> Result<()> {
+        let mb_count = (self.mb_per_line * self.mb_height).max(self.macroblock_types.len());
+        self.predictor_vectors
+            .resize(mb_count, [MotionVector::ZERO; 4]);
+        self.forward_vectors
+            .resize(mb_count, [MotionVector::ZERO; 4]);
+        self.backward_vectors
+            .resize(mb_count, [MotionVector::ZERO; 4]);
+        self.b_modes
+            .resize(mb_count, BPredictionMode::Bidirectional);
+        self.macroblock_types
+            .resize(mb_count, MacroblockType::Inter);
+
+        if self.frame_stats.is_some() {
+            self.picture
+                .set_motion_vectors(self.predictor_vectors.clone());
+        }
+
+        //We have now read out all of the macroblock and block data and
+        //queued it up into the various internal buffers we allocated for
+        //this purpose. Time to decode (the rest of) it all in one go.
+        let first_row = if self.b_frame.is_some() {
+            reconstruct_b_picture(
+                self.mb_per_line,
+                &self.macroblock_types,
+                &self.b_modes,
+                [&self.forward_vectors[..], &self.backward_vectors[..]],
+                self.references,
+                level_slices(&self.levels),
+                &mut self.picture,
+            )?;
+
+            0
+        } else if self.streaming_idct {
+            //Only the macroblocks missing from the end of the picture are
+            //left, and they have no levels.
+            gather_range(
+                &self.macroblock_types,
+                self.reference_picture(),
+                &self.predictor_vectors,
+                self.mb_per_line,
+                self.reconstructed_macroblocks..self.macroblock_types.len(),
+                &mut self.picture,
+            )?;
+
+            self.reconstructed_rows
+        } else if on_row.is_none() {
+            self.reconstruct_row_range(0..self.mb_height)?;
+
+            self.mb_height
+        } else {
+            self.reconstructed_rows
+        };
+
+        if let Some(on_row) = on_row.as_mut() {
+            for row in first_row..self.mb_height {
+                if self.b_frame.is_none() && !self.streaming_idct {
+                    self.reconstruct_row_range(row..row + 1)?;
+                }
+                on_row(&self.picture, self.luma_rows(row));
+            }
+        }
+
+        if self.chroma_degraded {
+            self.picture.degrade_chroma();
+        }
+
+        #[cfg(feature = "pipeline")]
+        if self
+            .decoder_options
+            .contains(DecoderOption::IN_LOOP_DEBLOCK)
+        {
+            deblock_picture(&mut self.picture);
+        }
+
+        Ok(())
+    }
+
+    /// Reconstruct a range of rows of macroblocks of a picture whose levels
+    /// are all kept.
+    fn reconstruct_row_range(&mut self, mb_rows: Range<usize>) -> Result<()> {
+        reconstruct_rows(
+            mb_rows,
+            self.mb_per_line,
+            &self.macroblock_types,
+            &self.predictor_vectors,
+            self.reference_picture(),
+            self.running_options,
+            level_slices(&self.levels),
+            &mut self.picture,
+        )
+    }
+
+    /// Reconstruct the B picture of a PB frame, if this is one.
+    ///
+    /// The B picture needs the finished P picture, so it can only be
+    /// reconstructed last.
+    fn reconstruct_pb_b_picture(
+        &self,
+        reference_tr: Option<u16>,
+    ) -> Result<Option<DecodedPicture>> {
+        let b_tr = match &self.pb_frame {
+            Some(pb_frame) => pb_frame.temporal_reference,
+            None => return Ok(None),
+        };
+
+        let format = self.picture.format();
+        let mut b_header = self.picture.as_header().clone();
+        b_header.temporal_reference = b_tr;
+
+        let mut b_picture =
+            DecodedPicture::new(b_header.clone(), format).ok_or(Error::PictureFormatInvalid)?;
+        b_picture.set_reference_temporal_reference(reference_tr);
+        let mut backward_prediction =
+            DecodedPicture::new(b_header, format).ok_or(Error::PictureFormatInvalid)?;
+        let b_macroblock_types = vec![MacroblockType::Inter; self.macroblock_types.len()];
+
+        gather(
+            &b_macroblock_types,
+            self.reference_picture(),
+            &self.forward_vectors,
+            self.mb_per_line,
+            &mut b_picture,
+        )?;
+        gather(
+            &b_macroblock_types,
+            Some(&self.picture),
+            &self.backward_vectors,
+            self.mb_per_line,
+            &mut backward_prediction,
+        )?;
+        average_bidirectional(
+            &mut b_picture,
+            &backward_prediction,
+            &self.backward_vectors,
+            &self.b_modes,
+            self.mb_per_line,
+        )?;
+
+        let [b_luma_levels, b_chroma_b_levels, b_chroma_r_levels] = &self.b_levels;
+        let luma_samples_per_row = b_picture.luma_samples_per_row();
+        let chroma_samples_per_row = b_picture.chroma_samples_per_row();
+        idct_channel(
+            b_luma_levels,
+            b_picture.as_luma_mut(),
+            self.mb_per_line * 2,
+            luma_samples_per_row,
+        );
+        idct_channel(
+            b_chroma_b_levels,
+            b_picture.as_chroma_b_mut(),
+            self.mb_per_line,
+            chroma_samples_per_row,
+        );
+        idct_channel(
+            b_chroma_r_levels,
+            b_picture.as_chroma_r_mut(),
+            self.mb_per_line,
+            chroma_samples_per_row,
+        );
+
+        if self.chroma_degraded {
+            b_picture.degrade_chroma();
+        }
+
+        #[cfg(feature = "pipeline")]
+        if self
+            .decoder_options
+            .contains(DecoderOption::IN_LOOP_DEBLOCK)
+        {
+            deblock_picture(&mut b_picture);
+        }
+
+        Ok(Some(b_picture))
+    }
+}
+
+/// Borrow IDCT level buffers in luma, chroma-B, chroma-R order as slices.
+fn level_slices(levels: &[Vec<DecodedDctBlock>; 3]) -> [&[DecodedDctBlock]; 3] {
+    let [luma_levels, chroma_b_levels, chroma_r_levels] = levels;
+
+    [luma_levels, chroma_b_levels, chroma_r_levels]
+}
+
+/// Determine if there are no start codes left in the bitstream.
+fn is_end_of_stream<R>(reader: &mut H263Reader<R>) -> Result<bool>
+where
+    R: Read,
+{
+    match reader.recognize_start_code(true) {
+        Ok(_) => Ok(false),
+        Err(e) if e.is_eof_error() => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+/// Determine if the bitstream continues with a start code, allowing for any
+/// stuffing bits needed to byte-align it.
+fn is_at_start_code<R>(reader: &mut H263Reader<R>) -> Result<bool>
+where
+    R: Read,
+{
+    match reader.recognize_start_code(false) {
+        Ok(skipped_bits) => Ok(skipped_bits.is_some()),
+        Err(e) if e.is_eof_error() => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Determine if the bitstream continues with at least 16 zero bits, which
+/// can only legally happen within a start code.
+///
+/// Running out of data is not considered a zero run.
+fn is_long_zero_run<R>(reader: &mut H263Reader<R>) -> Result<bool>
+where
+    R: Read,
+{
+    match reader.peek_bits::<u16>(16) {
+        Ok(bits) => Ok(bits == 0),
+        Err(e) if e.is_eof_error() => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Determine if a picture asks for the deblocking filter to be applied.
+#[cfg(feature = "pipeline")]
+fn requests_deblocking(header: &Picture) -> bool {
+    header
+        .options
+        .intersects(PictureOption::USE_DEBLOCKER | PictureOption::DEBLOCKING_FILTER)
+}
+
+/// Apply the deblocking filter to all three planes of a picture, if it asks
+/// for it, with the strength given by it's picture quantizer.
+#[cfg(feature = "pipeline")]
+fn deblock_picture(picture: &mut DecodedPicture) {
+    let header = picture.as_header();
+    if !requests_deblocking(header) {
+        return;
+    }
+
+    let strength = QUANT_TO_STRENGTH[header.quantizer.clamp(1, 31) as usize];
+    let luma_samples_per_row = picture.luma_samples_per_row();
+    let chroma_samples_per_row = picture.chroma_samples_per_row();
+
+    deblock_in_place(picture.as_luma_mut(), luma_samples_per_row, strength);
+    deblock_in_place(picture.as_chroma_b_mut(), chroma_samples_per_row, strength);
+    deblock_in_place(picture.as_chroma_r_mut(), chroma_samples_per_row, strength);
+}
+
+/// Reconstruct the pixels of a range of macroblock rows of a picture, from
+/// the motion vectors and IDCT levels decoded for them.
+///
+/// The IDCT level buffers are given in luma, chroma-B, chroma-R order, and
+/// must cover the whole picture. Motion compensation is overlapped if the
+/// `running_options` include Advanced Prediction (Annex F).
+#[allow(clippy::too_many_arguments)]
+fn reconstruct_rows(
+    mb_rows: Range<usize>,
+    mb_per_line: usize,
+    macroblock_types: &[MacroblockType],
+    motion_vectors: &[[MotionVector; 4]],
+    reference_picture: Option<&DecodedPicture>,
+    running_options: PictureOption,
+    levels: [&[DecodedDctBlock]; 3],
+    picture: &mut DecodedPicture,
+) -> Result<()> {
+    let gather = if running_options.contains(PictureOption::ADVANCED_PREDICTION) {
+        gather_range_overlapped
+    } else {
+        gather_range
+    };
+    gather(
+        macroblock_types,
+        reference_picture,
+        motion_vectors,
+        mb_per_line,
+        mb_rows.start * mb_per_line..mb_rows.end * mb_per_line,
+        picture,
+    )?;
+
+    idct_rows(mb_rows, mb_per_line, levels, picture);
+
+    Ok(())
+}
+
+/// Reconstruct a whole B picture of temporal scalability (Annex O).
+///
+/// Each inter macroblock is predicted from the forward reference picture,
+/// the backward one, or both, as given by it's entry in `b_modes`. The
+/// `motion_vectors` and `references` are both given in forward, backward
+/// order. `levels` must hold the levels of the whole picture.
+fn reconstruct_b_picture(
+    mb_per_line: usize,
+    macroblock_types: &[MacroblockType],
+    b_modes: &[BPredictionMode],
+    motion_vectors: [&[[MotionVector; 4]]; 2],
+    references: [Option<&DecodedPicture>; 2],
+    levels: [&[DecodedDctBlock]; 3],
+    picture: &mut DecodedPicture,
+) -> Result<()> {
+    let [forward_vectors, backward_vectors] = motion_vectors;
+    let [forward_reference, backward_reference] = references;
+    let mut backward_prediction =
+        DecodedPicture::new(picture.as_header().clone(), picture.format())
+            .ok_or(Error::PictureFormatInvalid)?;
+
+    gather(
+        macroblock_types,
+        forward_reference,
+        forward_vectors,
+        mb_per_line,
+        picture,
+    )?;
+    gather(
+        macroblock_types,
+        backward_reference,
+        backward_vectors,
+        mb_per_line,
+        &mut backward_prediction,
+    )?;
+    average_b_picture(
+        picture,
+        &backward_prediction,
+        macroblock_types,
+        b_modes,
+        mb_per_line,
+    );
+
+    let mb_height = levels[0].len() / (mb_per_line * 4);
+    idct_rows(0..mb_height, mb_per_line, levels, picture);
+
+    Ok(())
+}
+
+/// Add the IDCT of the levels of a range of rows of macroblocks to their
+/// prediction.
+fn idct_rows(
+    mb_rows: Range<usize>,
+    mb_per_line: usize,
+    levels: [&[DecodedDctBlock]; 3],
+    picture: &mut DecodedPicture,
+) {
+    //Each row of macroblocks is two rows of 8x8 luma blocks, and one row of
+    //each kind of chroma block.
+    let [luma_levels, chroma_b_levels, chroma_r_levels] = levels;
+    let luma_samples_per_row = picture.luma_samples_per_row();
+    let luma = picture.as_luma_mut();
+    let luma_rows = (mb_rows.start * 16 * luma_samples_per_row).min(luma.len())
+        ..(mb_rows.end * 16 * luma_samples_per_row).min(luma.len());
+    idct_channel(
+        &luma_levels[mb_rows.start * mb_per_line * 4..mb_rows.end * mb_per_line * 4],
+        &mut luma[luma_rows],
+        mb_per_line * 2,
+        luma_samples_per_row,
+    );
+
+    let chroma_samples_per_row = picture.chroma_samples_per_row();
+    let chroma_levels = mb_rows.start * mb_per_line..mb_rows.end * mb_per_line;
+    let chroma_b = picture.as_chroma_b_mut();
+    let chroma_rows = (mb_rows.start * 8 * chroma_samples_per_row).min(chroma_b.len())
+        ..(mb_rows.end * 8 * chroma_samples_per_row).min(chroma_b.len());
+    idct_channel(
+        &chroma_b_levels[chroma_levels.clone()],
+        &mut chroma_b[chroma_rows.clone()],
+        mb_per_line,
+        chroma_samples_per_row,
+    );
+    idct_channel(
+        &chroma_r_levels[chroma_levels],
+        &mut picture.as_chroma_r_mut()[chroma_rows],
+        mb_per_line,
+        chroma_samples_per_row,
+    );
+}
+
+/// Reconstruct a single macroblock of a picture.
+///
+/// This works the same as `reconstruct_rows`, except that `levels` only holds
+/// the four luma blocks, and one block of each chroma channel, of the
+/// macroblock numbered `mb`.
+fn reconstruct_macroblock(
+    mb: usize,
+    mb_per_line: usize,
+    macroblock_types: &[MacroblockType],
+    motion_vectors: &[[MotionVector; 4]],
+    reference_picture: Option<&DecodedPicture>,
+    levels: [&[DecodedDctBlock]; 3],
+    picture: &mut DecodedPicture,
+) -> Result<()> {
+    gather_range(
+        macroblock_types,
+        reference_picture,
+        motion_vectors,
+        mb_per_line,
+        mb..mb + 1,
+        picture,
+    )?;
+
+    let [luma_levels, chroma_b_levels, chroma_r_levels] = levels;
+    let blk_pos = ((mb % mb_per_line) * 2, (mb / mb_per_line) * 2);
+    let luma_samples_per_row = picture.luma_samples_per_row();
+    for (i, block) in luma_levels.iter().enumerate() {
+        idct_block(
+            block,
+            picture.as_luma_mut(),
+            luma_samples_per_row,
+            (blk_pos.0 + i % 2, blk_pos.1 + i / 2),
+        );
+    }
+
+    let chroma_samples_per_row = picture.chroma_samples_per_row();
+    let chroma_blk_pos = (blk_pos.0 / 2, blk_pos.1 / 2);
+    idct_block(
+        &chroma_b_levels[0],
+        picture.as_chroma_b_mut(),
+        chroma_samples_per_row,
+        chroma_blk_pos,
+    );
+    idct_block(
+        &chroma_r_levels[0],
+        picture.as_chroma_r_mut(),
+        chroma_samples_per_row,
+        chroma_blk_pos,
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decoder::{DecodeCost, DecodeStatus, DecodedPicture, DecoderOption, H263State};
+    use crate::error::Error;
+    use crate::parser::{scan_bitstream_index, H263Reader};
+    use crate::types::{MacroblockType, MotionVector, Picture, PictureTypeCode, SourceFormat};
+    use std::cell::Cell;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn decode_picture_at_tr() {
+        #[rustfmt::skip]
+        let data = [
+            // 16x16 I-frame, TR 0, flat luma
+            0x00, 0x00, 0x80, 0x00, 0x08, 0x08, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20,
+            // P-frame, TR 1, uncoded
+            0x00, 0x00, 0x80, 0x04, 0x08, 0x08, 0x22, 0xA0,
+            // Disposable P-frame, TR 2, uncoded
+            0x00, 0x00, 0x80, 0x08, 0x08, 0x08, 0x42, 0xA0,
+            // P-frame, TR 3, uncoded
+            0x00, 0x00, 0x80, 0x0C, 0x08, 0x08, 0x22, 0xA0,
+            // 16x16 I-frame, TR 4, brighter flat luma
+            0x00, 0x00, 0x80, 0x10, 0x08, 0x08, 0x02, 0xA6, 0x40, 0x40, 0x40, 0x40, 0x40, 0x40,
+            // P-frame, TR 5, uncoded
+            0x00, 0x00, 0x80, 0x14, 0x08, 0x08, 0x22, 0xA0,
+        ];
+        let options = DecoderOption::SORENSON_SPARK_BITSTREAM;
+        let mut source = Cursor::new(&data[..]);
+        let index = scan_bitstream_index(&mut source, options).unwrap();
+        assert_eq!(index.entries().len(), 6);
+
+        let mut reader = H263Reader::from_source(source);
+        let mut state = H263State::new(options);
+
+        let picture = state.decode_picture_at_tr(&mut reader, 5, &index).unwrap();
+        assert_eq!(picture.as_header().temporal_reference, 5);
+        assert_eq!(picture.as_luma(), &[32; 256][..]);
+
+        let picture = state.decode_picture_at_tr(&mut reader, 3, &index).unwrap();
+        assert_eq!(picture.as_header().temporal_reference, 3);
+        assert_eq!(picture.as_luma(), &[16; 256][..]);
+
+        let picture = state.decode_picture_at_tr(&mut reader, 2, &index).unwrap();
+        assert_eq!(picture.as_header().temporal_reference, 2);
+
+        assert!(state.decode_picture_at_tr(&mut reader, 6, &index).is_err());
+    }
+
+    #[test]
+    fn reference_temporal_reference() {
+        // 16x16 I-frame, TR 0
+        let i_frame = [
+            0x00, 0x00, 0x80, 0x00, 0x08, 0x08, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20,
+        ];
+        // P-frame, TR 1, uncoded
+        let p_frame = [0x00, 0x00, 0x80, 0x04, 0x08, 0x08, 0x22, 0xA0];
+        // Disposable P-frame, TR 2, uncoded
+        let disposable_frame = [0x00, 0x00, 0x80, 0x08, 0x08, 0x08, 0x42, 0xA0];
+
+        let mut state = H263State::new(DecoderOption::SORENSON_SPARK_BITSTREAM);
+        let mut tr_and_reference = vec![];
+        for data in [&i_frame[..], &p_frame[..], &disposable_frame[..]] {
+            state
+                .decode_next_picture(&mut H263Reader::from_source(data))
+                .unwrap();
+
+            let picture = state.get_last_picture().unwrap();
+            tr_and_reference.push((
+                picture.as_header().temporal_reference,
+                picture.reference_temporal_reference(),
+            ));
+        }
+
+        assert_eq!(
+            tr_and_reference,
+            vec![(0, None), (1, Some(0)), (2, Some(1))]
+        );
+
+        // P-frame, TR 3, uncoded, predicted past the disposable frame
+        let p_frame = [0x00, 0x00, 0x80, 0x0C, 0x08, 0x08, 0x22, 0xA0];
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&p_frame[..]))
+            .unwrap();
+
+        let picture = state.get_last_picture().unwrap();
+        assert_eq!(picture.as_header().temporal_reference, 3);
+        assert_eq!(picture.reference_temporal_reference(), Some(1));
+    }
+
+    #[test]
+    fn disposable_frames_are_not_referenced() {
+        // 16x16 I-frame, TR 0, luma 16
+        let i_frame = [
+            0x00, 0x00, 0x80, 0x00, 0x08, 0x08, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20,
+        ];
+        // Disposable P-frame, TR 1, one intra macroblock of luma 200
+        let disposable_frame = [
+            0x00, 0x00, 0x80, 0x04, 0x08, 0x08, 0x42, 0x83, 0x3C, 0x8C, 0x8C, 0x8C, 0x8C, 0x8C,
+            0x80,
+        ];
+        // P-frame, TR 2, uncoded
+        let p_frame = [0x00, 0x00, 0x80, 0x08, 0x08, 0x08, 0x22, 0xA0];
+
+        let mut state = H263State::new(DecoderOption::SORENSON_SPARK_BITSTREAM);
+        for data in [&i_frame[..], &disposable_frame[..]] {
+            state
+                .decode_next_picture(&mut H263Reader::from_source(data))
+                .unwrap();
+        }
+
+        assert!(state
+            .get_last_picture()
+            .unwrap()
+            .as_luma()
+            .iter()
+            .all(|&l| l == 200));
+        assert_eq!(
+            state
+                .get_reference_picture()
+                .unwrap()
+                .as_header()
+                .temporal_reference,
+            0
+        );
+
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&p_frame[..]))
+            .unwrap();
+
+        let picture = state.get_last_picture().unwrap();
+        assert_eq!(picture.reference_temporal_reference(), Some(0));
+        assert!(picture.as_luma().iter().all(|&l| l == 16));
+    }
+
+    #[test]
+    fn decoder_options() {
+        let state = H263State::new(DecoderOption::SORENSON_SPARK_BITSTREAM);
+        assert_eq!(
+            state.decoder_options(),
+            DecoderOption::SORENSON_SPARK_BITSTREAM
+        );
+
+        let state = H263State::new(DecoderOption::empty());
+        assert_eq!(state.decoder_options(), DecoderOption::empty());
+
+        let state = H263State::with_capacity(DecoderOption::SORENSON_SPARK_BITSTREAM, 4);
+        assert_eq!(
+            state.decoder_options(),
+            DecoderOption::SORENSON_SPARK_BITSTREAM
+        );
+        assert!(state.reference_states.capacity() >= 4);
+    }
+
+    #[test]
+    fn set_reference_picture() {
+        let options = DecoderOption::SORENSON_SPARK_BITSTREAM;
+
+        // 16x16 I-frame, TR 0, flat luma
+        let iframe = [
+            0x00, 0x00, 0x80, 0x00, 0x08, 0x08, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20,
+        ];
+        let mut state = H263State::new(options);
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&iframe[..]))
+            .unwrap();
+        let reference = state.get_last_picture().unwrap().clone();
+
+        // P-frame, TR 1, uncoded
+        let pframe = [0x00, 0x00, 0x80, 0x04, 0x08, 0x08, 0x22, 0xA0];
+        let mut state = H263State::new(options);
+        state.set_reference_picture(0, reference);
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&pframe[..]))
+            .unwrap();
+
+        let picture = state.get_last_picture().unwrap();
+        assert_eq!(picture.as_header().temporal_reference, 1);
+        assert_eq!(picture.as_luma(), &[16; 256][..]);
+    }
+
+    #[cfg(feature = "pipeline")]
+    #[test]
+    fn decode_and_deblock_and_convert() {
+        use h263_rs_deblock::deblock::deblock;
+        use h263_rs_yuv::bt601::yuv420_to_rgba;
+
+        // 32x16 I-frame with deblocking, two flat macroblocks of different
+        // brightness
+        let data = [
+            0x00, 0x00, 0x80, 0x00, 0x10, 0x08, 0x12, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x21,
+            0x31, 0x41, 0x41, 0x41, 0x41, 0x41, 0x40,
+        ];
+        let options = DecoderOption::SORENSON_SPARK_BITSTREAM;
+
+        let mut state = H263State::new(options);
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&data[..]))
+            .unwrap();
+        let (luma, chroma_b, chroma_r) = state.get_last_picture().unwrap().as_yuv();
+        let unfiltered = yuv420_to_rgba(luma, chroma_b, chroma_r, 32);
+        let expected = yuv420_to_rgba(
+            &deblock(luma, 32, 3),
+            &deblock(chroma_b, 16, 3),
+            &deblock(chroma_r, 16, 3),
+            32,
+        );
+        assert_ne!(unfiltered, expected);
+
+        let mut state = H263State::new(options);
+        let mut rgba = vec![0; 7];
+        let dimensions = state
+            .decode_and_deblock_and_convert(&mut H263Reader::from_source(&data[..]), &mut rgba)
+            .unwrap();
+
+        assert_eq!(dimensions, Some((32, 16)));
+        assert_eq!(rgba, expected);
+
+        // At the end of the bitstream, there is nothing to convert
+        let mut rgba = vec![0; 7];
+        assert_eq!(
+            state
+                .decode_and_deblock_and_convert(&mut H263Reader::from_source(&[][..]), &mut rgba)
+                .unwrap(),
+            None
+        );
+        assert_eq!(rgba, [0; 7]);
+
+        // The reference picture itself stays unfiltered
+        let (luma, chroma_b, chroma_r) = state.get_last_picture().unwrap().as_yuv();
+        assert_eq!(yuv420_to_rgba(luma, chroma_b, chroma_r, 32), unfiltered);
+    }
+
+    #[cfg(feature = "pipeline")]
+    #[test]
+    fn in_loop_deblock() {
+        use h263_rs_deblock::deblock::deblock;
+
+        // 32x16 I-frame with deblocking, two flat macroblocks of different
+        // brightness
+        let iframe = [
+            0x00, 0x00, 0x80, 0x00, 0x10, 0x08, 0x12, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x21,
+            0x31, 0x41, 0x41, 0x41, 0x41, 0x41, 0x40,
+        ];
+
+        // P-frame, TR 1, with deblocking, uncoded
+        let pframe = [0x00, 0x00, 0x80, 0x04, 0x10, 0x08, 0x32, 0xB0];
+
+        let decode = |options| {
+            let mut state = H263State::new(options);
+            let mut planes = vec![];
+            for data in [&iframe[..], &pframe[..]].iter() {
+                state
+                    .decode_next_picture(&mut H263Reader::from_source(*data))
+                    .unwrap();
+                let (luma, chroma_b, chroma_r) = state.get_last_picture().unwrap().as_yuv();
+                planes.push((luma.to_vec(), chroma_b.to_vec(), chroma_r.to_vec()));
+            }
+            planes
+        };
+
+        let postfiltered = decode(DecoderOption::SORENSON_SPARK_BITSTREAM);
+        let in_loop =
+            decode(DecoderOption::SORENSON_SPARK_BITSTREAM | DecoderOption::IN_LOOP_DEBLOCK);
+
+        // Without the option, the uncoded P-frame is an exact copy
+        let (luma, chroma_b, chroma_r) = &postfiltered[0];
+        assert_eq!(postfiltered[1], postfiltered[0]);
+
+        // With it, the I-frame is filtered before being predicted from, and
+        // the P-frame is then filtered on top of that
+        let filtered = (
+            deblock(luma, 32, 3),
+            deblock(chroma_b, 16, 3),
+            deblock(chroma_r, 16, 3),
+        );
+        assert_ne!(&filtered.0, luma);
+        assert_eq!(in_loop[0], filtered);
+        assert_eq!(
+            in_loop[1],
+            (
+                deblock(&filtered.0, 32, 3),
+                deblock(&filtered.1, 16, 3),
+                deblock(&filtered.2, 16, 3),
+            )
+        );
+        assert_ne!(in_loop[1], postfiltered[1]);
+
+        // The output pipeline doesn't filter the picture a second time
+        let mut state = H263State::new(
+            DecoderOption::SORENSON_SPARK_BITSTREAM | DecoderOption::IN_LOOP_DEBLOCK,
+        );
+        let mut rgba = vec![];
+        state
+            .decode_and_deblock_and_convert(&mut H263Reader::from_source(&iframe[..]), &mut rgba)
+            .unwrap();
+        assert_eq!(
+            rgba,
+            h263_rs_yuv::bt601::yuv420_to_rgba(&filtered.0, &filtered.1, &filtered.2, 32)
+        );
+    }
+
+    #[test]
+    fn pb_frame_intra_macroblock() {
+        // Sub-QCIF reference picture, with the luma of each column equal to
+        // it's position
+        let mut reference = subqcif_reference();
+        for (i, sample) in reference.as_luma_mut().iter_mut().enumerate() {
+            *sample = (i % 128) as u8;
+        }
+
+        // PB frame, TR 2, TRB 1: the first macroblock is intra with a flat
+        // luma and chroma of 64, and a motion vector of (2, 0) for it's B
+        // block; the rest are uncoded
+        let data = [
+            0x00, 0x00, 0x80, 0x0A, 0x06, 0x28, 0x10, 0x18, 0xC3, 0x50, 0x10, 0x10, 0x10, 0x10,
+            0x10, 0x3F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x80,
+        ];
+        let mut state = H263State::new(DecoderOption::empty());
+        state.set_reference_picture(0, reference);
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&data[..]))
+            .unwrap();
+
+        let p_picture = state.get_last_picture().unwrap();
+        assert_eq!(p_picture.as_header().temporal_reference, 2);
+        assert_eq!(&p_picture.as_luma()[..16], &[64; 16][..]);
+        assert_eq!(&p_picture.as_luma()[16..18], &[16, 17]);
+
+        // The B block is predicted forwards by (1, 0) from the reference, and
+        // backwards by (-1, 0) from the P block where that lands inside of it
+        let b_picture = state.get_last_b_picture().unwrap();
+        assert_eq!(b_picture.as_header().temporal_reference, 1);
+        for y in 0..16 {
+            let row = &b_picture.as_luma()[y * 128..y * 128 + 18];
+            assert_eq!(row[0], 1);
+            for (x, sample) in row.iter().enumerate().take(16).skip(1) {
+                assert_eq!(*sample as usize, (x + 1 + 64).div_ceil(2));
+            }
+            assert_eq!(&row[16..], &[16, 17]);
+        }
+        assert_eq!(
+            &b_picture.as_chroma_b()[..9],
+            &[128, 96, 96, 96, 96, 96, 96, 96, 128]
+        );
+    }
+
+    #[test]
+    fn pb_frame_zero_temporal_distance() {
+        // PB frame, TR 2, TRB 1, with the same macroblocks as in
+        // `pb_frame_intra_macroblock`
+        let data = [
+            0x00, 0x00, 0x80, 0x0A, 0x06, 0x28, 0x10, 0x18, 0xC3, 0x50, 0x10, 0x10, 0x10, 0x10,
+            0x10, 0x3F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x80,
+        ];
+
+        // TRD of 0: the B picture lands on the P picture
+        let mut state = H263State::new(DecoderOption::empty());
+        state.set_reference_picture(2, subqcif_reference());
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&data[..]))
+            .unwrap();
+        assert_eq!(
+            state
+                .get_last_b_picture()
+                .unwrap()
+                .as_header()
+                .temporal_reference,
+            2
+        );
+
+        // TRB and TRD of 0
+        let mut data = data;
+        data[6] &= !0x10;
+        let mut state = H263State::new(DecoderOption::empty());
+        state.set_reference_picture(2, subqcif_reference());
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&data[..]))
+            .unwrap();
+
+        let p_picture = state.get_last_picture().unwrap();
+        assert_eq!(p_picture.as_header().pb_reference, Some(0));
+        let b_picture = state.get_last_b_picture().unwrap();
+        assert_eq!(b_picture.as_header().temporal_reference, 2);
+        assert_eq!(&b_picture.as_luma()[16..18], &[128, 128]);
+    }
+
+    #[test]
+    fn improved_pb_frame_forward_prediction() {
+        // Sub-QCIF reference picture, with the luma of each column equal to
+        // it's position
+        let mut reference = subqcif_reference();
+        for (i, sample) in reference.as_luma_mut().iter_mut().enumerate() {
+            *sample = (i % 128) as u8;
+        }
+
+        // Improved PB frame, TR 2, TRB 1: the first macroblock is inter with
+        // no motion and forward-only B prediction by (1, 0); the rest are
+        // uncoded
+        let data = [
+            0x00, 0x00, 0x80, 0x0A, 0x1C, 0x90, 0x01, 0x08, 0x12, 0x08, 0x77, 0x97, 0xFF, 0xFF,
+            0xFF, 0xFF, 0xFF, 0xF8,
+        ];
+        let mut state = H263State::new(DecoderOption::empty());
+        state.set_reference_picture(0, reference);
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&data[..]))
+            .unwrap();
+
+        let p_picture = state.get_last_picture().unwrap();
+        assert_eq!(
+            p_picture.as_header().picture_type,
+            PictureTypeCode::ImprovedPbFrame
+        );
+        assert_eq!(&p_picture.as_luma()[..4], &[0, 1, 2, 3]);
+
+        // The first B macroblock only uses the reference picture, and the rest
+        // average the identical reference and P pictures
+        let b_picture = state.get_last_b_picture().unwrap();
+        assert_eq!(b_picture.as_header().temporal_reference, 1);
+        for y in 0..16 {
+            let row = &b_picture.as_luma()[y * 128..y * 128 + 18];
+            for (x, sample) in row.iter().enumerate().take(16) {
+                assert_eq!(*sample as usize, x + 1);
+            }
+            assert_eq!(&row[16..], &[16, 17]);
+        }
+    }
+
+    #[test]
+    fn skip_to_next_picture() {
+        // Container framing, then a 16x16 Sorenson I-frame
+        let data = [
+            0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x00, 0x00, 0x80, 0x00, 0x08, 0x08, 0x02, 0xA6, 0x20,
+            0x20, 0x20, 0x20, 0x20, 0x20,
+        ];
+        let mut state = H263State::new(DecoderOption::SORENSON_SPARK_BITSTREAM);
+
+        let mut reader = H263Reader::from_source(&data[..]);
+        assert!(matches!(
+            state.decode_next_picture(&mut reader),
+            Err(Error::MiddleOfBitstream)
+        ));
+        assert!(matches!(
+            state.skip_to_next_picture(&mut reader, 4),
+            Err(Error::MiddleOfBitstream)
+        ));
+        assert_eq!(reader.bit_position(), 0);
+
+        assert_eq!(state.skip_to_next_picture(&mut reader, 16).unwrap(), 40);
+        state.decode_next_picture(&mut reader).unwrap();
+        assert_eq!(&state.get_last_picture().unwrap().as_luma()[..4], &[16; 4]);
+
+        // A GOB header, then a sub-QCIF P-frame
+        let data = [
+            0x00, 0x00, 0x8E, 0x38, 0x00, 0x00, 0x80, 0x06, 0x06, 0x08, 0x3F, 0xFF, 0xFF, 0xFF,
+            0xFF, 0xFF, 0xC0,
+        ];
+        let mut state = H263State::new(DecoderOption::empty());
+        state.set_reference_picture(0, subqcif_reference());
+
+        let mut reader = H263Reader::from_source(&data[..]);
+        assert_eq!(state.skip_to_next_picture(&mut reader, 16).unwrap(), 32);
+        state.decode_next_picture(&mut reader).unwrap();
+        assert_eq!(
+            state
+                .get_last_picture()
+                .unwrap()
+                .as_header()
+                .temporal_reference,
+            1
+        );
+    }
+
+    #[test]
+    fn lenient_chroma_degraded() {
+        // 16x16 Sorenson I-frame, with a forbidden INTRADC in the chroma-B
+        // block
+        let data = [
+            0x00, 0x00, 0x80, 0x00, 0x08, 0x08, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x21, 0x00, 0x20,
+        ];
+
+        let mut state = H263State::new(DecoderOption::SORENSON_SPARK_BITSTREAM);
+        assert!(matches!(
+            state.decode_next_picture(&mut H263Reader::from_source(&data[..])),
+            Err(Error::InvalidIntraDc)
+        ));
+
+        let mut state =
+            H263State::new(DecoderOption::SORENSON_SPARK_BITSTREAM | DecoderOption::LENIENT);
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&data[..]))
+            .unwrap();
+
+        let picture = state.get_last_picture().unwrap();
+        assert!(picture.is_chroma_degraded());
+        assert_eq!(picture.as_luma(), &[16; 256][..]);
+        assert_eq!(picture.as_chroma_b(), &[128; 64][..]);
+        assert_eq!(picture.as_chroma_r(), &[128; 64][..]);
+
+        // P-frame, TR 1, with one uncoded macroblock, which inherits the lost
+        // chroma of it's reference
+        let data = [0x00, 0x00, 0x80, 0x04, 0x08, 0x08, 0x22, 0xA0];
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&data[..]))
+            .unwrap();
+
+        let picture = state.get_last_picture().unwrap();
+        assert_eq!(picture.as_header().temporal_reference, 1);
+        assert!(picture.is_chroma_degraded());
+        assert_eq!(picture.as_chroma_b(), &[128; 64][..]);
+
+        // Undamaged I-frames are not marked
+        let data = [
+            0x00, 0x00, 0x80, 0x00, 0x08, 0x08, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20,
+        ];
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&data[..]))
+            .unwrap();
+        assert!(!state.get_last_picture().unwrap().is_chroma_degraded());
+    }
+
+    #[test]
+    fn sorenson_start_codes() {
+        // Version 0, 16x16, I-frame, TR 0, one intra macroblock, directly
+        // followed by a P-frame, TR 1, with one uncoded macroblock
+        let data = [
+            0x00, 0x00, 0x80, 0x00, 0x08, 0x08, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20,
+            0x00, 0x00, 0x80, 0x04, 0x08, 0x08, 0x22, 0xA0,
+        ];
+
+        let mut state = H263State::new(DecoderOption::SORENSON_SPARK_BITSTREAM);
+        let mut reader = H263Reader::from_source(&data[..]);
+        state.decode_next_picture(&mut reader).unwrap();
+        assert_eq!(
+            state.get_last_picture().unwrap().as_header().picture_type,
+            PictureTypeCode::IFrame
+        );
+        state.decode_next_picture(&mut reader).unwrap();
+        assert_eq!(
+            state
+                .get_last_picture()
+                .unwrap()
+                .as_header()
+                .temporal_reference,
+            1
+        );
+        assert_eq!(
+            state.decode_next_picture(&mut reader).unwrap(),
+            DecodeStatus::EndOfStream
+        );
+
+        // Version 0, 32x16, I-frame, TR 0, two intra macroblocks, followed by
+        // a P-frame, TR 1, with a start code after it's first macroblock, and
+        // a P-frame, TR 2, with two uncoded macroblocks. The start code ends
+        // the first P-frame early, and is then read as the second one.
+        let data = [
+            0x00, 0x00, 0x80, 0x00, 0x10, 0x08, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x21,
+            0x31, 0x01, 0x01, 0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x80, 0x04, 0x10, 0x08, 0x22,
+            0xA0, 0x00, 0x00, 0x80, 0x08, 0x10, 0x08, 0x22, 0xB0,
+        ];
+
+        let mut state = H263State::new(DecoderOption::SORENSON_SPARK_BITSTREAM);
+        let mut reader = H263Reader::from_source(&data[..]);
+        for temporal_reference in 0..3 {
+            assert_eq!(
+                state.decode_next_picture(&mut reader).unwrap(),
+                DecodeStatus::Decoded
+            );
+            assert_eq!(
+                state
+                    .get_last_picture()
+                    .unwrap()
+                    .as_header()
+                    .temporal_reference,
+                temporal_reference
+            );
+        }
+        assert_eq!(
+            state.decode_next_picture(&mut reader).unwrap(),
+            DecodeStatus::EndOfStream
+        );
+    }
+
+    #[test]
+    fn lenient_sorenson_resynchronization() {
+        // Version 0, 32x16, I-frame, TR 0, two intra macroblocks, followed by
+        // a P-frame, TR 1, with an uncoded macroblock and then an invalid
+        // MCBPC and some garbage, and a P-frame, TR 2, with two uncoded
+        // macroblocks
+        let data = [
+            0x00, 0x00, 0x80, 0x00, 0x10, 0x08, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x21,
+            0x31, 0x01, 0x01, 0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x80, 0x04, 0x10, 0x08, 0x22,
+            0xA0, 0x06, 0xFF, 0xFF, 0x00, 0x00, 0x80, 0x08, 0x10, 0x08, 0x22, 0xB0,
+        ];
+
+        let mut state = H263State::new(DecoderOption::SORENSON_SPARK_BITSTREAM);
+        let mut reader = H263Reader::from_source(&data[..]);
+        state.decode_next_picture(&mut reader).unwrap();
+        assert!(matches!(
+            state.decode_next_picture(&mut reader),
+            Err(Error::InvalidMacroblockHeader)
+        ));
+
+        let mut state =
+            H263State::new(DecoderOption::SORENSON_SPARK_BITSTREAM | DecoderOption::LENIENT);
+        let mut reader = H263Reader::from_source(&data[..]);
+        for temporal_reference in 0..3 {
+            assert_eq!(
+                state.decode_next_picture(&mut reader).unwrap(),
+                DecodeStatus::Decoded
+            );
+            assert_eq!(
+                state
+                    .get_last_picture()
+                    .unwrap()
+                    .as_header()
+                    .temporal_reference,
+                temporal_reference
+            );
+        }
+        assert_eq!(
+            state.decode_next_picture(&mut reader).unwrap(),
+            DecodeStatus::EndOfStream
+        );
+    }
+
+    #[test]
+    fn clone_state() {
+        // Version 0, 16x16, I-frame, TR 0, one intra macroblock
+        let i_frame = [
+            0x00, 0x00, 0x80, 0x00, 0x08, 0x08, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20,
+        ];
+        // Version 0, P-frame, TR 1, one uncoded macroblock
+        let p_frame = [0x00, 0x00, 0x80, 0x04, 0x08, 0x08, 0x22, 0xA0];
+
+        let mut state = H263State::new(DecoderOption::SORENSON_SPARK_BITSTREAM);
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&i_frame[..]))
+            .unwrap();
+
+        let mut speculative = state.clone();
+        speculative
+            .decode_next_picture(&mut H263Reader::from_source(&p_frame[..]))
+            .unwrap();
+
+        assert_eq!(
+            speculative
+                .get_last_picture()
+                .unwrap()
+                .as_header()
+                .temporal_reference,
+            1
+        );
+        assert_eq!(
+            state
+                .get_last_picture()
+                .unwrap()
+                .as_header()
+                .temporal_reference,
+            0
+        );
+
+        // The original state can still decode the same picture
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&p_frame[..]))
+            .unwrap();
+        assert_eq!(
+            state.get_last_picture().unwrap().as_luma(),
+            speculative.get_last_picture().unwrap().as_luma()
+        );
+    }
+
+    #[test]
+    fn estimate_next_cost() {
+        // Version 0, 16x16, I-frame, TR 0, one intra macroblock, directly
+        // followed by a P-frame, TR 1, with one uncoded macroblock
+        let data = [
+            0x00, 0x00, 0x80, 0x00, 0x08, 0x08, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20,
+            0x00, 0x00, 0x80, 0x04, 0x08, 0x08, 0x22, 0xA0,
+        ];
+
+        let mut state = H263State::new(DecoderOption::SORENSON_SPARK_BITSTREAM);
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        assert_eq!(
+            state.estimate_next_cost(&mut reader).unwrap(),
+            DecodeCost {
+                picture_type: PictureTypeCode::IFrame,
+                dimensions: (16, 16),
+                coded_macroblocks: 1,
+                uncoded_macroblocks: 0,
+                coded_blocks: 0,
+            }
+        );
+        assert_eq!(reader.bit_position(), 0);
+        assert!(state.get_last_picture().is_none());
+
+        state.decode_next_picture(&mut reader).unwrap();
+
+        let position = reader.bit_position();
+        assert_eq!(
+            state.estimate_next_cost(&mut reader).unwrap(),
+            DecodeCost {
+                picture_type: PictureTypeCode::PFrame,
+                dimensions: (16, 16),
+                coded_macroblocks: 0,
+                uncoded_macroblocks: 1,
+                coded_blocks: 0,
+            }
+        );
+        assert_eq!(reader.bit_position(), position);
+
+        state.decode_next_picture(&mut reader).unwrap();
+        assert_eq!(
+            state
+                .get_last_picture()
+                .unwrap()
+                .as_header()
+                .temporal_reference,
+            1
+        );
+    }
+
+    #[test]
+    fn lenient_long_zero_run() {
+        // Sub-QCIF P-frame, TR 1, with one row of uncoded macroblocks and then
+        // 24 zero bits, followed by an unaligned sub-QCIF P-frame, TR 2, with
+        // all macroblocks uncoded
+        let data = [
+            0x00, 0x00, 0x80, 0x06, 0x06, 0x08, 0x3F, 0xC0, 0x00, 0x00, 0x00, 0x00, 0x20, 0x02,
+            0x81, 0x82, 0x0F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xF0,
+        ];
+
+        let mut state = H263State::new(DecoderOption::empty());
+        state.set_reference_picture(0, subqcif_reference());
+        let mut reader = H263Reader::from_source(&data[..]);
+        state.decode_next_picture(&mut reader).unwrap();
+        assert!(state.decode_next_picture(&mut reader).is_err());
+
+        let mut state = H263State::new(DecoderOption::LENIENT);
+        state.set_reference_picture(0, subqcif_reference());
+        let mut reader = H263Reader::from_source(&data[..]);
+        state.decode_next_picture(&mut reader).unwrap();
+        assert_eq!(
+            state
+                .get_last_picture()
+                .unwrap()
+                .as_header()
+                .temporal_reference,
+            1
+        );
+
+        assert_eq!(
+            state.decode_next_picture(&mut reader).unwrap(),
+            DecodeStatus::Decoded
+        );
+        assert_eq!(
+            state
+                .get_last_picture()
+                .unwrap()
+                .as_header()
+                .temporal_reference,
+            2
+        );
+        assert_eq!(
+            state.decode_next_picture(&mut reader).unwrap(),
+            DecodeStatus::EndOfStream
+        );
+    }
+
+    #[test]
+    fn collect_macroblock_stats() {
+        // 32x24 I-frame, four flat macroblocks of increasing brightness
+        let i_frame = [
+            0x00, 0x00, 0x80, 0x00, 0x10, 0x0C, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x21,
+            0x32, 0x02, 0x02, 0x02, 0x02, 0x02, 0x09, 0x98, 0x18, 0x18, 0x18, 0x18, 0x18, 0x4D,
+            0x01, 0x01, 0x01, 0x01, 0x01, 0x00,
+        ];
+        // 32x24 P-frame, TR 1, uncoded
+        let p_frame = [0x00, 0x00, 0x80, 0x04, 0x10, 0x0C, 0x22, 0xBC];
+
+        let mut state = H263State::new(DecoderOption::SORENSON_SPARK_BITSTREAM);
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&i_frame[..]))
+            .unwrap();
+        assert!(state.get_last_frame_stats().is_none());
+
+        let mut state = H263State::new(
+            DecoderOption::SORENSON_SPARK_BITSTREAM | DecoderOption::COLLECT_MACROBLOCK_STATS,
+        );
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&i_frame[..]))
+            .unwrap();
+
+        // MCBPC, CBPY, and six INTRADC values
+        let stats = state.get_last_frame_stats().unwrap();
+        assert_eq!(stats.macroblock_bits, vec![53; 4]);
+        assert_eq!(stats.total_macroblock_bits(), 212);
+        assert_eq!(stats.count_of(MacroblockType::Intra), 4);
+        assert_eq!(stats.count_of(MacroblockType::Inter), 0);
+        assert_eq!(stats.uncoded_macroblocks, 0);
+        assert_eq!(stats.stuffing_macroblocks, 0);
+
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&p_frame[..]))
+            .unwrap();
+
+        let stats = state.get_last_frame_stats().unwrap();
+        assert_eq!(stats.macroblock_bits, vec![1; 4]);
+        assert_eq!(stats.count_of(MacroblockType::Intra), 0);
+        assert_eq!(stats.uncoded_macroblocks, 4);
+
+        let picture = state.get_last_picture().unwrap();
+        assert_eq!(
+            picture.motion_vectors(),
+            Some(&[[MotionVector::ZERO; 4]; 4][..])
+        );
+    }
+
+    #[test]
+    fn streaming_idct() {
+        // 32x24 I-frame, four flat macroblocks of increasing brightness
+        let i_frame = [
+            0x00, 0x00, 0x80, 0x00, 0x10, 0x0C, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x21,
+            0x32, 0x02, 0x02, 0x02, 0x02, 0x02, 0x09, 0x98, 0x18, 0x18, 0x18, 0x18, 0x18, 0x4D,
+            0x01, 0x01, 0x01, 0x01, 0x01, 0x00,
+        ];
+        // 32x24 P-frame, TR 1, uncoded
+        let p_frame = [0x00, 0x00, 0x80, 0x04, 0x10, 0x0C, 0x22, 0xBC];
+
+        let mut whole = H263State::new(DecoderOption::SORENSON_SPARK_BITSTREAM);
+        let mut streaming =
+            H263State::new(DecoderOption::SORENSON_SPARK_BITSTREAM | DecoderOption::STREAMING_IDCT);
+        for data in [&i_frame[..], &p_frame[..]] {
+            whole
+                .decode_next_picture(&mut H263Reader::from_source(data))
+                .unwrap();
+            streaming
+                .decode_next_picture(&mut H263Reader::from_source(data))
+                .unwrap();
+
+            let expected = whole.get_last_picture().unwrap();
+            let actual = streaming.get_last_picture().unwrap();
+            assert_eq!(actual.as_luma(), expected.as_luma());
+            assert_eq!(actual.as_chroma_b(), expected.as_chroma_b());
+            assert_eq!(actual.as_chroma_r(), expected.as_chroma_r());
+        }
+
+        let luma = streaming.get_last_picture().unwrap().as_luma();
+        assert_eq!(&luma[14..18], &[16, 16, 32, 32]);
+        assert_eq!(luma[32 * 16], 48);
+    }
+
+    #[test]
+    fn decode_next_picture_progressive() {
+        // 32x24 I-frame, four flat macroblocks of increasing brightness
+        let data = [
+            0x00, 0x00, 0x80, 0x00, 0x10, 0x0C, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x21,
+            0x32, 0x02, 0x02, 0x02, 0x02, 0x02, 0x09, 0x98, 0x18, 0x18, 0x18, 0x18, 0x18, 0x4D,
+            0x01, 0x01, 0x01, 0x01, 0x01, 0x00,
+        ];
+        let options = DecoderOption::SORENSON_SPARK_BITSTREAM;
+
+        let mut state = H263State::new(options);
+        let mut rows = vec![];
+        state
+            .decode_next_picture_progressive(
+                &mut H263Reader::from_source(&data[..]),
+                |picture, luma_rows| {
+                    // Rows after the reported ones have not been reconstructed
+                    let luma = picture.as_luma();
+                    let left = 16 + 32 * (luma_rows.start / 16) as u8;
+                    assert_eq!(luma[luma_rows.start * 32], left);
+                    assert_eq!(luma[luma_rows.end * 32 - 1], left + 16);
+                    assert!(luma[luma_rows.end * 32..].iter().all(|sample| *sample == 0));
+
+                    rows.push(luma_rows);
+                },
+            )
+            .unwrap();
+
+        assert_eq!(rows, vec![0..16, 16..24]);
+
+        let mut batch_state = H263State::new(options);
+        batch_state
+            .decode_next_picture(&mut H263Reader::from_source(&data[..]))
+            .unwrap();
+        assert_eq!(
+            state.get_last_picture().unwrap().as_yuv(),
+            batch_state.get_last_picture().unwrap().as_yuv()
+        );
+    }
+
+    /// A sub-QCIF reference picture with a flat luma and chroma of 128.
+    fn subqcif_reference() -> DecodedPicture {
+        let header = Picture {
+            format: Some(SourceFormat::SubQcif),
+            quantizer: 8,
+            ..Picture::for_test(PictureTypeCode::IFrame)
+        };
+        let mut reference = DecodedPicture::new(header, SourceFormat::SubQcif).unwrap();
+        reference.as_luma_mut().fill(128);
+        reference.as_chroma_b_mut().fill(128);
+        reference.as_chroma_r_mut().fill(128);
+
+        reference
+    }
+
+    #[test]
+    fn uncoded_macroblock_predictor() {
+        let mut reference = subqcif_reference();
+        for (i, sample) in reference.as_luma_mut().iter_mut().enumerate() {
+            *sample = (i % 128) as u8;
+        }
+
+        // Sub-QCIF P-frame, TR 1: the first macroblock moves by (1, 0), the
+        // second is uncoded, and the third has a zero MVD. The rest are
+        // uncoded.
+        let data = [
+            0x00, 0x00, 0x80, 0x06, 0x06, 0x08, 0x1C, 0xB7, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFE,
+        ];
+        let mut state = H263State::new(DecoderOption::empty());
+        state.set_reference_picture(0, reference);
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&data[..]))
+            .unwrap();
+
+        // The uncoded macroblock's zero vector is the third macroblock's
+        // only candidate predictor, so it stays put rather than inheriting
+        // the first macroblock's motion.
+        let picture = state.get_last_picture().unwrap();
+        for y in 0..16 {
+            let row = &picture.as_luma()[y * 128..y * 128 + 48];
+            for (x, sample) in row.iter().enumerate() {
+                let expected = if x < 16 { x + 1 } else { x };
+                assert_eq!(*sample as usize, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn advanced_intra_coding_prediction() {
+        let mut reference = subqcif_reference();
+        for (i, sample) in reference.as_luma_mut().iter_mut().enumerate() {
+            *sample = (i % 128) as u8;
+        }
+        reference.as_chroma_b_mut().fill(16);
+        reference.as_chroma_r_mut().fill(16);
+
+        // Sub-QCIF P-frame, TR 1, with Advanced Intra Coding: the first
+        // macroblock is intra with DC prediction, the second is uncoded, and
+        // the third is intra with vertical prediction. None of them have any
+        // coefficients. The rest are uncoded.
+        let data = [
+            0x00, 0x00, 0x80, 0x06, 0x1C, 0x90, 0x81, 0x04, 0x12, 0x01, 0x8E, 0x1C, 0x7F, 0xFF,
+            0xFF, 0xFF, 0xFF, 0xFF,
+        ];
+        let mut state = H263State::new(DecoderOption::empty());
+        state.set_reference_picture(0, reference.clone());
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&data[..]))
+            .unwrap();
+
+        let mut streaming_state = H263State::new(DecoderOption::STREAMING_IDCT);
+        streaming_state.set_reference_picture(0, reference);
+        streaming_state
+            .decode_next_picture(&mut H263Reader::from_source(&data[..]))
+            .unwrap();
+        assert_eq!(
+            streaming_state.get_last_picture().unwrap().as_yuv(),
+            state.get_last_picture().unwrap().as_yuv()
+        );
+
+        // Neither intra macroblock has an intra neighbour to predict from,
+        // so both come out with the mid-gray DC prediction.
+        let picture = state.get_last_picture().unwrap();
+        for y in 0..16 {
+            let row = &picture.as_luma()[y * 128..y * 128 + 64];
+            for (x, sample) in row.iter().enumerate() {
+                let expected = if x < 16 || (32..48).contains(&x) {
+                    128
+                } else {
+                    x
+                };
+                assert_eq!(*sample as usize, expected, "at ({}, {})", x, y);
+            }
+        }
+        for chroma in [picture.as_chroma_b(), picture.as_chroma_r()] {
+            assert_eq!(
+                &chroma[..32],
+                &[[128; 8], [16; 8], [128; 8], [16; 8]].concat()[..]
+            );
+        }
+    }
+
+    #[test]
+    fn advanced_intra_coding_coefficients() {
+        // Sub-QCIF P-frame, TR 1, with Advanced Intra Coding: the first
+        // macroblock is intra with DC prediction, and codes a DC level of 1
+        // in it's first luma block. The second is intra with horizontal
+        // prediction, and codes a level of 1 just below the DC in it's first
+        // luma block. The rest are uncoded.
+        let data = [
+            0x00, 0x00, 0x80, 0x06, 0x1C, 0x90, 0x81, 0x04, 0x12, 0x01, 0x84, 0xE0, 0xF1, 0x1E,
+            0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFC,
+        ];
+        let mut state = H263State::new(DecoderOption::empty());
+        state.set_reference_picture(0, subqcif_reference());
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&data[..]))
+            .unwrap();
+
+        let mut streaming_state = H263State::new(DecoderOption::STREAMING_IDCT);
+        streaming_state.set_reference_picture(0, subqcif_reference());
+        streaming_state
+            .decode_next_picture(&mut H263Reader::from_source(&data[..]))
+            .unwrap();
+        assert_eq!(
+            streaming_state.get_last_picture().unwrap().as_yuv(),
+            state.get_last_picture().unwrap().as_yuv()
+        );
+
+        // The coded DC of 2 * 8 * 1 adds to the unavailable prediction of
+        // 1024, and the other three blocks of the first macroblock inherit
+        // it through prediction. The second macroblock's first column of
+        // blocks adds the vertical frequency below the DC to the first
+        // column of coefficients to the left of it, which it's second column
+        // of blocks in turn inherits. The bottom row of blocks only inherits
+        // the DC.
+        let picture = state.get_last_picture().unwrap();
+        let second_macroblock = [
+            133, 132, 132, 131, 129, 128, 128, 127, 130, 130, 130, 130, 130, 130, 130, 130,
+        ];
+        for (y, second) in second_macroblock.iter().enumerate() {
+            let row = &picture.as_luma()[y * 128..y * 128 + 48];
+            for (x, sample) in row.iter().enumerate() {
+                let expected = match x {
+                    0..=15 => 130,
+                    16..=31 => *second,
+                    _ => 128,
+                };
+                assert_eq!(*sample, expected, "at ({}, {})", x, y);
+            }
+        }
+        for chroma in [picture.as_chroma_b(), picture.as_chroma_r()] {
+            assert_eq!(&chroma[..24], &[128; 24][..]);
+        }
+    }
+
+    #[test]
+    fn end_of_sequence_resets_references() {
+        // Sub-QCIF P-frame, TR 1, uncoded
+        let pframe = [
+            0x00, 0x00, 0x80, 0x06, 0x06, 0x08, 0x3F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xC0,
+        ];
+        let mut state = H263State::new(DecoderOption::empty());
+        state.set_reference_picture(0, subqcif_reference());
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&pframe[..]))
+            .unwrap();
+        assert_eq!(
+            state.get_last_picture().unwrap().as_luma(),
+            &[128; 128 * 96][..]
+        );
+
+        // The same P-frame, after an EOS: it may not reference anything from
+        // the previous sequence
+        let eos_pframe = [
+            0x00, 0x00, 0xFC, 0x00, 0x00, 0x80, 0x06, 0x06, 0x08, 0x3F, 0xFF, 0xFF, 0xFF, 0xFF,
+            0xFF, 0xC0,
+        ];
+        let mut state = H263State::new(DecoderOption::empty());
+        state.set_reference_picture(0, subqcif_reference());
+        assert!(matches!(
+            state.decode_next_picture(&mut H263Reader::from_source(&eos_pframe[..])),
+            Err(Error::UncodedIFrameBlocks)
+        ));
+
+        // Failing to decode a picture leaves the old sequence intact
+        assert!(state.get_reference_picture().is_some());
+
+        state.reset();
+        assert!(state.get_last_picture().is_none());
+        assert!(state.get_reference_picture().is_none());
+    }
+
+    #[test]
+    fn format_change_starts_sequence() {
+        let options = DecoderOption::SORENSON_SPARK_BITSTREAM;
+        let mut state = H263State::new(options);
+
+        // 16x16 I-frame and P-frame
+        let data = [
+            0x00, 0x00, 0x80, 0x00, 0x08, 0x08, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20,
+        ];
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&data[..]))
+            .unwrap();
+        let data = [0x00, 0x00, 0x80, 0x04, 0x08, 0x08, 0x22, 0xA0];
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&data[..]))
+            .unwrap();
+
+        // 32x16 I-frame with a different temporal reference
+        let data = [
+            0x00, 0x00, 0x80, 0x00, 0x10, 0x08, 0x12, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x21,
+            0x31, 0x41, 0x41, 0x41, 0x41, 0x41, 0x40,
+        ];
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&data[..]))
+            .unwrap();
+
+        assert_eq!(state.reference_states.len(), 1);
+        assert_eq!(
+            state
+                .get_last_picture()
+                .unwrap()
+                .format()
+                .into_width_and_height(),
+            Some((32, 16))
+        );
+    }
+
+    #[test]
+    fn parse_picture_previous_format() {
+        // A decoded CIF picture, TR 0, whose header doesn't restate it
+        let mut state = H263State::new(DecoderOption::empty());
+        let header = Picture::for_test(PictureTypeCode::IFrame);
+        state.set_reference_picture(
+            0,
+            DecodedPicture::new(header.clone(), SourceFormat::FullCif).unwrap(),
+        );
+
+        // CIF P-frame, TR 2
+        let data = [0x00, 0x00, 0x80, 0x0A, 0x0E, 0x08, 0x00];
+
+        // The decoded format stands in for the one the header omits
+        let picture = state
+            .parse_picture(&mut H263Reader::from_source(&data[..]), Some(&header))
+            .unwrap()
+            .unwrap();
+        assert_eq!(picture.format, Some(SourceFormat::FullCif));
+        assert_eq!(picture.reference_picture_resampling, None);
+
+        // But a header that does state it's format is compared against
+        // instead, and changing from QCIF implicitly resamples
+        let header = Picture {
+            format: Some(SourceFormat::QuarterCif),
+            ..header
+        };
+        let picture = state
+            .parse_picture(&mut H263Reader::from_source(&data[..]), Some(&header))
+            .unwrap()
+            .unwrap();
+        assert_eq!(picture.reference_picture_resampling.unwrap().warps, None);
+
+        // Without a previous picture, there is nothing to compare against
+        let picture = state
+            .parse_picture(&mut H263Reader::from_source(&data[..]), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(picture.reference_picture_resampling, None);
+    }
+
+    #[test]
+    fn decode_status() {
+        let mut state = H263State::new(DecoderOption::empty());
+        state.set_reference_picture(0, subqcif_reference());
+
+        // Nothing at all
+        let mut reader = H263Reader::from_source(&[][..]);
+        assert_eq!(
+            state.decode_next_picture(&mut reader).unwrap(),
+            DecodeStatus::EndOfStream
+        );
+
+        // Sub-QCIF P-frame, TR 1, uncoded, then an EOS
+        let data = [
+            0x00, 0x00, 0x80, 0x06, 0x06, 0x08, 0x3F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xC0, 0x00,
+            0x00, 0xFC,
+        ];
+        let mut reader = H263Reader::from_source(&data[..]);
+        assert_eq!(
+            state.decode_next_picture(&mut reader).unwrap(),
+            DecodeStatus::Decoded
+        );
+        assert_eq!(
+            state.decode_next_picture(&mut reader).unwrap(),
+            DecodeStatus::EndOfStream
+        );
+        assert_eq!(
+            state.decode_next_picture(&mut reader).unwrap(),
+            DecodeStatus::EndOfStream
+        );
+
+        // The same P-frame, cut off partway through it's header
+        let mut reader = H263Reader::from_source(&data[..5]);
+        assert!(state
+            .decode_next_picture(&mut reader)
+            .unwrap_err()
+            .is_eof_error());
+    }
+
+    #[test]
+    fn temporal_scalability_references() {
+        let options = DecoderOption::USE_SCALABILITY_MODE;
+        let mut state = H263State::new(options);
+        state.set_reference_picture(0, subqcif_reference());
+        assert_eq!(
+            state
+                .get_b_picture_references()
+                .1
+                .map(|p| p.as_header().temporal_reference),
+            Some(0)
+        );
+        assert!(state.get_b_picture_references().0.is_none());
+
+        // Sub-QCIF base layer P-frame, TR 2, uncoded
+        let p_frame = [
+            0x00, 0x00, 0x80, 0x0A, 0x06, 0x02, 0x83, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFC,
+        ];
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&p_frame[..]))
+            .unwrap();
+
+        let references = |state: &H263State| {
+            let (forward, backward) = state.get_b_picture_references();
+
+            (
+                forward.map(|p| p.as_header().temporal_reference),
+                backward.map(|p| p.as_header().temporal_reference),
+            )
+        };
+        assert_eq!(references(&state), (Some(0), Some(2)));
+
+        // Sub-QCIF enhancement layer B-frame, TR 1, between the two
+        let b_frame = [
+            0x00, 0x00, 0x80, 0x06, 0x1C, 0x90, 0x01, 0x0C, 0x11, 0x0A, 0x1F, 0xFF, 0xFF, 0xFF,
+            0xFF, 0xFF, 0xE0,
+        ];
+        let mut reader = H263Reader::from_source(&b_frame[..]);
+        let header = state.parse_picture(&mut reader, None).unwrap().unwrap();
+        assert_eq!(header.picture_type, PictureTypeCode::BFrame);
+        assert!(header.picture_type.is_disposable());
+        assert_eq!(header.scalability_layer.unwrap().enhancement, 2);
+
+        // Decoding the B-frame leaves the references alone
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&b_frame[..]))
+            .unwrap();
+        assert_eq!(
+            state
+                .get_last_picture()
+                .unwrap()
+                .as_header()
+                .temporal_reference,
+            1
+        );
+        assert_eq!(references(&state), (Some(0), Some(2)));
+
+        // Sub-QCIF base layer P-frame, TR 4
+        let p_frame = [
+            0x00, 0x00, 0x80, 0x12, 0x06, 0x02, 0x83, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFC,
+        ];
+        state
+            .decode_next_picture(&mut H263Reader::from_source(&p_frame[..]))
+            .unwrap();
+        assert_eq!(references(&state), (Some(2), Some(4)));
+        assert_eq!(state.reference_states.len(), 2);
+    }
+
+    #[test]
+    fn temporal_scalability_b_picture() {
+        let options = DecoderOption::USE_SCALABILITY_MODE;
+        let mut state = H263State::new(options);
+
+        // Sub-QCIF base layer I-frame, TR 0, luma 40 + 20 * column, with two
+        // PSUPP bytes so that every macroblock row starts on a byte boundary
+        let mut i_frame = vec![0x00, 0x00, 0x80, 0x02, 0x04, 0x02, 0x84, 0x02, 0x00];
+        #[rustfmt::skip]
+        let mb_row = [
+            0x99, 0x41, 0x41, 0x41, 0x47, 0xFF, 0xFC, 0xCF, 0x0F, 0x0F, 0x0F, 0x3F, 0xFF, 0xE6,
+            0xA0, 0xA0, 0xA0, 0xA1, 0xFF, 0xFF, 0x36, 0x46, 0x46, 0x46, 0x4F, 0xFF, 0xF9, 0xBC,
+            0x3C, 0x3C, 0x3C, 0x7F, 0xFF, 0xCE, 0x32, 0x32, 0x32, 0x33, 0xFF, 0xFE, 0x74, 0x14,
+            0x14, 0x14, 0x1F, 0xFF, 0xF3, 0xB4, 0xB4, 0xB4, 0xB4, 0xFF, 0xFF,
+        ];
+        for _ in 0..6 {
+            i_frame.extend_from_slice(&mb_row);
+        }
+
+        // Sub-QCIF base layer P-frame, TR 2: MB0 moved by (4, 0) pixels, MB1 intra
+        // with luma 200, every other macroblock uncoded
+        #[rustfmt::skip]
+        let p_frame = [
+            0x00, 0x00, 0x80, 0x0A, 0x06, 0x02, 0x81, 0xC1, 0x68, 0x67, 0x91, 0x91, 0x91, 0x91,
+            0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xF8,
+        ];
+
+        // Sub-QCIF enhancement layer B-frame, TR 1: MB0 direct, MB1 forward,
+        // MB2 backward by (-16, 0) pixels, MB3 bidirectional with predicted
+        // vectors, MB4 intra with luma 30, every other macroblock direct
+        #[rustfmt::skip]
+        let b_frame = [
+            0x00, 0x00, 0x80, 0x06, 0x1C, 0x90, 0x01, 0x0C, 0x11, 0x0A, 0x14, 0xC8, 0x00, 0xB1,
+            0x3C, 0x08, 0xC7, 0x87, 0x87, 0x87, 0xBF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xF8,
+        ];
+
+        for data in [&i_frame[..], &p_frame[..], &b_frame[..]].iter() {
+            state
+                .decode_next_picture(&mut H263Reader::from_source(*data))
+                .unwrap();
+        }
+
+        let (forward, backward) = state.get_b_picture_references();
+        assert_eq!(forward.unwrap().as_header().temporal_reference, 0);
+        assert_eq!(backward.unwrap().as_header().temporal_reference, 2);
+
+        let picture = state.get_last_picture().unwrap();
+        assert_eq!(picture.as_header().temporal_reference, 1);
+
+        let luma = picture.as_luma();
+        let stride = picture.luma_samples_per_row();
+        for y in 0..96 {
+            for x in 0..128 {
+                let column = x / 16;
+                let expected = match (y / 16, column) {
+                    // Direct: forward (2, 0) from the I-frame averaged with
+                    // backward (-2, 0) from the P-frame's moved MB0
+                    (0, 0) if x >= 14 => 60,
+                    (0, 0) => 40,
+                    // Forward, from the I-frame
+                    (0, 1) => 60,
+                    // Backward, from the P-frame's intra MB1
+                    (0, 2) => 200,
+                    // Bidirectional, forward (0, 0) averaged with the
+                    // backward (-16, 0) predicted from MB2
+                    (0, 3) => 90,
+                    (0, 4) => 30,
+                    _ => 40 + 20 * column as u8,
+                };
+
+                assert_eq!(luma[y * stride + x], expected, "luma at ({}, {})", x, y);
+            }
+        }
+
+        assert!(picture.as_chroma_b().iter().all(|&s| s == 128));
+        assert!(picture.as_chroma_r().iter().all(|&s| s == 128));
+    }
+
+    /// A data source that only yields the data that has arrived so far.
+    struct Streamed<'a> {
+        data: &'a [u8],
+        position: usize,
+        arrived: &'a Cell<usize>,
+    }
+
+    impl Read for Streamed<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let mut available = &self.data[self.position..self.arrived.get()];
+            let count = available.read(buf)?;
+            self.position += count;
+
+            Ok(count)
+        }
+    }
+
+    #[test]
+    fn resume_after_more_data() {
+        // 16x16 I-frame, TR 0, flat luma
+        let data = [
+            0x00, 0x00, 0x80, 0x00, 0x08, 0x08, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20,
+        ];
+        let arrived = Cell::new(10);
+        let mut reader = H263Reader::from_source(Streamed {
+            data: &data,
+            position: 0,
+            arrived: &arrived,
+        });
+        let mut state = H263State::new(DecoderOption::SORENSON_SPARK_BITSTREAM);
+
+        // Running out of data partway through the first macroblock leaves
+        // the reader at the start of the picture, rather than partway into it
+        assert!(state
+            .decode_next_picture(&mut reader)
+            .unwrap_err()
+            .is_eof_error());
+        assert_eq!(reader.bit_position(), 0);
+        assert!(state.get_last_picture().is_none());
+
+        // Once the rest of the data arrives, the picture is decoded from the
+        // start
+        arrived.set(data.len());
+        assert_eq!(
+            state.decode_next_picture(&mut reader).unwrap(),
+            DecodeStatus::Decoded
+        );
+        assert_eq!(state.get_last_picture().unwrap().as_luma(), &[16; 256][..]);
+    }
+
+    #[test]
+    fn commit_parsed_macroblocks() {
+        // 32x16 I-frame, TR 0, flat luma, with the second macroblock
+        // starting at bit 111
+        let data = [
+            0x00, 0x00, 0x80, 0x00, 0x10, 0x08, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x21,
+            0x31, 0x01, 0x01, 0x01, 0x01, 0x01, 0x00,
+        ];
+        let arrived = Cell::new(17);
+        let mut reader = H263Reader::from_source(Streamed {
+            data: &data,
+            position: 0,
+            arrived: &arrived,
+        });
+        let mut state = H263State::new(DecoderOption::SORENSON_SPARK_BITSTREAM);
+
+        // The first macroblock is committed once it has been parsed, so
+        // running out of data in the third block of the second one leaves
+        // the reader there, and still reports the lack of data
+        assert!(state
+            .decode_next_picture(&mut reader)
+            .unwrap_err()
+            .is_eof_error());
+        assert_eq!(reader.bit_position(), 132);
+        assert!(state.get_last_picture().is_none());
     }
 }
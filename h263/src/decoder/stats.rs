@@ -0,0 +1,66 @@
+//! Decoding statistics
+
+use crate::types::{MacroblockType, PictureTypeCode};
+use std::collections::HashMap;
+
+/// Statistics about how the macroblocks of a picture were coded.
+///
+/// These are only collected if the decoder was constructed with
+/// `DecoderOption::COLLECT_MACROBLOCK_STATS`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// The number of bits each macroblock took up in the bitstream, in the
+    /// order they were decoded.
+    ///
+    /// This includes the macroblock header and all block data (including the
+    /// B blocks of a PB frame), but not stuffing, GOB headers, or the picture
+    /// header.
+    pub macroblock_bits: Vec<u64>,
+
+    /// How many coded macroblocks of each type were decoded.
+    pub macroblock_types: HashMap<MacroblockType, usize>,
+
+    /// How many macroblocks were not coded at all.
+    pub uncoded_macroblocks: usize,
+
+    /// How many macroblock stuffing codes were skipped over.
+    pub stuffing_macroblocks: usize,
+}
+
+impl FrameStats {
+    /// Get the total number of bits taken up by all macroblocks.
+    pub fn total_macroblock_bits(&self) -> u64 {
+        self.macroblock_bits.iter().sum()
+    }
+
+    /// Get how many coded macroblocks of a given type were decoded.
+    pub fn count_of(&self, mb_type: MacroblockType) -> usize {
+        self.macroblock_types.get(&mb_type).copied().unwrap_or(0)
+    }
+}
+
+/// An estimate of how expensive a picture will be to decode.
+///
+/// This is produced by `H263State::estimate_next_cost` from parsing the
+/// picture without reconstructing it. Every coded macroblock needs motion
+/// compensation or an IDCT, and every coded block an IDCT of more than just
+/// it's DC coefficient, so these counts are a reasonable proxy for how long
+/// reconstruction will take.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeCost {
+    /// The type of the picture.
+    pub picture_type: PictureTypeCode,
+
+    /// The width and height of the picture, in pixels.
+    pub dimensions: (u16, u16),
+
+    /// How many coded macroblocks the picture has.
+    pub coded_macroblocks: usize,
+
+    /// How many macroblocks were not coded at all.
+    pub uncoded_macroblocks: usize,
+
+    /// How many blocks have coefficients other than an intra DC, including
+    /// the B blocks of a PB frame.
+    pub coded_blocks: usize,
+}
@@ -2,17 +2,101 @@
 
 bitflags! {
     /// Options which influence the decoding of a bitstream.
-    #[derive(Copy, Clone)]
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
     pub struct DecoderOption : u8 {
         /// Attempt to decode the video as a Sorenson Spark bitstream.
         ///
         /// Sorenson Spark is a modified H.263 video format notably used in early
         /// iterations of Macromedia Flash Player. It was replaced with On2 VP6,
         /// and later on, standard H.264.
+        ///
+        /// Sorenson Spark pictures always consist of a single group of
+        /// blocks. Any start code found within a picture is taken to be the
+        /// start of the next picture, ending the current one early.
         const SORENSON_SPARK_BITSTREAM = 0b1;
 
         /// Whether or not the use of Annex O's Temporal, SNR, and Spatial
         /// Scalability mode has been negotiated.
         const USE_SCALABILITY_MODE = 0b10;
+
+        /// Reject bitstream constructs that are technically invalid, even if
+        /// they could be decoded anyway.
+        ///
+        /// Among other things, this validates the entire 22-bit picture start
+        /// code and the marker bits following it, rather than just the 17-bit
+        /// start code prefix. This makes it less likely for arbitrary binary
+        /// data to be mistaken for a picture, at the cost of refusing some
+        /// damaged pictures that would have otherwise partially decoded.
+        ///
+        /// Picture headers that combine their picture type with options that
+        /// the picture type cannot be used with are also rejected. See
+        /// `decode_picture` for the combinations that are checked.
+        const STRICT_MODE = 0b100;
+
+        /// Record how many bits each macroblock took up, and how many
+        /// macroblocks of each type there were, for every decoded picture.
+        ///
+        /// The statistics for the last decoded picture can be retrieved with
+        /// `H263State::get_last_frame_stats`. The motion vectors of each
+        /// macroblock are also kept on the decoded picture itself, see
+        /// `DecodedPicture::motion_vectors`.
+        const COLLECT_MACROBLOCK_STATS = 0b1000;
+
+        /// Reconstruct each macroblock as soon as it has been decoded, rather
+        /// than once the whole picture has been.
+        ///
+        /// This keeps only one macroblock's worth of transform coefficients
+        /// around at any time, instead of an entire picture's. It greatly
+        /// reduces memory use for large pictures, at the cost of some speed.
+        /// (The B pictures of PB frames still need the entire picture's
+        /// coefficients, and pictures using Advanced Prediction are still
+        /// reconstructed a row of macroblocks at a time.)
+        const STREAMING_IDCT = 0b10000;
+
+        /// Salvage as much of a damaged picture as possible, rather than
+        /// rejecting it.
+        ///
+        /// Currently, a picture with a corrupt chroma block is decoded with
+        /// it's chroma replaced by neutral gray, as long as it's luma could
+        /// be decoded. The picture, and any picture predicted from it up until
+        /// the next I-frame, is marked as such; see
+        /// `DecodedPicture::is_chroma_degraded`.
+        ///
+        /// A macroblock that starts with a run of 16 or more zero bits, which
+        /// H.263 only allows within start codes, is taken as a sign of
+        /// corruption: the decoder skips ahead to the next start code instead
+        /// of giving up on the rest of the picture.
+        const LENIENT = 0b100000;
+
+        /// Apply the deblocking filter within the decoding loop.
+        ///
+        /// Pictures that request deblocking, whether through
+        /// `PictureOption::USE_DEBLOCKER` or
+        /// `PictureOption::DEBLOCKING_FILTER`, have all three planes filtered
+        /// with the strength matching their picture quantizer as soon as they
+        /// are reconstructed. The filtered picture is the one kept for
+        /// reference, so later pictures are predicted from it. Rows reported
+        /// by progressive decoding are still unfiltered.
+        ///
+        /// `decode_and_deblock_and_convert` does not filter such pictures a
+        /// second time. This option is only available with the `pipeline`
+        /// feature.
+        #[cfg(feature = "pipeline")]
+        const IN_LOOP_DEBLOCK = 0b1000000;
     }
 }
+
+/// The outcome of successfully decoding from a bitstream.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecodeStatus {
+    /// A picture was decoded. More pictures may follow it.
+    Decoded,
+
+    /// The bitstream ended normally, before the start of another picture.
+    ///
+    /// This is only reported if there are no start codes left in the
+    /// bitstream at all, other than (optionally) a final end of sequence
+    /// code. Bitstreams that end partway through a picture still yield an
+    /// end-of-data error; see `Error::is_eof_error`.
+    EndOfStream,
+}
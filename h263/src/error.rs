@@ -35,12 +35,35 @@ pub enum Error {
     #[error("the H.263 bitstream has an invalid extension picture type")]
     InvalidPlusPType,
 
+    #[error("the H.263 bitstream has a picture type that is incompatible with it's options")]
+    IncompatiblePictureType,
+
     #[error("the H.263 bitstream has an invalid group-of-blocks header")]
     InvalidGobHeader,
 
+    #[error("the Sorenson Spark bitstream has an unknown version {0}")]
+    UnknownSorensonVersion(u8),
+
+    #[error("the H.263 bitstream has a group-of-blocks from a different sub-bitstream than it's picture")]
+    GobSubBitstreamMismatch,
+
+    #[deprecated = "use `InvalidSyntax` or `InvalidSemantics` instead"]
     #[error("the H.263 bitstream could not be decoded")]
     InvalidBitstream,
 
+    /// The bitstream does not follow the H.263 syntax, e.g. it contains codes
+    /// that are not in any VLC table, or marker bits that are not set.
+    ///
+    /// Decoding may be able to resume at the next start code.
+    #[error("the H.263 bitstream does not follow the bitstream syntax")]
+    InvalidSyntax,
+
+    /// The bitstream follows the H.263 syntax, but the values it contains
+    /// cannot be reconstructed into a picture, e.g. a motion vector points
+    /// out of range.
+    #[error("the H.263 bitstream contains values that cannot be reconstructed")]
+    InvalidSemantics,
+
     #[error("the decoded H.263 bitstream is missing it's picture format")]
     PictureFormatMissing,
 
@@ -53,8 +76,16 @@ pub enum Error {
     #[error("an I/O error occured: {0}")]
     UnhandledIoError(IoError),
 
+    #[error("the requested picture, or an I-frame before it, is not in the bitstream index")]
+    PictureNotIndexed,
+
     #[error("a feature in the H.263 bitstream being decoded is not yet supported")]
     UnimplementedDecoding,
+
+    /// The reader still holds data it has read out of it's source, but not
+    /// yet consumed, which would be lost along with it.
+    #[error("the H.263 reader has buffered data that was not consumed")]
+    UncommittedData,
 }
 
 impl From<IoError> for Error {
@@ -84,11 +115,24 @@ impl Error {
             || matches!(self, Error::InvalidMacroblockCodedBits)
     }
 
+    /// Determines if this is an error caused by block parsing.
+    ///
+    /// Block parsing errors can only be recovered from in lenient mode.
+    pub fn is_block_error(&self) -> bool {
+        matches!(
+            self,
+            Error::InvalidIntraDc | Error::InvalidShortCoefficient | Error::InvalidLongCoefficient
+        )
+    }
+
     /// Determines if this is an error caused by GOB parsing.
     ///
     /// GOB parsing errors end the current picture.
     pub fn is_gob_error(&self) -> bool {
-        matches!(self, Error::InvalidGobHeader)
+        matches!(
+            self,
+            Error::InvalidGobHeader | Error::GobSubBitstreamMismatch
+        )
     }
 }
 
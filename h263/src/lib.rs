@@ -6,12 +6,28 @@ extern crate bitflags;
 #[macro_use]
 extern crate lazy_static;
 
+#[cfg(feature = "log")]
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+mod macros;
+
 mod decoder;
 mod error;
 pub mod parser;
+pub mod sorenson;
 mod traits;
 mod types;
 
-pub use decoder::{DecoderOption, H263State};
+pub use decoder::{
+    dequantize, dequantize_advanced_intra, DecodeCost, DecodeStatus, DecodedPicture, DecoderOption,
+    FrameStats, H263State, YuvPlane,
+};
 pub use error::{Error, Result};
-pub use types::{PictureOption, PictureTypeCode};
+pub use types::{
+    BackchannelMessage, BackchannelMessageType, BackchannelReliability, Block, BlockBuilder,
+    BlockScan, CustomPictureClock, IntraDc, MacroblockType, Picture, PictureOption,
+    PictureTypeCode, ReferencePictureResampling, SourceFormat, TCoefficient,
+    WarpingDisplacementAccuracy, WarpingFillMode,
+};
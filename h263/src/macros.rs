@@ -0,0 +1,34 @@
+//! Logging macros
+//!
+//! With the `log` feature enabled, the `log` crate's macros are used directly.
+//! Otherwise, these stand-ins type-check their arguments and then discard
+//! them, so that instrumentation costs nothing.
+
+#[cfg(not(feature = "log"))]
+macro_rules! log_noop {
+    ($($arg:tt)+) => {
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! trace {
+    ($($arg:tt)+) => { log_noop!($($arg)+) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! debug {
+    ($($arg:tt)+) => { log_noop!($($arg)+) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! warn {
+    ($($arg:tt)+) => { log_noop!($($arg)+) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! error {
+    ($($arg:tt)+) => { log_noop!($($arg)+) };
+}
@@ -2,13 +2,21 @@
 
 mod block;
 mod gob;
+mod index;
 mod macroblock;
 mod picture;
 mod reader;
+mod structure;
 mod vlc;
 
 pub use block::decode_block;
 pub use gob::decode_gob;
+pub use index::{
+    index_stream, scan_bitstream_index, BitstreamIndex, BitstreamIndexEntry, FrameEntry,
+};
 pub use macroblock::decode_macroblock;
-pub use picture::decode_picture;
+pub(crate) use picture::decode_sorenson_ptype;
+pub use picture::{decode_end_of_sequence, decode_picture, decode_picture_with_previous_format};
 pub use reader::H263Reader;
+pub(crate) use structure::dump_picture_data;
+pub use structure::{dump_structure, GobNode, MacroblockNode, PictureNode, StreamTree};
@@ -654,6 +654,742 @@ const TCOEF_TABLE: [Entry<Option<ShortTCoefficient>>; 207] = [
     End(None),  //000000000x, slot 206
 ];
 
+/// The table of TCOEF values for intra blocks under Advanced Intra Coding
+/// (Annex I), and for inter blocks that don't fit the usual table under
+/// Alternative Inter VLC (Annex S).
+///
+/// ITU-T Recommendation H.263 (01/2005) Table I.2. It has the same codes as
+/// `TCOEF_TABLE`, including `ESCAPE`, and thus the same shape; only the
+/// `Run` each code stands for differs.
+const ADVANCED_INTRA_TCOEF_TABLE: [Entry<Option<ShortTCoefficient>>; 207] = [
+    Fork(8, 1), //x, slot 0
+    Fork(2, 3), //1x, slot 1
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 1,
+    })), //10, slot 2
+    Fork(4, 5), //11x, slot 3
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 2,
+    })), //110, slot 4
+    Fork(6, 7), //111x, slot 5
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 3,
+    })), //1110, slot 6
+    End(Some(Run {
+        last: false,
+        run: 1,
+        level: 1,
+    })), //1111, slot 7
+    Fork(28, 9), //0x, slot 8
+    Fork(15, 10), //01x, slot 9
+    Fork(12, 11), //011x, slot 10
+    End(Some(Run {
+        last: true,
+        run: 0,
+        level: 1,
+    })), //0111, slot 11
+    Fork(13, 14), //0110x, slot 12
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 4,
+    })), //01100, slot 13
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 5,
+    })), //01101, slot 14
+    Fork(16, 23), //010x, slot 15
+    Fork(17, 20), //0100x, slot 16
+    Fork(18, 19), //01000x, slot 17
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 6,
+    })), //010000, slot 18
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 7,
+    })), //010001, slot 19
+    Fork(21, 22), //01001x, slot 20
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 8,
+    })), //010010, slot 21
+    End(Some(Run {
+        last: false,
+        run: 4,
+        level: 1,
+    })), //010011, slot 22
+    Fork(25, 24), //0101x, slot 23
+    End(Some(Run {
+        last: false,
+        run: 2,
+        level: 1,
+    })), //01011, slot 24
+    Fork(26, 27), //01010x, slot 25
+    End(Some(Run {
+        last: false,
+        run: 1,
+        level: 2,
+    })), //010100, slot 26
+    End(Some(Run {
+        last: false,
+        run: 3,
+        level: 1,
+    })), //010101, slot 27
+    Fork(52, 29), //00x, slot 28
+    Fork(37, 30), //001x, slot 29
+    Fork(31, 34), //0011x, slot 30
+    Fork(32, 33), //00110x, slot 31
+    End(Some(Run {
+        last: true,
+        run: 0,
+        level: 2,
+    })), //001100, slot 32
+    End(Some(Run {
+        last: true,
+        run: 3,
+        level: 1,
+    })), //001101, slot 33
+    Fork(35, 36), //00111x, slot 34
+    End(Some(Run {
+        last: true,
+        run: 2,
+        level: 1,
+    })), //001110, slot 35
+    End(Some(Run {
+        last: true,
+        run: 1,
+        level: 1,
+    })), //001111, slot 36
+    Fork(38, 45), //0010x, slot 37
+    Fork(39, 42), //00100x, slot 38
+    Fork(40, 41), //001000x, slot 39
+    End(Some(Run {
+        last: true,
+        run: 0,
+        level: 3,
+    })), //0010000, slot 40
+    End(Some(Run {
+        last: true,
+        run: 4,
+        level: 1,
+    })), //0010001, slot 41
+    Fork(43, 44), //001001x, slot 42
+    End(Some(Run {
+        last: true,
+        run: 6,
+        level: 1,
+    })), //0010010, slot 43
+    End(Some(Run {
+        last: true,
+        run: 5,
+        level: 1,
+    })), //0010011, slot 44
+    Fork(46, 49), //00101x, slot 45
+    Fork(47, 48), //001010x, slot 46
+    End(Some(Run {
+        last: false,
+        run: 1,
+        level: 3,
+    })), //0010100, slot 47
+    End(Some(Run {
+        last: false,
+        run: 2,
+        level: 2,
+    })), //0010101, slot 48
+    Fork(50, 51), //001011x, slot 49
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 9,
+    })), //0010110, slot 50
+    End(Some(Run {
+        last: false,
+        run: 5,
+        level: 1,
+    })), //0010111, slot 51
+    Fork(90, 53), //000x, slot 52
+    Fork(69, 54), //0001x, slot 53
+    Fork(55, 62), //00011x, slot 54
+    Fork(56, 59), //000110x, slot 55
+    Fork(57, 58), //0001100x, slot 56
+    End(Some(Run {
+        last: true,
+        run: 11,
+        level: 1,
+    })), //00011000, slot 57
+    End(Some(Run {
+        last: true,
+        run: 10,
+        level: 1,
+    })), //00011001, slot 58
+    Fork(60, 61), //0001101x, slot 59
+    End(Some(Run {
+        last: true,
+        run: 9,
+        level: 1,
+    })), //00011010, slot 60
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 10,
+    })), //00011011, slot 61
+    Fork(63, 66), //000111x, slot 62
+    Fork(64, 65), //0001110x, slot 63
+    End(Some(Run {
+        last: false,
+        run: 6,
+        level: 1,
+    })), //00011100, slot 64
+    End(Some(Run {
+        last: false,
+        run: 3,
+        level: 2,
+    })), //00011101, slot 65
+    Fork(67, 68), //0001111x, slot 66
+    End(Some(Run {
+        last: false,
+        run: 1,
+        level: 4,
+    })), //00011110, slot 67
+    End(Some(Run {
+        last: false,
+        run: 7,
+        level: 1,
+    })), //00011111, slot 68
+    Fork(77, 70), //00010x, slot 69
+    Fork(71, 74), //000101x, slot 70
+    Fork(72, 73), //0001010x, slot 71
+    End(Some(Run {
+        last: true,
+        run: 7,
+        level: 1,
+    })), //00010100, slot 72
+    End(Some(Run {
+        last: true,
+        run: 8,
+        level: 1,
+    })), //00010101, slot 73
+    Fork(75, 76), //0001011x, slot 74
+    End(Some(Run {
+        last: true,
+        run: 13,
+        level: 1,
+    })), //00010110, slot 75
+    End(Some(Run {
+        last: true,
+        run: 12,
+        level: 1,
+    })), //00010111, slot 76
+    Fork(78, 85), //000100x, slot 77
+    Fork(79, 82), //0001000x, slot 78
+    Fork(80, 81), //00010000x, slot 79
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 11,
+    })), //000100000, slot 80
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 12,
+    })), //000100001, slot 81
+    Fork(83, 84), //00010001x, slot 82
+    End(Some(Run {
+        last: false,
+        run: 5,
+        level: 2,
+    })), //000100010, slot 83
+    End(Some(Run {
+        last: false,
+        run: 4,
+        level: 2,
+    })), //000100011, slot 84
+    Fork(86, 89), //0001001x, slot 85
+    Fork(87, 88), //00010010x, slot 86
+    End(Some(Run {
+        last: false,
+        run: 9,
+        level: 1,
+    })), //000100100, slot 87
+    End(Some(Run {
+        last: false,
+        run: 8,
+        level: 1,
+    })), //000100101, slot 88
+    End(Some(Run {
+        last: true,
+        run: 0,
+        level: 4,
+    })), //00010011, slot 89
+    Fork(124, 91), //0000x, slot 90
+    Fork(92, 109), //00001x, slot 91
+    Fork(93, 102), //000010x, slot 92
+    Fork(94, 99), //0000100x, slot 93
+    Fork(95, 98), //00001000x, slot 94
+    Fork(96, 97), //000010000x, slot 95
+    End(Some(Run {
+        last: false,
+        run: 11,
+        level: 1,
+    })), //0000100000, slot 96
+    End(Some(Run {
+        last: false,
+        run: 10,
+        level: 1,
+    })), //0000100001, slot 97
+    End(Some(Run {
+        last: true,
+        run: 0,
+        level: 5,
+    })), //000010001, slot 98
+    Fork(100, 101), //00001001x, slot 99
+    End(Some(Run {
+        last: true,
+        run: 0,
+        level: 6,
+    })), //000010010, slot 100
+    End(Some(Run {
+        last: true,
+        run: 1,
+        level: 2,
+    })), //000010011, slot 101
+    Fork(103, 106), //0000101x, slot 102
+    Fork(104, 105), //00001010x, slot 103
+    End(Some(Run {
+        last: true,
+        run: 2,
+        level: 2,
+    })), //000010100, slot 104
+    End(Some(Run {
+        last: true,
+        run: 15,
+        level: 1,
+    })), //000010101, slot 105
+    Fork(107, 108), //00001011x, slot 106
+    End(Some(Run {
+        last: true,
+        run: 16,
+        level: 1,
+    })), //000010110, slot 107
+    End(Some(Run {
+        last: true,
+        run: 18,
+        level: 1,
+    })), //000010111, slot 108
+    Fork(110, 117), //000011x, slot 109
+    Fork(111, 114), //0000110x, slot 110
+    Fork(112, 113), //00001100x, slot 111
+    End(Some(Run {
+        last: true,
+        run: 17,
+        level: 1,
+    })), //000011000, slot 112
+    End(Some(Run {
+        last: true,
+        run: 14,
+        level: 1,
+    })), //000011001, slot 113
+    Fork(115, 116), //00001101x, slot 114
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 13,
+    })), //000011010, slot 115
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 14,
+    })), //000011011, slot 116
+    Fork(118, 121), //0000111x, slot 117
+    Fork(119, 120), //00001110x, slot 118
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 15,
+    })), //000011100, slot 119
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 16,
+    })), //000011101, slot 120
+    Fork(122, 123), //00001111x, slot 121
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 17,
+    })), //000011110, slot 122
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 18,
+    })), //000011111, slot 123
+    Fork(174, 125), //00000x, slot 124
+    Fork(127, 126), //000001x, slot 125
+    End(Some(EscapeToLong)), //0000011, slot 126
+    Fork(128, 143), //0000010x, slot 127
+    Fork(129, 136), //00000100x, slot 128
+    Fork(130, 133), //000001000x, slot 129
+    Fork(131, 132), //0000010000x, slot 130
+    End(Some(Run {
+        last: false,
+        run: 13,
+        level: 1,
+    })), //00000100000, slot 131
+    End(Some(Run {
+        last: false,
+        run: 1,
+        level: 6,
+    })), //00000100001, slot 132
+    Fork(134, 135), //0000010001x, slot 133
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 20,
+    })), //00000100010, slot 134
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 19,
+    })), //00000100011, slot 135
+    Fork(137, 140), //000001001x, slot 136
+    Fork(138, 139), //0000010010x, slot 137
+    End(Some(Run {
+        last: true,
+        run: 2,
+        level: 3,
+    })), //00000100100, slot 138
+    End(Some(Run {
+        last: true,
+        run: 1,
+        level: 4,
+    })), //00000100101, slot 139
+    Fork(141, 142), //0000010011x, slot 140
+    End(Some(Run {
+        last: true,
+        run: 0,
+        level: 9,
+    })), //00000100110, slot 141
+    End(Some(Run {
+        last: true,
+        run: 0,
+        level: 8,
+    })), //00000100111, slot 142
+    Fork(144, 159), //00000101x, slot 143
+    Fork(145, 152), //000001010x, slot 144
+    Fork(146, 149), //0000010100x, slot 145
+    Fork(147, 148), //00000101000x, slot 146
+    End(Some(Run {
+        last: false,
+        run: 1,
+        level: 7,
+    })), //000001010000, slot 147
+    End(Some(Run {
+        last: false,
+        run: 3,
+        level: 4,
+    })), //000001010001, slot 148
+    Fork(150, 151), //00000101001x, slot 149
+    End(Some(Run {
+        last: false,
+        run: 5,
+        level: 3,
+    })), //000001010010, slot 150
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 25,
+    })), //000001010011, slot 151
+    Fork(153, 156), //0000010101x, slot 152
+    Fork(154, 155), //00000101010x, slot 153
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 24,
+    })), //000001010100, slot 154
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 23,
+    })), //000001010101, slot 155
+    Fork(157, 158), //00000101011x, slot 156
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 22,
+    })), //000001010110, slot 157
+    End(Some(Run {
+        last: false,
+        run: 0,
+        level: 21,
+    })), //000001010111, slot 158
+    Fork(160, 167), //000001011x, slot 159
+    Fork(161, 164), //0000010110x, slot 160
+    Fork(162, 163), //00000101100x, slot 161
+    End(Some(Run {
+        last: true,
+        run: 21,
+        level: 1,
+    })), //000001011000, slot 162
+    End(Some(Run {
+        last: true,
+        run: 22,
+        level: 1,
+    })), //000001011001, slot 163
+    Fork(165, 166), //00000101101x, slot 164
+    End(Some(Run {
+        last: true,
+        run: 23,
+        level: 1,
+    })), //000001011010, slot 165
+    End(Some(Run {
+        last: true,
+        run: 7,
+        level: 2,
+    })), //000001011011, slot 166
+    Fork(168, 171), //0000010111x, slot 167
+    Fork(169, 170), //00000101110x, slot 168
+    End(Some(Run {
+        last: true,
+        run: 6,
+        level: 2,
+    })), //000001011100, slot 169
+    End(Some(Run {
+        last: true,
+        run: 5,
+        level: 2,
+    })), //000001011101, slot 170
+    Fork(172, 173), //00000101111x, slot 171
+    End(Some(Run {
+        last: true,
+        run: 3,
+        level: 3,
+    })), //000001011110, slot 172
+    End(Some(Run {
+        last: true,
+        run: 0,
+        level: 10,
+    })), //000001011111, slot 173
+    Fork(190, 175), //000000x, slot 174
+    Fork(176, 183), //0000001x, slot 175
+    Fork(177, 180), //00000010x, slot 176
+    Fork(178, 179), //000000100x, slot 177
+    End(Some(Run {
+        last: false,
+        run: 12,
+        level: 1,
+    })), //0000001000, slot 178
+    End(Some(Run {
+        last: false,
+        run: 2,
+        level: 4,
+    })), //0000001001, slot 179
+    Fork(181, 182), //000000101x, slot 180
+    End(Some(Run {
+        last: false,
+        run: 8,
+        level: 2,
+    })), //0000001010, slot 181
+    End(Some(Run {
+        last: false,
+        run: 7,
+        level: 2,
+    })), //0000001011, slot 182
+    Fork(184, 187), //00000011x, slot 183
+    Fork(185, 186), //000000110x, slot 184
+    End(Some(Run {
+        last: false,
+        run: 6,
+        level: 2,
+    })), //0000001100, slot 185
+    End(Some(Run {
+        last: false,
+        run: 3,
+        level: 3,
+    })), //0000001101, slot 186
+    Fork(188, 189), //000000111x, slot 187
+    End(Some(Run {
+        last: false,
+        run: 2,
+        level: 3,
+    })), //0000001110, slot 188
+    End(Some(Run {
+        last: false,
+        run: 1,
+        level: 5,
+    })), //0000001111, slot 189
+    Fork(198, 191), //0000000x, slot 190
+    Fork(192, 195), //00000001x, slot 191
+    Fork(193, 194), //000000010x, slot 192
+    End(Some(Run {
+        last: true,
+        run: 0,
+        level: 7,
+    })), //0000000100, slot 193
+    End(Some(Run {
+        last: true,
+        run: 1,
+        level: 3,
+    })), //0000000101, slot 194
+    Fork(196, 197), //000000011x, slot 195
+    End(Some(Run {
+        last: true,
+        run: 3,
+        level: 2,
+    })), //0000000110, slot 196
+    End(Some(Run {
+        last: true,
+        run: 4,
+        level: 2,
+    })), //0000000111, slot 197
+    Fork(206, 199), //00000000x, slot 198
+    Fork(200, 203), //000000001x, slot 199
+    Fork(201, 202), //0000000010x, slot 200
+    End(Some(Run {
+        last: true,
+        run: 19,
+        level: 1,
+    })), //00000000100, slot 201
+    End(Some(Run {
+        last: true,
+        run: 20,
+        level: 1,
+    })), //00000000101, slot 202
+    Fork(204, 205), //0000000011x, slot 203
+    End(Some(Run {
+        last: false,
+        run: 9,
+        level: 2,
+    })), //00000000110, slot 204
+    End(Some(Run {
+        last: false,
+        run: 4,
+        level: 3,
+    })), //00000000111, slot 205
+    End(None),  //000000000x, slot 206
+];
+
+/// The VLC tables that a block's `TCOEF`s may be coded with.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum TCoefTable {
+    /// ITU-T Recommendation H.263 (01/2005) Table 16, used by default.
+    Standard,
+
+    /// ITU-T Recommendation H.263 (01/2005) Table I.2, used by intra blocks
+    /// under Advanced Intra Coding (Annex I), and by some inter blocks under
+    /// Alternative Inter VLC (Annex S).
+    AdvancedIntra,
+}
+
+/// Decode the `TCOEF`s of a block from the bitstream, up to and including
+/// the one flagged as the last, using the given VLC `table`.
+fn decode_tcoef<R>(
+    reader: &mut H263Reader<R>,
+    decoder_options: DecoderOption,
+    picture: &Picture,
+    running_options: PictureOption,
+    table: TCoefTable,
+) -> Result<Vec<TCoefficient>>
+where
+    R: Read,
+{
+    let table = match table {
+        TCoefTable::Standard => &TCOEF_TABLE[..],
+        TCoefTable::AdvancedIntra => &ADVANCED_INTRA_TCOEF_TABLE[..],
+    };
+
+    let mut tcoef = Vec::new();
+    let mut is_last = false;
+    while !is_last {
+        let short_tcoef = reader.read_vlc(table)?;
+
+        match short_tcoef.ok_or(Error::InvalidShortCoefficient)? {
+            EscapeToLong => {
+                //Sorenson version 0 uses the standard H.263 escape;
+                //version 1 adds a bit selecting between short (7-bit)
+                //and long (11-bit) levels.
+                let level_width =
+                    if decoder_options.contains(DecoderOption::SORENSON_SPARK_BITSTREAM) {
+                        match picture.version {
+                            Some(0) => 8,
+                            Some(1) if reader.read_bits::<u8>(1)? == 1 => 11,
+                            Some(1) => 7,
+                            Some(version) => return Err(Error::UnknownSorensonVersion(version)),
+                            None => return Err(Error::InternalDecoderError),
+                        }
+                    } else {
+                        8
+                    };
+
+                let last = reader.read_bits::<u8>(1)? == 1;
+                let run: u8 = reader.read_bits(6)?;
+                let mut level = reader.read_signed_bits(level_width)?;
+
+                if level == 0 {
+                    return Err(Error::InvalidLongCoefficient);
+                }
+
+                //H.263 Annex T: a level of -128 is forbidden, unless
+                //modified quantization uses it to escape to an 11-bit
+                //EXTENDED-LEVEL, sent as 5 LSBs and then 6 signed MSBs.
+                if level == -128
+                    && !decoder_options.contains(DecoderOption::SORENSON_SPARK_BITSTREAM)
+                {
+                    if running_options.contains(PictureOption::MODIFIED_QUANTIZATION) {
+                        let lsbs: i16 = reader.read_bits(5)?;
+                        let msbs: i16 = reader.read_signed_bits(6)?;
+
+                        level = (msbs << 5) | lsbs;
+                        if level == 0 {
+                            return Err(Error::InvalidLongCoefficient);
+                        }
+                    } else {
+                        return Err(Error::InvalidLongCoefficient);
+                    }
+                }
+
+                tcoef.push(TCoefficient {
+                    is_short: false,
+                    run,
+                    level,
+                });
+
+                is_last = last;
+            }
+            Run { last, run, level } => {
+                let sign: u8 = reader.read_bits(1)?;
+                if sign == 0 {
+                    tcoef.push(TCoefficient {
+                        is_short: true,
+                        run,
+                        level: level as i16,
+                    })
+                } else {
+                    tcoef.push(TCoefficient {
+                        is_short: true,
+                        run,
+                        level: -(level as i16),
+                    })
+                }
+
+                is_last = last;
+            }
+        };
+    }
+
+    Ok(tcoef)
+}
+
 /// Decode a block from the bitstream.
 ///
 /// The `running_options` should be the set of currently in-force options
@@ -667,88 +1403,73 @@ const TCOEF_TABLE: [Entry<Option<ShortTCoefficient>>; 207] = [
 /// `tcoef_present` should be flagged if the particular block being decoded is
 /// flagged in the corresponding macroblock's `CodedBlockPattern` as having
 /// transform coefficients.
+///
+/// Intra blocks in pictures using Advanced Intra Coding (Annex I) do not have
+/// an `INTRADC`: their DC coefficient is coded as the first `TCOEF`, and all
+/// of their `TCOEF`s use the alternative intra VLC of table I.2/H.263.
+/// Uncoded blocks of those macroblocks carry no bits at all, and are returned
+/// without any coefficients; all of them are predicted from the neighbouring
+/// blocks in the direction given by the macroblock's `INTRA_MODE`.
+///
+/// Under Alternative Inter VLC (Annex S), inter blocks may instead be coded
+/// with that same table I.2/H.263, which is only signalled by the block not
+/// fitting into 64 coefficients when read with the usual `TCOEF` table. Such
+/// blocks are read again from the start with table I.2/H.263.
 pub fn decode_block<R>(
     reader: &mut H263Reader<R>,
     decoder_options: DecoderOption,
     picture: &Picture,
     running_options: PictureOption,
     macroblock_type: MacroblockType,
-    mut tcoef_present: bool,
+    tcoef_present: bool,
 ) -> Result<Block>
 where
     R: Read,
 {
     reader.with_transaction(|reader| {
-        let intradc = if macroblock_type.is_intra() {
+        let is_advanced_intra = macroblock_type.is_intra()
+            && running_options.contains(PictureOption::ADVANCED_INTRA_CODING);
+
+        let intradc = if macroblock_type.is_intra() && !is_advanced_intra {
             Some(IntraDc::from_u8(reader.read_u8()?).ok_or(Error::InvalidIntraDc)?)
         } else {
             None
         };
 
-        let mut tcoef = Vec::new();
-        while tcoef_present {
-            let short_tcoef = reader.read_vlc(&TCOEF_TABLE[..])?;
-
-            match short_tcoef.ok_or(Error::InvalidShortCoefficient)? {
-                EscapeToLong => {
-                    let level_width = if decoder_options
-                        .contains(DecoderOption::SORENSON_SPARK_BITSTREAM)
-                        && picture.version == Some(1)
-                    {
-                        if reader.read_bits::<u8>(1)? == 1 {
-                            11
-                        } else {
-                            7
-                        }
-                    } else {
-                        8
-                    };
-
-                    let last = reader.read_bits::<u8>(1)? == 1;
-                    let run: u8 = reader.read_bits(6)?;
-                    let level = reader.read_signed_bits(level_width)?;
-
-                    if level == 0 {
-                        return Err(Error::InvalidLongCoefficient);
-                    }
+        let table = if is_advanced_intra {
+            TCoefTable::AdvancedIntra
+        } else {
+            TCoefTable::Standard
+        };
 
-                    //TODO: Modified Quantization (Annex T)
-                    if level == i16::MAX << level_width {
-                        if running_options.contains(PictureOption::MODIFIED_QUANTIZATION) {
-                            return Err(Error::UnimplementedDecoding);
-                        } else {
-                            return Err(Error::InvalidLongCoefficient);
-                        }
-                    }
+        let is_alternative_inter = macroblock_type.is_inter()
+            && running_options.contains(PictureOption::ALTERNATIVE_INTER_VLC);
 
-                    tcoef.push(TCoefficient {
-                        is_short: false,
-                        run,
-                        level,
-                    });
+        let tcoef = if !tcoef_present {
+            Vec::new()
+        } else if is_alternative_inter {
+            //H.263 S.3: A block that would hold more than 64 coefficients
+            //when read with the usual table was coded with table I.2.
+            let standard = reader.with_transaction_union(|reader| {
+                let tcoef = decode_tcoef(reader, decoder_options, picture, running_options, table)?;
+                let coefficients: usize = tcoef.iter().map(|t| t.run as usize + 1).sum();
 
-                    tcoef_present = !last;
-                }
-                Run { last, run, level } => {
-                    let sign: u8 = reader.read_bits(1)?;
-                    if sign == 0 {
-                        tcoef.push(TCoefficient {
-                            is_short: true,
-                            run,
-                            level: level as i16,
-                        })
-                    } else {
-                        tcoef.push(TCoefficient {
-                            is_short: true,
-                            run,
-                            level: -(level as i16),
-                        })
-                    }
+                Ok(Some(tcoef).filter(|_| coefficients <= 64))
+            })?;
 
-                    tcoef_present = !last;
-                }
-            };
-        }
+            match standard {
+                Some(tcoef) => tcoef,
+                None => decode_tcoef(
+                    reader,
+                    decoder_options,
+                    picture,
+                    running_options,
+                    TCoefTable::AdvancedIntra,
+                )?,
+            }
+        } else {
+            decode_tcoef(reader, decoder_options, picture, running_options, table)?
+        };
 
         Ok(Block { intradc, tcoef })
     })
@@ -757,8 +1478,12 @@ where
 #[cfg(test)]
 mod tests {
     use crate::decoder::DecoderOption;
-    use crate::parser::block::{decode_block, ShortTCoefficient, TCOEF_TABLE};
+    use crate::error::Error;
+    use crate::parser::block::{
+        decode_block, ShortTCoefficient, ADVANCED_INTRA_TCOEF_TABLE, TCOEF_TABLE,
+    };
     use crate::parser::reader::H263Reader;
+    use crate::parser::vlc::Entry;
     use crate::types::{
         Block, IntraDc, MacroblockType, Picture, PictureOption, PictureTypeCode, TCoefficient,
     };
@@ -1704,6 +2429,57 @@ mod tests {
         );
     }
 
+    #[test]
+    #[allow(clippy::inconsistent_digit_grouping)]
+    fn advanced_intra_tcoef_table() {
+        let bit_pattern = &[0b110_1111_0, 0b111_00110, 0b0_0000011];
+        let run = |last, run, level| Some(ShortTCoefficient::Run { last, run, level });
+
+        //The same codes stand for different runs and levels in each table,
+        //except for some that happen to agree, and the escape.
+        for (table, expected) in [
+            (
+                &TCOEF_TABLE[..],
+                [
+                    run(false, 1, 1),
+                    run(false, 0, 2),
+                    run(true, 0, 1),
+                    run(true, 4, 1),
+                ],
+            ),
+            (
+                &ADVANCED_INTRA_TCOEF_TABLE[..],
+                [
+                    run(false, 0, 2),
+                    run(false, 1, 1),
+                    run(true, 0, 1),
+                    run(true, 0, 2),
+                ],
+            ),
+        ] {
+            let mut reader = H263Reader::from_source(&bit_pattern[..]);
+            for expected in expected {
+                assert_eq!(reader.read_vlc(table).unwrap(), expected);
+            }
+            assert_eq!(
+                reader.read_vlc(table).unwrap(),
+                Some(ShortTCoefficient::EscapeToLong)
+            );
+        }
+
+        //Both tables have the same codes, and so the same shape.
+        for (standard, advanced_intra) in TCOEF_TABLE.iter().zip(ADVANCED_INTRA_TCOEF_TABLE.iter())
+        {
+            match (standard, advanced_intra) {
+                (Entry::Fork(..), Entry::Fork(..)) => assert_eq!(standard, advanced_intra),
+                (Entry::End(standard), Entry::End(advanced_intra)) => {
+                    assert_eq!(standard.is_some(), advanced_intra.is_some())
+                }
+                _ => panic!("tables differ in shape"),
+            }
+        }
+    }
+
     #[test]
     fn empty_inter_block() {
         let bitstream = [0];
@@ -2121,4 +2897,304 @@ mod tests {
             .unwrap()
         )
     }
+
+    #[test]
+    fn sorenson_v0_long_coded_intra_block() {
+        let bitstream = [0x63, 0x06, 0x0C, 0x14, 0x1C, 0xC1, 0x00];
+        let mut reader = H263Reader::from_source(&bitstream[..]);
+
+        let picture = Picture {
+            version: Some(0),
+            ..Picture::for_test(PictureTypeCode::IFrame)
+        };
+
+        assert_eq!(
+            Block {
+                intradc: IntraDc::from_level(0x318),
+                tcoef: vec![
+                    TCoefficient {
+                        is_short: false,
+                        run: 3,
+                        level: 5
+                    },
+                    TCoefficient {
+                        is_short: false,
+                        run: 12,
+                        level: 16
+                    }
+                ]
+            },
+            decode_block(
+                &mut reader,
+                DecoderOption::SORENSON_SPARK_BITSTREAM,
+                &picture,
+                PictureOption::empty(),
+                MacroblockType::Intra,
+                true
+            )
+            .unwrap()
+        )
+    }
+
+    #[test]
+    fn sorenson_unknown_version_block() {
+        let bitstream = [0x63, 0x06, 0x0C, 0x14, 0x1C, 0xC1, 0x00];
+        let mut reader = H263Reader::from_source(&bitstream[..]);
+
+        let picture = Picture {
+            version: Some(2),
+            ..Picture::for_test(PictureTypeCode::IFrame)
+        };
+
+        assert!(matches!(
+            decode_block(
+                &mut reader,
+                DecoderOption::SORENSON_SPARK_BITSTREAM,
+                &picture,
+                PictureOption::empty(),
+                MacroblockType::Intra,
+                true
+            ),
+            Err(Error::UnknownSorensonVersion(2))
+        ));
+    }
+
+    #[test]
+    fn modified_quantization_extended_level() {
+        let picture = Picture {
+            options: PictureOption::MODIFIED_QUANTIZATION,
+            has_plusptype: true,
+            has_opptype: true,
+            ..Picture::for_test(PictureTypeCode::PFrame)
+        };
+
+        //Levels of 300 and -300, both escaped through -128
+        let bitstream = [0x06, 0x0A, 0x01, 0x84, 0x83, 0x81, 0x01, 0x4D, 0x80];
+        let mut reader = H263Reader::from_source(&bitstream[..]);
+
+        assert_eq!(
+            Block {
+                intradc: None,
+                tcoef: vec![
+                    TCoefficient {
+                        is_short: false,
+                        run: 2,
+                        level: 300
+                    },
+                    TCoefficient {
+                        is_short: false,
+                        run: 0,
+                        level: -300
+                    }
+                ]
+            },
+            decode_block(
+                &mut reader,
+                DecoderOption::empty(),
+                &picture,
+                PictureOption::MODIFIED_QUANTIZATION,
+                MacroblockType::Inter,
+                true
+            )
+            .unwrap()
+        );
+
+        //An extended level of zero is forbidden
+        let bitstream = [0x07, 0x02, 0x00, 0x00, 0x00];
+        let mut reader = H263Reader::from_source(&bitstream[..]);
+
+        assert!(matches!(
+            decode_block(
+                &mut reader,
+                DecoderOption::empty(),
+                &picture,
+                PictureOption::MODIFIED_QUANTIZATION,
+                MacroblockType::Inter,
+                true
+            ),
+            Err(Error::InvalidLongCoefficient)
+        ));
+
+        //Without modified quantization, -128 itself is forbidden
+        let bitstream = [0x07, 0x02, 0x00];
+        let mut reader = H263Reader::from_source(&bitstream[..]);
+
+        assert!(matches!(
+            decode_block(
+                &mut reader,
+                DecoderOption::empty(),
+                &picture,
+                PictureOption::empty(),
+                MacroblockType::Inter,
+                true
+            ),
+            Err(Error::InvalidLongCoefficient)
+        ));
+    }
+
+    #[test]
+    fn advanced_intra_block() {
+        let bitstream = [0x30];
+        let mut reader = H263Reader::from_source(&bitstream[..]);
+
+        let picture = Picture {
+            options: PictureOption::ADVANCED_INTRA_CODING,
+            has_plusptype: true,
+            has_opptype: true,
+            ..Picture::for_test(PictureTypeCode::IFrame)
+        };
+
+        //The DC coefficient is the first TCOEF, coded with table I.2.
+        assert_eq!(
+            Block {
+                intradc: None,
+                tcoef: vec![TCoefficient {
+                    is_short: true,
+                    run: 0,
+                    level: 2
+                }]
+            },
+            decode_block(
+                &mut reader,
+                DecoderOption::empty(),
+                &picture,
+                PictureOption::ADVANCED_INTRA_CODING,
+                MacroblockType::Intra,
+                true
+            )
+            .unwrap()
+        );
+
+        //Uncoded blocks have no INTRADC to read.
+        assert_eq!(
+            Block {
+                intradc: None,
+                tcoef: vec![]
+            },
+            decode_block(
+                &mut reader,
+                DecoderOption::empty(),
+                &picture,
+                PictureOption::ADVANCED_INTRA_CODING,
+                MacroblockType::Intra,
+                false
+            )
+            .unwrap()
+        );
+
+        //Inter blocks are coded the same way with or without Annex I.
+        assert_eq!(
+            Block {
+                intradc: None,
+                tcoef: vec![]
+            },
+            decode_block(
+                &mut reader,
+                DecoderOption::empty(),
+                &picture,
+                PictureOption::ADVANCED_INTRA_CODING,
+                MacroblockType::Inter,
+                false
+            )
+            .unwrap()
+        );
+        assert_eq!(reader.bit_position(), 7);
+    }
+
+    #[test]
+    fn alternative_inter_vlc_block() {
+        let picture = Picture {
+            options: PictureOption::ALTERNATIVE_INTER_VLC,
+            has_plusptype: true,
+            has_opptype: true,
+            ..Picture::for_test(PictureTypeCode::PFrame)
+        };
+
+        //Runs of 26, 26 and 40 overrun the block, so they must have been
+        //coded with the alternative table, where the same codes are DC-heavy.
+        let bitstream = [0x05, 0x70, 0x2B, 0xC1, 0x7C];
+        let mut reader = H263Reader::from_source(&bitstream[..]);
+
+        assert_eq!(
+            Block {
+                intradc: None,
+                tcoef: vec![
+                    TCoefficient {
+                        is_short: true,
+                        run: 0,
+                        level: 21
+                    },
+                    TCoefficient {
+                        is_short: true,
+                        run: 0,
+                        level: -21
+                    },
+                    TCoefficient {
+                        is_short: true,
+                        run: 0,
+                        level: 10
+                    }
+                ]
+            },
+            decode_block(
+                &mut reader,
+                DecoderOption::empty(),
+                &picture,
+                PictureOption::ALTERNATIVE_INTER_VLC,
+                MacroblockType::Inter,
+                true
+            )
+            .unwrap()
+        );
+        assert_eq!(reader.bit_position(), 39);
+
+        //Without Annex S, they are read as usual.
+        let mut reader = H263Reader::from_source(&bitstream[..]);
+        let runs: Vec<u8> = decode_block(
+            &mut reader,
+            DecoderOption::empty(),
+            &picture,
+            PictureOption::empty(),
+            MacroblockType::Inter,
+            true,
+        )
+        .unwrap()
+        .tcoef
+        .iter()
+        .map(|tcoef| tcoef.run)
+        .collect();
+        assert_eq!(runs, [26, 26, 40]);
+        assert_eq!(reader.bit_position(), 39);
+
+        //Runs of 62 and 0 exactly fill the block.
+        let bitstream = [0x06, 0xF8, 0x04, 0x1C, 0x00, 0x10];
+        let mut reader = H263Reader::from_source(&bitstream[..]);
+
+        assert_eq!(
+            Block {
+                intradc: None,
+                tcoef: vec![
+                    TCoefficient {
+                        is_short: false,
+                        run: 62,
+                        level: 1
+                    },
+                    TCoefficient {
+                        is_short: false,
+                        run: 0,
+                        level: 1
+                    }
+                ]
+            },
+            decode_block(
+                &mut reader,
+                DecoderOption::empty(),
+                &picture,
+                PictureOption::ALTERNATIVE_INTER_VLC,
+                MacroblockType::Inter,
+                true
+            )
+            .unwrap()
+        );
+    }
 }
@@ -3,24 +3,26 @@
 use crate::decoder::DecoderOption;
 use crate::error::{Error, Result};
 use crate::parser::reader::H263Reader;
-use crate::types::GroupOfBlocks;
+use crate::types::{GroupOfBlocks, Picture};
 use std::io::Read;
 
 /// Attempts to read a GOB record from an H.263 bitstream.
 ///
 /// If no valid start code could be found in the bitstream, this function will
 /// raise an error. If it is currently at the start of a picture instead of a
-/// GOB, then it will yield `None`, signalling that the current data should
-/// be parsed as a picture.
+/// GOB, or at the end of the sequence, then it will yield `None`, signalling
+/// that the current data should be parsed as a picture.
 ///
 /// The set of `DecoderOptions` allows configuring certain information about
 /// the decoding process that cannot be determined by decoding the bitstream
-/// itself.
-///
-/// TODO: GOB decoding is a stub (and likely will always be so)
+/// itself. The `picture` is the header of the picture this GOB belongs to,
+/// which determines if `GSBI` is present. A GOB from a different continuous
+/// presence multipoint sub-bitstream than the picture is an error, as that
+/// indicates that GOBs have been lost or misrouted.
 pub fn decode_gob<R>(
     reader: &mut H263Reader<R>,
     _decoder_options: DecoderOption,
+    picture: &Picture,
 ) -> Result<Option<GroupOfBlocks>>
 where
     R: Read,
@@ -32,11 +34,91 @@ where
 
         reader.skip_bits(17 + skipped_bits)?;
 
-        let gob_id = reader.read_bits::<u8>(5)?;
-        if gob_id == 0 || gob_id == 15 {
+        let group_number = reader.read_bits::<u8>(5)?;
+        if matches!(group_number, 0 | 15 | 30 | 31) {
             return Ok(None);
         }
 
-        Err(Error::UnimplementedDecoding)
+        let multiplex_bitstream = if picture.multiplex_bitstream.is_some() {
+            Some(reader.read_bits::<u8>(2)?)
+        } else {
+            None
+        };
+
+        if multiplex_bitstream != picture.multiplex_bitstream {
+            return Err(Error::GobSubBitstreamMismatch);
+        }
+
+        let frame_id = reader.read_bits::<u8>(2)?;
+        let quantizer = reader.read_bits::<u8>(5)?;
+
+        Ok(Some(GroupOfBlocks {
+            group_number,
+            multiplex_bitstream,
+            frame_id,
+            quantizer,
+        }))
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::decoder::DecoderOption;
+    use crate::error::Error;
+    use crate::parser::gob::decode_gob;
+    use crate::parser::reader::H263Reader;
+    use crate::types::{Picture, PictureTypeCode};
+
+    fn cpm_picture(multiplex_bitstream: Option<u8>) -> Picture {
+        Picture {
+            multiplex_bitstream,
+            ..Picture::for_test(PictureTypeCode::PFrame)
+        }
+    }
+
+    #[test]
+    fn gob_header() {
+        // GN 3, GFID 2, GQUANT 7
+        let bitstream = [0x00, 0x00, 0x8E, 0x38];
+        let mut reader = H263Reader::from_source(&bitstream[..]);
+
+        let gob = decode_gob(&mut reader, DecoderOption::empty(), &cpm_picture(None))
+            .unwrap()
+            .unwrap();
+        assert_eq!(gob.group_number, 3);
+        assert_eq!(gob.multiplex_bitstream, None);
+        assert_eq!(gob.frame_id, 2);
+        assert_eq!(gob.quantizer, 7);
+
+        // PSC instead of a GOB
+        let bitstream = [0x00, 0x00, 0x80, 0x00];
+        let mut reader = H263Reader::from_source(&bitstream[..]);
+
+        assert!(
+            decode_gob(&mut reader, DecoderOption::empty(), &cpm_picture(None))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn gob_sub_bitstream() {
+        // GN 3, GSBI 1, GFID 2, GQUANT 7
+        let bitstream = [0x00, 0x00, 0x8D, 0x8E];
+
+        let mut reader = H263Reader::from_source(&bitstream[..]);
+        let gob = decode_gob(&mut reader, DecoderOption::empty(), &cpm_picture(Some(1)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(gob.group_number, 3);
+        assert_eq!(gob.multiplex_bitstream, Some(1));
+        assert_eq!(gob.frame_id, 2);
+        assert_eq!(gob.quantizer, 7);
+
+        let mut reader = H263Reader::from_source(&bitstream[..]);
+        assert!(matches!(
+            decode_gob(&mut reader, DecoderOption::empty(), &cpm_picture(Some(2))),
+            Err(Error::GobSubBitstreamMismatch)
+        ));
+    }
+}
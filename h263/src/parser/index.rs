@@ -0,0 +1,344 @@
+//! Bitstream indexing
+
+use crate::decoder::DecoderOption;
+use crate::error::{Error, Result};
+use crate::parser::picture::decode_picture;
+use crate::parser::reader::H263Reader;
+use crate::types::{Picture, PictureTypeCode};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+/// The location of a single picture within a bitstream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitstreamIndexEntry {
+    /// The temporal reference of the picture.
+    pub temporal_reference: u16,
+
+    /// The picture's type.
+    pub picture_type: PictureTypeCode,
+
+    /// The byte offset of the picture's start code within the bitstream.
+    pub byte_offset: u64,
+
+    /// The number of bytes between the start of this picture and the start of
+    /// the next one (or the end of the bitstream).
+    pub byte_length: u64,
+}
+
+/// The timing and type of a single picture, as found by `index_stream`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameEntry {
+    /// The bit offset of the picture's start code within the bitstream.
+    pub bit_offset: u64,
+
+    /// The picture's type.
+    pub picture_type: PictureTypeCode,
+
+    /// The temporal reference of the picture.
+    pub temporal_reference: u16,
+
+    /// Whether or not the picture can be decoded without any other picture.
+    pub is_keyframe: bool,
+}
+
+/// A list of all pictures in a bitstream, in bitstream order.
+///
+/// Indexes are built by `scan_bitstream_index` and allow seeking to a given
+/// picture without having to parse all of the pictures before it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BitstreamIndex {
+    entries: Vec<BitstreamIndexEntry>,
+}
+
+impl BitstreamIndex {
+    /// Get all of the pictures in the index, in bitstream order.
+    pub fn entries(&self) -> &[BitstreamIndexEntry] {
+        &self.entries
+    }
+
+    /// Find the position of the first picture in the index with a given
+    /// temporal reference.
+    ///
+    /// Temporal references wrap around, so longer bitstreams may contain more
+    /// than one picture with the same temporal reference. Only the first such
+    /// picture can be found this way.
+    pub fn position_of(&self, temporal_reference: u16) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.temporal_reference == temporal_reference)
+    }
+
+    /// Find the position of the closest I-frame at or before a given position
+    /// in the index.
+    pub fn keyframe_before(&self, position: usize) -> Option<usize> {
+        self.entries
+            .get(..=position)?
+            .iter()
+            .rposition(|entry| matches!(entry.picture_type, PictureTypeCode::IFrame))
+    }
+}
+
+/// Build an index of every picture in a bitstream.
+///
+/// The `source` is scanned from its current position to its end for
+/// byte-aligned start codes, and the picture header following each is parsed
+/// with the given `decoder_options`. Start codes that do not begin a picture
+/// (such as GOB headers) and picture headers that fail to parse are not
+/// included in the index. Upon success, `source` is seeked back to where it
+/// was when this function was called.
+///
+/// Byte offsets are relative to the start of `source`, not to the position
+/// the scan started from.
+pub fn scan_bitstream_index<R>(
+    source: &mut R,
+    decoder_options: DecoderOption,
+) -> Result<BitstreamIndex>
+where
+    R: Read + Seek,
+{
+    let start = source.stream_position()?;
+    let mut end = start;
+    let mut start_codes = Vec::new();
+
+    {
+        let mut window: u32 = 0xFFFFFF;
+
+        for byte in BufReader::new(&mut *source).bytes() {
+            window = ((window << 8) | byte? as u32) & 0xFFFFFF;
+            end += 1;
+
+            // All start codes are 16 zero bits followed by a one bit.
+            if window & 0xFFFF80 == 0x000080 {
+                start_codes.push(end - 3);
+            }
+        }
+    }
+
+    let mut index = BitstreamIndex::default();
+    let mut previous_picture: Option<Picture> = None;
+
+    for offset in start_codes {
+        source.seek(SeekFrom::Start(offset))?;
+
+        let mut reader = H263Reader::from_source(&mut *source);
+        if let Ok(Some(picture)) =
+            decode_picture(&mut reader, decoder_options, previous_picture.as_ref())
+        {
+            index.entries.push(BitstreamIndexEntry {
+                temporal_reference: picture.temporal_reference,
+                picture_type: picture.picture_type,
+                byte_offset: offset,
+                byte_length: 0,
+            });
+            previous_picture = Some(picture);
+        }
+    }
+
+    let mut next_offset = end;
+    for entry in index.entries.iter_mut().rev() {
+        entry.byte_length = next_offset - entry.byte_offset;
+        next_offset = entry.byte_offset;
+    }
+
+    source.seek(SeekFrom::Start(start))?;
+
+    Ok(index)
+}
+
+/// Lazily list every picture in a bitstream.
+///
+/// This is the streaming equivalent of `scan_bitstream_index`, for sources
+/// that cannot seek. Each picture header is parsed with the given
+/// `decoder_options`, and then the picture data is skipped over by scanning
+/// for the next start code, without decoding any macroblocks. Unlike
+/// `scan_bitstream_index`, start codes do not need to be byte-aligned.
+///
+/// Start codes that do not begin a picture (such as GOB headers) and picture
+/// headers that fail to parse are skipped. The iterator ends at the end of
+/// the `source`, or after yielding the first I/O error other than
+/// end-of-file. Bit offsets are relative to the position `source` was at
+/// when this function was called.
+pub fn index_stream<R>(
+    source: R,
+    decoder_options: DecoderOption,
+) -> impl Iterator<Item = Result<FrameEntry>>
+where
+    R: Read,
+{
+    let mut reader = H263Reader::from_source(source);
+    let mut previous_picture = None;
+    let mut done = false;
+
+    std::iter::from_fn(move || {
+        while !done {
+            match next_frame_entry(&mut reader, decoder_options, &mut previous_picture) {
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Ok(None) => continue,
+                Err(e) => {
+                    done = true;
+
+                    if !e.is_eof_error() {
+                        return Some(Err(e));
+                    }
+                }
+            }
+        }
+
+        None
+    })
+}
+
+/// Find the next start code in the bitstream, and parse the picture header
+/// following it, if any.
+///
+/// Yields `None` if the start code does not begin a picture, in which case
+/// the reader is moved past it.
+fn next_frame_entry<R>(
+    reader: &mut H263Reader<R>,
+    decoder_options: DecoderOption,
+    previous_picture: &mut Option<Picture>,
+) -> Result<Option<FrameEntry>>
+where
+    R: Read,
+{
+    let skipped_bits = reader
+        .recognize_start_code(true)?
+        .ok_or(Error::InternalDecoderError)?;
+    reader.skip_bits(skipped_bits)?;
+    reader.commit();
+
+    let bit_offset = reader.bit_position();
+    match decode_picture(reader, decoder_options, previous_picture.as_ref()) {
+        Ok(Some(picture)) => {
+            let entry = FrameEntry {
+                bit_offset,
+                picture_type: picture.picture_type,
+                temporal_reference: picture.temporal_reference,
+                is_keyframe: matches!(picture.picture_type, PictureTypeCode::IFrame),
+            };
+            *previous_picture = Some(picture);
+
+            Ok(Some(entry))
+        }
+        Err(e) if e.is_eof_error() => Err(e),
+        Ok(None) | Err(_) => {
+            reader.skip_bits(17)?;
+            reader.commit();
+
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decoder::DecoderOption;
+    use crate::parser::index::{
+        index_stream, scan_bitstream_index, BitstreamIndexEntry, FrameEntry,
+    };
+    use crate::types::PictureTypeCode;
+    use std::io::{Cursor, Seek};
+
+    #[test]
+    fn scan_sorenson() {
+        let data = [
+            0xFF, 0x00, // garbage
+            // Version 0, QCIF, I-frame, TR 1
+            0x00, 0x00, 0x80, 0x05, 0x82, 0x80, 0x12, 0x34, //
+            // Version 0, QCIF, P-frame, TR 2
+            0x00, 0x00, 0x80, 0x09, 0xA2, 0x80, //
+            // Version 0, QCIF, disposable P-frame, TR 3
+            0x00, 0x00, 0x80, 0x0D, 0xC2, 0x80,
+        ];
+        let mut source = Cursor::new(&data[..]);
+        source.set_position(1);
+
+        let index =
+            scan_bitstream_index(&mut source, DecoderOption::SORENSON_SPARK_BITSTREAM).unwrap();
+
+        assert_eq!(source.stream_position().unwrap(), 1);
+        assert_eq!(
+            index.entries(),
+            &[
+                BitstreamIndexEntry {
+                    temporal_reference: 1,
+                    picture_type: PictureTypeCode::IFrame,
+                    byte_offset: 2,
+                    byte_length: 8,
+                },
+                BitstreamIndexEntry {
+                    temporal_reference: 2,
+                    picture_type: PictureTypeCode::PFrame,
+                    byte_offset: 10,
+                    byte_length: 6,
+                },
+                BitstreamIndexEntry {
+                    temporal_reference: 3,
+                    picture_type: PictureTypeCode::DisposablePFrame,
+                    byte_offset: 16,
+                    byte_length: 6,
+                },
+            ]
+        );
+        assert_eq!(index.position_of(3), Some(2));
+        assert_eq!(index.position_of(4), None);
+        assert_eq!(index.keyframe_before(2), Some(0));
+    }
+
+    #[test]
+    fn index_sorenson_stream() {
+        let data = [
+            0xFF, 0x00, // garbage
+            // Version 0, QCIF, I-frame, TR 1
+            0x00, 0x00, 0x80, 0x05, 0x82, 0x80, 0x12, 0x34, //
+            // Version 0, QCIF, P-frame, TR 2
+            0x00, 0x00, 0x80, 0x09, 0xA2, 0x80, //
+            // Version 0, QCIF, disposable P-frame, TR 3
+            0x00, 0x00, 0x80, 0x0D, 0xC2, 0x80,
+        ];
+
+        let entries = index_stream(&data[..], DecoderOption::SORENSON_SPARK_BITSTREAM)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                FrameEntry {
+                    bit_offset: 16,
+                    picture_type: PictureTypeCode::IFrame,
+                    temporal_reference: 1,
+                    is_keyframe: true,
+                },
+                FrameEntry {
+                    bit_offset: 80,
+                    picture_type: PictureTypeCode::PFrame,
+                    temporal_reference: 2,
+                    is_keyframe: false,
+                },
+                FrameEntry {
+                    bit_offset: 128,
+                    picture_type: PictureTypeCode::DisposablePFrame,
+                    temporal_reference: 3,
+                    is_keyframe: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn index_stream_skips_gobs() {
+        let data = [
+            // Sub-QCIF P-frame, TR 1, all uncoded
+            0x00, 0x00, 0x80, 0x06, 0x06, 0x08, 0x3F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xC0, //
+            // GOB 3
+            0x00, 0x00, 0x8E, 0x38, //
+            // Sub-QCIF P-frame, TR 2
+            0x00, 0x00, 0x80, 0x0A, 0x06, 0x08, 0x3F,
+        ];
+
+        let entries = index_stream(&data[..], DecoderOption::empty())
+            .map(|entry| entry.map(|e| (e.bit_offset, e.temporal_reference)))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(entries, vec![(0, 1), (136, 2)]);
+    }
+}
@@ -6,8 +6,8 @@ use crate::error::{Error, Result};
 use crate::parser::reader::H263Reader;
 use crate::parser::vlc::{Entry, Entry::End, Entry::Fork};
 use crate::types::{
-    CodedBlockPattern, HalfPel, Macroblock, MacroblockType, MotionVector, Picture, PictureOption,
-    PictureTypeCode,
+    BPredictionMode, CodedBlockPattern, HalfPel, IntraPredictionMode, Macroblock, MacroblockType,
+    MotionVector, Picture, PictureOption, PictureTypeCode, QuantizerChange,
 };
 use std::io::Read;
 
@@ -180,6 +180,126 @@ const MCBPC_P_TABLE: [Entry<BlockPatternEntry>; 53] = [
     )), //0000000001111, slot 52
 ];
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BMacroblockTypeEntry {
+    Stuffing,
+
+    Invalid,
+
+    Valid(MacroblockType, Option<BPredictionMode>, bool),
+}
+
+/// The decoding table for MBTYPE in B pictures (Annex O).
+///
+/// The output of this table is the type of the macroblock, how it is
+/// predicted if it is an inter macroblock, and the presence of `CBPC` and
+/// `CBPY`. Intra macroblocks always carry both.
+const MBTYPE_B_TABLE: [Entry<BMacroblockTypeEntry>; 33] = [
+    Fork(1, 32),                         //x, slot 0
+    Fork(2, 27),                         //0x, slot 1
+    Fork(3, 24),                         //00x, slot 2
+    Fork(4, 17),                         //000x, slot 3
+    Fork(5, 16),                         //0000x, slot 4
+    Fork(6, 15),                         //00000x, slot 5
+    Fork(7, 14),                         //000000x, slot 6
+    Fork(8, 13),                         //0000000x, slot 7
+    Fork(9, 12),                         //00000000x, slot 8
+    Fork(10, 11),                        //000000000x, slot 9
+    End(BMacroblockTypeEntry::Invalid),  //0000000000, not a valid prefix, slot 10
+    End(BMacroblockTypeEntry::Stuffing), //0000000001, slot 11
+    End(BMacroblockTypeEntry::Invalid),  //000000001, not a valid prefix, slot 12
+    End(BMacroblockTypeEntry::Valid(
+        MacroblockType::IntraQ,
+        None,
+        true,
+    )), //00000001, slot 13
+    End(BMacroblockTypeEntry::Valid(
+        MacroblockType::Intra,
+        None,
+        true,
+    )), //0000001, slot 14
+    End(BMacroblockTypeEntry::Valid(
+        MacroblockType::InterQ,
+        Some(BPredictionMode::Bidirectional),
+        true,
+    )), //000001, slot 15
+    End(BMacroblockTypeEntry::Valid(
+        MacroblockType::InterQ,
+        Some(BPredictionMode::Direct),
+        true,
+    )), //00001, slot 16
+    Fork(18, 21),                        //0001x, slot 17
+    Fork(19, 20),                        //00010x, slot 18
+    End(BMacroblockTypeEntry::Valid(
+        MacroblockType::Inter,
+        Some(BPredictionMode::Bidirectional),
+        false,
+    )), //000100, slot 19
+    End(BMacroblockTypeEntry::Valid(
+        MacroblockType::Inter,
+        Some(BPredictionMode::Bidirectional),
+        true,
+    )), //000101, slot 20
+    Fork(22, 23),                        //00011x, slot 21
+    End(BMacroblockTypeEntry::Valid(
+        MacroblockType::InterQ,
+        Some(BPredictionMode::Forward),
+        true,
+    )), //000110, slot 22
+    End(BMacroblockTypeEntry::Valid(
+        MacroblockType::InterQ,
+        Some(BPredictionMode::Backward),
+        true,
+    )), //000111, slot 23
+    Fork(25, 26),                        //001x, slot 24
+    End(BMacroblockTypeEntry::Valid(
+        MacroblockType::Inter,
+        Some(BPredictionMode::Backward),
+        false,
+    )), //0010, slot 25
+    End(BMacroblockTypeEntry::Valid(
+        MacroblockType::Inter,
+        Some(BPredictionMode::Backward),
+        true,
+    )), //0011, slot 26
+    Fork(28, 31),                        //01x, slot 27
+    Fork(29, 30),                        //010x, slot 28
+    End(BMacroblockTypeEntry::Valid(
+        MacroblockType::Inter,
+        Some(BPredictionMode::Forward),
+        false,
+    )), //0100, slot 29
+    End(BMacroblockTypeEntry::Valid(
+        MacroblockType::Inter,
+        Some(BPredictionMode::Forward),
+        true,
+    )), //0101, slot 30
+    End(BMacroblockTypeEntry::Valid(
+        MacroblockType::Inter,
+        Some(BPredictionMode::Direct),
+        true,
+    )), //011, slot 31
+    End(BMacroblockTypeEntry::Valid(
+        MacroblockType::Inter,
+        Some(BPredictionMode::Direct),
+        false,
+    )), //1, slot 32
+];
+
+/// The decoding table for CBPC in B pictures (Annex O).
+///
+/// The output of this table is the presence of the chroma-B and chroma-R
+/// blocks.
+const CBPC_B_TABLE: [Entry<(bool, bool)>; 7] = [
+    Fork(1, 2),          //x, slot 0
+    End((false, false)), //0, slot 1
+    Fork(3, 4),          //1x, slot 2
+    End((false, true)),  //10, slot 3
+    Fork(5, 6),          //11x, slot 4
+    End((true, true)),   //110, slot 5
+    End((true, false)),  //111, slot 6
+];
+
 /// The decoding table for MODB (used in PB Frames).
 ///
 /// The output of this table is two booleans, the first indicating the presence
@@ -193,6 +313,25 @@ const MODB_TABLE: [Entry<(bool, bool)>; 5] = [
     End((true, true)),   //11, slot 4
 ];
 
+/// The decoding table for MODB in improved PB frames (H.263 Table M.1).
+///
+/// The output of this table is the presence of a `CodedBlockPattern` and
+/// motion vectors for the B-blocks, as in `MODB_TABLE`, and the prediction
+/// mode of the B-blocks.
+const IMPROVED_MODB_TABLE: [Entry<(bool, bool, BPredictionMode)>; 11] = [
+    Fork(1, 2),                                          //x, slot 0
+    End((false, false, BPredictionMode::Bidirectional)), //0, slot 1
+    Fork(3, 4),                                          //1x, slot 2
+    End((true, false, BPredictionMode::Bidirectional)),  //10, slot 3
+    Fork(5, 6),                                          //11x, slot 4
+    End((false, true, BPredictionMode::Forward)),        //110, slot 5
+    Fork(7, 8),                                          //111x, slot 6
+    End((true, true, BPredictionMode::Forward)),         //1110, slot 7
+    Fork(9, 10),                                         //1111x, slot 8
+    End((false, false, BPredictionMode::Backward)),      //11110, slot 9
+    End((true, false, BPredictionMode::Backward)),       //11111, slot 10
+];
+
 fn decode_cbpb<R>(reader: &mut H263Reader<R>) -> Result<CodedBlockPattern>
 where
     R: Read,
@@ -254,157 +393,190 @@ const CBPY_TABLE_INTRA: [Entry<Option<[bool; 4]>>; 33] = [
     End(Some([true, true, true, true])),     //11, slot 32
 ];
 
-fn decode_dquant<R>(reader: &mut H263Reader<R>) -> Result<i8>
+fn decode_dquant<R>(reader: &mut H263Reader<R>) -> Result<QuantizerChange>
 where
     R: Read,
 {
     reader.with_transaction(|reader| {
-        Ok(match reader.read_bits::<u8>(2)? {
+        Ok(QuantizerChange::Delta(match reader.read_bits::<u8>(2)? {
             0 => -1,
             1 => -2,
             2 => 1,
             3 => 2,
             _ => return Err(Error::InternalDecoderError),
-        })
+        }))
+    })
+}
+
+/// Decode the `DQUANT` of a macroblock under Modified Quantization.
+///
+/// A leading one bit is followed by a single bit that steps the quantizer
+/// up or down, by an amount that depends on the quantizer in force; a
+/// leading zero bit is followed by a new quantizer in five bits.
+///
+/// ITU-T Recommendation H.263 (01/2005) T.4
+fn decode_dquant_annex_t<R>(reader: &mut H263Reader<R>) -> Result<QuantizerChange>
+where
+    R: Read,
+{
+    reader.with_transaction(|reader| {
+        if reader.read_bits::<u8>(1)? == 1 {
+            Ok(QuantizerChange::Step(reader.read_bits::<u8>(1)? == 1))
+        } else {
+            match reader.read_bits::<u8>(5)? {
+                0 => Err(Error::InvalidMacroblockHeader),
+                quantizer => Ok(QuantizerChange::Absolute(quantizer)),
+            }
+        }
     })
 }
 
+/// The coding table for the intra prediction mode of Advanced Intra Coding.
+///
+/// ITU-T Recommendation H.263 (01/2005) Table I.1.
+const INTRA_MODE_TABLE: [Entry<IntraPredictionMode>; 5] = [
+    Fork(1, 2),                           //x, slot 0
+    End(IntraPredictionMode::Dc),         //0, slot 1
+    Fork(3, 4),                           //1x, slot 2
+    End(IntraPredictionMode::Vertical),   //10, slot 3
+    End(IntraPredictionMode::Horizontal), //11, slot 4
+];
+
 /// The standard motion vector decode table.
 ///
-/// This table yields `f32`s, not `HalfPel`s, due to the fact that you cannot
-/// construct a `HalfPel` in a `const` in stable Rust. It should be converted
-/// the moment you get this data.
-const MVD_TABLE: [Entry<Option<f32>>; 130] = [
-    Fork(2, 1),       //x, slot 0
-    End(Some(0.0)),   //1, slot 1
-    Fork(6, 3),       //0x, slot 2
-    Fork(4, 5),       //01x, slot 3
-    End(Some(0.5)),   //010, slot 4
-    End(Some(-0.5)),  //011, slot 5
-    Fork(10, 7),      //00x, slot 6
-    Fork(8, 9),       //001x, slot 7
-    End(Some(1.0)),   //0010, slot 8
-    End(Some(-1.0)),  //0011, slot 9
-    Fork(14, 11),     //000x, slot 10
-    Fork(12, 13),     //0001x, slot 11
-    End(Some(1.5)),   //00010, slot 12
-    End(Some(-1.5)),  //00011, slot 13
-    Fork(26, 15),     //0000x, slot 14
-    Fork(19, 16),     //00001x, slot 15
-    Fork(17, 18),     //000011x, slot 16
-    End(Some(2.0)),   //0000110, slot 17
-    End(Some(-2.0)),  //0000111, slot 18
-    Fork(23, 20),     //000010x, slot 19
-    Fork(21, 22),     //0000101x, slot 20
-    End(Some(2.5)),   //00001010, slot 21
-    End(Some(-2.5)),  //00001011, slot 22
-    Fork(24, 25),     //0000100x, slot 23
-    End(Some(3.0)),   //00001000, slot 24
-    End(Some(-3.0)),  //00001001, slot 25
-    Fork(50, 27),     //00000x, slot 26
-    Fork(31, 28),     //000001x, slot 27
-    Fork(29, 30),     //0000011x, slot 28
-    End(Some(3.5)),   //00000110, slot 29
-    End(Some(-3.5)),  //00000111, slot 30
-    Fork(39, 32),     //0000010x, slot 31
-    Fork(36, 33),     //00000101x, slot 32
-    Fork(34, 35),     //000001011x, slot 33
-    End(Some(4.0)),   //0000010110, slot 34
-    End(Some(-4.0)),  //0000010111, slot 35
-    Fork(37, 38),     //000001010x, slot 36
-    End(Some(4.5)),   //0000010100, slot 37
-    End(Some(-4.5)),  //0000010101, slot 38
-    Fork(43, 40),     //00000100x, slot 39
-    Fork(41, 42),     //000001001x, slot 40
-    End(Some(5.0)),   //0000010010, slot 41
-    End(Some(-5.0)),  //0000010011, slot 42
-    Fork(47, 44),     //000001000x, slot 43
-    Fork(45, 46),     //0000010001x, slot 44
-    End(Some(5.5)),   //00000100010, slot 45
-    End(Some(-5.5)),  //00000100011, slot 46
-    Fork(48, 49),     //0000010000x, slot 47
-    End(Some(6.0)),   //00000100000, slot 48
-    End(Some(-6.0)),  //00000100001, slot 49
-    Fork(82, 51),     //000000x, slot 50
-    Fork(67, 52),     //0000001x, slot 51
-    Fork(60, 53),     //00000011x, slot 52
-    Fork(57, 54),     //000000111x, slot 53
-    Fork(55, 56),     //0000001111x, slot 54
-    End(Some(6.5)),   //00000011110, slot 55
-    End(Some(-6.5)),  //00000011111, slot 56
-    Fork(58, 59),     //0000001110x, slot 57
-    End(Some(7.0)),   //00000011100, slot 58
-    End(Some(-7.0)),  //00000011101, slot 59
-    Fork(64, 61),     //000000110x, slot 60
-    Fork(62, 63),     //0000001101x, slot 61
-    End(Some(7.5)),   //00000011010, slot 62
-    End(Some(-7.5)),  //00000011011, slot 63
-    Fork(65, 66),     //0000001100x, slot 64
-    End(Some(8.0)),   //00000011000, slot 65
-    End(Some(-8.0)),  //00000011001, slot 66
-    Fork(75, 68),     //00000010x, slot 67
-    Fork(72, 69),     //000000101x, slot 68
-    Fork(70, 71),     //0000001011x, slot 69
-    End(Some(8.5)),   //00000010110, slot 70
-    End(Some(-8.5)),  //00000010111, slot 71
-    Fork(73, 74),     //0000001010x, slot 72
-    End(Some(9.0)),   //00000010100, slot 73
-    End(Some(-9.0)),  //00000010101, slot 74
-    Fork(79, 76),     //000000100x, slot 75
-    Fork(77, 78),     //0000001001x, slot 76
-    End(Some(9.5)),   //00000010010, slot 77
-    End(Some(-9.5)),  //00000010011, slot 78
-    Fork(80, 81),     //0000001000x, slot 79
-    End(Some(10.0)),  //00000010000, slot 80
-    End(Some(-10.0)), //00000010001, slot 81
-    Fork(98, 83),     //0000000x, slot 82
-    Fork(91, 84),     //00000001x, slot 83
-    Fork(88, 85),     //000000011x, slot 84
-    Fork(86, 87),     //0000000111x, slot 85
-    End(Some(10.5)),  //00000001110, slot 86
-    End(Some(-10.5)), //00000001111, slot 87
-    Fork(89, 90),     //0000000110x, slot 88
-    End(Some(11.0)),  //00000001100, slot 89
-    End(Some(-11.0)), //00000001101, slot 90
-    Fork(95, 92),     //000000010x, slot 91
-    Fork(93, 94),     //0000000101x, slot 92
-    End(Some(11.5)),  //00000001010, slot 93
-    End(Some(-11.5)), //00000001011, slot 94
-    Fork(96, 97),     //0000000100x, slot 95
-    End(Some(12.0)),  //00000001000, slot 96
-    End(Some(-12.0)), //00000001001, slot 97
-    Fork(114, 99),    //00000000x, slot 98
-    Fork(107, 100),   //000000001x, slot 99
-    Fork(104, 101),   //0000000011x, slot 100
-    Fork(102, 103),   //00000000111x, slot 101
-    End(Some(12.5)),  //000000001110, slot 102
-    End(Some(-12.5)), //000000001111, slot 103
-    Fork(105, 106),   //00000000110x, slot 104
-    End(Some(13.0)),  //00000001100, slot 105
-    End(Some(-13.0)), //00000001101, slot 106
-    Fork(111, 108),   //0000000010x, slot 107
-    Fork(109, 110),   //00000000101x, slot 108
-    End(Some(13.5)),  //000000001010, slot 109
-    End(Some(-13.5)), //000000001011, slot 110
-    Fork(112, 113),   //00000000100x, slot 111
-    End(Some(14.0)),  //000000001000, slot 112
-    End(Some(-14.0)), //000000001001, slot 113
-    Fork(122, 115),   //000000000x, slot 114
-    Fork(119, 116),   //0000000001x, slot 115
-    Fork(117, 118),   //00000000011x, slot 116
-    End(Some(14.5)),  //000000000110, slot 117
-    End(Some(-14.5)), //000000000111, slot 118
-    Fork(120, 121),   //00000000010x, slot 119
-    End(Some(15.0)),  //000000000100, slot 120
-    End(Some(-15.0)), //000000000101, slot 121
-    Fork(129, 123),   //0000000000x, slot 122
-    Fork(127, 124),   //00000000001x, slot 123
-    Fork(125, 126),   //000000000011x, slot 124
-    End(Some(15.5)),  //0000000000110, slot 125
-    End(Some(-15.5)), //0000000000111, slot 126
-    Fork(129, 128),   //000000000010x, slot 127
-    End(Some(-16.0)), //0000000000101, slot 128
-    End(None),        //00000000000 or 0000000000100 patterns, slot 129
+/// This table yields `HalfPel`s, and thus all of it's values are stored in
+/// half-pixel units (i.e. doubled).
+const MVD_TABLE: [Entry<Option<HalfPel>>; 130] = [
+    Fork(2, 1),                         //x, slot 0
+    End(Some(HalfPel::from_unit(0))),   //1, slot 1
+    Fork(6, 3),                         //0x, slot 2
+    Fork(4, 5),                         //01x, slot 3
+    End(Some(HalfPel::from_unit(1))),   //010, slot 4
+    End(Some(HalfPel::from_unit(-1))),  //011, slot 5
+    Fork(10, 7),                        //00x, slot 6
+    Fork(8, 9),                         //001x, slot 7
+    End(Some(HalfPel::from_unit(2))),   //0010, slot 8
+    End(Some(HalfPel::from_unit(-2))),  //0011, slot 9
+    Fork(14, 11),                       //000x, slot 10
+    Fork(12, 13),                       //0001x, slot 11
+    End(Some(HalfPel::from_unit(3))),   //00010, slot 12
+    End(Some(HalfPel::from_unit(-3))),  //00011, slot 13
+    Fork(26, 15),                       //0000x, slot 14
+    Fork(19, 16),                       //00001x, slot 15
+    Fork(17, 18),                       //000011x, slot 16
+    End(Some(HalfPel::from_unit(4))),   //0000110, slot 17
+    End(Some(HalfPel::from_unit(-4))),  //0000111, slot 18
+    Fork(23, 20),                       //000010x, slot 19
+    Fork(21, 22),                       //0000101x, slot 20
+    End(Some(HalfPel::from_unit(5))),   //00001010, slot 21
+    End(Some(HalfPel::from_unit(-5))),  //00001011, slot 22
+    Fork(24, 25),                       //0000100x, slot 23
+    End(Some(HalfPel::from_unit(6))),   //00001000, slot 24
+    End(Some(HalfPel::from_unit(-6))),  //00001001, slot 25
+    Fork(50, 27),                       //00000x, slot 26
+    Fork(31, 28),                       //000001x, slot 27
+    Fork(29, 30),                       //0000011x, slot 28
+    End(Some(HalfPel::from_unit(7))),   //00000110, slot 29
+    End(Some(HalfPel::from_unit(-7))),  //00000111, slot 30
+    Fork(39, 32),                       //0000010x, slot 31
+    Fork(36, 33),                       //00000101x, slot 32
+    Fork(34, 35),                       //000001011x, slot 33
+    End(Some(HalfPel::from_unit(8))),   //0000010110, slot 34
+    End(Some(HalfPel::from_unit(-8))),  //0000010111, slot 35
+    Fork(37, 38),                       //000001010x, slot 36
+    End(Some(HalfPel::from_unit(9))),   //0000010100, slot 37
+    End(Some(HalfPel::from_unit(-9))),  //0000010101, slot 38
+    Fork(43, 40),                       //00000100x, slot 39
+    Fork(41, 42),                       //000001001x, slot 40
+    End(Some(HalfPel::from_unit(10))),  //0000010010, slot 41
+    End(Some(HalfPel::from_unit(-10))), //0000010011, slot 42
+    Fork(47, 44),                       //000001000x, slot 43
+    Fork(45, 46),                       //0000010001x, slot 44
+    End(Some(HalfPel::from_unit(11))),  //00000100010, slot 45
+    End(Some(HalfPel::from_unit(-11))), //00000100011, slot 46
+    Fork(48, 49),                       //0000010000x, slot 47
+    End(Some(HalfPel::from_unit(12))),  //00000100000, slot 48
+    End(Some(HalfPel::from_unit(-12))), //00000100001, slot 49
+    Fork(82, 51),                       //000000x, slot 50
+    Fork(67, 52),                       //0000001x, slot 51
+    Fork(60, 53),                       //00000011x, slot 52
+    Fork(57, 54),                       //000000111x, slot 53
+    Fork(55, 56),                       //0000001111x, slot 54
+    End(Some(HalfPel::from_unit(13))),  //00000011110, slot 55
+    End(Some(HalfPel::from_unit(-13))), //00000011111, slot 56
+    Fork(58, 59),                       //0000001110x, slot 57
+    End(Some(HalfPel::from_unit(14))),  //00000011100, slot 58
+    End(Some(HalfPel::from_unit(-14))), //00000011101, slot 59
+    Fork(64, 61),                       //000000110x, slot 60
+    Fork(62, 63),                       //0000001101x, slot 61
+    End(Some(HalfPel::from_unit(15))),  //00000011010, slot 62
+    End(Some(HalfPel::from_unit(-15))), //00000011011, slot 63
+    Fork(65, 66),                       //0000001100x, slot 64
+    End(Some(HalfPel::from_unit(16))),  //00000011000, slot 65
+    End(Some(HalfPel::from_unit(-16))), //00000011001, slot 66
+    Fork(75, 68),                       //00000010x, slot 67
+    Fork(72, 69),                       //000000101x, slot 68
+    Fork(70, 71),                       //0000001011x, slot 69
+    End(Some(HalfPel::from_unit(17))),  //00000010110, slot 70
+    End(Some(HalfPel::from_unit(-17))), //00000010111, slot 71
+    Fork(73, 74),                       //0000001010x, slot 72
+    End(Some(HalfPel::from_unit(18))),  //00000010100, slot 73
+    End(Some(HalfPel::from_unit(-18))), //00000010101, slot 74
+    Fork(79, 76),                       //000000100x, slot 75
+    Fork(77, 78),                       //0000001001x, slot 76
+    End(Some(HalfPel::from_unit(19))),  //00000010010, slot 77
+    End(Some(HalfPel::from_unit(-19))), //00000010011, slot 78
+    Fork(80, 81),                       //0000001000x, slot 79
+    End(Some(HalfPel::from_unit(20))),  //00000010000, slot 80
+    End(Some(HalfPel::from_unit(-20))), //00000010001, slot 81
+    Fork(98, 83),                       //0000000x, slot 82
+    Fork(91, 84),                       //00000001x, slot 83
+    Fork(88, 85),                       //000000011x, slot 84
+    Fork(86, 87),                       //0000000111x, slot 85
+    End(Some(HalfPel::from_unit(21))),  //00000001110, slot 86
+    End(Some(HalfPel::from_unit(-21))), //00000001111, slot 87
+    Fork(89, 90),                       //0000000110x, slot 88
+    End(Some(HalfPel::from_unit(22))),  //00000001100, slot 89
+    End(Some(HalfPel::from_unit(-22))), //00000001101, slot 90
+    Fork(95, 92),                       //000000010x, slot 91
+    Fork(93, 94),                       //0000000101x, slot 92
+    End(Some(HalfPel::from_unit(23))),  //00000001010, slot 93
+    End(Some(HalfPel::from_unit(-23))), //00000001011, slot 94
+    Fork(96, 97),                       //0000000100x, slot 95
+    End(Some(HalfPel::from_unit(24))),  //00000001000, slot 96
+    End(Some(HalfPel::from_unit(-24))), //00000001001, slot 97
+    Fork(114, 99),                      //00000000x, slot 98
+    Fork(107, 100),                     //000000001x, slot 99
+    Fork(104, 101),                     //0000000011x, slot 100
+    Fork(102, 103),                     //00000000111x, slot 101
+    End(Some(HalfPel::from_unit(25))),  //000000001110, slot 102
+    End(Some(HalfPel::from_unit(-25))), //000000001111, slot 103
+    Fork(105, 106),                     //00000000110x, slot 104
+    End(Some(HalfPel::from_unit(26))),  //00000001100, slot 105
+    End(Some(HalfPel::from_unit(-26))), //00000001101, slot 106
+    Fork(111, 108),                     //0000000010x, slot 107
+    Fork(109, 110),                     //00000000101x, slot 108
+    End(Some(HalfPel::from_unit(27))),  //000000001010, slot 109
+    End(Some(HalfPel::from_unit(-27))), //000000001011, slot 110
+    Fork(112, 113),                     //00000000100x, slot 111
+    End(Some(HalfPel::from_unit(28))),  //000000001000, slot 112
+    End(Some(HalfPel::from_unit(-28))), //000000001001, slot 113
+    Fork(122, 115),                     //000000000x, slot 114
+    Fork(119, 116),                     //0000000001x, slot 115
+    Fork(117, 118),                     //00000000011x, slot 116
+    End(Some(HalfPel::from_unit(29))),  //000000000110, slot 117
+    End(Some(HalfPel::from_unit(-29))), //000000000111, slot 118
+    Fork(120, 121),                     //00000000010x, slot 119
+    End(Some(HalfPel::from_unit(30))),  //000000000100, slot 120
+    End(Some(HalfPel::from_unit(-30))), //000000000101, slot 121
+    Fork(129, 123),                     //0000000000x, slot 122
+    Fork(127, 124),                     //00000000001x, slot 123
+    Fork(125, 126),                     //000000000011x, slot 124
+    End(Some(HalfPel::from_unit(31))),  //0000000000110, slot 125
+    End(Some(HalfPel::from_unit(-31))), //0000000000111, slot 126
+    Fork(129, 128),                     //000000000010x, slot 127
+    End(Some(HalfPel::from_unit(-32))), //0000000000101, slot 128
+    End(None),                          //00000000000 or 0000000000100 patterns, slot 129
 ];
 
 /// Decode a motion vector from the bitstream.
@@ -423,13 +595,10 @@ where
         if running_options.contains(PictureOption::UNRESTRICTED_MOTION_VECTORS)
             && picture.has_plusptype
         {
-            let x = reader.read_umv()?;
-            let y = reader.read_umv()?;
-
-            Ok((x, y).into())
+            reader.read_umv_pair()
         } else {
-            let x = HalfPel::from(reader.read_vlc(&MVD_TABLE[..])?.ok_or(Error::InvalidMvd)?);
-            let y = HalfPel::from(reader.read_vlc(&MVD_TABLE[..])?.ok_or(Error::InvalidMvd)?);
+            let x = reader.read_vlc(&MVD_TABLE[..])?.ok_or(Error::InvalidMvd)?;
+            let y = reader.read_vlc(&MVD_TABLE[..])?.ok_or(Error::InvalidMvd)?;
 
             Ok((x, y).into())
         }
@@ -451,6 +620,17 @@ where
     R: Read,
 {
     reader.with_transaction(|reader| {
+        //TODO: The macroblock layer of EI and EP pictures (Annex O)
+        match picture.picture_type {
+            PictureTypeCode::BFrame => {
+                return decode_b_macroblock(reader, picture, running_options)
+            }
+            PictureTypeCode::EiFrame | PictureTypeCode::EpFrame => {
+                return Err(Error::UnimplementedDecoding)
+            }
+            _ => {}
+        }
+
         let is_coded: u8 = if matches!(picture.picture_type, PictureTypeCode::IFrame) {
             0
         } else {
@@ -460,7 +640,10 @@ where
         if is_coded == 0 {
             let mcbpc = match picture.picture_type {
                 PictureTypeCode::IFrame => reader.read_vlc(&MCBPC_I_TABLE[..])?,
-                PictureTypeCode::PFrame => reader.read_vlc(&MCBPC_P_TABLE[..])?,
+                PictureTypeCode::PFrame
+                | PictureTypeCode::DisposablePFrame
+                | PictureTypeCode::PbFrame
+                | PictureTypeCode::ImprovedPbFrame => reader.read_vlc(&MCBPC_P_TABLE[..])?,
                 _ => return Err(Error::UnimplementedDecoding),
             };
 
@@ -470,10 +653,26 @@ where
                 BlockPatternEntry::Valid(mbt, chroma_b, chroma_r) => (mbt, chroma_b, chroma_r),
             };
 
-            let (has_cbpb, has_mvdb) = if matches!(picture.picture_type, PictureTypeCode::PbFrame) {
-                reader.read_vlc(&MODB_TABLE[..])?
+            let intra_prediction_mode = if mb_type.is_intra()
+                && running_options.contains(PictureOption::ADVANCED_INTRA_CODING)
+            {
+                Some(reader.read_vlc(&INTRA_MODE_TABLE[..])?)
             } else {
-                (false, false)
+                None
+            };
+
+            let (has_cbpb, has_mvdb, b_prediction_mode) = match picture.picture_type {
+                PictureTypeCode::PbFrame => {
+                    let (has_cbpb, has_mvdb) = reader.read_vlc(&MODB_TABLE[..])?;
+
+                    (has_cbpb, has_mvdb, Some(BPredictionMode::Bidirectional))
+                }
+                PictureTypeCode::ImprovedPbFrame => {
+                    let (has_cbpb, has_mvdb, mode) = reader.read_vlc(&IMPROVED_MODB_TABLE[..])?;
+
+                    (has_cbpb, has_mvdb, Some(mode))
+                }
+                _ => (false, false, None),
             };
 
             let codes_luma = if mb_type.is_intra() {
@@ -494,12 +693,12 @@ where
                 None
             };
 
-            let d_quantizer = if running_options.contains(PictureOption::MODIFIED_QUANTIZATION) {
-                return Err(Error::UnimplementedDecoding);
-            } else if mb_type.has_quantizer() {
-                Some(decode_dquant(reader)?)
-            } else {
+            let d_quantizer = if !mb_type.has_quantizer() {
                 None
+            } else if running_options.contains(PictureOption::MODIFIED_QUANTIZATION) {
+                Some(decode_dquant_annex_t(reader)?)
+            } else {
+                Some(decode_dquant(reader)?)
             };
 
             let motion_vector = if mb_type.is_inter() || picture.picture_type.is_any_pbframe() {
@@ -518,13 +717,8 @@ where
                 None
             };
 
-            let motion_vectors_b = if has_mvdb {
-                let mv1 = decode_motion_vector(reader, picture, running_options)?;
-                let mv2 = decode_motion_vector(reader, picture, running_options)?;
-                let mv3 = decode_motion_vector(reader, picture, running_options)?;
-                let mv4 = decode_motion_vector(reader, picture, running_options)?;
-
-                Some([mv1, mv2, mv3, mv4])
+            let motion_vector_b = if has_mvdb {
+                Some(decode_motion_vector(reader, picture, running_options)?)
             } else {
                 None
             };
@@ -540,7 +734,9 @@ where
                 d_quantizer,
                 motion_vector,
                 addl_motion_vectors,
-                motion_vectors_b,
+                motion_vector_b,
+                b_prediction_mode,
+                intra_prediction_mode,
             })
         } else {
             Ok(Macroblock::Uncoded)
@@ -548,13 +744,115 @@ where
     })
 }
 
+/// Decode a macroblock header of a B picture (Annex O) from the bitstream
+/// referenced by `reader`.
+///
+/// B picture macroblocks have no `COD`: skipped macroblocks are instead coded
+/// as direct predicted, with no coefficients. The forward and backward motion
+/// vector differences are yielded in `motion_vector` and `motion_vector_b`
+/// respectively.
+///
+/// ITU-T Recommendation H.263 (01/2005) O.4
+fn decode_b_macroblock<R>(
+    reader: &mut H263Reader<R>,
+    picture: &Picture,
+    running_options: PictureOption,
+) -> Result<Macroblock>
+where
+    R: Read,
+{
+    reader.with_transaction(|reader| {
+        let (mb_type, b_prediction_mode, has_cbp) = match reader.read_vlc(&MBTYPE_B_TABLE[..])? {
+            BMacroblockTypeEntry::Stuffing => return Ok(Macroblock::Stuffing),
+            BMacroblockTypeEntry::Invalid => return Err(Error::InvalidMacroblockHeader),
+            BMacroblockTypeEntry::Valid(mb_type, b_prediction_mode, has_cbp) => {
+                (mb_type, b_prediction_mode, has_cbp)
+            }
+        };
+
+        let (codes_chroma_b, codes_chroma_r) = if has_cbp {
+            reader.read_vlc(&CBPC_B_TABLE[..])?
+        } else {
+            (false, false)
+        };
+
+        let intra_prediction_mode = if mb_type.is_intra()
+            && running_options.contains(PictureOption::ADVANCED_INTRA_CODING)
+        {
+            Some(reader.read_vlc(&INTRA_MODE_TABLE[..])?)
+        } else {
+            None
+        };
+
+        let codes_luma = if !has_cbp {
+            [false; 4]
+        } else if mb_type.is_intra() {
+            match reader.read_vlc(&CBPY_TABLE_INTRA)? {
+                Some(v) => v,
+                None => return Err(Error::InvalidMacroblockCodedBits),
+            }
+        } else {
+            match reader.read_vlc(&CBPY_TABLE_INTRA)? {
+                Some([v1, v2, v3, v4]) => [!v1, !v2, !v3, !v4],
+                None => return Err(Error::InvalidMacroblockCodedBits),
+            }
+        };
+
+        let d_quantizer = if !mb_type.has_quantizer() {
+            None
+        } else if running_options.contains(PictureOption::MODIFIED_QUANTIZATION) {
+            Some(decode_dquant_annex_t(reader)?)
+        } else {
+            Some(decode_dquant(reader)?)
+        };
+
+        let motion_vector = if matches!(
+            b_prediction_mode,
+            Some(BPredictionMode::Forward | BPredictionMode::Bidirectional)
+        ) {
+            Some(decode_motion_vector(reader, picture, running_options)?)
+        } else {
+            None
+        };
+
+        let motion_vector_b = if matches!(
+            b_prediction_mode,
+            Some(BPredictionMode::Backward | BPredictionMode::Bidirectional)
+        ) {
+            Some(decode_motion_vector(reader, picture, running_options)?)
+        } else {
+            None
+        };
+
+        Ok(Macroblock::Coded {
+            mb_type,
+            coded_block_pattern: CodedBlockPattern {
+                codes_luma,
+                codes_chroma_b,
+                codes_chroma_r,
+            },
+            coded_block_pattern_b: None,
+            d_quantizer,
+            motion_vector,
+            addl_motion_vectors: None,
+            motion_vector_b,
+            b_prediction_mode,
+            intra_prediction_mode,
+        })
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use crate::parser::macroblock::{
-        BlockPatternEntry, CBPY_TABLE_INTRA, MCBPC_I_TABLE, MCBPC_P_TABLE, MODB_TABLE, MVD_TABLE,
+        decode_macroblock, BMacroblockTypeEntry, BlockPatternEntry, CBPC_B_TABLE, CBPY_TABLE_INTRA,
+        IMPROVED_MODB_TABLE, MBTYPE_B_TABLE, MCBPC_I_TABLE, MCBPC_P_TABLE, MODB_TABLE, MVD_TABLE,
     };
     use crate::parser::reader::H263Reader;
-    use crate::types::MacroblockType;
+    use crate::types::{
+        BPredictionMode, HalfPel, IntraPredictionMode, Macroblock, MacroblockType, MotionVector,
+        Picture, PictureOption, PictureTypeCode, QuantizerChange,
+    };
 
     #[test]
     #[allow(clippy::inconsistent_digit_grouping)]
@@ -749,6 +1047,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn disposable_pframe_macroblock() {
+        //COD, MCBPC (Inter), CBPY (no luma blocks), then a zero MVD. Sorenson
+        //disposable P-frames share the macroblock layer of P-frames.
+        let bit_pattern = [0b0_1_11_1_1_00];
+
+        for picture_type in [PictureTypeCode::PFrame, PictureTypeCode::DisposablePFrame] {
+            let mut reader = H263Reader::from_source(&bit_pattern[..]);
+            let picture = Picture::for_test(picture_type);
+
+            match decode_macroblock(&mut reader, &picture, PictureOption::empty()).unwrap() {
+                Macroblock::Coded {
+                    mb_type,
+                    coded_block_pattern,
+                    motion_vector,
+                    ..
+                } => {
+                    assert_eq!(mb_type, MacroblockType::Inter);
+                    assert_eq!(coded_block_pattern.codes_luma, [false; 4]);
+                    assert!(!coded_block_pattern.codes_chroma_b);
+                    assert!(!coded_block_pattern.codes_chroma_r);
+                    assert_eq!(
+                        motion_vector.map(<(HalfPel, HalfPel)>::from),
+                        Some((HalfPel::zero(), HalfPel::zero()))
+                    );
+                }
+                mb => panic!("expected coded macroblock, got {:?}", mb),
+            }
+
+            assert_eq!(reader.read_bits::<u8>(2).unwrap(), 0);
+        }
+    }
+
     #[test]
     #[allow(clippy::inconsistent_digit_grouping)]
     fn macroblock_modb_table() {
@@ -760,6 +1091,121 @@ mod tests {
         assert_eq!(reader.read_vlc(&MODB_TABLE).unwrap(), (true, true));
     }
 
+    #[test]
+    #[allow(clippy::inconsistent_digit_grouping)]
+    fn macroblock_improved_modb_table() {
+        let bit_pattern = &[0b0_10_110_11, 0b10_11110_1, 0b1111_0000];
+        let mut reader = H263Reader::from_source(&bit_pattern[..]);
+
+        assert_eq!(
+            reader.read_vlc(&IMPROVED_MODB_TABLE).unwrap(),
+            (false, false, BPredictionMode::Bidirectional)
+        );
+        assert_eq!(
+            reader.read_vlc(&IMPROVED_MODB_TABLE).unwrap(),
+            (true, false, BPredictionMode::Bidirectional)
+        );
+        assert_eq!(
+            reader.read_vlc(&IMPROVED_MODB_TABLE).unwrap(),
+            (false, true, BPredictionMode::Forward)
+        );
+        assert_eq!(
+            reader.read_vlc(&IMPROVED_MODB_TABLE).unwrap(),
+            (true, true, BPredictionMode::Forward)
+        );
+        assert_eq!(
+            reader.read_vlc(&IMPROVED_MODB_TABLE).unwrap(),
+            (false, false, BPredictionMode::Backward)
+        );
+        assert_eq!(
+            reader.read_vlc(&IMPROVED_MODB_TABLE).unwrap(),
+            (true, false, BPredictionMode::Backward)
+        );
+    }
+
+    #[test]
+    #[allow(clippy::inconsistent_digit_grouping)]
+    fn macroblock_mbtype_b_table() {
+        let bit_pattern = &[
+            0b1_011_0000,
+            0b1_0100_010,
+            0b1_000110_0,
+            0b010_0011_0,
+            0b00111_000,
+            0b100_00010,
+            0b1_000001_0,
+            0b00000000,
+            0b1_0000001,
+            0b00000001,
+        ];
+        let mut reader = H263Reader::from_source(&bit_pattern[..]);
+
+        for (mb_type, b_prediction_mode, has_cbp) in [
+            (MacroblockType::Inter, Some(BPredictionMode::Direct), false),
+            (MacroblockType::Inter, Some(BPredictionMode::Direct), true),
+            (MacroblockType::InterQ, Some(BPredictionMode::Direct), true),
+            (MacroblockType::Inter, Some(BPredictionMode::Forward), false),
+            (MacroblockType::Inter, Some(BPredictionMode::Forward), true),
+            (MacroblockType::InterQ, Some(BPredictionMode::Forward), true),
+            (
+                MacroblockType::Inter,
+                Some(BPredictionMode::Backward),
+                false,
+            ),
+            (MacroblockType::Inter, Some(BPredictionMode::Backward), true),
+            (
+                MacroblockType::InterQ,
+                Some(BPredictionMode::Backward),
+                true,
+            ),
+            (
+                MacroblockType::Inter,
+                Some(BPredictionMode::Bidirectional),
+                false,
+            ),
+            (
+                MacroblockType::Inter,
+                Some(BPredictionMode::Bidirectional),
+                true,
+            ),
+            (
+                MacroblockType::InterQ,
+                Some(BPredictionMode::Bidirectional),
+                true,
+            ),
+        ] {
+            assert_eq!(
+                reader.read_vlc(&MBTYPE_B_TABLE).unwrap(),
+                BMacroblockTypeEntry::Valid(mb_type, b_prediction_mode, has_cbp)
+            );
+        }
+
+        assert_eq!(
+            reader.read_vlc(&MBTYPE_B_TABLE).unwrap(),
+            BMacroblockTypeEntry::Stuffing
+        );
+        assert_eq!(
+            reader.read_vlc(&MBTYPE_B_TABLE).unwrap(),
+            BMacroblockTypeEntry::Valid(MacroblockType::Intra, None, true)
+        );
+        assert_eq!(
+            reader.read_vlc(&MBTYPE_B_TABLE).unwrap(),
+            BMacroblockTypeEntry::Valid(MacroblockType::IntraQ, None, true)
+        );
+    }
+
+    #[test]
+    #[allow(clippy::inconsistent_digit_grouping)]
+    fn macroblock_cbpc_b_table() {
+        let bit_pattern = &[0b0_10_110_11, 0b1_0000000];
+        let mut reader = H263Reader::from_source(&bit_pattern[..]);
+
+        assert_eq!(reader.read_vlc(&CBPC_B_TABLE).unwrap(), (false, false));
+        assert_eq!(reader.read_vlc(&CBPC_B_TABLE).unwrap(), (false, true));
+        assert_eq!(reader.read_vlc(&CBPC_B_TABLE).unwrap(), (true, true));
+        assert_eq!(reader.read_vlc(&CBPC_B_TABLE).unwrap(), (true, false));
+    }
+
     #[test]
     #[allow(clippy::inconsistent_digit_grouping)]
     fn macroblock_cbpy_table() {
@@ -938,73 +1384,437 @@ mod tests {
         ];
         let mut reader = H263Reader::from_source(&bit_pattern[..]);
 
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-16.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-15.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-15.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-14.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-14.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-13.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-13.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-12.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-12.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-11.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-11.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-10.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-10.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-9.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-9.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-8.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-8.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-7.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-7.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-6.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-6.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-5.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-5.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-4.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-4.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-3.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-3.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-2.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-2.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-1.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-1.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(-0.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(0.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(0.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(1.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(1.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(2.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(2.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(3.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(3.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(4.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(4.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(5.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(5.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(6.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(6.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(7.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(7.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(8.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(8.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(9.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(9.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(10.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(10.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(11.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(11.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(12.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(12.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(13.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(13.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(14.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(14.5));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(15.0));
-        assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), Some(15.5));
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-32))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-31))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-30))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-29))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-28))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-27))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-26))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-25))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-24))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-23))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-22))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-21))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-20))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-19))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-18))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-17))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-16))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-15))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-14))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-13))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-12))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-11))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-10))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-9))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-8))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-7))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-6))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-5))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-4))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-3))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-2))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(-1))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(0))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(1))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(2))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(3))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(4))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(5))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(6))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(7))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(8))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(9))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(10))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(11))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(12))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(13))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(14))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(15))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(16))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(17))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(18))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(19))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(20))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(21))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(22))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(23))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(24))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(25))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(26))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(27))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(28))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(29))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(30))
+        );
+        assert_eq!(
+            reader.read_vlc(&MVD_TABLE).unwrap(),
+            Some(HalfPel::from_unit(31))
+        );
         assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), None);
         assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), None);
         assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), None);
         assert_eq!(reader.read_vlc(&MVD_TABLE).unwrap(), None);
     }
+
+    #[test]
+    fn inter4v_macroblock_with_umv() {
+        //COD, MCBPC (Inter4V), CBPY, then four UMV-coded vectors. The last
+        //vector is (1, 1) and thus carries a start code emulation bit.
+        let bit_pattern = [
+            0b0010_1100,
+            0b0010_1001,
+            0b0000_1100,
+            0b1100_0000,
+            0b0010_0000,
+        ];
+        let mut reader = H263Reader::from_source(&bit_pattern[..]);
+        let options =
+            PictureOption::UNRESTRICTED_MOTION_VECTORS | PictureOption::ADVANCED_PREDICTION;
+        let picture = Picture {
+            options,
+            has_plusptype: true,
+            has_opptype: true,
+            ..Picture::for_test(PictureTypeCode::PFrame)
+        };
+
+        let mv = |x, y| MotionVector::from((HalfPel::from_unit(x), HalfPel::from_unit(y)));
+
+        match decode_macroblock(&mut reader, &picture, options).unwrap() {
+            Macroblock::Coded {
+                mb_type,
+                motion_vector,
+                addl_motion_vectors,
+                ..
+            } => {
+                assert_eq!(mb_type, MacroblockType::Inter4V);
+                assert_eq!(motion_vector, Some(mv(1, -1)));
+                assert_eq!(addl_motion_vectors, Some([mv(0, 2), mv(-2, 3), mv(1, 1)]));
+            }
+            mb => panic!("expected coded macroblock, got {:?}", mb),
+        }
+
+        assert_eq!(reader.bit_position(), 35);
+    }
+
+    #[test]
+    #[allow(clippy::inconsistent_digit_grouping)]
+    fn b_picture_macroblock() {
+        //MBTYPE (Forward + CBP), CBPC-B, CBPY (all luma blocks), MVDFW (4, 0),
+        //then MBTYPE (stuffing). B pictures have no COD.
+        let bit_pattern = [0b0101_10_00, 0b11_000001, 0b0110_1_000, 0b0000001_0];
+        let mut reader = H263Reader::from_source(&bit_pattern[..]);
+        let picture = Picture::for_test(PictureTypeCode::BFrame);
+
+        match decode_macroblock(&mut reader, &picture, PictureOption::empty()).unwrap() {
+            Macroblock::Coded {
+                mb_type,
+                coded_block_pattern,
+                motion_vector,
+                motion_vector_b,
+                b_prediction_mode,
+                ..
+            } => {
+                assert_eq!(mb_type, MacroblockType::Inter);
+                assert_eq!(coded_block_pattern.codes_luma, [true; 4]);
+                assert!(!coded_block_pattern.codes_chroma_b);
+                assert!(coded_block_pattern.codes_chroma_r);
+                assert_eq!(
+                    motion_vector,
+                    Some(MotionVector::from((
+                        HalfPel::from_unit(8),
+                        HalfPel::from_unit(0)
+                    )))
+                );
+                assert_eq!(motion_vector_b, None);
+                assert_eq!(b_prediction_mode, Some(BPredictionMode::Forward));
+            }
+            mb => panic!("expected coded macroblock, got {:?}", mb),
+        }
+
+        assert_eq!(reader.bit_position(), 21);
+        assert!(matches!(
+            decode_macroblock(&mut reader, &picture, PictureOption::empty()).unwrap(),
+            Macroblock::Stuffing
+        ));
+    }
+
+    #[test]
+    fn advanced_intra_coding_mode() {
+        //MCBPC (Intra), INTRA_MODE (horizontal), CBPY, then the same with
+        //INTRA_MODE (DC only).
+        let bit_pattern = [0b1_11_0011_1, 0b0_0011_000];
+        let mut reader = H263Reader::from_source(&bit_pattern[..]);
+        let options = PictureOption::ADVANCED_INTRA_CODING;
+        let picture = Picture {
+            options,
+            has_plusptype: true,
+            has_opptype: true,
+            ..Picture::for_test(PictureTypeCode::IFrame)
+        };
+
+        for (expected_mode, expected_position) in [
+            (IntraPredictionMode::Horizontal, 7),
+            (IntraPredictionMode::Dc, 13),
+        ] {
+            match decode_macroblock(&mut reader, &picture, options).unwrap() {
+                Macroblock::Coded {
+                    mb_type,
+                    coded_block_pattern,
+                    intra_prediction_mode,
+                    ..
+                } => {
+                    assert_eq!(mb_type, MacroblockType::Intra);
+                    assert_eq!(coded_block_pattern.codes_luma, [false; 4]);
+                    assert_eq!(intra_prediction_mode, Some(expected_mode));
+                }
+                mb => panic!("expected coded macroblock, got {:?}", mb),
+            }
+
+            assert_eq!(reader.bit_position(), expected_position);
+        }
+    }
+
+    #[test]
+    fn modified_quantization_dquant() {
+        //MCBPC (IntraQ), CBPY, DQUANT (step down), then the same with DQUANT
+        //(absolute 12), then DQUANT (absolute 0) which is invalid.
+        let bit_pattern = [
+            0b0001_0011,
+            0b10_0001_00,
+            0b11_0_01100,
+            0b0001_0011,
+            0b0_00000_00,
+        ];
+        let mut reader = H263Reader::from_source(&bit_pattern[..]);
+        let options = PictureOption::MODIFIED_QUANTIZATION;
+        let picture = Picture {
+            options,
+            has_plusptype: true,
+            has_opptype: true,
+            ..Picture::for_test(PictureTypeCode::IFrame)
+        };
+
+        for (expected_change, expected_position) in [
+            (QuantizerChange::Step(false), 10),
+            (QuantizerChange::Absolute(12), 24),
+        ] {
+            match decode_macroblock(&mut reader, &picture, options).unwrap() {
+                Macroblock::Coded {
+                    mb_type,
+                    d_quantizer,
+                    ..
+                } => {
+                    assert_eq!(mb_type, MacroblockType::IntraQ);
+                    assert_eq!(d_quantizer, Some(expected_change));
+                }
+                mb => panic!("expected coded macroblock, got {:?}", mb),
+            }
+
+            assert_eq!(reader.bit_position(), expected_position);
+        }
+
+        assert!(decode_macroblock(&mut reader, &picture, options).is_err());
+        assert_eq!(reader.bit_position(), 24);
+
+        assert_eq!(QuantizerChange::Step(false).apply(1), 3);
+        assert_eq!(QuantizerChange::Step(true).apply(1), 2);
+        assert_eq!(QuantizerChange::Step(false).apply(10), 9);
+        assert_eq!(QuantizerChange::Step(true).apply(15), 17);
+        assert_eq!(QuantizerChange::Step(false).apply(28), 25);
+        assert_eq!(QuantizerChange::Step(true).apply(29), 31);
+        assert_eq!(QuantizerChange::Step(true).apply(31), 26);
+        assert_eq!(QuantizerChange::Absolute(12).apply(31), 12);
+        assert_eq!(QuantizerChange::Delta(2).apply(30), 31);
+        assert_eq!(QuantizerChange::Delta(-2).apply(2), 1);
+    }
 }
@@ -4,10 +4,10 @@ use crate::decoder::DecoderOption;
 use crate::error::{Error, Result};
 use crate::parser::reader::H263Reader;
 use crate::types::{
-    BPictureQuantizer, BackchannelMessage, CustomPictureClock, CustomPictureFormat,
-    MotionVectorRange, Picture, PictureOption, PictureTypeCode, PixelAspectRatio,
-    ReferencePictureResampling, ReferencePictureSelectionMode, ScalabilityLayer, SliceSubmode,
-    SourceFormat,
+    BPictureQuantizer, BackchannelMessage, BackchannelMessageType, BackchannelReliability,
+    CustomPictureClock, CustomPictureFormat, MotionVectorRange, Picture, PictureOption,
+    PictureTypeCode, PixelAspectRatio, ReferencePictureResampling, ReferencePictureSelectionMode,
+    ScalabilityLayer, SliceSubmode, SourceFormat, WarpingDisplacementAccuracy, WarpingFillMode,
 };
 use std::io::Read;
 
@@ -18,7 +18,10 @@ use std::io::Read;
 pub type PType = (PictureOption, Option<(SourceFormat, PictureTypeCode)>);
 
 /// Decodes the first 8 bits of `PTYPE`.
-fn decode_ptype<R>(reader: &mut H263Reader<R>) -> Result<PType>
+///
+/// Under `DecoderOption::STRICT_MODE`, I-frames that enable PB-frames mode
+/// are rejected; otherwise, they are decoded as PB frames.
+fn decode_ptype<R>(reader: &mut H263Reader<R>, decoder_options: DecoderOption) -> Result<PType>
 where
     R: Read,
 {
@@ -55,9 +58,9 @@ where
 
         let low_ptype_bits: u8 = reader.read_bits(5)?;
         let mut r#type = if low_ptype_bits & 0x10 != 0 {
-            PictureTypeCode::IFrame
-        } else {
             PictureTypeCode::PFrame
+        } else {
+            PictureTypeCode::IFrame
         };
 
         if low_ptype_bits & 0x08 != 0 {
@@ -73,6 +76,13 @@ where
         }
 
         if low_ptype_bits & 0x01 != 0 {
+            //H.263 5.1.3: PB-frames mode requires an INTER picture.
+            if r#type == PictureTypeCode::IFrame
+                && decoder_options.contains(DecoderOption::STRICT_MODE)
+            {
+                return Err(Error::IncompatiblePictureType);
+            }
+
             r#type = PictureTypeCode::PbFrame;
         }
 
@@ -265,10 +275,27 @@ where
     })
 }
 
+/// Check that a picture type can be used with the decoder options in force.
+///
+/// This rejects reserved picture types, as well as B, EI, and EP pictures
+/// when the Temporal, SNR, and Spatial Scalability mode (Annex O) has not
+/// been negotiated.
+fn check_picture_type(picture_type: PictureTypeCode, decoder_options: DecoderOption) -> Result<()> {
+    match picture_type {
+        PictureTypeCode::Reserved(_) => Err(Error::IncompatiblePictureType),
+        PictureTypeCode::BFrame | PictureTypeCode::EiFrame | PictureTypeCode::EpFrame
+            if !decoder_options.contains(DecoderOption::USE_SCALABILITY_MODE) =>
+        {
+            Err(Error::IncompatiblePictureType)
+        }
+        _ => Ok(()),
+    }
+}
+
 type SorensonPType = (SourceFormat, PictureTypeCode, PictureOption);
 
 /// Attempts to read a Sorenson-equivalent PTYPE from the bitstream.
-fn decode_sorenson_ptype<R>(reader: &mut H263Reader<R>) -> Result<SorensonPType>
+pub(crate) fn decode_sorenson_ptype<R>(reader: &mut H263Reader<R>) -> Result<SorensonPType>
 where
     R: Read,
 {
@@ -332,6 +359,10 @@ where
 /// is present in the bitstream. If it is present, then this function should
 /// be called immediately after parsing it. Otherwise, this function should be
 /// called after parsing `PQUANT`.
+///
+/// Reading `CPM` at the wrong one of these two positions misaligns every
+/// field between them, so `decode_picture` picks the position based on
+/// `has_plusptype` alone.
 fn decode_cpm_and_psbi<R>(reader: &mut H263Reader<R>) -> Result<Option<u8>>
 where
     R: Read,
@@ -399,10 +430,7 @@ where
     reader.with_transaction(|reader| {
         let cpcfc = reader.read_u8()?;
 
-        Ok(CustomPictureClock {
-            times_1001: cpcfc & 0x80 != 0,
-            divisor: cpcfc & 0x7F,
-        })
+        CustomPictureClock::new(cpcfc & 0x80 != 0, cpcfc & 0x7F).ok_or(Error::InvalidSemantics)
     })
 }
 
@@ -422,7 +450,7 @@ where
             return Ok(MotionVectorRange::Unlimited);
         }
 
-        Err(Error::InvalidBitstream)
+        Err(Error::InvalidSyntax)
     })
 }
 
@@ -448,6 +476,9 @@ where
 }
 
 /// Attempts to read `ELNUM` and `RLNUM` from the bitstream.
+///
+/// `RLNUM` is only present if `UFEP` is 001, which `decode_plusptype` signals
+/// with the `HAS_REFERENCE_LAYER_NUMBER` follower.
 fn decode_elnum_rlnum<R>(
     reader: &mut H263Reader<R>,
     followers: PlusPTypeFollower,
@@ -514,7 +545,18 @@ where
 }
 
 /// Attempts to read `BCI` and `BCM` from the bitstream.
-fn decode_bcm<R>(reader: &mut H263Reader<R>) -> Result<Option<BackchannelMessage>>
+///
+/// The optional fields of `BCM` depend on the rest of the picture header:
+/// `ELNUMI` is only present in scalable bitstreams, `BCPM` only when
+/// continuous presence multipoint is in use, and `GN/MBA` is a macroblock
+/// address of `mba_bits` bits if slices are in use, or a 5 bit GOB number
+/// if `mba_bits` is `None`.
+fn decode_bcm<R>(
+    reader: &mut H263Reader<R>,
+    has_scalability: bool,
+    has_cpm: bool,
+    mba_bits: Option<u32>,
+) -> Result<Option<BackchannelMessage>>
 where
     R: Read,
 {
@@ -522,7 +564,59 @@ where
         let bci: u8 = reader.read_bits(1)?;
 
         if bci == 1 {
-            Err(Error::UnimplementedDecoding)
+            let message_type = match reader.read_bits::<u8>(2)? {
+                0b10 => BackchannelMessageType::NegativeAcknowledge,
+                0b11 => BackchannelMessageType::Acknowledge,
+                reserved => BackchannelMessageType::Reserved(reserved),
+            };
+
+            let reliable = if reader.read_bits::<u8>(1)? == 0 {
+                BackchannelReliability::Reliable
+            } else {
+                BackchannelReliability::Unreliable
+            };
+
+            let temporal_reference = reader.read_bits(10)?;
+
+            let enhancement_layer = if has_scalability && reader.read_bits::<u8>(1)? == 1 {
+                Some(reader.read_bits(4)?)
+            } else {
+                None
+            };
+
+            let sub_bitstream = if has_cpm && reader.read_bits::<u8>(1)? == 1 {
+                Some(reader.read_bits(2)?)
+            } else {
+                None
+            };
+
+            //BEPB1 and BEPB2 prevent start code emulation.
+            if reader.read_bits::<u8>(1)? != 1 {
+                return Err(Error::InvalidSyntax);
+            }
+
+            let gob_macroblock_address = reader.read_bits(mba_bits.unwrap_or(5))?;
+
+            if reader.read_bits::<u8>(1)? != 1 {
+                return Err(Error::InvalidSyntax);
+            }
+
+            let requested_temporal_reference =
+                if matches!(message_type, BackchannelMessageType::NegativeAcknowledge) {
+                    Some(reader.read_bits(10)?)
+                } else {
+                    None
+                };
+
+            Ok(Some(BackchannelMessage {
+                message_type,
+                reliable,
+                temporal_reference,
+                enhancement_layer,
+                sub_bitstream,
+                gob_macroblock_address: Some(gob_macroblock_address),
+                requested_temporal_reference,
+            }))
         } else {
             let not_bci: u8 = reader.read_bits(1)?;
 
@@ -530,18 +624,76 @@ where
                 Ok(None)
             } else {
                 // BCI must be `1` or `01`
-                Err(Error::InvalidBitstream)
+                Err(Error::InvalidSyntax)
             }
         }
     })
 }
 
+/// Determine the length of a macroblock address in pictures of a given
+/// format, as per H.263 (01/2005) table K.2.
+fn macroblock_address_bits(format: SourceFormat) -> Option<u32> {
+    let (width, height) = format.into_width_and_height()?;
+    let mb_count = (width as u32).div_ceil(16) * (height as u32).div_ceil(16);
+
+    Some(u32::BITS - mb_count.saturating_sub(1).leading_zeros())
+}
+
 /// Attempts to read `RPRP` from the bitstream.
-fn decode_rprp<R>(reader: &mut H263Reader<R>) -> Result<Option<ReferencePictureResampling>>
+///
+/// If `is_explicit` is not flagged, the picture merely changed size without
+/// using Reference Picture Resampling (Annex P) and no bits are read; the
+/// reference picture is instead resampled by the implicit factor of 4, with
+/// no warping parameters and the edges of the reference picture extended.
+fn decode_rprp<R>(
+    reader: &mut H263Reader<R>,
+    is_explicit: bool,
+) -> Result<Option<ReferencePictureResampling>>
 where
     R: Read,
 {
-    reader.with_transaction(|_reader| Err(Error::UnimplementedDecoding))
+    if !is_explicit {
+        return Ok(Some(ReferencePictureResampling {
+            accuracy: WarpingDisplacementAccuracy::HalfPixel,
+            warps: None,
+            fill_mode: WarpingFillMode::Clip,
+        }));
+    }
+
+    reader.with_transaction(|reader| {
+        let accuracy = match reader.read_bits::<u8>(2)? {
+            0b10 => WarpingDisplacementAccuracy::HalfPixel,
+            0b11 => WarpingDisplacementAccuracy::SixteenthPixel,
+            _ => return Err(Error::InvalidSyntax),
+        };
+
+        //The warping parameters are coded in pairs, the same way as motion
+        //vectors in UMV mode, including the start code emulation prevention.
+        let mut warps = [0; 8];
+        for pair in warps.chunks_exact_mut(2) {
+            let (x, y) = reader.read_umv_pair()?.into();
+            pair[0] = x.into_unit();
+            pair[1] = y.into_unit();
+        }
+
+        let fill_mode = match reader.read_bits::<u8>(2)? {
+            0b00 => WarpingFillMode::Color {
+                y: reader.read_u8()?,
+                cb: reader.read_u8()?,
+                cr: reader.read_u8()?,
+            },
+            0b01 => WarpingFillMode::Black,
+            0b10 => WarpingFillMode::Gray,
+            0b11 => WarpingFillMode::Clip,
+            _ => return Err(Error::InternalDecoderError),
+        };
+
+        Ok(Some(ReferencePictureResampling {
+            accuracy,
+            warps: Some(warps),
+            fill_mode,
+        }))
+    })
 }
 
 /// Attempts to read `TRB` from the bitstream.
@@ -594,6 +746,40 @@ where
     })
 }
 
+/// Attempts to read an end of sequence code (`EOS`) from an H.263 bitstream.
+///
+/// Yields `true` if the reader was pointing to an optionally-aligned `EOS`,
+/// which is consumed. Otherwise, yields `false` and leaves the reader where it
+/// was. `EOS` codes only appear in H.263 bitstreams; Sorenson Spark reuses the
+/// bits that would identify one as a version field.
+pub fn decode_end_of_sequence<R>(reader: &mut H263Reader<R>) -> Result<bool>
+where
+    R: Read,
+{
+    let eos_bits = reader.with_lookahead(|reader| {
+        let skipped_bits = match reader.recognize_start_code(false)? {
+            Some(skipped_bits) => skipped_bits,
+            None => return Ok(None),
+        };
+
+        reader.skip_bits(17 + skipped_bits)?;
+
+        if reader.read_bits::<u8>(5)? == 31 {
+            Ok(Some(22 + skipped_bits))
+        } else {
+            Ok(None)
+        }
+    })?;
+
+    match eos_bits {
+        Some(eos_bits) => {
+            reader.skip_bits(eos_bits)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
 /// Attempts to read a picture record from an H.263 bitstream.
 ///
 /// If no valid start code could be found in the bitstream, this function will
@@ -603,29 +789,71 @@ where
 ///
 /// The set of `DecoderOptions` allows configuring certain information about
 /// the decoding process that cannot be determined by decoding the bitstream
-/// itself.
+/// itself. Under `DecoderOption::STRICT_MODE`, the entire picture start code
+/// is validated before it is accepted, and pictures whose type contradicts
+/// their other options yield `IncompatiblePictureType`. The combinations
+/// checked are:
 ///
-/// `previous_picture_options` is the set of options that were enabled by the
-/// last decoded picture. If this is the first decoded picture in the
-/// bitstream, then this should be an empty set.
+///  * I-frames with PB-frames mode enabled in `PTYPE`
+///  * Reserved picture types in `MPPTYPE`
+///  * B, EI, and EP pictures without `DecoderOption::USE_SCALABILITY_MODE`
+///
+/// `previous_picture` is the header of the last decoded picture, if any. It
+/// supplies the options that pictures may carry forward without restating
+/// them, as well as the format that they may do the same with.
 pub fn decode_picture<R>(
     reader: &mut H263Reader<R>,
     decoder_options: DecoderOption,
     previous_picture: Option<&Picture>,
 ) -> Result<Option<Picture>>
+where
+    R: Read,
+{
+    decode_picture_with_previous_format(
+        reader,
+        decoder_options,
+        previous_picture,
+        previous_picture.and_then(|p| p.format),
+    )
+}
+
+/// Attempts to read a picture record from an H.263 bitstream, given the
+/// source format the previous picture was decoded with.
+///
+/// This works the same as `decode_picture`, except that `previous_format`
+/// is the format that the `previous_picture` was actually decoded with,
+/// even if it's header did not restate it. Whether the format of the
+/// picture changed (and thus, whether it carries an `RPRP`) can only be
+/// determined correctly with it. `previous_format` should be `None` if there
+/// is no `previous_picture`.
+pub fn decode_picture_with_previous_format<R>(
+    reader: &mut H263Reader<R>,
+    decoder_options: DecoderOption,
+    previous_picture: Option<&Picture>,
+    previous_format: Option<SourceFormat>,
+) -> Result<Option<Picture>>
 where
     R: Read,
 {
     reader.with_transaction_union(|reader| {
-        let skipped_bits = reader
-            .recognize_start_code(false)?
-            .ok_or(Error::MiddleOfBitstream)?;
+        let skipped_bits = if decoder_options.contains(DecoderOption::STRICT_MODE)
+            && !decoder_options.contains(DecoderOption::SORENSON_SPARK_BITSTREAM)
+        {
+            reader.recognize_picture_start_code(false)?
+        } else {
+            reader.recognize_start_code(false)?
+        }
+        .ok_or(Error::MiddleOfBitstream)?;
 
         reader.skip_bits(17 + skipped_bits)?;
 
         let gob_id = reader.read_bits(5)?;
 
         if decoder_options.contains(DecoderOption::SORENSON_SPARK_BITSTREAM) {
+            if gob_id > 1 {
+                return Err(Error::UnknownSorensonVersion(gob_id));
+            }
+
             let temporal_reference = reader.read_u8()? as u16;
             let (source_format, picture_type, options) = decode_sorenson_ptype(reader)?;
             let quantizer: u8 = reader.read_bits(5)?;
@@ -662,9 +890,8 @@ where
         }
 
         let low_tr = reader.read_u8()?;
-        let (mut options, maybe_format_and_type) = decode_ptype(reader)?;
-        let mut multiplex_bitstream = None;
-        let (mut format, picture_type, followers, has_plusptype, has_opptype) =
+        let (mut options, maybe_format_and_type) = decode_ptype(reader, decoder_options)?;
+        let (mut format, picture_type, followers, has_plusptype, has_opptype, plusptype_cpm) =
             match maybe_format_and_type {
                 Some((format, picture_type)) => (
                     Some(format),
@@ -672,6 +899,7 @@ where
                     PlusPTypeFollower::empty(),
                     false,
                     false,
+                    None,
                 ),
                 None => {
                     let (extra_options, maybe_format, picture_type, followers, has_opptype) =
@@ -685,21 +913,27 @@ where
 
                     options |= extra_options;
 
-                    multiplex_bitstream = Some(decode_cpm_and_psbi(reader)?);
-
-                    (maybe_format, picture_type, followers, true, has_opptype)
+                    //H.263 5.1.20: With a PLUSPTYPE, CPM follows it directly.
+                    let cpm = decode_cpm_and_psbi(reader)?;
+
+                    (
+                        maybe_format,
+                        picture_type,
+                        followers,
+                        true,
+                        has_opptype,
+                        cpm,
+                    )
                 }
             };
 
         //TODO: H.263 5.1.4.4-6 indicate a number of semantic restrictions on
         //picture options, modes, and followers. We should be inspecting our
         //set of options and raising an error if they're incorrect at this
-        //time.
-
-        //TODO: Some pictures don't restate their previous format, but the
-        //contents of the picture rely on if the format has changed. We need
-        //`decode_picture` to be able to look up previous picture headers
-        //somehow.
+        //time. Only the picture type is checked so far.
+        if decoder_options.contains(DecoderOption::STRICT_MODE) {
+            check_picture_type(picture_type, decoder_options)?;
+        }
 
         if followers.contains(PlusPTypeFollower::HAS_CUSTOM_FORMAT) {
             format = Some(SourceFormat::Extended(decode_cpfmt(reader)?));
@@ -752,29 +986,48 @@ where
         };
 
         let backchannel_message = if options.contains(PictureOption::REFERENCE_PICTURE_SELECTION) {
-            decode_bcm(reader)?
+            let mba_bits = if options.contains(PictureOption::SLICE_STRUCTURED) {
+                let format = format
+                    .or(previous_format)
+                    .ok_or(Error::PictureFormatMissing)?;
+
+                Some(macroblock_address_bits(format).ok_or(Error::PictureFormatInvalid)?)
+            } else {
+                None
+            };
+
+            decode_bcm(
+                reader,
+                scalability_layer.is_some(),
+                plusptype_cpm.is_some(),
+                mba_bits,
+            )?
         } else {
             None
         };
 
-        //TODO: this should be checking against the reference picture to see if we need RPRP
-        let reference_picture_resampling = if options
-            .contains(PictureOption::REFERENCE_PICTURE_RESAMPLING)
-            || previous_picture
-                .map(|p| p.format != format)
-                .unwrap_or(false)
+        //Pictures that don't restate their format keep the previous one.
+        let is_explicit_rprp = options.contains(PictureOption::REFERENCE_PICTURE_RESAMPLING);
+        let is_format_change = matches!(
+            (format, previous_format),
+            (Some(format), Some(previous_format)) if format != previous_format
+        );
+        let reference_picture_resampling = if is_explicit_rprp
+            || (!matches!(picture_type, PictureTypeCode::IFrame) && is_format_change)
         {
-            decode_rprp(reader)?
+            decode_rprp(reader, is_explicit_rprp)?
         } else {
             None
         };
 
         let quantizer: u8 = reader.read_bits(5)?;
 
-        if multiplex_bitstream.is_none() {
-            multiplex_bitstream = Some(decode_cpm_and_psbi(reader)?);
-        }
-        let multiplex_bitstream = multiplex_bitstream.unwrap();
+        //H.263 5.1.20: Without a PLUSPTYPE, CPM follows PQUANT instead.
+        let multiplex_bitstream = if has_plusptype {
+            plusptype_cpm
+        } else {
+            decode_cpm_and_psbi(reader)?
+        };
 
         //TODO: This needs to know the picture clock, which has the usual
         //reference picture thing I mentioned before in the last TODO
@@ -815,3 +1068,423 @@ where
         }))
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strict_picture_start_code() {
+        // Sub-QCIF P-frame, whose PTYPE does not start with the marker bits
+        let data = [0x00, 0x00, 0x80, 0x04, 0x06, 0x08, 0x3F];
+
+        let mut reader = H263Reader::from_source(&data[..]);
+        assert!(decode_picture(&mut reader, DecoderOption::empty(), None).is_err());
+
+        let mut reader = H263Reader::from_source(&data[..]);
+        assert!(matches!(
+            decode_picture(&mut reader, DecoderOption::STRICT_MODE, None),
+            Err(Error::MiddleOfBitstream)
+        ));
+
+        // The same, with the marker bits
+        let data = [0x00, 0x00, 0x80, 0x06, 0x06, 0x08, 0x3F];
+
+        let mut reader = H263Reader::from_source(&data[..]);
+        let picture = decode_picture(&mut reader, DecoderOption::STRICT_MODE, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(picture.temporal_reference, 1);
+    }
+
+    #[test]
+    fn incompatible_picture_type() {
+        // Sub-QCIF I-frame with PB-frames mode
+        let data = [0x00, 0x00, 0x80, 0x06, 0x04, 0x28, 0x10];
+
+        let mut reader = H263Reader::from_source(&data[..]);
+        let picture = decode_picture(&mut reader, DecoderOption::empty(), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(picture.picture_type, PictureTypeCode::PbFrame);
+
+        let mut reader = H263Reader::from_source(&data[..]);
+        assert!(matches!(
+            decode_picture(&mut reader, DecoderOption::STRICT_MODE, None),
+            Err(Error::IncompatiblePictureType)
+        ));
+
+        // PLUSPTYPE B-frame, without scalability mode
+        let data = [0x00, 0x00, 0x80, 0x06, 0x1C, 0x30, 0x48, 0x00, 0x00];
+
+        let mut reader = H263Reader::from_source(&data[..]);
+        assert!(!matches!(
+            decode_picture(&mut reader, DecoderOption::empty(), None),
+            Err(Error::IncompatiblePictureType)
+        ));
+
+        let mut reader = H263Reader::from_source(&data[..]);
+        assert!(matches!(
+            decode_picture(&mut reader, DecoderOption::STRICT_MODE, None),
+            Err(Error::IncompatiblePictureType)
+        ));
+        assert!(!matches!(
+            decode_picture(
+                &mut reader,
+                DecoderOption::STRICT_MODE | DecoderOption::USE_SCALABILITY_MODE,
+                None
+            ),
+            Err(Error::IncompatiblePictureType)
+        ));
+    }
+
+    #[test]
+    fn cpm_placement() {
+        // Sub-QCIF I-frame, PQUANT 7, CPM 1, PSBI 2, PEI 0
+        let data = [0x00, 0x00, 0x80, 0x06, 0x04, 0x07, 0xC0];
+
+        let mut reader = H263Reader::from_source(&data[..]);
+        let picture = decode_picture(&mut reader, DecoderOption::empty(), None)
+            .unwrap()
+            .unwrap();
+        assert!(!picture.has_plusptype);
+        assert_eq!(picture.quantizer, 7);
+        assert_eq!(picture.multiplex_bitstream, Some(2));
+        assert_eq!(reader.bit_position(), 52);
+
+        // PLUSPTYPE with OPPTYPE, sub-QCIF I-frame, CPM 1, PSBI 2, PQUANT 7,
+        // PEI 0
+        let data = [0x00, 0x00, 0x80, 0x06, 0x1C, 0x90, 0x01, 0x00, 0x1C, 0x70];
+
+        let mut reader = H263Reader::from_source(&data[..]);
+        let picture = decode_picture(&mut reader, DecoderOption::empty(), None)
+            .unwrap()
+            .unwrap();
+        assert!(picture.has_plusptype);
+        assert_eq!(picture.quantizer, 7);
+        assert_eq!(picture.multiplex_bitstream, Some(2));
+        assert_eq!(reader.bit_position(), 77);
+    }
+
+    #[test]
+    fn inherited_format() {
+        // CIF I-frame, TR 0
+        let data = [0x00, 0x00, 0x80, 0x02, 0x0C, 0x08, 0x00];
+        let mut reader = H263Reader::from_source(&data[..]);
+        let i_frame = decode_picture(&mut reader, DecoderOption::empty(), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(i_frame.format, Some(SourceFormat::FullCif));
+
+        // P-frame, TR 1, with a PLUSPTYPE that doesn't restate the format
+        let data = [0x00, 0x00, 0x80, 0x06, 0x1C, 0x10, 0x48, 0x00];
+        let mut reader = H263Reader::from_source(&data[..]);
+        let p_frame = decode_picture_with_previous_format(
+            &mut reader,
+            DecoderOption::empty(),
+            Some(&i_frame),
+            i_frame.format,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(p_frame.picture_type, PictureTypeCode::PFrame);
+        assert_eq!(p_frame.format, None);
+        assert_eq!(p_frame.reference_picture_resampling, None);
+        assert_eq!(reader.bit_position(), 57);
+
+        // CIF P-frame, TR 2, following the one that inherited CIF
+        let data = [0x00, 0x00, 0x80, 0x0A, 0x0E, 0x08, 0x00];
+        let mut reader = H263Reader::from_source(&data[..]);
+        let picture = decode_picture_with_previous_format(
+            &mut reader,
+            DecoderOption::empty(),
+            Some(&p_frame),
+            Some(SourceFormat::FullCif),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(picture.format, Some(SourceFormat::FullCif));
+        assert_eq!(picture.reference_picture_resampling, None);
+        assert_eq!(reader.bit_position(), 50);
+
+        // The same P-frame, changing from QCIF, is implicitly resampled
+        let mut reader = H263Reader::from_source(&data[..]);
+        let picture = decode_picture_with_previous_format(
+            &mut reader,
+            DecoderOption::empty(),
+            Some(&p_frame),
+            Some(SourceFormat::QuarterCif),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(picture.reference_picture_resampling.unwrap().warps, None);
+        assert_eq!(reader.bit_position(), 50);
+    }
+
+    #[test]
+    fn bcm() {
+        // ACK of TR 5, GOB 3
+        let data = [0xE0, 0x16, 0x38];
+        let mut reader = H263Reader::from_source(&data[..]);
+        assert_eq!(
+            decode_bcm(&mut reader, false, false, None).unwrap(),
+            Some(BackchannelMessage {
+                message_type: BackchannelMessageType::Acknowledge,
+                reliable: BackchannelReliability::Reliable,
+                temporal_reference: 5,
+                enhancement_layer: None,
+                sub_bitstream: None,
+                gob_macroblock_address: Some(3),
+                requested_temporal_reference: None,
+            })
+        );
+        assert_eq!(reader.bit_position(), 21);
+
+        // Unreliable NACK of TR 1023, layer 3, sub-bitstream 2, sub-QCIF
+        // macroblock 47, requesting TR 4
+        let data = [0xDF, 0xFE, 0x7B, 0x7C, 0x04];
+        let mut reader = H263Reader::from_source(&data[..]);
+        assert_eq!(
+            decode_bcm(
+                &mut reader,
+                true,
+                true,
+                macroblock_address_bits(SourceFormat::SubQcif)
+            )
+            .unwrap(),
+            Some(BackchannelMessage {
+                message_type: BackchannelMessageType::NegativeAcknowledge,
+                reliable: BackchannelReliability::Unreliable,
+                temporal_reference: 1023,
+                enhancement_layer: Some(3),
+                sub_bitstream: Some(2),
+                gob_macroblock_address: Some(47),
+                requested_temporal_reference: Some(4),
+            })
+        );
+        assert_eq!(reader.bit_position(), 40);
+
+        // BEPB1 must be set
+        let data = [0xE0, 0x14, 0x38];
+        let mut reader = H263Reader::from_source(&data[..]);
+        assert!(matches!(
+            decode_bcm(&mut reader, false, false, None),
+            Err(Error::InvalidSyntax)
+        ));
+        assert_eq!(reader.bit_position(), 0);
+
+        // No backchannel message
+        let data = [0x40];
+        let mut reader = H263Reader::from_source(&data[..]);
+        assert_eq!(decode_bcm(&mut reader, false, false, None).unwrap(), None);
+        assert_eq!(reader.bit_position(), 2);
+    }
+
+    #[test]
+    fn mba_lengths() {
+        assert_eq!(macroblock_address_bits(SourceFormat::SubQcif), Some(6));
+        assert_eq!(macroblock_address_bits(SourceFormat::QuarterCif), Some(7));
+        assert_eq!(macroblock_address_bits(SourceFormat::FullCif), Some(9));
+        assert_eq!(macroblock_address_bits(SourceFormat::FourCif), Some(11));
+        assert_eq!(macroblock_address_bits(SourceFormat::SixteenCif), Some(13));
+        assert_eq!(macroblock_address_bits(SourceFormat::Reserved), None);
+    }
+
+    #[test]
+    fn end_of_sequence() {
+        // Five bits of data, then a byte-aligned EOS, followed by a
+        // byte-aligned picture start code
+        let data = [
+            0b1010_1000,
+            0b0000_0000,
+            0b0000_0000,
+            0b1111_1100,
+            0b0000_0000,
+            0b0000_0000,
+            0b1000_0000,
+        ];
+        let mut reader = H263Reader::from_source(&data[..]);
+        reader.skip_bits(5).unwrap();
+
+        assert!(decode_end_of_sequence(&mut reader).unwrap());
+        assert!(!decode_end_of_sequence(&mut reader).unwrap());
+        assert_eq!(reader.recognize_start_code(false).unwrap(), Some(2));
+    }
+
+    #[test]
+    fn cpcfc() {
+        let data = [0b1001_1110, 0b0000_0001, 0b1000_0000];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        assert_eq!(
+            decode_cpcfc(&mut reader).unwrap(),
+            CustomPictureClock::new(true, 30).unwrap()
+        );
+        assert_eq!(
+            decode_cpcfc(&mut reader).unwrap(),
+            CustomPictureClock::new(false, 1).unwrap()
+        );
+        assert!(matches!(
+            decode_cpcfc(&mut reader),
+            Err(Error::InvalidSemantics)
+        ));
+    }
+
+    #[test]
+    fn uui() {
+        // Extended, unlimited, then the invalid `00`
+        let data = [0b1010_0000];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        assert!(matches!(
+            decode_uui(&mut reader),
+            Ok(MotionVectorRange::Extended)
+        ));
+        assert!(matches!(
+            decode_uui(&mut reader),
+            Ok(MotionVectorRange::Unlimited)
+        ));
+        assert!(matches!(decode_uui(&mut reader), Err(Error::InvalidSyntax)));
+    }
+
+    #[test]
+    fn rprp() {
+        // Half-pixel accuracy, warps of (1, 0), (0, -1), (0, 0), (0, 0), black
+        let data = [0b1000_0110, 0b1011_1101];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        assert_eq!(
+            decode_rprp(&mut reader, true).unwrap(),
+            Some(ReferencePictureResampling {
+                accuracy: WarpingDisplacementAccuracy::HalfPixel,
+                warps: Some([1, 0, 0, -1, 0, 0, 0, 0]),
+                fill_mode: WarpingFillMode::Black,
+            })
+        );
+        assert_eq!(reader.bit_position(), 16);
+
+        // Sixteenth-pixel accuracy, warps of (1, 1) and it's emulation
+        // prevention bit, then (0, 0) three times, filled with a color
+        let data = [
+            0b1100_0000,
+            0b1111_1110,
+            0b0000_1000,
+            0b0100_0000,
+            0b0100_0000,
+            0b0000_0000,
+        ];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        assert_eq!(
+            decode_rprp(&mut reader, true).unwrap(),
+            Some(ReferencePictureResampling {
+                accuracy: WarpingDisplacementAccuracy::SixteenthPixel,
+                warps: Some([1, 1, 0, 0, 0, 0, 0, 0]),
+                fill_mode: WarpingFillMode::Color {
+                    y: 16,
+                    cb: 128,
+                    cr: 128
+                },
+            })
+        );
+        assert_eq!(reader.bit_position(), 41);
+
+        // The reserved accuracies
+        let data = [0b0000_0000];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        assert!(matches!(
+            decode_rprp(&mut reader, true),
+            Err(Error::InvalidSyntax)
+        ));
+        assert_eq!(reader.bit_position(), 0);
+
+        // Implicit factor-of-4 resampling reads nothing
+        assert_eq!(
+            decode_rprp(&mut reader, false).unwrap(),
+            Some(ReferencePictureResampling {
+                accuracy: WarpingDisplacementAccuracy::HalfPixel,
+                warps: None,
+                fill_mode: WarpingFillMode::Clip,
+            })
+        );
+        assert_eq!(reader.bit_position(), 0);
+    }
+
+    #[test]
+    fn rlnum_requires_opptype() {
+        // UFEP 000, P-frame, ELNUM 5, then four bits that are not RLNUM
+        let data = [0b0000_0100, 0b0001_0101, 0b1010_0000];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        let (_, _, picture_type, followers, has_opptype) = decode_plusptype(
+            &mut reader,
+            DecoderOption::USE_SCALABILITY_MODE,
+            PictureOption::empty(),
+        )
+        .unwrap();
+
+        assert!(!has_opptype);
+        assert!(matches!(picture_type, PictureTypeCode::PFrame));
+        assert!(!followers.contains(PlusPTypeFollower::HAS_REFERENCE_LAYER_NUMBER));
+
+        let layer = decode_elnum_rlnum(&mut reader, followers).unwrap();
+        assert_eq!(layer.enhancement, 5);
+        assert_eq!(layer.reference, None);
+        assert_eq!(reader.read_bits::<u8>(4).unwrap(), 0b1010);
+    }
+
+    #[test]
+    fn rlnum_with_opptype() {
+        // UFEP 001, QCIF, P-frame, ELNUM 5, RLNUM 10
+        let data = [
+            0b0010_1000,
+            0b0000_0000,
+            0b0100_0001,
+            0b0000_0101,
+            0b0110_1000,
+        ];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        let (_, _, _, followers, has_opptype) = decode_plusptype(
+            &mut reader,
+            DecoderOption::USE_SCALABILITY_MODE,
+            PictureOption::empty(),
+        )
+        .unwrap();
+
+        assert!(has_opptype);
+        assert!(followers.contains(PlusPTypeFollower::HAS_REFERENCE_LAYER_NUMBER));
+
+        let layer = decode_elnum_rlnum(&mut reader, followers).unwrap();
+        assert_eq!(layer.enhancement, 5);
+        assert_eq!(layer.reference, Some(10));
+    }
+
+    #[test]
+    fn sorenson_versions() {
+        // Version 0, QCIF, I-frame
+        let data = [0x00, 0x00, 0x80, 0x01, 0x82, 0x80];
+        let mut reader = H263Reader::from_source(&data[..]);
+        let picture = decode_picture(&mut reader, DecoderOption::SORENSON_SPARK_BITSTREAM, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(picture.version, Some(0));
+
+        // Version 1, QCIF, I-frame
+        let data = [0x00, 0x00, 0x84, 0x01, 0x82, 0x80];
+        let mut reader = H263Reader::from_source(&data[..]);
+        let picture = decode_picture(&mut reader, DecoderOption::SORENSON_SPARK_BITSTREAM, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(picture.version, Some(1));
+
+        // Version 2, QCIF, I-frame
+        let data = [0x00, 0x00, 0x88, 0x01, 0x82, 0x80];
+        let mut reader = H263Reader::from_source(&data[..]);
+        assert!(matches!(
+            decode_picture(&mut reader, DecoderOption::SORENSON_SPARK_BITSTREAM, None),
+            Err(Error::UnknownSorensonVersion(2))
+        ));
+    }
+}
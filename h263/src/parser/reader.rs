@@ -3,10 +3,10 @@
 use crate::error::{Error, Result};
 use crate::parser::vlc::{Entry, Table};
 use crate::traits::BitReadable;
-use crate::types::HalfPel;
+use crate::types::{HalfPel, MotionVector};
 use std::cmp::min;
 use std::collections::VecDeque;
-use std::io::Read;
+use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Seek, SeekFrom};
 
 /// A reader that allows decoding an H.263 compliant bitstream.
 ///
@@ -27,6 +27,40 @@ where
     /// If this value modulo eight is nonzero, then reads out of the internal
     /// buffer must read
     bits_read: usize,
+
+    /// How many bits were discarded from the front of the internal buffer
+    /// by previous commits.
+    bits_committed: u64,
+
+    /// The bit position past which no data may be read, if the reader is
+    /// currently in a `with_limit` call.
+    bit_limit: Option<u64>,
+
+    /// How many times data has been discarded from the front of the internal
+    /// buffer.
+    ///
+    /// Checkpoints index into the internal buffer, and are only valid for as
+    /// long as that data stays in it. Each checkpoint records the generation
+    /// it was taken in, so that rolling back to one taken before the buffer
+    /// was last drained can be detected and refused.
+    generation: u64,
+
+    /// How many transactions, union transactions, and lookaheads are
+    /// currently in progress on this reader.
+    ///
+    /// The internal buffer may only be committed when this is zero, as any
+    /// in-progress transaction holds a checkpoint into it.
+    transaction_depth: u32,
+}
+
+/// A position in the internal buffer of an `H263Reader` to roll back to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Checkpoint {
+    /// The generation of the internal buffer the checkpoint was taken in.
+    generation: u64,
+
+    /// How many bits of the internal buffer had been read.
+    bits_read: usize,
 }
 
 impl<R> H263Reader<R>
@@ -39,9 +73,94 @@ where
             source,
             buffer: VecDeque::new(),
             bits_read: 0,
+            bits_committed: 0,
+            bit_limit: None,
+            generation: 0,
+            transaction_depth: 0,
         }
     }
 
+    /// Unwrap the reader, yielding the underlying data source.
+    ///
+    /// The data source is left just after the last byte consumed by the
+    /// reader; a byte that has only been partially consumed counts as
+    /// consumed. If the reader has buffered any bytes beyond that, they would
+    /// be lost, so this yields `Error::UncommittedData` instead (and the data
+    /// source is dropped along with the reader). Check that
+    /// `buffered_unconsumed_bytes` is zero first to avoid this, or use
+    /// `into_parts` to get the buffered bytes back.
+    pub fn into_inner(self) -> Result<R> {
+        if self.buffered_unconsumed_bytes() > 0 {
+            return Err(Error::UncommittedData);
+        }
+
+        Ok(self.source)
+    }
+
+    /// Unwrap the reader, yielding the underlying data source and any bytes
+    /// the reader has buffered out of it but not yet consumed.
+    ///
+    /// The data source is left at the position it was read to, which may be
+    /// past the end of the bitstream the reader has actually consumed. The
+    /// returned bytes are the ones between the two, and should be read before
+    /// any further data from the source.
+    ///
+    /// The bytes can only be handed back whole, so if the reader is partway
+    /// through a byte, the rest of it's bits would be lost. This yields
+    /// `Error::UncommittedData` instead (and the data source is dropped along
+    /// with the reader). Check that `bit_position` is a multiple of eight, or
+    /// skip to the end of the byte, first to avoid this.
+    pub fn into_parts(self) -> Result<(R, Vec<u8>)> {
+        if !self.bits_read.is_multiple_of(8) {
+            return Err(Error::UncommittedData);
+        }
+
+        let consumed = self.bits_read / 8;
+        let leftover = self.buffer.into_iter().skip(consumed).collect();
+
+        Ok((self.source, leftover))
+    }
+
+    /// Get a mutable reference to the underlying data source.
+    ///
+    /// Reading from the source directly skips over any data this reader has
+    /// already buffered, so this is only safe to do with an empty buffer.
+    pub(crate) fn source_mut(&mut self) -> &mut R {
+        &mut self.source
+    }
+
+    /// Get the number of bits read from the data source so far.
+    ///
+    /// This counts from the position the source was at when the reader was
+    /// created, or from the start of the source after a `seek_to_byte`. Bits
+    /// that are only in the internal buffer are accounted for. Rolling back a
+    /// transaction also rolls back the position.
+    pub fn bit_position(&self) -> u64 {
+        self.bits_committed + self.bits_read as u64
+    }
+
+    /// Get the number of bytes read from the data source so far.
+    ///
+    /// This is `bit_position` in whole bytes, and is subject to all of the
+    /// same rules. A byte that has only been partially consumed counts as
+    /// consumed, the same as in `buffered_unconsumed_bytes`.
+    pub fn bytes_consumed(&self) -> usize {
+        self.bit_position().div_ceil(8) as usize
+    }
+
+    /// Get the number of bytes that have been read out of the data source and
+    /// into the internal buffer, but not yet consumed.
+    ///
+    /// Lookaheads, failed transactions, and VLC decoding can all read ahead
+    /// of the current bit position, which leaves the data source further
+    /// along than the bitstream actually is. Subtracting this from the
+    /// position of the data source yields the position of the first byte that
+    /// has not been consumed. A byte that has only been partially consumed
+    /// counts as consumed.
+    pub fn buffered_unconsumed_bytes(&self) -> usize {
+        self.buffer.len().saturating_sub(self.bits_read.div_ceil(8))
+    }
+
     /// Fill the internal read buffer with a given number of bytes.
     ///
     /// This function will yield all I/O errors wrapped inside of the
@@ -63,12 +182,21 @@ where
         let bits_available = (self.buffer.len() * 8).saturating_sub(self.bits_read);
         let bits_short = (bits_needed as usize).saturating_sub(bits_available);
 
-        (bits_short / 8) + usize::from(bits_short % 8 != 0)
+        bits_short.div_ceil(8)
     }
 
     /// Ensure that at least a certain number of additional bits can be read
     /// from the internal buffer.
+    ///
+    /// Attempting to read past the limit of an enclosing `with_limit` yields
+    /// an end-of-file error, the same as running out of source data.
     fn ensure_bits(&mut self, bits_needed: u32) -> Result<()> {
+        if let Some(bit_limit) = self.bit_limit {
+            if self.bit_position() + bits_needed as u64 > bit_limit {
+                return Err(IoError::from(IoErrorKind::UnexpectedEof).into());
+            }
+        }
+
         let bytes = self.needed_bytes_for_bits(bits_needed);
         self.buffer_bytes(bytes)
     }
@@ -257,6 +385,93 @@ where
         })
     }
 
+    /// Recognize a start code anywhere within the next `max_skip_bits` bits
+    /// of the bitstream.
+    ///
+    /// This works the same as `recognize_start_code` with `in_error`, except
+    /// that the scan gives up and yields `None` once the start code would be
+    /// more than `max_skip_bits` ahead. Use this to look for the first start
+    /// code in data that might not be an H.263 bitstream at all.
+    pub fn recognize_start_code_within(&mut self, max_skip_bits: u32) -> Result<Option<u32>> {
+        self.with_lookahead(|reader| {
+            let mut skip_bits = 0;
+            let mut maybe_code: u32 = reader.peek_bits(17)?;
+
+            while maybe_code != 1 {
+                if skip_bits >= max_skip_bits {
+                    return Ok(None);
+                }
+
+                reader.skip_bits(1)?;
+                skip_bits += 1;
+                maybe_code = reader.peek_bits(17)?;
+            }
+
+            Ok(Some(skip_bits))
+        })
+    }
+
+    /// Skip forward to just after the next start code in the bitstream.
+    ///
+    /// This resynchronizes the reader after an error, the same way as
+    /// `recognize_start_code` with `in_error`, except that the reader is left
+    /// positioned after the 17-bit start code prefix rather than before it.
+    /// It yields the number of bits that were skipped before the start code,
+    /// or `None` if the bitstream ended without one, in which case the reader
+    /// is not moved.
+    pub fn find_next_start_code(&mut self) -> Result<Option<u32>> {
+        match self.recognize_start_code(true) {
+            Ok(Some(skip_bits)) => {
+                self.skip_bits(skip_bits + 17)?;
+
+                Ok(Some(skip_bits))
+            }
+            Ok(None) => Ok(None),
+            Err(e) if e.is_eof_error() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Attempt to recognize a complete picture start code.
+    ///
+    /// This works the same as `recognize_start_code`, except that the whole
+    /// 22-bit `PSC` (`0000 0000 0000 0000 1 00000`) and the two bits that must
+    /// start every `PTYPE` (`10`, following the `TR`) are checked, rather than
+    /// just the 17-bit
+    /// prefix that all start codes share. Candidates that fail this check are
+    /// not start codes; when `in_error` is signalled, they are skipped over
+    /// instead of being returned.
+    ///
+    /// Start codes of any other kind (such as GOB headers and `EOS`) are
+    /// still recognized, so that callers can tell them apart from pictures.
+    /// This is stricter than necessary for Sorenson Spark bitstreams, which
+    /// use the `GN` bits as a version field.
+    pub fn recognize_picture_start_code(&mut self, in_error: bool) -> Result<Option<u32>> {
+        self.with_lookahead(|reader| {
+            let max_skip_bits = reader.realignment_bits();
+            let mut skip_bits = 0;
+
+            loop {
+                let maybe_code: u32 = reader.peek_bits(32)?;
+                let is_start_code = maybe_code >> 15 == 1;
+                let group_number = (maybe_code >> 10) & 0x1F;
+                let is_picture = group_number == 0 && maybe_code & 0x03 == 0x02;
+                let is_other = group_number != 0;
+
+                if is_start_code && (is_picture || is_other) {
+                    return Ok(Some(skip_bits));
+                }
+
+                if !in_error && skip_bits >= max_skip_bits {
+                    return Ok(None);
+                }
+
+                reader.skip_bits(1)?;
+                skip_bits += 1;
+            }
+        })
+    }
+
     /// Read a variable-length code.
     ///
     /// The table consists of a list of `Entry`s. All `Fork`s in the table must
@@ -265,14 +480,23 @@ where
     ///
     /// This function yields `Error::InternalDecoderError` in the event that
     /// the given table is invalid, as well as all other unhandled I/O errors.
+    /// A table whose links form a cycle is detected once more entries have
+    /// been visited than the table contains, so that no input can make the
+    /// read run indefinitely.
     /// In the event that an error is returned, the position of the bitstream
     /// is undefined. This is in contrast to fixed-length read functions which
     /// consistently leave the bitstream in the same position if enough bits
     /// for the type could not be read.
     pub fn read_vlc<T: Clone>(&mut self, table: &Table<T>) -> Result<T> {
         let mut index = 0;
+        let mut visited = 0;
 
         Ok(loop {
+            if visited >= table.len() {
+                return Err(Error::InternalDecoderError);
+            }
+            visited += 1;
+
             match table.get(index) {
                 Some(Entry::End(t)) => break t.clone(),
                 Some(Entry::Fork(zero, one)) => {
@@ -318,11 +542,34 @@ where
                 _ => return Err(Error::InternalDecoderError),
             }
             bulk <<= 1;
+
+            //The mantissa holds one bit less than the bulk, so it can never
+            //reach the bulk, let alone overflow.
+            debug_assert!(mantissa < bulk);
         }
 
         Err(Error::InvalidMvd)
     }
 
+    /// Read the X and Y components of an unrestricted motion vector.
+    ///
+    /// Both components use the bit format of `read_umv`. As per H.263
+    /// (01/2005) D.2, if both components are one half-pel, then an additional
+    /// bit follows them in order to prevent start code emulation, which will
+    /// be skipped.
+    pub fn read_umv_pair(&mut self) -> Result<MotionVector> {
+        self.with_transaction(|reader| {
+            let x = reader.read_umv()?;
+            let y = reader.read_umv()?;
+
+            if x == HalfPel::from_unit(1) && y == HalfPel::from_unit(1) {
+                reader.skip_bits(1)?;
+            }
+
+            Ok((x, y).into())
+        })
+    }
+
     /// Yield a checkpoint value that can be used to abort a complex read
     /// operation.
     ///
@@ -330,10 +577,13 @@ where
     /// internal buffer may be restored using the returned checkpoint.
     ///
     /// This is not an arbitrary seek mechanism: checkpoints are only valid
-    /// for as long as the internal buffer retains the same amount of data, or
-    /// more.
-    fn checkpoint(&self) -> usize {
-        self.bits_read
+    /// until the internal buffer is next drained, by a `commit` or a
+    /// `seek_to_byte`.
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            generation: self.generation,
+            bits_read: self.bits_read,
+        }
     }
 
     /// Restore a previously-created checkpoint.
@@ -341,27 +591,57 @@ where
     /// Upon restoring a checkpoint, all bits read from this reader after the
     /// creation of the checkpoint will be readable again.
     ///
-    /// Checkpoints handed to this function must be valid. Specifically, the
-    /// internal buffer must not have been cleared (e.g. via `commit`) between
-    /// the creation and use of this checkpoint.
-    fn rollback(&mut self, checkpoint: usize) -> Result<()> {
-        if checkpoint > (self.buffer.len() * 8) {
+    /// Checkpoints that were invalidated by draining the internal buffer
+    /// cannot be restored. They yield an error, without moving the bitstream
+    /// position.
+    fn rollback(&mut self, checkpoint: Checkpoint) -> Result<()> {
+        if checkpoint.generation != self.generation || checkpoint.bits_read > self.buffer.len() * 8
+        {
             return Err(Error::InternalDecoderError);
         }
 
-        self.bits_read = checkpoint;
+        self.bits_read = checkpoint.bits_read;
 
         Ok(())
     }
 
+    /// Restore a previously-created checkpoint after a read operation failed,
+    /// and yield it's error.
+    ///
+    /// Checkpoints invalidated by a `commit` made during the read operation
+    /// cannot be restored, and are left alone: the error that caused the
+    /// rollback is still the one worth reporting.
+    fn rollback_error<T>(&mut self, checkpoint: Checkpoint, error: Error) -> Result<T> {
+        if checkpoint.generation == self.generation {
+            self.rollback(checkpoint)?;
+        }
+
+        Err(error)
+    }
+
     /// Invalidate any previous checkpoints and discard the internal buffer.
     ///
     /// This should only be called once all of the data necessary to represent
-    /// a user-facing object has been read. All existing checkpoints will be
-    /// invalidated.
+    /// a user-facing object has been read. The outermost successful
+    /// transaction will call this automatically.
+    ///
+    /// This may also be called within a transaction, to bound the size of the
+    /// internal buffer while parsing large amounts of data that will never
+    /// need to be read again. Doing so invalidates the checkpoints of all
+    /// enclosing transactions, which can then no longer roll back: if one of
+    /// them fails, it yields it's error without moving the bitstream position
+    /// back, and if it is a lookahead that succeeds or a union transaction
+    /// that yields `None`, it yields `Error::InternalDecoderError` instead.
     pub fn commit(&mut self) {
-        self.buffer.drain(0..self.bits_read / 8);
+        let bytes_read = self.bits_read / 8;
+        if bytes_read == 0 {
+            return;
+        }
+
+        self.bits_committed += (bytes_read * 8) as u64;
+        self.buffer.drain(0..bytes_read);
         self.bits_read %= 8;
+        self.generation += 1;
     }
 
     /// Run some struct-parsing code in such a way that it will not advance the
@@ -370,19 +650,25 @@ where
     /// Closures passed to this function must yield a `Result`. The buffer
     /// position will not be modified if the function yields an `Err`.
     ///
-    /// TODO: This function does not discard successfully parsed buffer data
-    /// via `commit` due to the lack of safety tracking on checkpoints. This
-    /// function should be reentrant.
+    /// Transactions may be nested. Successfully parsed buffer data is only
+    /// discarded (via `commit`) once the outermost transaction succeeds.
+    /// A transaction cannot roll back past a `commit` made within it: if it
+    /// fails after one, it's error is yielded with the bitstream position
+    /// left where the failure occurred.
     pub fn with_transaction<F, T>(&mut self, f: F) -> Result<T>
     where
         F: FnOnce(&mut Self) -> Result<T>,
     {
         let checkpoint = self.checkpoint();
 
+        self.transaction_depth += 1;
         let result = f(self);
+        self.transaction_depth -= 1;
 
-        if result.is_err() {
-            self.rollback(checkpoint)?;
+        match result {
+            Err(e) => return self.rollback_error(checkpoint, e),
+            Ok(_) if self.transaction_depth == 0 => self.commit(),
+            Ok(_) => {}
         }
 
         result
@@ -398,19 +684,22 @@ where
     /// allow parsing data that may be one of multiple types; ergo, in this
     /// case a `None` value means "try some other type".
     ///
-    /// TODO: This function does not discard successfully parsed buffer data
-    /// via `commit` due to the lack of safety tracking on checkpoints. This
-    /// function should be reentrant.
+    /// Union transactions nest with all other transactions in the same way
+    /// that `with_transaction` does.
     pub fn with_transaction_union<F, T>(&mut self, f: F) -> Result<Option<T>>
     where
         F: FnOnce(&mut Self) -> Result<Option<T>>,
     {
         let checkpoint = self.checkpoint();
 
+        self.transaction_depth += 1;
         let result = f(self);
+        self.transaction_depth -= 1;
 
-        match &result {
-            Ok(None) | Err(_) => self.rollback(checkpoint)?,
+        match result {
+            Err(e) => return self.rollback_error(checkpoint, e),
+            Ok(None) => self.rollback(checkpoint)?,
+            _ if self.transaction_depth == 0 => self.commit(),
             _ => {}
         };
 
@@ -424,26 +713,321 @@ where
     /// allow signalling rollback failure; the bitstream position will never be
     /// modified.
     ///
-    /// TODO: This function does not discard successfully parsed buffer data
-    /// via `commit` due to the lack of safety tracking on checkpoints. This
-    /// function should be reentrant.
+    /// Lookaheads count as transactions for the purposes of nesting: no
+    /// transaction nested within a lookahead will commit buffer data.
     pub fn with_lookahead<F, T>(&mut self, f: F) -> Result<T>
     where
         F: FnOnce(&mut Self) -> Result<T>,
     {
         let checkpoint = self.checkpoint();
 
+        self.transaction_depth += 1;
         let result = f(self);
+        self.transaction_depth -= 1;
 
-        self.rollback(checkpoint)?;
+        match result {
+            Err(e) => self.rollback_error(checkpoint, e),
+            Ok(_) => {
+                self.rollback(checkpoint)?;
+
+                result
+            }
+        }
+    }
+
+    /// Run some parsing code in such a way that it cannot read more than a
+    /// given number of bytes.
+    ///
+    /// The limit is counted from the start of the byte the reader is
+    /// currently in, and covers both buffered data and data yet to be read
+    /// from the data source. Any attempt to read past it within the closure
+    /// yields an end-of-file error, exactly as if the data source had ended
+    /// there. This is intended for containers that give the size of each
+    /// picture, so that corrupt data cannot cause the decoder to read into
+    /// the next one.
+    ///
+    /// Limits may be nested, in which case the most restrictive one applies.
+    /// This does not create a transaction.
+    pub fn with_limit<F, T>(&mut self, bytes: usize, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        let bit_limit = (self.bit_position() / 8 + bytes as u64) * 8;
+        let old_limit = self.bit_limit;
+        self.bit_limit = Some(old_limit.map_or(bit_limit, |old| old.min(bit_limit)));
+
+        let result = f(self);
+
+        self.bit_limit = old_limit;
 
         result
     }
 }
 
+impl<R> H263Reader<R>
+where
+    R: Read + Seek,
+{
+    /// Move the reader to a given byte offset within the data source.
+    ///
+    /// All buffered data is discarded, including data that has been read but
+    /// not yet committed. This invalidates all existing checkpoints, and thus
+    /// must not be done within a transaction.
+    pub fn seek_to_byte(&mut self, byte_offset: u64) -> Result<()> {
+        self.source.seek(SeekFrom::Start(byte_offset))?;
+        self.buffer.clear();
+        self.bits_read = 0;
+        self.bits_committed = byte_offset * 8;
+        self.generation += 1;
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::error::Error;
     use crate::parser::reader::H263Reader;
+    use crate::parser::vlc::Entry;
+    use crate::types::{HalfPel, MotionVector};
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn bit_position() {
+        let data = [0xFF, 0x72, 0x1C, 0x1F];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        assert_eq!(0, reader.bit_position());
+        reader.read_bits::<u8>(3).unwrap();
+        assert_eq!(3, reader.bit_position());
+
+        reader
+            .with_transaction(|reader| reader.read_bits::<u16>(10))
+            .unwrap();
+        assert_eq!(13, reader.bit_position());
+
+        reader
+            .with_transaction(|reader| {
+                reader.read_bits::<u8>(5)?;
+                reader.read_bits::<u32>(32)
+            })
+            .unwrap_err();
+        assert_eq!(13, reader.bit_position());
+    }
+
+    #[test]
+    fn bytes_consumed() {
+        let data = [0xFF, 0x72, 0x1C, 0x1F, 0x00];
+        let mut reader = H263Reader::from_source(&data[..]);
+        assert_eq!(0, reader.bytes_consumed());
+
+        reader.read_u8().unwrap();
+        assert_eq!(1, reader.bytes_consumed());
+
+        reader.read_bits::<u8>(3).unwrap();
+        assert_eq!(11, reader.bit_position());
+        assert_eq!(2, reader.bytes_consumed());
+
+        //Commits discard the buffer, but not the position.
+        reader
+            .with_transaction(|reader| {
+                reader.read_bits::<u16>(10)?;
+                reader.commit();
+                reader.read_bits::<u8>(2)
+            })
+            .unwrap();
+        assert_eq!(23, reader.bit_position());
+        assert_eq!(3, reader.bytes_consumed());
+
+        reader.read_bits::<u8>(1).unwrap();
+        reader.commit();
+        assert_eq!(24, reader.bit_position());
+        assert_eq!(3, reader.bytes_consumed());
+
+        //Failed transactions roll the position back.
+        reader
+            .with_transaction(|reader| reader.read_bits::<u32>(32))
+            .unwrap_err();
+        assert_eq!(3, reader.bytes_consumed());
+    }
+
+    #[test]
+    fn buffered_unconsumed_bytes() {
+        let data = [0xFF, 0x72, 0x1C, 0x1F];
+        let mut reader = H263Reader::from_source(&data[..]);
+        assert_eq!(0, reader.buffered_unconsumed_bytes());
+
+        reader.peek_bits::<u32>(17).unwrap();
+        assert_eq!(3, reader.buffered_unconsumed_bytes());
+
+        reader.read_bits::<u8>(3).unwrap();
+        assert_eq!(2, reader.buffered_unconsumed_bytes());
+
+        reader
+            .with_transaction(|reader| {
+                reader.read_bits::<u8>(5)?;
+                reader.read_bits::<u32>(32)
+            })
+            .unwrap_err();
+        assert_eq!(3, reader.buffered_unconsumed_bytes());
+
+        reader.read_bits::<u16>(13).unwrap();
+        assert_eq!(2, reader.buffered_unconsumed_bytes());
+    }
+
+    #[test]
+    fn into_inner() {
+        let data = [0xFF, 0x72, 0x1C, 0x1F];
+        let mut reader = H263Reader::from_source(Cursor::new(&data[..]));
+
+        reader.skip_bits(12).unwrap();
+        let mut cursor = reader.into_inner().unwrap();
+        assert_eq!(cursor.position(), 2);
+
+        let mut trailer = Vec::new();
+        cursor.read_to_end(&mut trailer).unwrap();
+        assert_eq!(trailer, [0x1C, 0x1F]);
+
+        // Looking ahead leaves bytes in the buffer
+        let mut reader = H263Reader::from_source(Cursor::new(&data[..]));
+        reader.peek_bits::<u16>(16).unwrap();
+        assert!(matches!(reader.into_inner(), Err(Error::UncommittedData)));
+    }
+
+    #[test]
+    fn into_parts() {
+        let data = [0xFF, 0x72, 0x1C, 0x1F];
+
+        // Looking ahead leaves bytes in the buffer, which are handed back
+        let mut reader = H263Reader::from_source(Cursor::new(&data[..]));
+        reader.skip_bits(8).unwrap();
+        reader.peek_bits::<u16>(16).unwrap();
+        let (mut cursor, leftover) = reader.into_parts().unwrap();
+        assert_eq!(cursor.position(), 3);
+        assert_eq!(leftover, [0x72, 0x1C]);
+
+        let mut trailer = Vec::new();
+        cursor.read_to_end(&mut trailer).unwrap();
+        assert_eq!(trailer, [0x1F]);
+
+        // The rest of a partially read byte can't be handed back
+        let mut reader = H263Reader::from_source(Cursor::new(&data[..]));
+        reader.skip_bits(12).unwrap();
+        assert!(matches!(reader.into_parts(), Err(Error::UncommittedData)));
+    }
+
+    #[test]
+    fn commit_within_transaction() {
+        let data = [0xFF, 0x72, 0x1C, 0x1F];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        //Inner transactions can roll back to data read after a commit.
+        reader
+            .with_transaction(|reader| {
+                reader.read_bits::<u16>(12)?;
+                reader.commit();
+                assert_eq!(12, reader.bit_position());
+                assert_eq!(0, reader.buffered_unconsumed_bytes());
+
+                reader
+                    .with_transaction(|reader| {
+                        reader.read_bits::<u8>(4)?;
+                        reader.read_bits::<u32>(32)
+                    })
+                    .unwrap_err();
+                assert_eq!(12, reader.bit_position());
+
+                reader.read_bits::<u8>(4)
+            })
+            .unwrap();
+        assert_eq!(16, reader.bit_position());
+
+        //Outer transactions cannot roll back past a commit, but still yield
+        //the error that they failed with.
+        let result = reader.with_transaction(|reader| {
+            reader.read_bits::<u8>(8)?;
+            reader.commit();
+            reader.read_bits::<u32>(32)
+        });
+        assert!(result.unwrap_err().is_eof_error());
+        assert_eq!(24, reader.bit_position());
+
+        //Successful union transactions that need to roll back past a commit
+        //can't.
+        let result = reader.with_transaction_union(|reader| {
+            reader.read_bits::<u8>(8)?;
+            reader.commit();
+            Ok(None::<u8>)
+        });
+        assert!(matches!(result, Err(Error::InternalDecoderError)));
+    }
+
+    #[test]
+    fn stale_checkpoints() {
+        let data = [0xFF, 0x72, 0x1C, 0x1F];
+        let mut reader = H263Reader::from_source(Cursor::new(&data[..]));
+
+        //Commits that don't drain the buffer keep checkpoints valid.
+        reader.read_bits::<u8>(4).unwrap();
+        let checkpoint = reader.checkpoint();
+        reader.commit();
+        reader.read_bits::<u8>(2).unwrap();
+        reader.rollback(checkpoint).unwrap();
+        assert_eq!(4, reader.bit_position());
+
+        //Commits that do drain it invalidate them.
+        reader.read_bits::<u8>(8).unwrap();
+        reader.commit();
+        assert!(matches!(
+            reader.rollback(checkpoint),
+            Err(Error::InternalDecoderError)
+        ));
+        assert_eq!(12, reader.bit_position());
+
+        //So do seeks, even when the checkpoint is within the new buffer.
+        reader.seek_to_byte(1).unwrap();
+        reader.read_bits::<u8>(4).unwrap();
+        let checkpoint = reader.checkpoint();
+        reader.seek_to_byte(0).unwrap();
+        reader.read_bits::<u8>(8).unwrap();
+        assert!(matches!(
+            reader.rollback(checkpoint),
+            Err(Error::InternalDecoderError)
+        ));
+        assert_eq!(8, reader.bit_position());
+    }
+
+    #[test]
+    fn with_limit() {
+        let data = [0xFF, 0x72, 0x1C, 0x1F];
+        let mut reader = H263Reader::from_source(&data[..]);
+        reader.read_bits::<u8>(3).unwrap();
+
+        let error = reader
+            .with_limit(2, |reader| {
+                assert_eq!(0x1F, reader.read_bits::<u8>(5)?);
+                assert_eq!(0x72, reader.read_bits::<u8>(8)?);
+                reader.peek_bits::<u8>(1)
+            })
+            .unwrap_err();
+        assert!(error.is_eof_error());
+        assert_eq!(16, reader.bit_position());
+
+        reader
+            .with_limit(2, |reader| {
+                assert!(reader
+                    .with_limit(1, |reader| reader.read_bits::<u16>(9))
+                    .unwrap_err()
+                    .is_eof_error());
+                assert!(reader
+                    .with_limit(4, |reader| reader.read_bits::<u32>(17))
+                    .unwrap_err()
+                    .is_eof_error());
+                assert_eq!(0x1C1F, reader.read_bits::<u16>(16)?);
+                Ok(())
+            })
+            .unwrap();
+    }
 
     #[test]
     fn read_unaligned_bits() {
@@ -550,6 +1134,33 @@ mod tests {
         assert_eq!(Some(3), reader.recognize_start_code(false).unwrap());
     }
 
+    #[test]
+    fn start_code_within() {
+        let data = [0xFF, 0xF0, 0x00, 0x00, 0x80, 0x00];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        assert_eq!(reader.recognize_start_code_within(12).unwrap(), None);
+        assert_eq!(reader.recognize_start_code_within(16).unwrap(), Some(16));
+        assert_eq!(reader.bit_position(), 0);
+    }
+
+    #[test]
+    fn picture_start_code() {
+        // A start code prefix followed by a GN of 0, but not the PTYPE marker
+        let data = [0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x80, 0x02];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        assert_eq!(Some(0), reader.recognize_start_code(false).unwrap());
+        assert_eq!(None, reader.recognize_picture_start_code(false).unwrap());
+        assert_eq!(Some(32), reader.recognize_picture_start_code(true).unwrap());
+
+        // GOB start codes are still start codes
+        let data = [0x00, 0x00, 0x88, 0x00];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        assert_eq!(Some(0), reader.recognize_picture_start_code(false).unwrap());
+    }
+
     #[test]
     fn resynchronize_to_start_code() {
         let data = [0x13, 0x80, 0x00, 0x40, 0x00];
@@ -557,4 +1168,124 @@ mod tests {
 
         assert_eq!(Some(9), reader.recognize_start_code(true).unwrap());
     }
+
+    #[test]
+    fn find_next_start_code() {
+        let data = [0x13, 0x80, 0x00, 0x40, 0x00, 0xFF];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        assert_eq!(Some(9), reader.find_next_start_code().unwrap());
+        assert_eq!(reader.bit_position(), 26);
+        assert_eq!(None, reader.find_next_start_code().unwrap());
+        assert_eq!(reader.bit_position(), 26);
+    }
+
+    #[test]
+    fn nested_transactions() {
+        let data = [0x12, 0x34, 0x56, 0x78];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        let value = reader
+            .with_transaction(|reader| {
+                let a: u8 = reader.read_u8()?;
+                let bc = reader.with_transaction(|reader| {
+                    let b: u8 = reader.read_u8()?;
+                    let c: u8 = reader.with_transaction(|reader| reader.read_u8())?;
+
+                    // Inner transactions must not discard data the outer ones
+                    // may still roll back.
+                    assert_eq!(reader.buffer.len(), 3);
+
+                    Ok((b, c))
+                })?;
+
+                assert_eq!(reader.buffer.len(), 3);
+
+                Ok((a, bc))
+            })
+            .unwrap();
+
+        assert_eq!(value, (0x12, (0x34, 0x56)));
+        assert_eq!(reader.buffer.len(), 0);
+        assert_eq!(reader.bits_read, 0);
+        assert_eq!(reader.read_u8().unwrap(), 0x78);
+
+        // A failed inner transaction still allows the outer one to roll back.
+        let mut reader = H263Reader::from_source(&data[..]);
+        reader
+            .with_transaction(|reader| {
+                reader.read_bits::<u16>(16)?;
+                reader.with_transaction(|reader| reader.read_u8())?;
+                reader.with_transaction(|reader| reader.read_bits::<u16>(16))
+            })
+            .unwrap_err();
+
+        assert_eq!(reader.read_u8().unwrap(), 0x12);
+    }
+
+    #[test]
+    fn read_vlc_cycle() {
+        // Both branches of the root lead back to it
+        let table: [Entry<u8>; 2] = [Entry::Fork(0, 1), Entry::Fork(0, 0)];
+        let data = [0x55; 4];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        assert!(matches!(
+            reader.read_vlc(&table[..]),
+            Err(Error::InternalDecoderError)
+        ));
+
+        let table = [Entry::Fork(1, 2), Entry::End(0u8), Entry::End(1u8)];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        assert_eq!(reader.read_vlc(&table[..]).unwrap(), 0);
+        assert_eq!(reader.read_vlc(&table[..]).unwrap(), 1);
+    }
+
+    #[test]
+    fn read_umv() {
+        // 0 (the zero shortcut), +1/2, -1/2, +5/2, -4095/2
+        let data = [
+            0b1000_0100,
+            0b0111_0001,
+            0b1111_1111,
+            0b1111_1111,
+            0b1111_1100,
+        ];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        assert_eq!(reader.read_umv().unwrap(), HalfPel::from_unit(0));
+        assert_eq!(reader.read_umv().unwrap(), HalfPel::from_unit(1));
+        assert_eq!(reader.read_umv().unwrap(), HalfPel::from_unit(-1));
+        assert_eq!(reader.read_umv().unwrap(), HalfPel::from_unit(5));
+        assert_eq!(reader.read_umv().unwrap(), HalfPel::from_unit(-4095));
+    }
+
+    #[test]
+    fn read_umv_overflow() {
+        // +4096/2 is one bit too large to be represented
+        let data = [0b0010_1010, 0b1010_1010, 0b1010_1010, 0b1000_0000];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        assert!(matches!(reader.read_umv(), Err(Error::InvalidMvd)));
+    }
+
+    #[test]
+    fn read_umv_pair() {
+        // (0, -1/2), (+1/2, +1/2), the emulation prevention bit, then 0
+        let data = [0b1010_0000, 0b0011_0000];
+        let mut reader = H263Reader::from_source(&data[..]);
+
+        assert_eq!(
+            reader.read_umv_pair().unwrap(),
+            MotionVector::from((HalfPel::from_unit(0), HalfPel::from_unit(-1)))
+        );
+        assert_eq!(
+            reader.read_umv_pair().unwrap(),
+            MotionVector::from((HalfPel::from_unit(1), HalfPel::from_unit(1)))
+        );
+
+        // The prevention bit was skipped, so the next UMV is the zero shortcut
+        assert_eq!(reader.read_umv().unwrap(), HalfPel::from_unit(0));
+    }
 }
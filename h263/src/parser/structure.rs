@@ -0,0 +1,337 @@
+//! Bitstream structure dumping
+
+use crate::decoder::DecoderOption;
+use crate::error::{Error, Result};
+use crate::parser::block::decode_block;
+use crate::parser::gob::decode_gob;
+use crate::parser::macroblock::decode_macroblock;
+use crate::parser::picture::{decode_end_of_sequence, decode_picture_with_previous_format};
+use crate::parser::reader::H263Reader;
+use crate::types::{
+    Block, CodedBlockPattern, GroupOfBlocks, Macroblock, MacroblockType, Picture, PictureOption,
+    SourceFormat,
+};
+use std::io::Read;
+
+/// The parsed syntax of an entire bitstream, as produced by `dump_structure`.
+#[derive(Debug, Default)]
+pub struct StreamTree {
+    /// Every picture in the bitstream, in bitstream order.
+    pub pictures: Vec<PictureNode>,
+}
+
+/// The parsed syntax of a single picture.
+#[derive(Debug)]
+pub struct PictureNode {
+    /// The picture's header, exactly as it was parsed.
+    pub header: Picture,
+
+    /// Whether or not an end of sequence code (`EOS`) preceded this picture.
+    pub after_end_of_sequence: bool,
+
+    /// The picture's groups of blocks, in bitstream order.
+    ///
+    /// The first group always exists and never has a header, as it is started
+    /// by the picture header instead.
+    pub gobs: Vec<GobNode>,
+}
+
+/// The parsed syntax of a single group of blocks.
+#[derive(Debug)]
+pub struct GobNode {
+    /// The GOB header, or `None` for the group that starts the picture.
+    pub header: Option<GroupOfBlocks>,
+
+    /// The macroblocks within this group, including stuffing.
+    pub macroblocks: Vec<MacroblockNode>,
+}
+
+/// The parsed syntax of a single macroblock.
+#[derive(Debug)]
+pub struct MacroblockNode {
+    /// The macroblock header.
+    pub macroblock: Macroblock,
+
+    /// The macroblock's four luma blocks followed by it's two chroma blocks,
+    /// or nothing if the macroblock was not coded.
+    pub blocks: Vec<Block>,
+
+    /// The blocks of the B picture of a PB frame, in the same order as
+    /// `blocks`. This is empty outside of PB frames.
+    pub b_blocks: Vec<Block>,
+}
+
+/// Parse an entire bitstream into a tree of it's syntax elements.
+///
+/// Every picture is parsed from the `source`, down to the transform
+/// coefficients of each block, using the same parsing functions as the
+/// decoder. No reconstruction is performed, so this is useful for inspecting
+/// a bitstream rather than decoding it.
+///
+/// Parsing stops at the end of the `source`. Unlike the decoder, no attempt
+/// is made to recover from errors: any bitstream error other than the source
+/// ending midway through a picture is returned.
+pub fn dump_structure<R>(source: R, decoder_options: DecoderOption) -> Result<StreamTree>
+where
+    R: Read,
+{
+    let mut reader = H263Reader::from_source(source);
+    let mut tree = StreamTree::default();
+    let mut running_options = PictureOption::empty();
+    let mut format: Option<SourceFormat> = None;
+
+    loop {
+        if is_end_of_stream(&mut reader)? {
+            break;
+        }
+
+        let after_end_of_sequence =
+            !is_sorenson(decoder_options) && decode_end_of_sequence(&mut reader)?;
+        if after_end_of_sequence {
+            running_options = PictureOption::empty();
+            format = None;
+
+            if is_end_of_stream(&mut reader)? {
+                break;
+            }
+        }
+
+        let previous_picture = tree
+            .pictures
+            .last()
+            .filter(|_| !after_end_of_sequence)
+            .map(|p| &p.header);
+        let header = decode_picture_with_previous_format(
+            &mut reader,
+            decoder_options,
+            previous_picture,
+            format,
+        )?
+        .ok_or(Error::MiddleOfBitstream)?;
+
+        running_options = header.running_options(running_options);
+        format = header.format.or(format);
+
+        let gobs = dump_picture_data(
+            &mut reader,
+            decoder_options,
+            &header,
+            running_options,
+            format.ok_or(Error::PictureFormatMissing)?,
+        )?;
+
+        tree.pictures.push(PictureNode {
+            header,
+            after_end_of_sequence,
+            gobs,
+        });
+    }
+
+    Ok(tree)
+}
+
+/// Parse all of the groups of blocks of a picture whose header has already
+/// been read.
+pub(crate) fn dump_picture_data<R>(
+    reader: &mut H263Reader<R>,
+    decoder_options: DecoderOption,
+    picture: &Picture,
+    running_options: PictureOption,
+    format: SourceFormat,
+) -> Result<Vec<GobNode>>
+where
+    R: Read,
+{
+    let (width, height) = format
+        .into_width_and_height()
+        .ok_or(Error::PictureFormatInvalid)?;
+    let mb_per_line = (width as usize).div_ceil(16);
+    let mb_count = mb_per_line * (height as usize).div_ceil(16);
+    let mb_rows_per_gob = match height {
+        0..=400 => 1,
+        401..=800 => 2,
+        _ => 4,
+    };
+
+    let mut gobs = vec![GobNode {
+        header: None,
+        macroblocks: Vec::new(),
+    }];
+    let mut next_macroblock = 0;
+
+    while next_macroblock < mb_count {
+        let macroblock = match decode_macroblock(reader, picture, running_options) {
+            Ok(macroblock) => macroblock,
+            Err(ref e) if e.is_macroblock_error() && !is_sorenson(decoder_options) => {
+                match decode_gob(reader, decoder_options, picture) {
+                    Ok(Some(gob)) => {
+                        next_macroblock = gob.group_number as usize * mb_rows_per_gob * mb_per_line;
+                        gobs.push(GobNode {
+                            header: Some(gob),
+                            macroblocks: Vec::new(),
+                        });
+                        continue;
+                    }
+                    Ok(None) => break,
+                    Err(e) if e.is_eof_error() => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            Err(Error::InvalidMacroblockHeader)
+                if is_sorenson(decoder_options) && is_at_start_code(reader)? =>
+            {
+                break
+            }
+            Err(e) if e.is_eof_error() => break,
+            Err(e) => return Err(e),
+        };
+
+        let mut blocks = Vec::new();
+        let mut b_blocks = Vec::new();
+
+        if let Macroblock::Coded {
+            mb_type,
+            coded_block_pattern,
+            coded_block_pattern_b,
+            b_prediction_mode,
+            ..
+        } = &macroblock
+        {
+            for tcoef_present in block_codes(coded_block_pattern) {
+                blocks.push(decode_block(
+                    reader,
+                    decoder_options,
+                    picture,
+                    running_options,
+                    *mb_type,
+                    tcoef_present,
+                )?);
+            }
+
+            if b_prediction_mode.is_some() {
+                let coded_block_pattern_b = coded_block_pattern_b.clone().unwrap_or_default();
+                for tcoef_present in block_codes(&coded_block_pattern_b) {
+                    b_blocks.push(decode_block(
+                        reader,
+                        decoder_options,
+                        picture,
+                        running_options,
+                        MacroblockType::Inter,
+                        tcoef_present,
+                    )?);
+                }
+            }
+        }
+
+        if !matches!(macroblock, Macroblock::Stuffing) {
+            next_macroblock += 1;
+        }
+
+        gobs.last_mut()
+            .ok_or(Error::InternalDecoderError)?
+            .macroblocks
+            .push(MacroblockNode {
+                macroblock,
+                blocks,
+                b_blocks,
+            });
+    }
+
+    Ok(gobs)
+}
+
+/// List which of a macroblock's six blocks have coefficients, in bitstream
+/// order.
+fn block_codes(coded_block_pattern: &CodedBlockPattern) -> [bool; 6] {
+    let [l0, l1, l2, l3] = coded_block_pattern.codes_luma;
+
+    [
+        l0,
+        l1,
+        l2,
+        l3,
+        coded_block_pattern.codes_chroma_b,
+        coded_block_pattern.codes_chroma_r,
+    ]
+}
+
+fn is_sorenson(decoder_options: DecoderOption) -> bool {
+    decoder_options.contains(DecoderOption::SORENSON_SPARK_BITSTREAM)
+}
+
+/// Determine if the bitstream continues with a start code, allowing for any
+/// stuffing bits needed to byte-align it.
+fn is_at_start_code<R>(reader: &mut H263Reader<R>) -> Result<bool>
+where
+    R: Read,
+{
+    match reader.recognize_start_code(false) {
+        Ok(skipped_bits) => Ok(skipped_bits.is_some()),
+        Err(e) if e.is_eof_error() => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Determine if there are no start codes left in the bitstream.
+fn is_end_of_stream<R>(reader: &mut H263Reader<R>) -> Result<bool>
+where
+    R: Read,
+{
+    match reader.recognize_start_code(true) {
+        Ok(_) => Ok(false),
+        Err(e) if e.is_eof_error() => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decoder::DecoderOption;
+    use crate::parser::structure::dump_structure;
+    use crate::types::{Macroblock, MacroblockType, PictureTypeCode};
+
+    #[test]
+    fn dump_sorenson() {
+        let data = [
+            // Version 0, 16x16, I-frame, TR 0, one intra macroblock
+            0x00, 0x00, 0x80, 0x00, 0x08, 0x08, 0x02, 0xA6, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20,
+            // Version 0, P-frame, TR 1, one uncoded macroblock
+            0x00, 0x00, 0x80, 0x04, 0x08, 0x08, 0x22, 0xA0,
+        ];
+
+        let tree = dump_structure(&data[..], DecoderOption::SORENSON_SPARK_BITSTREAM).unwrap();
+
+        assert_eq!(tree.pictures.len(), 2);
+
+        let intra = &tree.pictures[0];
+        assert_eq!(intra.header.picture_type, PictureTypeCode::IFrame);
+        assert_eq!(intra.gobs.len(), 1);
+        assert!(intra.gobs[0].header.is_none());
+        assert_eq!(intra.gobs[0].macroblocks.len(), 1);
+
+        let mb = &intra.gobs[0].macroblocks[0];
+        assert!(matches!(
+            mb.macroblock,
+            Macroblock::Coded {
+                mb_type: MacroblockType::Intra,
+                ..
+            }
+        ));
+        assert_eq!(mb.blocks.len(), 6);
+        assert!(mb.b_blocks.is_empty());
+        for block in &mb.blocks {
+            assert_eq!(block.intradc.map(|dc| dc.into_level()), Some(128));
+            assert!(block.tcoef.is_empty());
+        }
+
+        let inter = &tree.pictures[1];
+        assert_eq!(inter.header.picture_type, PictureTypeCode::PFrame);
+        assert_eq!(inter.header.temporal_reference, 1);
+        assert_eq!(inter.gobs[0].macroblocks.len(), 1);
+        assert!(matches!(
+            inter.gobs[0].macroblocks[0].macroblock,
+            Macroblock::Uncoded
+        ));
+        assert!(inter.gobs[0].macroblocks[0].blocks.is_empty());
+    }
+}
@@ -0,0 +1,243 @@
+//! Sorenson Spark bitstream diagnostics
+
+use crate::error::Error;
+use crate::parser::{decode_sorenson_ptype, H263Reader};
+use crate::types::{PictureTypeCode, SourceFormat};
+use std::fmt;
+
+/// The largest picture width that Sorenson Spark encoders are known to
+/// produce.
+const MAX_KNOWN_WIDTH: u16 = 640;
+
+/// The largest picture height that Sorenson Spark encoders are known to
+/// produce.
+const MAX_KNOWN_HEIGHT: u16 = 480;
+
+/// A commonly-seen problem with a Sorenson Spark bitstream.
+///
+/// Warnings do not necessarily prevent a bitstream from being decoded; they
+/// are intended to explain why a given bitstream may not decode correctly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SorensonWarning {
+    /// A picture header could not be read in full, because the data ended
+    /// before it did.
+    TruncatedHeader { offset: usize },
+
+    /// A picture header could not be read, because it contains a reserved
+    /// picture type or source format, or the data could not be read.
+    InvalidHeader { offset: usize },
+
+    /// The version code of a picture is neither of the two known versions.
+    UnknownVersion { offset: usize, version: u8 },
+
+    /// The version code of a picture does not match the one of the first
+    /// picture in the data.
+    VersionMismatch {
+        offset: usize,
+        expected: u8,
+        version: u8,
+    },
+
+    /// The quantizer of a picture is zero, which is forbidden.
+    ZeroQuantizer { offset: usize },
+
+    /// The dimensions of a picture are larger than what any known encoder
+    /// produces, which usually indicates a corrupted size field.
+    OversizedFrame {
+        offset: usize,
+        width: u16,
+        height: u16,
+    },
+}
+
+impl SorensonWarning {
+    /// The byte offset of the start code of the picture this warning is about.
+    pub fn offset(&self) -> usize {
+        match *self {
+            Self::TruncatedHeader { offset }
+            | Self::InvalidHeader { offset }
+            | Self::UnknownVersion { offset, .. }
+            | Self::VersionMismatch { offset, .. }
+            | Self::ZeroQuantizer { offset }
+            | Self::OversizedFrame { offset, .. } => offset,
+        }
+    }
+}
+
+impl fmt::Display for SorensonWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::TruncatedHeader { offset } => {
+                write!(f, "picture header at byte {} is truncated", offset)
+            }
+            Self::InvalidHeader { offset } => {
+                write!(f, "picture header at byte {} is invalid", offset)
+            }
+            Self::UnknownVersion { offset, version } => write!(
+                f,
+                "picture at byte {} has unknown version {}",
+                offset, version
+            ),
+            Self::VersionMismatch {
+                offset,
+                expected,
+                version,
+            } => write!(
+                f,
+                "picture at byte {} has version {}, but the stream started with version {}",
+                offset, version, expected
+            ),
+            Self::ZeroQuantizer { offset } => {
+                write!(
+                    f,
+                    "picture at byte {} has a forbidden quantizer of 0",
+                    offset
+                )
+            }
+            Self::OversizedFrame {
+                offset,
+                width,
+                height,
+            } => write!(
+                f,
+                "picture at byte {} is {}x{}, larger than {}x{}",
+                offset, width, height, MAX_KNOWN_WIDTH, MAX_KNOWN_HEIGHT
+            ),
+        }
+    }
+}
+
+/// Check a Sorenson Spark bitstream for commonly-seen corruptions.
+///
+/// Every byte-aligned picture start code in `data` is located, and the
+/// picture header following it is checked. This does not decode any picture
+/// data, and never fails: problems are reported as a list of warnings, in the
+/// order they appear in the data.
+pub fn validate_stream(data: &[u8]) -> Vec<SorensonWarning> {
+    let mut warnings = vec![];
+    let mut first_version = None;
+    let mut offset = 0;
+
+    while offset + 3 <= data.len() {
+        // Sorenson pictures always start on a byte boundary with the 17-bit
+        // start code, the last bit of which is the top bit of the third byte.
+        if data[offset] != 0 || data[offset + 1] != 0 || data[offset + 2] & 0x80 == 0 {
+            offset += 1;
+            continue;
+        }
+
+        let mut reader = H263Reader::from_source(&data[offset..]);
+        let header = reader.skip_bits(17).and_then(|_| {
+            let version: u8 = reader.read_bits(5)?;
+            let _temporal_reference = reader.read_u8()?;
+            let (format, picture_type, _options) = decode_sorenson_ptype(&mut reader)?;
+            if matches!(picture_type, PictureTypeCode::Reserved(_)) {
+                return Err(Error::InvalidPType);
+            }
+            if matches!(format, SourceFormat::Reserved) {
+                return Err(Error::PictureFormatInvalid);
+            }
+            let quantizer: u8 = reader.read_bits(5)?;
+
+            Ok((version, format, quantizer))
+        });
+
+        match header {
+            Ok((version, format, quantizer)) => {
+                if version > 1 {
+                    warnings.push(SorensonWarning::UnknownVersion { offset, version });
+                }
+
+                match first_version {
+                    None => first_version = Some(version),
+                    Some(expected) if expected != version => {
+                        warnings.push(SorensonWarning::VersionMismatch {
+                            offset,
+                            expected,
+                            version,
+                        })
+                    }
+                    Some(_) => {}
+                }
+
+                if quantizer == 0 {
+                    warnings.push(SorensonWarning::ZeroQuantizer { offset });
+                }
+
+                if let Some((width, height)) = format.into_width_and_height() {
+                    if width > MAX_KNOWN_WIDTH || height > MAX_KNOWN_HEIGHT {
+                        warnings.push(SorensonWarning::OversizedFrame {
+                            offset,
+                            width,
+                            height,
+                        });
+                    }
+                }
+            }
+            Err(e) if e.is_eof_error() => {
+                warnings.push(SorensonWarning::TruncatedHeader { offset })
+            }
+            Err(_) => warnings.push(SorensonWarning::InvalidHeader { offset }),
+        }
+
+        offset += 3;
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sorenson::{validate_stream, SorensonWarning};
+
+    #[test]
+    fn valid_header() {
+        // Version 0, QCIF, I-frame, quantizer 5
+        let data = [0x00, 0x00, 0x80, 0x01, 0x82, 0x80];
+
+        assert_eq!(validate_stream(&data), vec![]);
+    }
+
+    #[test]
+    fn corrupted_headers() {
+        let data = [
+            // Version 0, QCIF, I-frame, quantizer 0
+            0x00, 0x00, 0x80, 0x01, 0x80, 0x00, //
+            // Version 2, 800x600, I-frame, quantizer 5
+            0x00, 0x00, 0x88, 0x04, 0x81, 0x90, 0x01, 0x2C, 0x02, 0x80, //
+            // Version 0, QCIF, reserved picture type
+            0x00, 0x00, 0x80, 0x01, 0xE2, 0x80, //
+            // Truncated
+            0x00, 0x00, 0x80, 0x01,
+        ];
+
+        let warnings = validate_stream(&data);
+        assert_eq!(
+            warnings,
+            vec![
+                SorensonWarning::ZeroQuantizer { offset: 0 },
+                SorensonWarning::UnknownVersion {
+                    offset: 6,
+                    version: 2
+                },
+                SorensonWarning::VersionMismatch {
+                    offset: 6,
+                    expected: 0,
+                    version: 2
+                },
+                SorensonWarning::OversizedFrame {
+                    offset: 6,
+                    width: 800,
+                    height: 600
+                },
+                SorensonWarning::InvalidHeader { offset: 16 },
+                SorensonWarning::TruncatedHeader { offset: 22 },
+            ]
+        );
+        assert_eq!(warnings[5].offset(), 22);
+        assert_eq!(
+            warnings[0].to_string(),
+            "picture at byte 0 has a forbidden quantizer of 0"
+        );
+    }
+}
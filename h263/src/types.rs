@@ -1,7 +1,7 @@
 //! Parsed H.263 bitstream types
 
 use std::cmp::Ordering;
-use std::ops::{Add, Div, Neg};
+use std::ops::{Add, Div, Mul, Neg, Sub};
 
 /// ITU-T Recommendation H.263 (01/2005) 5.1.2-5.1.4 `TR`, `PTYPE`, `PLUSPTYPE`
 /// and 5.1.8 `ETR`.
@@ -9,7 +9,7 @@ use std::ops::{Add, Div, Neg};
 /// The `Picture` configures the current displayed frame's various options,
 /// such as it's resolution, the use of any optional H.263 features, and the
 /// intra-prediction mode used.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Picture {
     /// The version code.
     ///
@@ -107,6 +107,15 @@ pub struct Picture {
     /// The number of non-transmitted frames to the B half of the current PB
     /// frame. This field should not be present if not using PB frames or their
     /// improved variety.
+    ///
+    /// `TRb` counts forward from the previous reference picture, while the
+    /// P half of the frame lies `TRd` pictures after it, where `TRd` is the
+    /// difference between this picture's temporal reference and the previous
+    /// one. The B picture thus has a temporal reference of
+    /// `temporal_reference - TRd + TRb`, and its motion vectors are derived
+    /// by scaling those of the P picture by `TRb / TRd`. A valid stream has
+    /// `0 < TRb < TRd`; other values are tolerated when decoding, but do not
+    /// yield meaningful prediction.
     pub pb_reference: Option<u8>,
 
     /// ITU-T Recommendation H.263 (01/2005) 5.1.23 `DBQUANT`
@@ -121,6 +130,55 @@ pub struct Picture {
     pub extra: Vec<u8>,
 }
 
+impl Picture {
+    /// Determine the options in force for this picture, given those that
+    /// were in force for the previous one.
+    ///
+    /// Options only present in `OPPTYPE` or `MPPTYPE` carry over from the
+    /// previous picture whenever this picture's header leaves them out.
+    pub(crate) fn running_options(&self, previous: PictureOption) -> PictureOption {
+        if self.has_plusptype && self.has_opptype {
+            self.options
+        } else if self.has_plusptype {
+            (self.options & !*OPPTYPE_OPTIONS) | (previous & *OPPTYPE_OPTIONS)
+        } else {
+            (self.options & !*OPPTYPE_OPTIONS & !*MPPTYPE_OPTIONS)
+                | (previous & (*OPPTYPE_OPTIONS | *MPPTYPE_OPTIONS))
+        }
+    }
+}
+
+#[cfg(test)]
+impl Picture {
+    /// A picture header of the given type, with every optional field left
+    /// out, no options, and a quantizer of 1.
+    ///
+    /// Tests override whichever fields they need with struct update syntax.
+    pub(crate) fn for_test(picture_type: PictureTypeCode) -> Self {
+        Self {
+            version: None,
+            temporal_reference: 0,
+            format: None,
+            options: PictureOption::empty(),
+            has_plusptype: false,
+            has_opptype: false,
+            picture_type,
+            motion_vector_range: None,
+            slice_submode: None,
+            scalability_layer: None,
+            reference_picture_selection_mode: None,
+            prediction_reference: None,
+            backchannel_message: None,
+            reference_picture_resampling: None,
+            quantizer: 1,
+            multiplex_bitstream: None,
+            pb_reference: None,
+            pb_quantizer: None,
+            extra: Vec::new(),
+        }
+    }
+}
+
 /// The default resolution options available in H.263.
 ///
 /// The `CIF` refers to "Common Interchange Format", a video teleconferencing
@@ -178,6 +236,40 @@ impl SourceFormat {
             }
         }
     }
+
+    /// Determine the horizontal and vertical motion vector component limits
+    /// of this source format, when the extended motion vector range of
+    /// unrestricted motion vector mode is in force.
+    ///
+    /// Per ITU-T Recommendation H.263 (01/2005) Table D.1, the limits depend
+    /// on the width and height of the picture: CIF and smaller get
+    /// `HalfPel::EXTENDED_RANGE`, 4CIF gets `EXTENDED_RANGE_QUADCIF`, and
+    /// 16CIF gets `EXTENDED_RANGE_SIXTEENCIF`. Custom formats wider than 16CIF
+    /// get `EXTENDED_RANGE_BEYONDCIF` horizontally. `Reserved` formats are
+    /// treated as CIF-sized.
+    ///
+    /// Vectors must lie within `-range..range` of the returned `range` for
+    /// each component.
+    pub fn extended_mv_range(&self) -> (HalfPel, HalfPel) {
+        match self.into_width_and_height() {
+            Some((width, height)) => {
+                let x_range = match width {
+                    0..=352 => HalfPel::EXTENDED_RANGE,
+                    353..=704 => HalfPel::EXTENDED_RANGE_QUADCIF,
+                    705..=1408 => HalfPel::EXTENDED_RANGE_SIXTEENCIF,
+                    _ => HalfPel::EXTENDED_RANGE_BEYONDCIF,
+                };
+                let y_range = match height {
+                    0..=288 => HalfPel::EXTENDED_RANGE,
+                    289..=576 => HalfPel::EXTENDED_RANGE_QUADCIF,
+                    _ => HalfPel::EXTENDED_RANGE_SIXTEENCIF,
+                };
+
+                (x_range, y_range)
+            }
+            None => (HalfPel::EXTENDED_RANGE, HalfPel::EXTENDED_RANGE),
+        }
+    }
 }
 
 bitflags! {
@@ -247,7 +339,7 @@ lazy_static! {
 ///
 /// Certain `PictureTypeCode`s will prohibit the use of particular
 /// `PictureOption`s.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum PictureTypeCode {
     /// A full picture update that can be independently decoded.
     IFrame,
@@ -293,8 +385,13 @@ impl PictureTypeCode {
         matches!(self, Self::PbFrame) || matches!(self, Self::ImprovedPbFrame)
     }
 
+    /// Determine if no other picture may be predicted from pictures of this
+    /// type.
+    ///
+    /// This is the case for Sorenson's disposable P-frames, and for the B
+    /// pictures of temporal scalability (Annex O).
     pub fn is_disposable(self) -> bool {
-        matches!(self, Self::DisposablePFrame)
+        matches!(self, Self::DisposablePFrame | Self::BFrame)
     }
 }
 
@@ -365,24 +462,42 @@ pub enum PixelAspectRatio {
 /// 1,800,000hz, and divide it by the effective divisor to produce a frame
 /// rate. The effective divisor is `divisor` times either 1000 or 1001,
 /// depending on the `times_1001` flag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct CustomPictureClock {
     /// Whether or not the divisor is multiplied by 1000 or 1001.
     ///
     /// `true` indicates 1001, whilst `false` indicates 1000.
-    #[allow(dead_code)]
     pub times_1001: bool,
 
     /// The divisor, itself stored divided by a constant factor (see
     /// `times_1001`.)
-    #[allow(dead_code)]
+    ///
+    /// This is never zero for clocks constructed with `new`.
     pub divisor: u8,
 }
 
+impl CustomPictureClock {
+    /// Construct a custom picture clock.
+    ///
+    /// Yields `None` if the `divisor` is zero, as that would describe an
+    /// infinite frame rate.
+    pub fn new(times_1001: bool, divisor: u8) -> Option<Self> {
+        if divisor == 0 {
+            return None;
+        }
+
+        Some(Self {
+            times_1001,
+            divisor,
+        })
+    }
+}
+
 /// ITU-T Recommendation H.263 (01/2005) 5.1.9 `UUI`
 ///
 /// Indicates the new motion vector range limitations when
 /// `UNRESTRICTED_MOTION_VECTORS` are enabled.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum MotionVectorRange {
     /// Motion vector component ranges are extended to limits that are
     /// prescribed in ITU-T Recommendation H.263 (01/2005) D.1 and D.2.
@@ -396,7 +511,7 @@ bitflags! {
     /// ITU-T Recommendation H.263 (01/2005) 5.1.9 `SSS`
     ///
     /// Indicates slice configuration when slice-structured mode is enabled.
-    #[derive(Debug)]
+    #[derive(Clone, Debug)]
     pub struct SliceSubmode : u8 {
         /// Slices must be rectantular rather than free-running.
         const RECTANGULAR_SLICES = 0b1;
@@ -409,7 +524,7 @@ bitflags! {
 /// ITU-T Recommendation H.263 (01/2005) 5.1.11-5.1.12 `ELNUM`, `RLNUM`
 ///
 /// Only present if Temporal, SNR, and Spatial Scalability is enabled.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ScalabilityLayer {
     /// The 4-bit enhancement layer index.
     pub enhancement: u8,
@@ -427,7 +542,7 @@ bitflags! {
     ///
     /// Indicates what backchannel messages the encoder would like out of it's
     /// decoding partner.
-    #[derive(Debug)]
+    #[derive(Clone, Debug)]
     pub struct ReferencePictureSelectionMode : u8 {
         const RESERVED = 0b1;
         const REQUEST_NEGATIVE_ACKNOWLEDGEMENT = 0b10;
@@ -441,39 +556,40 @@ bitflags! {
 /// video stream is sending in response to an opposing video stream. It may be
 /// presented to the encoder with a separate logical channel, or it may be
 /// muxed into a video stream that the encoder is also expected to decode.
-#[derive(Debug)]
-#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BackchannelMessage {
     /// What message type is being back-channeled.
-    message_type: BackchannelMessageType,
+    pub message_type: BackchannelMessageType,
 
     /// Whether or not the backchanneler has reliable reference numbers to the
     /// opposing video stream. This being set to `Unreliable` indicates that
     /// the references in this message may not be correct.
-    reliable: BackchannelReliability,
+    pub reliable: BackchannelReliability,
 
     /// The temporal reference of the picture being backchanneled.
-    temporal_reference: u16,
+    pub temporal_reference: u16,
 
     /// The enhancement layer being backchanneled, or `None` if no layer was
     /// specified.
-    enhancement_layer: Option<u8>,
+    pub enhancement_layer: Option<u8>,
 
     /// The sub-bitstream number being backchanneled.
-    sub_bitstream: Option<u8>,
+    pub sub_bitstream: Option<u8>,
 
     /// The GOB number or macroblock address being backchanneled.
-    gob_macroblock_address: Option<u16>,
+    ///
+    /// This is a macroblock address if the slice structured mode is in use,
+    /// and a GOB number otherwise.
+    pub gob_macroblock_address: Option<u16>,
 
     /// The temporal reference being requested for retransmission (if NACK).
-    requested_temporal_reference: Option<u16>,
+    pub requested_temporal_reference: Option<u16>,
 }
 
 /// ITU-T Recommendation H.263 (01/2005) N.4.2.1 `BT`
 ///
 /// Indicates the backchanneler's decoding status of the opposing video stream.
-#[derive(Debug)]
-#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum BackchannelMessageType {
     /// Positive acknowledgement of correct decoding of the opposing video
     /// stream.
@@ -491,8 +607,7 @@ pub enum BackchannelMessageType {
 ///
 /// Whether or not the backchanneling decoder has reliable values for temporal
 /// references, group-of-block numbers, or macroblock addresses.
-#[derive(Debug)]
-#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum BackchannelReliability {
     Reliable,
     Unreliable,
@@ -501,22 +616,29 @@ pub enum BackchannelReliability {
 /// ITU-T Recommendation H.263 (01/2005) P.2 `RPRP`
 ///
 /// The parameters necessary for reference-picture resampling.
-#[derive(Debug)]
-#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ReferencePictureResampling {
-    accuracy: WarpingDisplacementAccuracy,
+    /// The accuracy of the warping parameters.
+    pub accuracy: WarpingDisplacementAccuracy,
 
-    /// The eight warping parameters for reference picture resampling.
+    /// The eight warping parameters for reference picture resampling, in
+    /// units of `accuracy`.
     ///
     /// Each parameter is encoded according to table `D.3` in H.263 (01/2005).
     /// This is a variable-length code whose decoded values max out at around
     /// 11 bits.
-    warps: Option<[u16; 8]>,
+    ///
+    /// This is `None` for the implicit factor-of-4 resampling of a picture
+    /// that changes size without signalling any parameters.
+    pub warps: Option<[i16; 8]>,
+
+    /// How to fill in the parts of the resampled picture that lie outside
+    /// of the reference picture.
+    pub fill_mode: WarpingFillMode,
 }
 
 /// ITU-T Recommendation H.263 (01/2005) P.2.1 `WDA`
-#[derive(Debug)]
-#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum WarpingDisplacementAccuracy {
     /// Warping parameters are quantized to half-pixel accuracy.
     HalfPixel,
@@ -525,8 +647,26 @@ pub enum WarpingDisplacementAccuracy {
     SixteenthPixel,
 }
 
+/// ITU-T Recommendation H.263 (01/2005) P.2.3 `FILL_MODE`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WarpingFillMode {
+    /// Fill with the given color, as given by P.2.4 `Y_FILL`, `CB_FILL` and
+    /// `CR_FILL`.
+    Color { y: u8, cb: u8, cr: u8 },
+
+    /// Fill with black.
+    Black,
+
+    /// Fill with gray.
+    Gray,
+
+    /// Extend the edges of the reference picture, the same way motion
+    /// vectors pointing out of the picture do.
+    Clip,
+}
+
 /// ITU-T Recommendation H.263 (01/2005), 5.1.23 `DBQUANT`
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum BPictureQuantizer {
     /// B-Quantizer is five-fourths
     Five,
@@ -541,12 +681,28 @@ pub enum BPictureQuantizer {
     Eight,
 }
 
+impl BPictureQuantizer {
+    /// Derive the quantizer of the B block of a PB frame macroblock (`BQUANT`)
+    /// from the quantizer of it's P block.
+    pub fn b_quantizer(&self, quantizer: u8) -> u8 {
+        let numerator = match self {
+            Self::Five => 5,
+            Self::Six => 6,
+            Self::Seven => 7,
+            Self::Eight => 8,
+        };
+
+        (numerator * quantizer as u16 / 4).clamp(1, 31) as u8
+    }
+}
+
 /// ITU-T Recommendation H.263 (01/2005), 5.2.x `GN`, `GSBI`, `GFID`, `GQUANT`
 ///
 /// In an H.264-compliant bitstream, each picture is composed of one or more
 /// groups of blocks. The first group of blocks is implied and *not*
 /// transmitted in a compliant bitstream. Sorenson bitstreams treat all
 /// pictures as a single group of blocks, and thus will not use this structure.
+#[derive(Clone, Debug)]
 pub struct GroupOfBlocks {
     /// The GOB number.
     ///
@@ -599,20 +755,100 @@ pub enum Macroblock {
         /// of the B frame.
         coded_block_pattern_b: Option<CodedBlockPattern>,
 
-        /// ITU-T Recommendation H.263 (01/2005) 5.3.6 `DQUANT`
-        d_quantizer: Option<i8>,
+        /// ITU-T Recommendation H.263 (01/2005) 5.3.6 `DQUANT`, T.4 `DQUANT`
+        d_quantizer: Option<QuantizerChange>,
 
-        /// ITU-T Recommendation H.263 (01/2005) 5.3.7 `MVD`
+        /// ITU-T Recommendation H.263 (01/2005) 5.3.7 `MVD`, O.4 `MVDFW`
         motion_vector: Option<MotionVector>,
 
         /// ITU-T Recommendation H.263 (01/2005) 5.3.8 `MVD2-4`
         addl_motion_vectors: Option<[MotionVector; 3]>,
 
-        /// ITU-T Recommendation H.263 (01/2005) 5.3.9 `MVDB`
-        motion_vectors_b: Option<[MotionVector; 4]>,
+        /// ITU-T Recommendation H.263 (01/2005) 5.3.9 `MVDB`, O.4 `MVDBW`
+        motion_vector_b: Option<MotionVector>,
+
+        /// How the B-blocks of a PB frame macroblock, or an inter macroblock
+        /// of a B picture, are predicted.
+        ///
+        /// This is always `Bidirectional` for PB frames, as only improved PB
+        /// frames can signal anything else. It is `None` outside of any kind
+        /// of PB frame or B picture, and for intra macroblocks of B pictures.
+        b_prediction_mode: Option<BPredictionMode>,
+
+        /// ITU-T Recommendation H.263 (01/2005) I.2 `INTRA_MODE`
+        ///
+        /// This is only present on intra macroblocks of pictures using
+        /// Advanced Intra Coding (Annex I).
+        intra_prediction_mode: Option<IntraPredictionMode>,
     },
 }
 
+/// ITU-T Recommendation H.263 (01/2005), M.2 and O.4 B-block prediction modes
+///
+/// Indicates which of the pictures surrounding the B picture of an improved
+/// PB frame the B-blocks of a macroblock are predicted from, or which of the
+/// reference pictures an inter macroblock of a B picture of temporal
+/// scalability (Annex O) is predicted from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BPredictionMode {
+    /// Predict from both the previous reference picture and the P picture,
+    /// the same way as in normal PB frames.
+    ///
+    /// In B pictures, both motion vectors are coded instead, in `MVDFW` and
+    /// `MVDBW`.
+    Bidirectional,
+
+    /// Predict only from the previous reference picture, using the motion
+    /// vector coded in `MVDB`.
+    ///
+    /// In B pictures, the motion vector is coded in `MVDFW`.
+    Forward,
+
+    /// Predict only from the co-located macroblock of the P picture, without
+    /// any motion vector.
+    ///
+    /// In B pictures, the motion vector is coded in `MVDBW`.
+    Backward,
+
+    /// Predict from both reference pictures of a B picture, with motion
+    /// vectors scaled from those of the co-located macroblock of the backward
+    /// reference picture, as in PB frames without `MVDB`.
+    ///
+    /// Only B pictures use this mode.
+    Direct,
+}
+
+/// ITU-T Recommendation H.263 (01/2005), I.3 intra prediction modes
+///
+/// Indicates which neighbouring blocks the coefficients of each block of an
+/// intra macroblock are predicted from, under Advanced Intra Coding (Annex I).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum IntraPredictionMode {
+    /// Predict only the DC coefficient, from the average of the blocks above
+    /// and to the left.
+    Dc,
+
+    /// Predict the DC coefficient and the first row of AC coefficients from
+    /// the block above.
+    Vertical,
+
+    /// Predict the DC coefficient and the first column of AC coefficients
+    /// from the block to the left.
+    Horizontal,
+}
+
+impl IntraPredictionMode {
+    /// Get the order in which the coefficients of blocks predicted in this
+    /// mode are transmitted.
+    pub fn scan(self) -> BlockScan {
+        match self {
+            Self::Dc => BlockScan::Zigzag,
+            Self::Vertical => BlockScan::AlternateHorizontal,
+            Self::Horizontal => BlockScan::AlternateVertical,
+        }
+    }
+}
+
 impl Macroblock {
     /// Get the macroblock type of this macroblock.
     ///
@@ -624,10 +860,78 @@ impl Macroblock {
             Self::Coded { mb_type, .. } => Some(*mb_type),
         }
     }
+
+    /// Determine if this macroblock contains coded picture data.
+    pub fn is_coded(&self) -> bool {
+        matches!(self, Self::Coded { .. })
+    }
+
+    /// Determine if this macroblock was skipped, i.e. not coded and instead
+    /// copied from the reference picture.
+    pub fn is_skipped(&self) -> bool {
+        matches!(self, Self::Uncoded)
+    }
+
+    /// Determine if this macroblock is stuffing, which does not correspond to
+    /// any macroblock of the picture at all.
+    pub fn is_stuffing(&self) -> bool {
+        matches!(self, Self::Stuffing)
+    }
+}
+
+/// A change to the quantizer in force, as signalled by `DQUANT`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum QuantizerChange {
+    /// Add a difference to the quantizer in force.
+    ///
+    /// ITU-T Recommendation H.263 (01/2005) 5.3.6 `DQUANT`
+    Delta(i8),
+
+    /// Step the quantizer in force up or down by an amount that depends on
+    /// it's current value. This is the short form of Modified Quantization's
+    /// `DQUANT`, and `true` indicates the `11` code.
+    ///
+    /// ITU-T Recommendation H.263 (01/2005) T.4 `DQUANT`, Table T.1
+    Step(bool),
+
+    /// Replace the quantizer in force outright. This is the long form of
+    /// Modified Quantization's `DQUANT`.
+    ///
+    /// ITU-T Recommendation H.263 (01/2005) T.4 `DQUANT`
+    Absolute(u8),
+}
+
+impl QuantizerChange {
+    /// Apply this change to the quantizer in force, yielding the quantizer
+    /// for the current macroblock.
+    ///
+    /// The result is always a valid quantizer between 1 and 31.
+    pub fn apply(self, quantizer: u8) -> u8 {
+        let quantizer = quantizer.clamp(1, 31);
+        let new_quantizer = match self {
+            Self::Delta(delta) => quantizer as i8 + delta,
+            Self::Step(is_11) => {
+                let (step_10, step_11) = match quantizer {
+                    1 => (2, 1),
+                    2..=10 => (-1, 1),
+                    11..=20 => (-2, 2),
+                    21..=28 => (-3, 3),
+                    29 => (-3, 2),
+                    30 => (-3, 1),
+                    _ => (-3, -5),
+                };
+
+                quantizer as i8 + if is_11 { step_11 } else { step_10 }
+            }
+            Self::Absolute(quantizer) => quantizer as i8,
+        };
+
+        new_quantizer.clamp(1, 31) as u8
+    }
 }
 
 /// ITU-T Recommendation H.263 (01/2005), 5.3.2 `MCBPC` (block-type half)
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum MacroblockType {
     /// Macroblock.
     Inter,
@@ -679,7 +983,7 @@ impl MacroblockType {
 ///
 /// Coded block pattern bits that indicate which blocks contain frequency
 /// components to be coded for.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct CodedBlockPattern {
     pub codes_luma: [bool; 4],
     pub codes_chroma_b: bool,
@@ -687,7 +991,7 @@ pub struct CodedBlockPattern {
 }
 
 /// Half-pixel motion vector components.
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct HalfPel(i16);
 
 impl From<f32> for HalfPel {
@@ -703,14 +1007,36 @@ impl HalfPel {
     pub const EXTENDED_RANGE_SIXTEENCIF: Self = Self(256);
     pub const EXTENDED_RANGE_BEYONDCIF: Self = Self(512);
 
+    /// A half-pel of zero length.
+    pub const ZERO: Self = Self(0);
+
     /// Construct a half-pel from some value that already contains half-pel
     /// units.
-    pub fn from_unit(unit: i16) -> Self {
+    pub const fn from_unit(unit: i16) -> Self {
         HalfPel(unit)
     }
 
-    pub fn zero() -> Self {
-        Self(0)
+    /// Get the number of half-pel units in this half-pel.
+    pub const fn into_unit(self) -> i16 {
+        self.0
+    }
+
+    /// Get the length of this half-pel in whole pixels.
+    ///
+    /// This is the inverse of the `From<f32>` conversion, for displaying or
+    /// logging motion vectors.
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / 2.0
+    }
+
+    pub const fn zero() -> Self {
+        Self::ZERO
+    }
+
+    /// Determine if the half-pel lands on a whole pixel, and thus needs no
+    /// interpolation.
+    pub fn is_full_pixel(self) -> bool {
+        self.0 % 2 == 0
     }
 
     /// Separate the half-pixel into lerp parameters.
@@ -719,15 +1045,30 @@ impl HalfPel {
     /// sample from, and the second indicates if you also need to lerp with the
     /// pixel to the right or bottom.
     pub fn into_lerp_parameters(self) -> (i16, bool) {
-        if self.0 % 2 == 0 {
+        if self.is_full_pixel() {
             (self.0 / 2, false)
-        } else if self < HalfPel::zero() {
+        } else if self < HalfPel::ZERO {
             (self.0 / 2 - 1, true)
         } else {
             (self.0 / 2, true)
         }
     }
 
+    /// Separate the half-pixel into lerp parameters, if it is within a
+    /// reasonable range.
+    ///
+    /// Motion vectors decoded from a bitstream are never larger than
+    /// `EXTENDED_RANGE_BEYONDCIF`, and the vectors derived from them for PB
+    /// frames are at most twice as large. Anything further out than that can
+    /// only come from corrupt data, and yields `None`.
+    pub fn checked_into_lerp_parameters(self) -> Option<(i16, bool)> {
+        if self.0.unsigned_abs() > 2 * Self::EXTENDED_RANGE_BEYONDCIF.0 as u16 {
+            None
+        } else {
+            Some(self.into_lerp_parameters())
+        }
+    }
+
     /// Invert the HalfPel around the restricted MVD component range.
     ///
     /// For example, given a HalfPel decoded from the Vector column of H.263
@@ -767,6 +1108,16 @@ impl HalfPel {
         }
     }
 
+    /// Scale the half-pel by a ratio of temporal distances.
+    ///
+    /// The product is computed at full precision and then divided with
+    /// truncation towards zero, which is the rounding H.263 (01/2005) G.4
+    /// specifies for deriving B block vectors. `denominator` must not be
+    /// zero.
+    pub fn scale(self, numerator: i16, denominator: i16) -> Self {
+        Self((self.0 as i32 * numerator as i32 / denominator as i32) as i16)
+    }
+
     /// Given this and two other values, determine the median value and return
     /// it.
     pub fn median_of(self, mhs: Self, rhs: Self) -> Self {
@@ -806,6 +1157,22 @@ impl Add<HalfPel> for HalfPel {
     }
 }
 
+impl Sub<HalfPel> for HalfPel {
+    type Output = HalfPel;
+
+    fn sub(self, rhs: Self) -> Self {
+        HalfPel(self.0 - rhs.0)
+    }
+}
+
+impl Mul<i16> for HalfPel {
+    type Output = HalfPel;
+
+    fn mul(self, rhs: i16) -> Self {
+        HalfPel(self.0 * rhs)
+    }
+}
+
 impl Div<i16> for HalfPel {
     type Output = HalfPel;
 
@@ -823,22 +1190,57 @@ impl Neg for HalfPel {
 }
 
 /// A motion vector consisting of X and Y components.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct MotionVector(HalfPel, HalfPel);
 
 impl MotionVector {
-    pub fn zero() -> Self {
-        Self(HalfPel::zero(), HalfPel::zero())
+    /// A motion vector that does not move anything.
+    pub const ZERO: Self = Self(HalfPel::ZERO, HalfPel::ZERO);
+
+    /// Construct a motion vector from it's X and Y components.
+    pub const fn new(x: HalfPel, y: HalfPel) -> Self {
+        Self(x, y)
+    }
+
+    pub const fn zero() -> Self {
+        Self::ZERO
+    }
+
+    /// Determine if both components land on whole pixels, so that motion
+    /// compensation is a plain copy.
+    pub fn is_full_pixel(self) -> bool {
+        self.0.is_full_pixel() && self.1.is_full_pixel()
     }
 
     pub fn into_lerp_parameters(self) -> ((i16, bool), (i16, bool)) {
         (self.0.into_lerp_parameters(), self.1.into_lerp_parameters())
     }
 
+    /// Separate both components into lerp parameters, yielding `None` if
+    /// either of them is out of range.
+    ///
+    /// See `HalfPel::checked_into_lerp_parameters`.
+    pub fn checked_into_lerp_parameters(self) -> Option<((i16, bool), (i16, bool))> {
+        Some((
+            self.0.checked_into_lerp_parameters()?,
+            self.1.checked_into_lerp_parameters()?,
+        ))
+    }
+
     pub fn average_sum_of_mvs(self) -> Self {
         Self(self.0.average_sum_of_mvs(), self.1.average_sum_of_mvs())
     }
 
+    /// Scale both components by a ratio of temporal distances.
+    ///
+    /// See `HalfPel::scale`.
+    pub fn scale(self, numerator: i16, denominator: i16) -> Self {
+        Self(
+            self.0.scale(numerator, denominator),
+            self.1.scale(numerator, denominator),
+        )
+    }
+
     /// Given this and two other motion vectors, determine the median value of
     /// each component and return it.
     pub fn median_of(self, mhs: Self, rhs: Self) -> Self {
@@ -869,6 +1271,22 @@ impl Add<MotionVector> for MotionVector {
     }
 }
 
+impl Sub<MotionVector> for MotionVector {
+    type Output = MotionVector;
+
+    fn sub(self, rhs: Self) -> Self {
+        MotionVector(self.0 - rhs.0, self.1 - rhs.1)
+    }
+}
+
+impl Mul<i16> for MotionVector {
+    type Output = MotionVector;
+
+    fn mul(self, rhs: i16) -> Self {
+        MotionVector(self.0 * rhs, self.1 * rhs)
+    }
+}
+
 impl Div<i16> for MotionVector {
     type Output = MotionVector;
 
@@ -892,6 +1310,54 @@ pub struct Block {
     pub tcoef: Vec<TCoefficient>,
 }
 
+impl Block {
+    /// Start building a block with a given DC component.
+    pub fn builder(intradc: Option<IntraDc>) -> BlockBuilder {
+        BlockBuilder {
+            coefficients: intradc.is_some() as usize,
+            block: Block {
+                intradc,
+                tcoef: Vec::new(),
+            },
+        }
+    }
+}
+
+/// Builds a `Block` one coefficient at a time, checking that all of them fit
+/// within the 64 coefficients of the block.
+#[derive(PartialEq, Eq, Debug)]
+pub struct BlockBuilder {
+    /// The block built so far.
+    block: Block,
+
+    /// How many of the block's coefficients have been consumed so far,
+    /// including the DC component and all zero runs.
+    coefficients: usize,
+}
+
+impl BlockBuilder {
+    /// Append a coefficient to the block.
+    ///
+    /// This yields `None` if the coefficient (and the run of zeroes preceding
+    /// it) would not fit in the rest of the block.
+    pub fn coefficient(mut self, tcoef: TCoefficient) -> Option<Self> {
+        let coefficients = self.coefficients + tcoef.run as usize + 1;
+        if coefficients > 64 {
+            return None;
+        }
+
+        self.coefficients = coefficients;
+        self.block.tcoef.push(tcoef);
+
+        Some(self)
+    }
+
+    /// Finish building the block.
+    pub fn build(self) -> Block {
+        self.block
+    }
+}
+
 /// This type has nothing to do with H.263 itself, it's just to keep some trivial
 /// (or at least simpler) special cases of the IDCT inputs separate as an optimization.
 /// It's easier and faster to do it here instead of trying to detect them later.
@@ -952,6 +1418,10 @@ impl IntraDc {
     }
 
     /// Retrieve the reconstruction level of the DC component.
+    ///
+    /// Intra DC levels are never negative. This nevertheless yields an `i16`
+    /// so that it can be used interchangeably with the (signed) levels of the
+    /// `TCOEF`s that make up the rest of the block.
     pub fn into_level(self) -> i16 {
         if self.0 == 0xFF {
             1024
@@ -961,6 +1431,60 @@ impl IntraDc {
     }
 }
 
+/// The order in which the coefficients of a block are transmitted.
+///
+/// Each scan lists the raster position (x + y*8) within the block of each
+/// coefficient, in the order they appear in the bitstream.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BlockScan {
+    /// The usual zigzag scan.
+    ///
+    /// ITU-T Recommendation H.263 (01/2005) Figure 14.
+    Zigzag,
+
+    /// The alternate-horizontal scan, used by Advanced Intra Coding (Annex I)
+    /// for blocks predicted from the block above.
+    ///
+    /// ITU-T Recommendation H.263 (01/2005) Figure I.2.
+    AlternateHorizontal,
+
+    /// The alternate-vertical scan, used by Advanced Intra Coding (Annex I)
+    /// for blocks predicted from the block to the left.
+    ///
+    /// ITU-T Recommendation H.263 (01/2005) Figure I.3.
+    AlternateVertical,
+}
+
+const ZIGZAG_SCAN: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+const ALTERNATE_HORIZONTAL_SCAN: [usize; 64] = [
+    0, 1, 2, 3, 8, 9, 16, 17, 10, 11, 4, 5, 6, 7, 15, 14, 13, 12, 19, 18, 24, 25, 32, 33, 26, 27,
+    20, 21, 22, 23, 28, 29, 30, 31, 34, 35, 40, 41, 48, 49, 42, 43, 36, 37, 38, 39, 44, 45, 46, 47,
+    50, 51, 56, 57, 58, 59, 52, 53, 54, 55, 60, 61, 62, 63,
+];
+
+const ALTERNATE_VERTICAL_SCAN: [usize; 64] = [
+    0, 8, 16, 24, 1, 9, 2, 10, 17, 25, 32, 40, 48, 56, 57, 49, 41, 33, 26, 18, 3, 11, 4, 12, 19,
+    27, 34, 42, 50, 58, 35, 43, 51, 59, 20, 28, 5, 13, 6, 14, 21, 29, 36, 44, 52, 60, 37, 45, 53,
+    61, 22, 30, 7, 15, 23, 31, 38, 46, 54, 62, 39, 47, 55, 63,
+];
+
+impl BlockScan {
+    /// Get the raster position of each coefficient of a block, in scan
+    /// order.
+    pub fn order(&self) -> &'static [usize; 64] {
+        match self {
+            Self::Zigzag => &ZIGZAG_SCAN,
+            Self::AlternateHorizontal => &ALTERNATE_HORIZONTAL_SCAN,
+            Self::AlternateVertical => &ALTERNATE_VERTICAL_SCAN,
+        }
+    }
+}
+
 /// ITU-T Recommendation H.263 (01/2005) 5.4.2 `TCOEF`
 ///
 /// Represents an IDCT coefficient stored in quantized, run-length encoded
@@ -984,3 +1508,324 @@ pub struct TCoefficient {
     /// Sorenson Spark version 1 bitstreams, `LEVEL` is either 7 or 11 bits.
     pub level: i16,
 }
+
+impl TCoefficient {
+    /// Construct a coefficient, checking that it can exist in a block.
+    ///
+    /// This yields `None` if the `run` is longer than the 63 coefficients
+    /// that can precede the last one of a block, or if the `level` is zero,
+    /// as zero coefficients are always coded as part of a run instead. Whether
+    /// or not this is the `LAST` coefficient of it's block is not recorded
+    /// here; it is determined by it's position within `Block::tcoef`.
+    pub fn new(is_short: bool, run: u8, level: i16) -> Option<Self> {
+        if run > 63 || level == 0 {
+            return None;
+        }
+
+        Some(Self {
+            is_short,
+            run,
+            level,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tcoefficient_new() {
+        assert_eq!(
+            TCoefficient::new(true, 63, -1),
+            Some(TCoefficient {
+                is_short: true,
+                run: 63,
+                level: -1
+            })
+        );
+        assert_eq!(TCoefficient::new(false, 64, 1), None);
+        assert_eq!(TCoefficient::new(false, 0, 0), None);
+    }
+
+    #[test]
+    fn block_builder() {
+        let block = Block::builder(IntraDc::from_u8(16))
+            .coefficient(TCoefficient::new(true, 0, 2).unwrap())
+            .unwrap()
+            .coefficient(TCoefficient::new(true, 60, -1).unwrap())
+            .unwrap()
+            .build();
+        assert_eq!(block.intradc, IntraDc::from_u8(16));
+        assert_eq!(block.tcoef.len(), 2);
+
+        // With the DC component, 63 coefficients are left
+        assert!(Block::builder(IntraDc::from_u8(16))
+            .coefficient(TCoefficient::new(false, 62, 1).unwrap())
+            .is_some());
+        assert!(Block::builder(IntraDc::from_u8(16))
+            .coefficient(TCoefficient::new(false, 63, 1).unwrap())
+            .is_none());
+        assert!(Block::builder(None)
+            .coefficient(TCoefficient::new(false, 63, 1).unwrap())
+            .unwrap()
+            .coefficient(TCoefficient::new(false, 0, 1).unwrap())
+            .is_none());
+    }
+
+    #[test]
+    fn custom_picture_clock_new() {
+        assert_eq!(CustomPictureClock::new(false, 0), None);
+        assert_eq!(CustomPictureClock::new(true, 0), None);
+
+        let clock = CustomPictureClock::new(true, 30).unwrap();
+        assert!(clock.times_1001);
+        assert_eq!(clock.divisor, 30);
+
+        let clock = CustomPictureClock::new(false, 127).unwrap();
+        assert!(!clock.times_1001);
+        assert_eq!(clock.divisor, 127);
+    }
+
+    #[test]
+    fn source_format_extended_mv_range() {
+        assert_eq!(
+            SourceFormat::SubQcif.extended_mv_range(),
+            (HalfPel::EXTENDED_RANGE, HalfPel::EXTENDED_RANGE)
+        );
+        assert_eq!(
+            SourceFormat::FullCif.extended_mv_range(),
+            (HalfPel::EXTENDED_RANGE, HalfPel::EXTENDED_RANGE)
+        );
+        assert_eq!(
+            SourceFormat::FourCif.extended_mv_range(),
+            (
+                HalfPel::EXTENDED_RANGE_QUADCIF,
+                HalfPel::EXTENDED_RANGE_QUADCIF
+            )
+        );
+        assert_eq!(
+            SourceFormat::SixteenCif.extended_mv_range(),
+            (
+                HalfPel::EXTENDED_RANGE_SIXTEENCIF,
+                HalfPel::EXTENDED_RANGE_SIXTEENCIF
+            )
+        );
+        assert_eq!(
+            SourceFormat::Reserved.extended_mv_range(),
+            (HalfPel::EXTENDED_RANGE, HalfPel::EXTENDED_RANGE)
+        );
+
+        let wide = SourceFormat::Extended(CustomPictureFormat {
+            pixel_aspect_ratio: PixelAspectRatio::Square,
+            picture_width_indication: 2048,
+            picture_height_indication: 288,
+        });
+        assert_eq!(
+            wide.extended_mv_range(),
+            (HalfPel::EXTENDED_RANGE_BEYONDCIF, HalfPel::EXTENDED_RANGE)
+        );
+    }
+
+    #[test]
+    fn intradc_level_round_trip() {
+        for code in 1..=u8::MAX {
+            match IntraDc::from_u8(code) {
+                Some(dc) => {
+                    let level = dc.into_level();
+                    assert!((8..=2032).contains(&level));
+                    assert_eq!(IntraDc::from_level(level as u16), Some(dc));
+                }
+                None => assert_eq!(code, 128),
+            }
+        }
+
+        for level in (8..=2032).step_by(8) {
+            let dc = IntraDc::from_level(level).unwrap();
+            assert_eq!(dc.into_level(), level as i16);
+        }
+
+        assert_eq!(IntraDc::from_level(1024), IntraDc::from_u8(0xFF));
+        assert_eq!(IntraDc::from_u8(0xFF).unwrap().into_level(), 1024);
+    }
+
+    #[test]
+    fn intradc_invalid_levels() {
+        assert_eq!(IntraDc::from_u8(0), None);
+        assert_eq!(IntraDc::from_u8(128), None);
+
+        for level in [0, 1, 7, 9, 1020, 2031, 2040, 2048, 4096, u16::MAX] {
+            assert_eq!(IntraDc::from_level(level), None);
+        }
+    }
+
+    #[test]
+    fn macroblock_predicates() {
+        let coded = Macroblock::Coded {
+            mb_type: MacroblockType::Inter,
+            coded_block_pattern: CodedBlockPattern::default(),
+            coded_block_pattern_b: None,
+            d_quantizer: None,
+            motion_vector: Some(MotionVector::zero()),
+            addl_motion_vectors: None,
+            motion_vector_b: None,
+            b_prediction_mode: None,
+            intra_prediction_mode: None,
+        };
+        assert!(coded.is_coded());
+        assert!(!coded.is_skipped());
+        assert!(!coded.is_stuffing());
+
+        assert!(!Macroblock::Uncoded.is_coded());
+        assert!(Macroblock::Uncoded.is_skipped());
+        assert!(!Macroblock::Uncoded.is_stuffing());
+
+        assert!(!Macroblock::Stuffing.is_coded());
+        assert!(!Macroblock::Stuffing.is_skipped());
+        assert!(Macroblock::Stuffing.is_stuffing());
+    }
+
+    #[test]
+    fn motion_vector_eq_hash() {
+        use std::collections::HashSet;
+
+        let mv = MotionVector::from((HalfPel::from_unit(3), HalfPel::from_unit(-2)));
+        assert_eq!(mv, mv + MotionVector::zero());
+        assert_ne!(mv, MotionVector::zero());
+        assert_ne!(
+            mv,
+            MotionVector::from((HalfPel::from_unit(-2), HalfPel::from_unit(3)))
+        );
+
+        let vectors: HashSet<MotionVector> = [mv, MotionVector::zero(), mv, mv * 1]
+            .iter()
+            .copied()
+            .collect();
+        assert_eq!(vectors.len(), 2);
+        assert!(vectors.contains(&MotionVector::zero()));
+    }
+
+    #[test]
+    fn zero_constants() {
+        const VECTORS: [MotionVector; 4] = [MotionVector::ZERO; 4];
+        const RIGHT: MotionVector = MotionVector::new(HalfPel::from_unit(1), HalfPel::ZERO);
+
+        assert_eq!(VECTORS, [MotionVector::zero(); 4]);
+        assert_eq!(HalfPel::ZERO, HalfPel::from_unit(0));
+        assert_eq!(
+            RIGHT,
+            MotionVector::from((HalfPel::from_unit(1), HalfPel::zero()))
+        );
+    }
+
+    #[test]
+    fn half_pel_f32_round_trip() {
+        const LEFT: HalfPel = HalfPel::from_unit(-3);
+
+        assert_eq!(LEFT.to_f32(), -1.5);
+        assert_eq!(HalfPel::from(LEFT.to_f32()), LEFT);
+        assert_eq!(HalfPel::STANDARD_RANGE.to_f32(), 16.0);
+        assert_eq!(HalfPel::ZERO.to_f32(), 0.0);
+    }
+
+    #[test]
+    fn full_pixel() {
+        assert!(HalfPel::ZERO.is_full_pixel());
+        assert!(HalfPel::from_unit(-4).is_full_pixel());
+        assert!(!HalfPel::from_unit(-3).is_full_pixel());
+        assert!(!HalfPel::from_unit(5).is_full_pixel());
+
+        assert!(MotionVector::new(HalfPel::from_unit(2), HalfPel::from_unit(-6)).is_full_pixel());
+        assert!(!MotionVector::new(HalfPel::from_unit(2), HalfPel::from_unit(-1)).is_full_pixel());
+        assert!(!MotionVector::new(HalfPel::from_unit(1), HalfPel::ZERO).is_full_pixel());
+    }
+
+    #[test]
+    fn checked_lerp_parameters() {
+        assert_eq!(
+            HalfPel::from_unit(-3).checked_into_lerp_parameters(),
+            Some((-2, true))
+        );
+        assert_eq!(
+            HalfPel::from_unit(1024).checked_into_lerp_parameters(),
+            Some((512, false))
+        );
+        assert_eq!(
+            HalfPel::from_unit(-1024).checked_into_lerp_parameters(),
+            Some((-512, false))
+        );
+        assert_eq!(
+            HalfPel::from_unit(1025).checked_into_lerp_parameters(),
+            None
+        );
+        assert_eq!(
+            HalfPel::from_unit(i16::MIN).checked_into_lerp_parameters(),
+            None
+        );
+
+        let mv = MotionVector::from((HalfPel::from_unit(5), HalfPel::from_unit(i16::MAX)));
+        assert_eq!(mv.checked_into_lerp_parameters(), None);
+        let mv = MotionVector::from((HalfPel::from_unit(5), HalfPel::from_unit(-4)));
+        assert_eq!(
+            mv.checked_into_lerp_parameters(),
+            Some(((2, true), (-2, false)))
+        );
+    }
+
+    #[test]
+    fn block_scan_order() {
+        for scan in [
+            BlockScan::Zigzag,
+            BlockScan::AlternateHorizontal,
+            BlockScan::AlternateVertical,
+        ] {
+            let mut seen = [false; 64];
+            for pos in scan.order() {
+                assert!(!seen[*pos]);
+                seen[*pos] = true;
+            }
+        }
+
+        assert_eq!(
+            &BlockScan::Zigzag.order()[..10],
+            &[0, 1, 8, 16, 9, 2, 3, 10, 17, 24]
+        );
+        assert_eq!(
+            &BlockScan::AlternateHorizontal.order()[..10],
+            &[0, 1, 2, 3, 8, 9, 16, 17, 10, 11]
+        );
+        assert_eq!(
+            &BlockScan::AlternateVertical.order()[..10],
+            &[0, 8, 16, 24, 1, 9, 2, 10, 17, 25]
+        );
+        assert_eq!(BlockScan::Zigzag.order()[63], 63);
+    }
+
+    #[test]
+    fn motion_vector_scale() {
+        // TRB/TRD of 1/2: 1.5 pixels forward scales to half a pixel
+        assert_eq!(HalfPel::from_unit(3).scale(1, 2), HalfPel::from_unit(1));
+        assert_eq!(HalfPel::from_unit(3).scale(-1, 2), HalfPel::from_unit(-1));
+        assert_eq!(HalfPel::from_unit(-3).scale(1, 2), HalfPel::from_unit(-1));
+
+        // TRB/TRD of 2/3 and -1/3
+        assert_eq!(HalfPel::from_unit(7).scale(2, 3), HalfPel::from_unit(4));
+        assert_eq!(HalfPel::from_unit(7).scale(-1, 3), HalfPel::from_unit(-2));
+
+        // TRB/TRD of 5/5 and 0/5
+        assert_eq!(HalfPel::from_unit(-9).scale(5, 5), HalfPel::from_unit(-9));
+        assert_eq!(HalfPel::from_unit(-9).scale(0, 5), HalfPel::zero());
+
+        // Intermediate products that don't fit in an i16
+        assert_eq!(
+            HalfPel::from_unit(2048).scale(255, 256),
+            HalfPel::from_unit(2040)
+        );
+
+        let mv = MotionVector::from((HalfPel::from_unit(5), HalfPel::from_unit(-6)));
+        assert_eq!(
+            mv.scale(3, 4),
+            MotionVector::from((HalfPel::from_unit(3), HalfPel::from_unit(-4)))
+        );
+    }
+}
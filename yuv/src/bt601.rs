@@ -1,41 +1,97 @@
 //! YUV-to-RGB decode
 
 // TODO: Replace with `std::simd` when it's stable
+#[cfg(feature = "simd")]
 use wide::{i32x4, u8x16};
 
+use crate::color::{ColorMatrix, ConversionConstants, YuvRange};
+
+/// The error returned when an output buffer is not the right size for the
+/// picture being converted into it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SizeError {
+    /// The length the output buffer needed to have.
+    pub expected: usize,
+
+    /// The length the output buffer actually had.
+    pub actual: usize,
+}
+
+impl std::fmt::Display for SizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Output buffer holds {} elements, but {} are needed",
+            self.actual, self.expected
+        )
+    }
+}
+
+impl std::error::Error for SizeError {}
+
 // Operates on 4 pixels at a time, one pixel per SIMD lane,
 // with 32 bits of intermediate per-component precision for
 // each, so as to fill the 128-bit SIMD registers on WASM.
-// And i32x4 also allows the neat transpose trick at the end.
-// The output is an interleaved array of 4 RGBA pixels.
+// The output is the R, G, and B components of the 4 pixels,
+// in the fixed point format of `consts`, not yet rounded or clamped.
+#[cfg(feature = "simd")]
 #[inline]
-fn yuv_to_rgba_4x(yuv: (&[u8; 4], &[u8; 2], &[u8; 2]), rgba: &mut [u8; 16]) {
+fn yuv_to_rgb_fixed_4x(
+    yuv: (&[u8; 4], &[u8; 4], &[u8; 4]),
+    consts: &ConversionConstants,
+) -> (i32x4, i32x4, i32x4) {
     let (y, cb, cr) = yuv;
 
-    // Expanding the 4 bytes into a i32x4, and duplicating chroma samples horizontally.
-    // The -16 and -128 are simply undoing the offsets in the input representation.
-    let y = i32x4::from([y[0] as i32, y[1] as i32, y[2] as i32, y[3] as i32]) - i32x4::splat(16);
+    // Expanding the 4 bytes of each component into a i32x4.
+    // The luma offset and -128 are simply undoing the offsets in the input representation.
+    let y = i32x4::from([y[0] as i32, y[1] as i32, y[2] as i32, y[3] as i32])
+        - i32x4::splat(consts.luma_offset);
     let cb =
-        i32x4::from([cb[0] as i32, cb[0] as i32, cb[1] as i32, cb[1] as i32]) - i32x4::splat(128);
+        i32x4::from([cb[0] as i32, cb[1] as i32, cb[2] as i32, cb[3] as i32]) - i32x4::splat(128);
     let cr =
-        i32x4::from([cr[0] as i32, cr[0] as i32, cr[1] as i32, cr[1] as i32]) - i32x4::splat(128);
+        i32x4::from([cr[0] as i32, cr[1] as i32, cr[2] as i32, cr[3] as i32]) - i32x4::splat(128);
 
-    // The rest of the magic numbers are the coefficients converted to 16.16 fixed point, and rounded.
-    // They also include the extension from reduced (16..235 and 16...240) to full-range (0..255).
-    let gray = y * i32x4::splat(76309); // 76309 == round((255.0 / 219.0) * 65536.0)
-    let cr2r = cr * i32x4::splat(104597); // 104597 == round((255.0 / 224.0) * 1.402 * 65536.0)
-    let cr2g = cr * i32x4::splat(-53279); // -53279 == round(-(255.0 / 224.0) * 1.402 * (0.299 / 0.587) * 65536.0)
-    let cb2g = cb * i32x4::splat(-25675); // -25675 == round(-(255.0 / 224.0) * 1.772 * (0.114 / 0.587) * 65536.0)
-    let cb2b = cb * i32x4::splat(132201); // 132201 == round((255.0 / 224.0) * 1.772 * 65536.0)
+    let gray = y * i32x4::splat(consts.gray);
+    let cr2r = cr * i32x4::splat(consts.cr2r);
+    let cr2g = cr * i32x4::splat(consts.cr2g);
+    let cb2g = cb * i32x4::splat(consts.cb2g);
+    let cb2b = cb * i32x4::splat(consts.cb2b);
 
-    // This is 0.5 in 16.16 format, added to make the rightshift round correctly
-    let half = i32x4::splat(32768);
+    (gray + cr2r, gray + cr2g + cb2g, gray + cb2b)
+}
+
+// Converts 4 pixels at a time into 8 bits per component.
+// And i32x4 also allows the neat transpose trick at the end.
+// The output is an interleaved array of 4 RGBA pixels.
+#[cfg(feature = "simd")]
+#[inline]
+fn yuv_to_rgba_4x(
+    yuv: (&[u8; 4], &[u8; 4], &[u8; 4]),
+    consts: &ConversionConstants,
+    rgba: &mut [u8; 16],
+) {
+    yuv_to_rgba_biased_4x(yuv, consts, [consts.half(); 4], rgba)
+}
+
+// Same as `yuv_to_rgba_4x`, but with a separate bias for each pixel, in
+// the fixed point format of `consts`, added before the fraction is shifted off.
+#[cfg(feature = "simd")]
+#[inline]
+fn yuv_to_rgba_biased_4x(
+    yuv: (&[u8; 4], &[u8; 4], &[u8; 4]),
+    consts: &ConversionConstants,
+    bias: [i32; 4],
+    rgba: &mut [u8; 16],
+) {
+    let (r, g, b) = yuv_to_rgb_fixed_4x(yuv, consts);
+    let bias = i32x4::from(bias);
+    let shift = consts.fraction_bits as i32;
 
     // We could skip the shift here, then simply cast the result into [u8; 16], and take
     // bytes 2, 4, 10, 14 instead (after clamping), but it's not any faster, it seems.
-    let r: i32x4 = (gray + cr2r + half) >> 16;
-    let g: i32x4 = (gray + cr2g + cb2g + half) >> 16;
-    let b: i32x4 = (gray + cb2b + half) >> 16;
+    let r: i32x4 = (r + bias) >> shift;
+    let g: i32x4 = (g + bias) >> shift;
+    let b: i32x4 = (b + bias) >> shift;
 
     // Clamping to the valid output range
     // A simple clamp(x, 0, 255) doesn't work, because it seems to
@@ -58,17 +114,145 @@ fn yuv_to_rgba_4x(yuv: (&[u8; 4], &[u8; 2], &[u8; 2]), rgba: &mut [u8; 16]) {
     rgba.copy_from_slice(bytemuck::cast::<i32x4, u8x16>(rgba_4x).as_array_ref())
 }
 
+// Same as `yuv_to_rgba_4x`, but keeping 8 more bits of precision
+// from the fixed point intermediates, for 16 bits per component.
+#[cfg(feature = "simd")]
+#[inline]
+fn yuv_to_rgba16_4x(
+    yuv: (&[u8; 4], &[u8; 4], &[u8; 4]),
+    consts: &ConversionConstants,
+    rgba: &mut [u16; 16],
+) {
+    let (r, g, b) = yuv_to_rgb_fixed_4x(yuv, consts);
+    let shift = consts.fraction_bits as i32 - 8;
+
+    // This is 0.5 with 8 bits of fraction kept, added to make the rightshift round correctly
+    let half = i32x4::splat(1 << (shift - 1));
+    let max = i32x4::splat(255 << 8);
+
+    let r: i32x4 = (r + half) >> shift;
+    let g: i32x4 = (g + half) >> shift;
+    let b: i32x4 = (b + half) >> shift;
+
+    let r = r.max(i32x4::ZERO).min(max);
+    let g = g.max(i32x4::ZERO).min(max);
+    let b = b.max(i32x4::ZERO).min(max);
+
+    // Stretching 0..=0xFF00 to 0..=0xFFFF, so that full intensity is u16::MAX
+    let r: i32x4 = r + (r >> 8);
+    let g: i32x4 = g + (g >> 8);
+    let b: i32x4 = b + (b >> 8);
+
+    let (r, g, b) = (r.to_array(), g.to_array(), b.to_array());
+
+    for (i, rgba) in rgba.chunks_exact_mut(4).enumerate() {
+        rgba[0] = r[i] as u16;
+        rgba[1] = g[i] as u16;
+        rgba[2] = b[i] as u16;
+        rgba[3] = u16::MAX;
+    }
+}
+
+// The pure-scalar equivalent of the SIMD kernel above, used when the `simd`
+// feature is disabled. Converts a single pixel, with the exact same
+// fixed-point arithmetic, so the results are bit-identical.
+#[cfg(not(feature = "simd"))]
+#[inline]
+fn yuv_to_rgb_fixed(yuv: (u8, u8, u8), consts: &ConversionConstants) -> (i32, i32, i32) {
+    let y = yuv.0 as i32 - consts.luma_offset;
+    let cb = yuv.1 as i32 - 128;
+    let cr = yuv.2 as i32 - 128;
+
+    let gray = y * consts.gray;
+
+    (
+        gray + cr * consts.cr2r,
+        gray + cr * consts.cr2g + cb * consts.cb2g,
+        gray + cb * consts.cb2b,
+    )
+}
+
+#[cfg(not(feature = "simd"))]
+#[inline]
+fn yuv_to_rgba_biased(yuv: (u8, u8, u8), consts: &ConversionConstants, bias: i32, rgba: &mut [u8]) {
+    let (r, g, b) = yuv_to_rgb_fixed(yuv, consts);
+    let shift = consts.fraction_bits;
+
+    rgba[0] = ((r + bias) >> shift).clamp(0, 255) as u8;
+    rgba[1] = ((g + bias) >> shift).clamp(0, 255) as u8;
+    rgba[2] = ((b + bias) >> shift).clamp(0, 255) as u8;
+    rgba[3] = 255;
+}
+
+#[cfg(not(feature = "simd"))]
+#[inline]
+fn yuv_to_rgba16(yuv: (u8, u8, u8), consts: &ConversionConstants, rgba: &mut [u16]) {
+    let (r, g, b) = yuv_to_rgb_fixed(yuv, consts);
+    let shift = consts.fraction_bits - 8;
+    let half = 1 << (shift - 1);
+    let stretch = |x: i32| {
+        let x = ((x + half) >> shift).clamp(0, 255 << 8);
+        (x + (x >> 8)) as u16
+    };
+
+    rgba[0] = stretch(r);
+    rgba[1] = stretch(g);
+    rgba[2] = stretch(b);
+    rgba[3] = u16::MAX;
+}
+
+// Same interface as the SIMD kernel, but simply loops over the 4 pixels.
+#[cfg(not(feature = "simd"))]
+#[inline]
+fn yuv_to_rgba_4x(
+    yuv: (&[u8; 4], &[u8; 4], &[u8; 4]),
+    consts: &ConversionConstants,
+    rgba: &mut [u8; 16],
+) {
+    yuv_to_rgba_biased_4x(yuv, consts, [consts.half(); 4], rgba)
+}
+
+#[cfg(not(feature = "simd"))]
+#[inline]
+fn yuv_to_rgba_biased_4x(
+    yuv: (&[u8; 4], &[u8; 4], &[u8; 4]),
+    consts: &ConversionConstants,
+    bias: [i32; 4],
+    rgba: &mut [u8; 16],
+) {
+    let (y, cb, cr) = yuv;
+
+    for (i, rgba) in rgba.chunks_exact_mut(4).enumerate() {
+        yuv_to_rgba_biased((y[i], cb[i], cr[i]), consts, bias[i], rgba);
+    }
+}
+
+#[cfg(not(feature = "simd"))]
+#[inline]
+fn yuv_to_rgba16_4x(
+    yuv: (&[u8; 4], &[u8; 4], &[u8; 4]),
+    consts: &ConversionConstants,
+    rgba: &mut [u16; 16],
+) {
+    let (y, cb, cr) = yuv;
+
+    for (i, rgba) in rgba.chunks_exact_mut(4).enumerate() {
+        yuv_to_rgba16((y[i], cb[i], cr[i]), consts, rgba);
+    }
+}
+
 // A single-pixel version, only for testing.
 #[cfg(test)]
 #[inline]
-fn yuv_to_rgb(yuv: (u8, u8, u8)) -> (u8, u8, u8) {
+fn yuv_to_rgb(yuv: (u8, u8, u8), range: YuvRange) -> (u8, u8, u8) {
     let mut rgba_4x = [0u8; 16];
     yuv_to_rgba_4x(
         (
             &[yuv.0, yuv.0, yuv.0, yuv.0],
-            &[yuv.1, yuv.1],
-            &[yuv.2, yuv.2],
+            &[yuv.1, yuv.1, yuv.1, yuv.1],
+            &[yuv.2, yuv.2, yuv.2, yuv.2],
         ),
+        ConversionConstants::new(ColorMatrix::Bt601, range),
         &mut rgba_4x,
     );
 
@@ -90,29 +274,64 @@ fn yuv_to_rgb(yuv: (u8, u8, u8)) -> (u8, u8, u8) {
     (rgba_4x[0], rgba_4x[1], rgba_4x[2])
 }
 
-/// Convert planar YUV 4:2:0 data into interleaved RGBA 8888 data.
+/// A 4x4 Bayer matrix, for ordered dithering.
+const BAYER_4X4: [[i32; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Drives one of the 4-pixel conversion kernels over an entire picture,
+/// producing `components` interleaved components of type `T` per pixel.
 ///
-/// This function yields an RGBA picture with the same number of pixels as were
-/// provided in the `y` picture. The `chroma_b` and `chroma_r` samples are
-/// simply reused without any interpolation for all four corresponding pixels.
-/// This is not the most correct, or nicest, but it's what Flash Player does.
+/// The kernel writes the 4 pixels into an `O`, which has to be an array of
+/// exactly `4 * components` elements of `T`. It is also given the luma row
+/// the pixels are on. Groups of 4 pixels always start at a multiple of 4
+/// horizontally, even on the right edge of pictures with a width that is not
+/// divisible by 4.
 ///
-/// Preconditions:
-///  - `y.len()` must be an integer multiple of `y_width`
-///  - `chroma_b` and `chroma_r` must be the same size
-///  - With `y_height` computed as `y.len() / y_width`, `br_width` as half of `y_width` (rounded up),
-///    and `br_height` as `chroma_b.len() / br_width`: `br_height` must be half of `y_height`, rounded up
-pub fn yuv420_to_rgba(y: &[u8], chroma_b: &[u8], chroma_r: &[u8], y_width: usize) -> Vec<u8> {
+/// See `yuv420_to_rgba` for the preconditions on the input.
+#[inline]
+fn yuv420_to_rgba_with<T: bytemuck::Pod, O: bytemuck::Pod>(
+    y: &[u8],
+    chroma_b: &[u8],
+    chroma_r: &[u8],
+    y_width: usize,
+    components: usize,
+    kernel: impl Fn((&[u8; 4], &[u8; 4], &[u8; 4]), usize, &mut O),
+) -> Vec<T> {
+    let mut rgba = vec![T::zeroed(); y.len() * components];
+    yuv420_to_rgba_into_with(
+        y, chroma_b, chroma_r, y_width, components, &mut rgba, kernel,
+    );
+
+    rgba
+}
+
+/// Same as `yuv420_to_rgba_with`, but writing into `rgba`, which must hold
+/// exactly `components` elements for each luma sample.
+#[inline]
+fn yuv420_to_rgba_into_with<T: bytemuck::Pod, O: bytemuck::Pod>(
+    y: &[u8],
+    chroma_b: &[u8],
+    chroma_r: &[u8],
+    y_width: usize,
+    components: usize,
+    rgba: &mut [T],
+    kernel: impl Fn((&[u8; 4], &[u8; 4], &[u8; 4]), usize, &mut O),
+) {
+    debug_assert_eq!(
+        std::mem::size_of::<O>(),
+        4 * components * std::mem::size_of::<T>()
+    );
+    debug_assert_eq!(rgba.len(), y.len() * components);
+
     // Shortcut for the no-op case to avoid all kinds of overflows below
     if y.is_empty() {
         debug_assert_eq!(chroma_b.len(), 0);
         debug_assert_eq!(chroma_r.len(), 0);
         debug_assert_eq!(y_width, 0);
-        return vec![];
+        return;
     }
 
-    // the + 1 is for rounding odd numbers up
-    let br_width = (y_width + 1) / 2;
+    // rounding odd numbers up
+    let br_width = y_width.div_ceil(2);
 
     debug_assert_eq!(y.len() % y_width, 0);
     debug_assert_eq!(chroma_b.len() % br_width, 0);
@@ -122,11 +341,10 @@ pub fn yuv420_to_rgba(y: &[u8], chroma_b: &[u8], chroma_r: &[u8], y_width: usize
     let y_height = y.len() / y_width;
     let br_height = chroma_b.len() / br_width;
 
-    // the + 1 is for rounding odd numbers up
-    debug_assert_eq!((y_height + 1) / 2, br_height);
+    // rounding odd numbers up
+    debug_assert_eq!(y_height.div_ceil(2), br_height);
 
-    let mut rgba = vec![0; y.len() * 4];
-    let rgba_stride = y_width * 4; // 4 bytes per pixel, interleaved
+    let rgba_stride = y_width * components; // interleaved components per pixel
 
     // Iteration is done in a row-major order to fit the slice layouts.
     for luma_rowindex in 0..y_height {
@@ -134,7 +352,7 @@ pub fn yuv420_to_rgba(y: &[u8], chroma_b: &[u8], chroma_r: &[u8], y_width: usize
 
         let y_remainder = y_width % 4;
         let br_remainder = br_width % 2;
-        let rgba_remainder = y_remainder * 4;
+        let rgba_remainder = y_remainder * components;
 
         // This block is here just so the mutable borrow of rgba_row expires sooner.
         {
@@ -155,11 +373,15 @@ pub fn yuv420_to_rgba(y: &[u8], chroma_b: &[u8], chroma_r: &[u8], y_width: usize
             // We need half as many chroma samples for each iteration
             let cb_iter = bytemuck::cast_slice::<u8, [u8; 2]>(cb_row).iter();
             let cr_iter = bytemuck::cast_slice::<u8, [u8; 2]>(cr_row).iter();
-            // Similar to how Y is iterated on, but with 4 channels per pixel
-            let rgba_iter = bytemuck::cast_slice_mut::<u8, [u8; 16]>(rgba_row).iter_mut();
+            // Similar to how Y is iterated on, but with all channels of each pixel
+            let rgba_iter = bytemuck::cast_slice_mut::<T, O>(rgba_row).iter_mut();
 
             for (((y, cb), cr), rgba) in y_iter.zip(cb_iter).zip(cr_iter).zip(rgba_iter) {
-                yuv_to_rgba_4x((y, cb, cr), rgba);
+                // Duplicating chroma samples horizontally
+                let cb = [cb[0], cb[0], cb[1], cb[1]];
+                let cr = [cr[0], cr[0], cr[1], cr[1]];
+
+                kernel((y, &cb, &cr), luma_rowindex, rgba);
             }
         }
 
@@ -174,27 +396,270 @@ pub fn yuv420_to_rgba(y: &[u8], chroma_b: &[u8], chroma_r: &[u8], y_width: usize
                 &mut rgba[luma_rowindex * rgba_stride..(luma_rowindex + 1) * rgba_stride];
 
             let mut y = [0u8; 4];
-            let mut cb = [0u8; 2];
-            let mut cr = [0u8; 2];
+            let mut cb = [0u8; 4];
+            let mut cr = [0u8; 4];
 
             for x in y_width - y_remainder..y_width {
                 y[x % 4] = y_row[x];
-                cb[(x % 4) / 2] = cb_row[x / 2];
-                cr[(x % 4) / 2] = cr_row[x / 2];
+                cb[x % 4] = cb_row[x / 2];
+                cr[x % 4] = cr_row[x / 2];
             }
 
-            let mut rgba_4x = [0u8; 16];
-            yuv_to_rgba_4x((&y, &cb, &cr), &mut rgba_4x);
+            let mut rgba_4x = O::zeroed();
+            kernel((&y, &cb, &cr), luma_rowindex, &mut rgba_4x);
+            let rgba_4x = bytemuck::cast_slice::<O, T>(std::slice::from_ref(&rgba_4x));
 
             for i in rgba_stride - rgba_remainder..rgba_stride {
-                rgba_row[i] = rgba_4x[i % 16];
+                rgba_row[i] = rgba_4x[i % (4 * components)];
             }
         }
     }
+}
+
+/// Converts a picture with chroma at full resolution (4:4:4) into
+/// interleaved RGBA 8888 data, 4 pixels at a time.
+///
+/// Rows don't matter for this, so the planes are simply processed as a
+/// whole, with only the last few pixels of the entire picture left over.
+fn yuv444_to_rgba(y: &[u8], cb: &[u8], cr: &[u8], consts: &ConversionConstants) -> Vec<u8> {
+    debug_assert_eq!(y.len(), cb.len());
+    debug_assert_eq!(y.len(), cr.len());
+
+    let mut rgba = vec![0u8; y.len() * 4];
+    let whole = y.len() / 4 * 4;
+
+    let y_iter = bytemuck::cast_slice::<u8, [u8; 4]>(&y[..whole]).iter();
+    let cb_iter = bytemuck::cast_slice::<u8, [u8; 4]>(&cb[..whole]).iter();
+    let cr_iter = bytemuck::cast_slice::<u8, [u8; 4]>(&cr[..whole]).iter();
+    let rgba_iter = bytemuck::cast_slice_mut::<u8, [u8; 16]>(&mut rgba[..whole * 4]).iter_mut();
+
+    for (((y, cb), cr), rgba) in y_iter.zip(cb_iter).zip(cr_iter).zip(rgba_iter) {
+        yuv_to_rgba_4x((y, cb, cr), consts, rgba);
+    }
+
+    if whole < y.len() {
+        let mut y_4x = [0u8; 4];
+        let mut cb_4x = [0u8; 4];
+        let mut cr_4x = [0u8; 4];
+        let remainder = y.len() - whole;
+
+        y_4x[..remainder].copy_from_slice(&y[whole..]);
+        cb_4x[..remainder].copy_from_slice(&cb[whole..]);
+        cr_4x[..remainder].copy_from_slice(&cr[whole..]);
+
+        let mut rgba_4x = [0u8; 16];
+        yuv_to_rgba_4x((&y_4x, &cb_4x, &cr_4x), consts, &mut rgba_4x);
+        rgba[whole * 4..].copy_from_slice(&rgba_4x[..remainder * 4]);
+    }
 
     rgba
 }
 
+/// Convert planar YUV 4:2:0 data into interleaved RGBA 8888 data.
+///
+/// This function yields an RGBA picture with the same number of pixels as were
+/// provided in the `y` picture. The `chroma_b` and `chroma_r` samples are
+/// simply reused without any interpolation for all four corresponding pixels.
+/// This is not the most correct, or nicest, but it's what Flash Player does.
+/// See `yuv420_to_rgba_bilinear` for a smoother alternative.
+///
+/// Preconditions:
+///  - `y.len()` must be an integer multiple of `y_width`
+///  - `chroma_b` and `chroma_r` must be the same size
+///  - With `y_height` computed as `y.len() / y_width`, `br_width` as half of `y_width` (rounded up),
+///    and `br_height` as `chroma_b.len() / br_width`: `br_height` must be half of `y_height`, rounded up
+pub fn yuv420_to_rgba(y: &[u8], chroma_b: &[u8], chroma_r: &[u8], y_width: usize) -> Vec<u8> {
+    yuv420_to_rgba_with_matrix(y, chroma_b, chroma_r, y_width, ColorMatrix::Bt601)
+}
+
+/// Convert planar YUV 4:2:0 data into interleaved RGBA 8888 data, writing it
+/// into `rgba`.
+///
+/// This is the same conversion as `yuv420_to_rgba`, but instead of allocating
+/// a new buffer for every picture, a single buffer can be reused for all of
+/// them. `rgba` must hold exactly four bytes for each pixel of the picture,
+/// that is, `y.len() * 4` of them. Otherwise, a `SizeError` is returned, and
+/// `rgba` is left untouched.
+///
+/// The preconditions are the same as for `yuv420_to_rgba`.
+pub fn yuv420_to_rgba_into(
+    y: &[u8],
+    chroma_b: &[u8],
+    chroma_r: &[u8],
+    y_width: usize,
+    rgba: &mut [u8],
+) -> Result<(), SizeError> {
+    if rgba.len() != y.len() * 4 {
+        return Err(SizeError {
+            expected: y.len() * 4,
+            actual: rgba.len(),
+        });
+    }
+
+    yuv420_to_rgba_into_with(
+        y,
+        chroma_b,
+        chroma_r,
+        y_width,
+        4,
+        rgba,
+        |yuv, _row, rgba| yuv_to_rgba_4x(yuv, &ConversionConstants::BT601, rgba),
+    );
+
+    Ok(())
+}
+
+/// Convert planar YUV 4:2:0 data into interleaved RGBA 8888 data, using the
+/// coefficients of the given color `matrix`.
+///
+/// This is the same conversion as `yuv420_to_rgba`, which always uses
+/// `ColorMatrix::Bt601`. The preconditions are the same, too.
+pub fn yuv420_to_rgba_with_matrix(
+    y: &[u8],
+    chroma_b: &[u8],
+    chroma_r: &[u8],
+    y_width: usize,
+    matrix: ColorMatrix,
+) -> Vec<u8> {
+    yuv420_to_rgba_with_matrix_and_range(y, chroma_b, chroma_r, y_width, matrix, YuvRange::Limited)
+}
+
+/// Convert planar YUV 4:2:0 data in the given `range` into interleaved RGBA
+/// 8888 data, using the coefficients of the given color `matrix`.
+///
+/// With `YuvRange::Full`, luma is not offset by 16, and no components are
+/// expanded from the reduced range before conversion. This is otherwise the
+/// same conversion as `yuv420_to_rgba_with_matrix`, which always uses
+/// `YuvRange::Limited`. The preconditions are the same, too.
+pub fn yuv420_to_rgba_with_matrix_and_range(
+    y: &[u8],
+    chroma_b: &[u8],
+    chroma_r: &[u8],
+    y_width: usize,
+    matrix: ColorMatrix,
+    range: YuvRange,
+) -> Vec<u8> {
+    let consts = ConversionConstants::new(matrix, range);
+
+    yuv420_to_rgba_with(y, chroma_b, chroma_r, y_width, 4, |yuv, _row, rgba| {
+        yuv_to_rgba_4x(yuv, consts, rgba)
+    })
+}
+
+/// Convert planar YUV 4:2:0 data into interleaved RGB 888 data.
+///
+/// This is the same conversion as `yuv420_to_rgba`, except that there is no
+/// alpha component, so each pixel takes up only 3 bytes. This suits uploading
+/// into 3-byte textures, or writing image formats without alpha.
+///
+/// The preconditions are the same as for `yuv420_to_rgba`.
+pub fn yuv420_to_rgb(y: &[u8], chroma_b: &[u8], chroma_r: &[u8], y_width: usize) -> Vec<u8> {
+    yuv420_to_rgba_with(y, chroma_b, chroma_r, y_width, 3, |yuv, _row, rgb| {
+        let mut rgba = [0u8; 16];
+        yuv_to_rgba_4x(yuv, &ConversionConstants::BT601, &mut rgba);
+
+        let rgb: &mut [u8; 12] = rgb;
+        for (rgb, rgba) in rgb.chunks_exact_mut(3).zip(rgba.chunks_exact(4)) {
+            rgb.copy_from_slice(&rgba[..3]);
+        }
+    })
+}
+
+/// Convert planar YUV 4:2:0 data into interleaved RGBA 8888 data, with
+/// bilinear chroma upsampling.
+///
+/// This is the same conversion as `yuv420_to_rgba`, except that instead of
+/// reusing each chroma sample for all four corresponding pixels, the chroma
+/// planes are first interpolated to full resolution, as with
+/// `upsample::chroma_420_to_444_bilinear`. This avoids the blocky look of
+/// colored edges, at the cost of an extra pass over the chroma planes.
+///
+/// The preconditions are the same as for `yuv420_to_rgba`.
+pub fn yuv420_to_rgba_bilinear(
+    y: &[u8],
+    chroma_b: &[u8],
+    chroma_r: &[u8],
+    y_width: usize,
+) -> Vec<u8> {
+    if y.is_empty() {
+        return vec![];
+    }
+
+    let (cb, cr) =
+        crate::upsample::chroma_420_to_444_bilinear(chroma_b, chroma_r, y_width, y.len() / y_width);
+
+    yuv444_to_rgba(y, &cb, &cr, &ConversionConstants::BT601)
+}
+
+/// Convert the luma plane of a YUV picture into single-channel grayscale.
+///
+/// Only the range expansion of luma is applied, with the same fixed-point
+/// arithmetic as `yuv420_to_rgba`, so each output sample is exactly what all
+/// three color components would be for that pixel with neutral chroma. This
+/// is useful for monochrome content, or for cheap previews.
+///
+/// `y.len()` must be an integer multiple of `y_width`.
+pub fn y_to_gray(y: &[u8], y_width: usize) -> Vec<u8> {
+    if !y.is_empty() {
+        debug_assert_eq!(y.len() % y_width, 0);
+    }
+
+    let consts = &ConversionConstants::BT601;
+
+    y.iter()
+        .map(|&y| {
+            let gray = (y as i32 - consts.luma_offset) * consts.gray;
+
+            ((gray + consts.half()) >> consts.fraction_bits).clamp(0, 255) as u8
+        })
+        .collect()
+}
+
+/// Convert planar YUV 4:2:0 data into interleaved RGBA 8888 data, with
+/// ordered dithering.
+///
+/// This is the same conversion as `yuv420_to_rgba`, except for how each
+/// component is rounded to 8 bits. Instead of always rounding to the nearest
+/// value, the threshold for rounding up is taken from a 4x4 Bayer matrix,
+/// indexed by the position of the pixel. This breaks up the banding that
+/// rounding causes in smooth gradients, while keeping the output
+/// deterministic.
+///
+/// The preconditions are the same as for `yuv420_to_rgba`.
+pub fn yuv420_to_rgba_dithered(
+    y: &[u8],
+    chroma_b: &[u8],
+    chroma_r: &[u8],
+    y_width: usize,
+) -> Vec<u8> {
+    yuv420_to_rgba_with(y, chroma_b, chroma_r, y_width, 4, |yuv, row, rgba| {
+        let consts = &ConversionConstants::BT601;
+
+        // Thresholds are spread evenly between 0 and 1 in fixed point, with
+        // the same 0.5 average as plain rounding.
+        let bias =
+            BAYER_4X4[row % 4].map(|threshold| (threshold * 2 + 1) << (consts.fraction_bits - 5));
+
+        yuv_to_rgba_biased_4x(yuv, consts, bias, rgba)
+    })
+}
+
+/// Convert planar YUV 4:2:0 data into interleaved RGBA data, with 16 bits
+/// per component.
+///
+/// This is the same conversion as `yuv420_to_rgba`, but it keeps 8 more bits
+/// of the precision of the internal fixed-point computation, instead of
+/// rounding them off. The output range is stretched so that full intensity
+/// is `u16::MAX`, the same way as widening an 8-bit value by multiplying it
+/// with 257 would, and the alpha component is always `u16::MAX`.
+///
+/// The preconditions are the same as for `yuv420_to_rgba`.
+pub fn yuv420_to_rgba16(y: &[u8], chroma_b: &[u8], chroma_r: &[u8], y_width: usize) -> Vec<u16> {
+    yuv420_to_rgba_with(y, chroma_b, chroma_r, y_width, 4, |yuv, _row, rgba| {
+        yuv_to_rgba16_4x(yuv, &ConversionConstants::BT601, rgba)
+    })
+}
+
 #[test]
 fn test_yuv_to_rgb() {
     // From the H.263 Rec.:
@@ -204,24 +669,100 @@ fn test_yuv_to_rgb() {
     // Peak colour difference = 16 and 240
 
     // not quite black
-    assert_eq!(yuv_to_rgb((17, 128, 128)), (1, 1, 1));
+    assert_eq!(yuv_to_rgb((17, 128, 128), YuvRange::Limited), (1, 1, 1));
     // exactly black
-    assert_eq!(yuv_to_rgb((16, 128, 128)), (0, 0, 0));
+    assert_eq!(yuv_to_rgb((16, 128, 128), YuvRange::Limited), (0, 0, 0));
     // and clamping also works
-    assert_eq!(yuv_to_rgb((15, 128, 128)), (0, 0, 0));
-    assert_eq!(yuv_to_rgb((0, 128, 128)), (0, 0, 0));
+    assert_eq!(yuv_to_rgb((15, 128, 128), YuvRange::Limited), (0, 0, 0));
+    assert_eq!(yuv_to_rgb((0, 128, 128), YuvRange::Limited), (0, 0, 0));
 
     // not quite white
-    assert_eq!(yuv_to_rgb((234, 128, 128)), (254, 254, 254));
+    assert_eq!(
+        yuv_to_rgb((234, 128, 128), YuvRange::Limited),
+        (254, 254, 254)
+    );
     // exactly white
-    assert_eq!(yuv_to_rgb((235, 128, 128)), (255, 255, 255));
+    assert_eq!(
+        yuv_to_rgb((235, 128, 128), YuvRange::Limited),
+        (255, 255, 255)
+    );
     // and clamping also works
-    assert_eq!(yuv_to_rgb((236, 128, 128)), (255, 255, 255));
-    assert_eq!(yuv_to_rgb((255, 128, 128)), (255, 255, 255));
+    assert_eq!(
+        yuv_to_rgb((236, 128, 128), YuvRange::Limited),
+        (255, 255, 255)
+    );
+    assert_eq!(
+        yuv_to_rgb((255, 128, 128), YuvRange::Limited),
+        (255, 255, 255)
+    );
 
     // (16 + 235) / 2 = 125.5, for middle grays
-    assert_eq!(yuv_to_rgb((125, 128, 128)), (127, 127, 127));
-    assert_eq!(yuv_to_rgb((126, 128, 128)), (128, 128, 128));
+    assert_eq!(
+        yuv_to_rgb((125, 128, 128), YuvRange::Limited),
+        (127, 127, 127)
+    );
+    assert_eq!(
+        yuv_to_rgb((126, 128, 128), YuvRange::Limited),
+        (128, 128, 128)
+    );
+}
+
+#[test]
+fn test_yuv_to_rgb_full_range() {
+    // In full range:
+    // Black = 0
+    // White = 255
+    // Zero colour difference = 128
+
+    // not quite black
+    assert_eq!(yuv_to_rgb((1, 128, 128), YuvRange::Full), (1, 1, 1));
+    // exactly black
+    assert_eq!(yuv_to_rgb((0, 128, 128), YuvRange::Full), (0, 0, 0));
+
+    // not quite white
+    assert_eq!(yuv_to_rgb((254, 128, 128), YuvRange::Full), (254, 254, 254));
+    // exactly white
+    assert_eq!(yuv_to_rgb((255, 128, 128), YuvRange::Full), (255, 255, 255));
+
+    // 255 / 2 = 127.5, for middle grays
+    assert_eq!(yuv_to_rgb((127, 128, 128), YuvRange::Full), (127, 127, 127));
+    assert_eq!(yuv_to_rgb((128, 128, 128), YuvRange::Full), (128, 128, 128));
+
+    // full red
+    assert_eq!(yuv_to_rgb((76, 85, 255), YuvRange::Full), (254, 0, 0)); // red rounds down to 254
+
+    // shadows are no longer crushed into black
+    assert_eq!(
+        yuv420_to_rgba_with_matrix_and_range(
+            &[8],
+            &[128],
+            &[128],
+            1,
+            ColorMatrix::Bt601,
+            YuvRange::Full
+        ),
+        vec![8, 8, 8, 255]
+    );
+    assert_eq!(yuv420_to_rgba(&[8], &[128], &[128], 1), vec![0, 0, 0, 255]);
+}
+
+#[test]
+fn test_y_to_gray() {
+    assert_eq!(y_to_gray(&[], 0), vec![0u8; 0]);
+
+    // The same test points as in `test_yuv_to_rgb`
+    assert_eq!(
+        y_to_gray(&[0, 15, 16, 17, 125, 126, 234, 235, 236, 255], 5),
+        vec![0, 0, 0, 1, 127, 128, 254, 255, 255, 255]
+    );
+
+    let y: Vec<u8> = (0..=255).collect();
+    let gray = y_to_gray(&y, 16);
+    let rgba = yuv420_to_rgba(&y, &[128; 8 * 8], &[128; 8 * 8], 16);
+
+    for (gray, rgba) in gray.iter().zip(rgba.chunks_exact(4)) {
+        assert_eq!(&[*gray, *gray, *gray, 255], rgba);
+    }
 }
 
 // Inverse conversion, for testing purposes only
@@ -272,34 +813,46 @@ fn test_rgb_to_yuv() {
 
 #[test]
 fn test_rgb_yuv_rgb_roundtrip_sanity() {
-    assert_eq!(yuv_to_rgb(rgb_to_yuv((0, 0, 0))), (0, 0, 0));
-    assert_eq!(yuv_to_rgb(rgb_to_yuv((127, 127, 127))), (127, 127, 127));
-    assert_eq!(yuv_to_rgb(rgb_to_yuv((128, 128, 128))), (128, 128, 128));
-    assert_eq!(yuv_to_rgb(rgb_to_yuv((255, 255, 255))), (255, 255, 255));
+    assert_eq!(
+        yuv_to_rgb(rgb_to_yuv((0, 0, 0)), YuvRange::Limited),
+        (0, 0, 0)
+    );
+    assert_eq!(
+        yuv_to_rgb(rgb_to_yuv((127, 127, 127)), YuvRange::Limited),
+        (127, 127, 127)
+    );
+    assert_eq!(
+        yuv_to_rgb(rgb_to_yuv((128, 128, 128)), YuvRange::Limited),
+        (128, 128, 128)
+    );
+    assert_eq!(
+        yuv_to_rgb(rgb_to_yuv((255, 255, 255)), YuvRange::Limited),
+        (255, 255, 255)
+    );
 
     assert_eq!(
-        yuv_to_rgb(rgb_to_yuv((255, 0, 0))),
+        yuv_to_rgb(rgb_to_yuv((255, 0, 0)), YuvRange::Limited),
         (254, 0, 0) // !!! there is a rounding error here
     );
     assert_eq!(
-        yuv_to_rgb(rgb_to_yuv((0, 255, 0))),
+        yuv_to_rgb(rgb_to_yuv((0, 255, 0)), YuvRange::Limited),
         (0, 255, 1) // !!! there is a rounding error here
     );
     assert_eq!(
-        yuv_to_rgb(rgb_to_yuv((0, 0, 255))),
+        yuv_to_rgb(rgb_to_yuv((0, 0, 255)), YuvRange::Limited),
         (0, 0, 255) // there is NO rounding error here
     );
 
     assert_eq!(
-        yuv_to_rgb(rgb_to_yuv((0, 255, 255))),
+        yuv_to_rgb(rgb_to_yuv((0, 255, 255)), YuvRange::Limited),
         (1, 255, 255) // !!! there is a rounding error here
     );
     assert_eq!(
-        yuv_to_rgb(rgb_to_yuv((255, 0, 255))),
+        yuv_to_rgb(rgb_to_yuv((255, 0, 255)), YuvRange::Limited),
         (255, 0, 254) // !!! there is a rounding error here
     );
     assert_eq!(
-        yuv_to_rgb(rgb_to_yuv((255, 255, 0))),
+        yuv_to_rgb(rgb_to_yuv((255, 255, 0)), YuvRange::Limited),
         (255, 255, 0) // there is NO rounding error here
     );
 
@@ -316,7 +869,7 @@ fn test_rgb_yuv_rgb_roundtrip_sanity() {
         (188, 189, 34),
         (23, 190, 207),
     ] {
-        let rgb2 = yuv_to_rgb(rgb_to_yuv(rgb));
+        let rgb2 = yuv_to_rgb(rgb_to_yuv(rgb), YuvRange::Limited);
         // Allowing for a difference of at most 1 on each component in both directions,
         // to account for the limited precision in YUV form, and two roundings
         assert!((rgb.0 as i32 - rgb2.0 as i32).abs() <= 1);
@@ -411,7 +964,35 @@ fn test_yuv420_to_rgba_tiny() {
     );
 
     // The middle row/column of pixels use the top/left row/column of chroma samples:
-    assert_eq!(yuv_to_rgb((125, 90, 240)), (255, 51, 50));
+    assert_eq!(yuv_to_rgb((125, 90, 240), YuvRange::Limited), (255, 51, 50));
+}
+
+#[test]
+fn test_yuv420_to_rgba_bt709() {
+    let convert = |yuv: (u8, u8, u8)| {
+        let rgba = yuv420_to_rgba_with_matrix(&[yuv.0], &[yuv.1], &[yuv.2], 1, ColorMatrix::Bt709);
+        (rgba[0], rgba[1], rgba[2])
+    };
+
+    // Grays don't depend on the matrix
+    assert_eq!(convert((16, 128, 128)), (0, 0, 0));
+    assert_eq!(convert((126, 128, 128)), (128, 128, 128));
+    assert_eq!(convert((235, 128, 128)), (255, 255, 255));
+
+    // BT.709 primaries, rounded to 8 bits in YUV form
+    assert_eq!(convert((63, 102, 240)), (255, 1, 0)); // red, with green rounded up to 1
+    assert_eq!(convert((173, 42, 26)), (0, 255, 1)); // green, with blue rounded up to 1
+    assert_eq!(convert((32, 240, 118)), (1, 0, 255)); // blue, with red rounded up to 1
+
+    // BT.601 green, which comes out as a different green under BT.709
+    assert_eq!(yuv_to_rgb((145, 54, 34), YuvRange::Limited), (0, 255, 1));
+    assert_ne!(convert((145, 54, 34)), (0, 255, 1));
+
+    // The default matrix is the one H.263 uses
+    assert_eq!(
+        yuv420_to_rgba_with_matrix(&[81], &[90], &[240], 1, ColorMatrix::default()),
+        yuv420_to_rgba(&[81], &[90], &[240], 1)
+    );
 }
 
 #[test]
@@ -481,3 +1062,273 @@ fn test_yuv420_to_rgba_medium() {
         ]
     );
 }
+
+#[test]
+fn test_yuv420_to_rgba_into() {
+    let y: Vec<u8> = (0..7 * 5).map(|i| (i * 7) as u8).collect();
+    let cb: Vec<u8> = (0..4 * 3).map(|i| (i * 21) as u8).collect();
+    let cr: Vec<u8> = (0..4 * 3).map(|i| (250 - i * 17) as u8).collect();
+
+    // Stale contents are all overwritten
+    let mut rgba = vec![0xAA; 7 * 5 * 4];
+    yuv420_to_rgba_into(&y, &cb, &cr, 7, &mut rgba).unwrap();
+    assert_eq!(rgba, yuv420_to_rgba(&y, &cb, &cr, 7));
+
+    let mut short = vec![0; 7 * 5 * 4 - 1];
+    assert_eq!(
+        yuv420_to_rgba_into(&y, &cb, &cr, 7, &mut short),
+        Err(SizeError {
+            expected: 140,
+            actual: 139
+        })
+    );
+    assert!(short.iter().all(|&c| c == 0));
+
+    let mut long = vec![0; 7 * 5 * 4 + 4];
+    assert!(yuv420_to_rgba_into(&y, &cb, &cr, 7, &mut long).is_err());
+
+    assert_eq!(yuv420_to_rgba_into(&[], &[], &[], 0, &mut []), Ok(()));
+
+    // Byte-identical to the allocating conversion at every size, whether or
+    // not the rows and columns fill whole SIMD chunks
+    for y_width in 1..10usize {
+        for y_height in 1..6usize {
+            let br_len = y_width.div_ceil(2) * y_height.div_ceil(2);
+            let y: Vec<u8> = (0..y_width * y_height).map(|i| (i * 37) as u8).collect();
+            let cb: Vec<u8> = (0..br_len).map(|i| (i * 53 + 16) as u8).collect();
+            let cr: Vec<u8> = (0..br_len).map(|i| (240 - i * 11) as u8).collect();
+
+            let mut rgba = vec![0; y.len() * 4];
+            yuv420_to_rgba_into(&y, &cb, &cr, y_width, &mut rgba).unwrap();
+            assert_eq!(rgba, yuv420_to_rgba(&y, &cb, &cr, y_width));
+        }
+    }
+}
+
+#[test]
+fn test_yuv420_to_rgba_bilinear_psnr() {
+    // A 32x32 picture of smooth gradients in all three components, with the
+    // chroma subsampled by averaging each 2x2 area
+    let (width, height) = (32usize, 32usize);
+    let y: Vec<u8> = (0..width * height)
+        .map(|i| (16 + (i % width) * 3 + (i / width) * 2) as u8)
+        .collect();
+    let cb_444: Vec<u8> = (0..width * height)
+        .map(|i| (40 + (i % width) * 3 + (i / width) * 2) as u8)
+        .collect();
+    let cr_444: Vec<u8> = (0..width * height)
+        .map(|i| (220 - (i % width) * 2 - (i / width) * 3) as u8)
+        .collect();
+    let subsample = |plane: &[u8]| -> Vec<u8> {
+        (0..width * height / 4)
+            .map(|i| {
+                let (x, y) = ((i % (width / 2)) * 2, (i / (width / 2)) * 2);
+                let sum = plane[x + y * width] as u16
+                    + plane[x + 1 + y * width] as u16
+                    + plane[x + (y + 1) * width] as u16
+                    + plane[x + 1 + (y + 1) * width] as u16;
+                ((sum + 2) / 4) as u8
+            })
+            .collect()
+    };
+    let (cb, cr) = (subsample(&cb_444), subsample(&cr_444));
+
+    let ground_truth = yuv444_to_rgba(&y, &cb_444, &cr_444, &ConversionConstants::BT601);
+    let psnr = |rgba: &[u8]| {
+        let (sum, count) = rgba
+            .chunks_exact(4)
+            .zip(ground_truth.chunks_exact(4))
+            .flat_map(|(a, b)| a[..3].iter().zip(&b[..3]))
+            .fold((0.0, 0), |(sum, count), (&a, &b)| {
+                (sum + (a as f64 - b as f64).powi(2), count + 1)
+            });
+
+        10.0 * (255.0f64.powi(2) / (sum / count as f64)).log10()
+    };
+
+    let nearest = psnr(&yuv420_to_rgba(&y, &cb, &cr, width));
+    let bilinear = psnr(&yuv420_to_rgba_bilinear(&y, &cb, &cr, width));
+
+    assert!(bilinear > nearest + 3.0);
+    assert!(bilinear > 40.0);
+}
+
+#[test]
+fn test_yuv420_to_rgba_bilinear() {
+    assert_eq!(yuv420_to_rgba_bilinear(&[], &[], &[], 0), vec![0u8; 0]);
+
+    // Without any chroma detail, there's nothing to interpolate
+    #[rustfmt::skip]
+    let y = [
+        16u8, 235u8, 125u8,
+        81u8, 145u8,  60u8,
+        30u8, 200u8, 100u8,
+    ];
+    let cb = [90u8, 90u8, 90u8, 90u8];
+    let cr = [240u8, 240u8, 240u8, 240u8];
+    assert_eq!(
+        yuv420_to_rgba_bilinear(&y, &cb, &cr, 3),
+        yuv420_to_rgba(&y, &cb, &cr, 3)
+    );
+
+    // A 2x2 picture only has a single chroma sample
+    assert_eq!(
+        yuv420_to_rgba_bilinear(&[81, 81, 145, 145], &[90], &[240], 2),
+        yuv420_to_rgba(&[81, 81, 145, 145], &[90], &[240], 2)
+    );
+
+    // Every pixel is converted with it's own interpolated chroma, on both the
+    // SIMD and the remainder paths
+    for width in 1..10usize {
+        let height = 5;
+        let br_width = width.div_ceil(2);
+        let y: Vec<u8> = (0..width * height).map(|i| (16 + i * 5) as u8).collect();
+        let cb: Vec<u8> = (0..br_width * 3).map(|i| (30 + i * 37) as u8).collect();
+        let cr: Vec<u8> = (0..br_width * 3).map(|i| (240 - i * 13) as u8).collect();
+
+        let (cb_444, cr_444) = crate::upsample::chroma_420_to_444_bilinear(&cb, &cr, width, height);
+        let rgba = yuv420_to_rgba_bilinear(&y, &cb, &cr, width);
+
+        assert_eq!(rgba.len(), y.len() * 4);
+        for (i, rgba) in rgba.chunks_exact(4).enumerate() {
+            let (r, g, b) = yuv_to_rgb((y[i], cb_444[i], cr_444[i]), YuvRange::Limited);
+            assert_eq!(rgba, &[r, g, b, 255]);
+        }
+    }
+
+    // A sharp chroma edge is softened, but stays in between both sides
+    let y = [126u8; 8 * 2];
+    let cb = [128u8, 128, 128, 128];
+    let cr = [16u8, 16, 240, 240];
+    let nearest = yuv420_to_rgba(&y, &cb, &cr, 8);
+    let bilinear = yuv420_to_rgba_bilinear(&y, &cb, &cr, 8);
+    let red = |rgba: &[u8], x: usize| rgba[x * 4];
+
+    assert_eq!(red(&bilinear, 0), red(&nearest, 0));
+    assert_eq!(red(&bilinear, 7), red(&nearest, 7));
+    assert!(red(&bilinear, 3) > red(&nearest, 3));
+    assert!(red(&bilinear, 4) < red(&nearest, 4));
+    for x in 0..7 {
+        assert!(red(&bilinear, x) <= red(&bilinear, x + 1));
+    }
+}
+
+#[test]
+fn test_yuv420_to_rgb() {
+    assert_eq!(yuv420_to_rgb(&[], &[], &[], 0), vec![0u8; 0]);
+
+    // Every width from 1 to 9 exercises both the SIMD and the remainder paths
+    for width in 1..10usize {
+        let br_width = width.div_ceil(2);
+        let y: Vec<u8> = (0..width * 3).map(|i| (16 + i * 7) as u8).collect();
+        let cb: Vec<u8> = (0..br_width * 2).map(|i| (40 + i * 23) as u8).collect();
+        let cr: Vec<u8> = (0..br_width * 2).map(|i| (220 - i * 19) as u8).collect();
+
+        let rgba = yuv420_to_rgba(&y, &cb, &cr, width);
+        let rgb = yuv420_to_rgb(&y, &cb, &cr, width);
+
+        assert_eq!(rgb.len(), y.len() * 3);
+        for (rgb, rgba) in rgb.chunks_exact(3).zip(rgba.chunks_exact(4)) {
+            assert_eq!(rgb, &rgba[..3]);
+        }
+    }
+}
+
+#[test]
+fn test_yuv420_to_rgba16() {
+    assert_eq!(yuv420_to_rgba16(&[], &[], &[], 0), vec![0u16; 0]);
+
+    // black, white, and clamping
+    assert_eq!(
+        yuv420_to_rgba16(&[16u8], &[128u8], &[128u8], 1),
+        vec![0u16, 0u16, 0u16, 65535u16]
+    );
+    assert_eq!(
+        yuv420_to_rgba16(&[235u8], &[128u8], &[128u8], 1),
+        vec![65535u16, 65535u16, 65535u16, 65535u16]
+    );
+    assert_eq!(
+        yuv420_to_rgba16(&[255u8], &[128u8], &[128u8], 1),
+        vec![65535u16, 65535u16, 65535u16, 65535u16]
+    );
+
+    // A middle gray retains the fraction that the 8-bit version rounds off
+    // (`yuv_to_rgb((125, 128, 128), YuvRange::Limited)` is 127, from the exact 126.9178...)
+    assert_eq!(
+        yuv420_to_rgba16(&[125u8], &[128u8], &[128u8], 1),
+        vec![32617u16, 32617u16, 32617u16, 65535u16]
+    );
+
+    // Other than the extra precision, it should agree with the 8-bit version,
+    // on both the SIMD and the remainder paths.
+    let y: Vec<u8> = (0..7 * 4).map(|i| (i * 9) as u8).collect();
+    let cb: Vec<u8> = (0..4 * 2).map(|i| (i * 31) as u8).collect();
+    let cr: Vec<u8> = (0..4 * 2).map(|i| (255 - i * 29) as u8).collect();
+
+    let rgba = yuv420_to_rgba(&y, &cb, &cr, 7);
+    let rgba16 = yuv420_to_rgba16(&y, &cb, &cr, 7);
+
+    assert_eq!(rgba.len(), rgba16.len());
+    for (c8, c16) in rgba.iter().zip(rgba16.iter()) {
+        assert!((*c8 as i32 - (*c16 as i32 / 257)).abs() <= 1);
+    }
+}
+
+#[test]
+fn test_yuv420_to_rgba_dithered() {
+    assert_eq!(yuv420_to_rgba_dithered(&[], &[], &[], 0), vec![0u8; 0]);
+
+    // Black and white have no fraction to dither
+    assert_eq!(
+        yuv420_to_rgba_dithered(&[16u8; 16], &[128u8; 4], &[128u8; 4], 4),
+        yuv420_to_rgba(&[16u8; 16], &[128u8; 4], &[128u8; 4], 4)
+    );
+    assert_eq!(
+        yuv420_to_rgba_dithered(&[235u8; 16], &[128u8; 4], &[128u8; 4], 4),
+        yuv420_to_rgba(&[235u8; 16], &[128u8; 4], &[128u8; 4], 4)
+    );
+
+    // A middle gray of 126.9178... only rounds down where the threshold is
+    // the lowest, in the top left of the Bayer matrix
+    let rgba = yuv420_to_rgba_dithered(&[125u8; 8 * 8], &[128u8; 16], &[128u8; 16], 8);
+    for (i, pixel) in rgba.chunks_exact(4).enumerate() {
+        let (x, y) = (i % 8, i / 8);
+        let expected = if x % 4 == 0 && y % 4 == 0 { 126 } else { 127 };
+        assert_eq!(pixel, &[expected, expected, expected, 255]);
+    }
+
+    // Dithering never moves a component by more than one step, on both the
+    // SIMD and the remainder paths
+    let y: Vec<u8> = (0..7 * 4).map(|i| (i * 9) as u8).collect();
+    let cb: Vec<u8> = (0..4 * 2).map(|i| (i * 31) as u8).collect();
+    let cr: Vec<u8> = (0..4 * 2).map(|i| (255 - i * 29) as u8).collect();
+
+    let rgba = yuv420_to_rgba(&y, &cb, &cr, 7);
+    let dithered = yuv420_to_rgba_dithered(&y, &cb, &cr, 7);
+
+    assert_eq!(rgba.len(), dithered.len());
+    for (c, d) in rgba.iter().zip(dithered.iter()) {
+        assert!((*c as i32 - *d as i32).abs() <= 1);
+    }
+}
+
+#[test]
+fn test_conversion_constants() {
+    let consts = ConversionConstants::BT601;
+    let one = (1 << consts.fraction_bits) as f64;
+    let luma_scale = 255.0 / 219.0;
+    let chroma_scale = 255.0 / 224.0;
+
+    assert_eq!(consts.gray, (luma_scale * one).round() as i32);
+    assert_eq!(consts.cr2r, (chroma_scale * 1.402 * one).round() as i32);
+    assert_eq!(
+        consts.cr2g,
+        (-chroma_scale * 1.402 * (0.299 / 0.587) * one).round() as i32
+    );
+    assert_eq!(
+        consts.cb2g,
+        (-chroma_scale * 1.772 * (0.114 / 0.587) * one).round() as i32
+    );
+    assert_eq!(consts.cb2b, (chroma_scale * 1.772 * one).round() as i32);
+    assert_eq!(consts.half(), 32768);
+}
@@ -0,0 +1,44 @@
+//! YUV-to-RGB decode with the BT.709 color matrix
+//!
+//! The conversion kernels are shared with `bt601`, only the coefficients
+//! differ, so this is a thin wrapper that picks `ColorMatrix::Bt709`.
+
+use crate::bt601::yuv420_to_rgba_with_matrix;
+use crate::color::ColorMatrix;
+
+/// Convert planar YUV 4:2:0 data into interleaved RGBA 8888 data, using the
+/// BT.709 color matrix (Kb = 0.0722, Kr = 0.2126).
+///
+/// This is the same conversion as `bt601::yuv420_to_rgba`, with the same
+/// preconditions, for content encoded from HD sources. To choose the matrix
+/// at runtime, use `bt601::yuv420_to_rgba_with_matrix` instead.
+pub fn yuv420_to_rgba(y: &[u8], chroma_b: &[u8], chroma_r: &[u8], y_width: usize) -> Vec<u8> {
+    yuv420_to_rgba_with_matrix(y, chroma_b, chroma_r, y_width, ColorMatrix::Bt709)
+}
+
+#[test]
+fn test_yuv420_to_rgba() {
+    // A 4x4 picture, with the BT.709 primaries and white
+    #[rustfmt::skip]
+    let rgba = yuv420_to_rgba(
+        &[ 63,  63, 173, 173,
+           63,  63, 173, 173,
+           32,  32, 235, 235,
+           32,  32, 235, 235],
+        &[102,  42,
+          240, 128],
+        &[240,  26,
+          118, 128],
+        4,
+    );
+
+    let pixel = |x: usize, y: usize| {
+        let i = (x + y * 4) * 4;
+        (rgba[i], rgba[i + 1], rgba[i + 2], rgba[i + 3])
+    };
+
+    assert_eq!(pixel(0, 0), (255, 1, 0, 255)); // red, with green rounded up to 1
+    assert_eq!(pixel(3, 1), (0, 255, 1, 255)); // green, with blue rounded up to 1
+    assert_eq!(pixel(1, 2), (1, 0, 255, 255)); // blue, with red rounded up to 1
+    assert_eq!(pixel(2, 3), (255, 255, 255, 255));
+}
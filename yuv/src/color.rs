@@ -0,0 +1,126 @@
+//! Color matrices and ranges of YUV data, and the coefficients of converting
+//! it to RGB
+
+/// The matrix coefficients used to turn YUV into RGB.
+///
+/// H.263 itself always uses BT.601, but content authored for larger picture
+/// formats is sometimes encoded with BT.709 instead.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ColorMatrix {
+    /// ITU-R BT.601, as specified by H.263.
+    #[default]
+    Bt601,
+
+    /// ITU-R BT.709, as used by HD video.
+    Bt709,
+}
+
+/// The range of values the YUV components take.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum YuvRange {
+    /// Studio swing, with luma from 16 (black) to 235 (white), and chroma
+    /// from 16 to 240. This is what H.263 specifies.
+    #[default]
+    Limited,
+
+    /// Full swing, with luma from 0 (black) to 255 (white), and chroma from
+    /// 0 to 255. Some Sorenson Spark encoders produce this instead.
+    Full,
+}
+
+/// The fixed-point coefficients of a YUV-to-RGB conversion.
+///
+/// Each coefficient is stored with `fraction_bits` bits of fractional
+/// precision, and for limited range input, all of them include the extension
+/// from reduced (16..235 and 16..240) to full range (0..255). The kernels
+/// in `bt601` read everything they need from here, so trying a different
+/// precision (or different coefficients) only needs a different set of
+/// constants.
+///
+/// `fraction_bits` has to be more than 8, since 16-bit output keeps 8 more
+/// bits of the fraction than 8-bit output does.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ConversionConstants {
+    /// The luma value of black, subtracted before any multiplication.
+    pub(crate) luma_offset: i32,
+
+    /// Multiplier for luma, in all three output components.
+    pub(crate) gray: i32,
+
+    /// Multiplier for Cr, in the red output component.
+    pub(crate) cr2r: i32,
+
+    /// Multiplier for Cr, in the green output component.
+    pub(crate) cr2g: i32,
+
+    /// Multiplier for Cb, in the green output component.
+    pub(crate) cb2g: i32,
+
+    /// Multiplier for Cb, in the blue output component.
+    pub(crate) cb2b: i32,
+
+    /// How many of the low bits of the coefficients are the fraction.
+    pub(crate) fraction_bits: u32,
+}
+
+impl ConversionConstants {
+    /// The BT.601 coefficients, in 16.16 fixed point, and rounded.
+    pub(crate) const BT601: Self = Self {
+        luma_offset: 16,
+        gray: 76309,  // 76309 == round((255.0 / 219.0) * 65536.0)
+        cr2r: 104597, // 104597 == round((255.0 / 224.0) * 1.402 * 65536.0)
+        cr2g: -53279, // -53279 == round(-(255.0 / 224.0) * 1.402 * (0.299 / 0.587) * 65536.0)
+        cb2g: -25675, // -25675 == round(-(255.0 / 224.0) * 1.772 * (0.114 / 0.587) * 65536.0)
+        cb2b: 132201, // 132201 == round((255.0 / 224.0) * 1.772 * 65536.0)
+        fraction_bits: 16,
+    };
+
+    /// The BT.709 coefficients, in 16.16 fixed point, and rounded.
+    pub(crate) const BT709: Self = Self {
+        luma_offset: 16,
+        gray: 76309,  // 76309 == round((255.0 / 219.0) * 65536.0)
+        cr2r: 117489, // 117489 == round((255.0 / 224.0) * 1.5748 * 65536.0)
+        cr2g: -34925, // -34925 == round(-(255.0 / 224.0) * 1.5748 * (0.2126 / 0.7152) * 65536.0)
+        cb2g: -13975, // -13975 == round(-(255.0 / 224.0) * 1.8556 * (0.0722 / 0.7152) * 65536.0)
+        cb2b: 138438, // 138438 == round((255.0 / 224.0) * 1.8556 * 65536.0)
+        fraction_bits: 16,
+    };
+
+    /// The full range BT.601 coefficients, in 16.16 fixed point, and rounded.
+    pub(crate) const BT601_FULL: Self = Self {
+        luma_offset: 0,
+        gray: 65536,  // 65536 == 1.0 * 65536.0
+        cr2r: 91881,  // 91881 == round(1.402 * 65536.0)
+        cr2g: -46802, // -46802 == round(-1.402 * (0.299 / 0.587) * 65536.0)
+        cb2g: -22553, // -22553 == round(-1.772 * (0.114 / 0.587) * 65536.0)
+        cb2b: 116130, // 116130 == round(1.772 * 65536.0)
+        fraction_bits: 16,
+    };
+
+    /// The full range BT.709 coefficients, in 16.16 fixed point, and rounded.
+    pub(crate) const BT709_FULL: Self = Self {
+        luma_offset: 0,
+        gray: 65536,  // 65536 == 1.0 * 65536.0
+        cr2r: 103206, // 103206 == round(1.5748 * 65536.0)
+        cr2g: -30679, // -30679 == round(-1.5748 * (0.2126 / 0.7152) * 65536.0)
+        cb2g: -12276, // -12276 == round(-1.8556 * (0.0722 / 0.7152) * 65536.0)
+        cb2b: 121609, // 121609 == round(1.8556 * 65536.0)
+        fraction_bits: 16,
+    };
+
+    /// Look up the constants for a given matrix and input range.
+    pub(crate) fn new(matrix: ColorMatrix, range: YuvRange) -> &'static Self {
+        match (matrix, range) {
+            (ColorMatrix::Bt601, YuvRange::Limited) => &Self::BT601,
+            (ColorMatrix::Bt709, YuvRange::Limited) => &Self::BT709,
+            (ColorMatrix::Bt601, YuvRange::Full) => &Self::BT601_FULL,
+            (ColorMatrix::Bt709, YuvRange::Full) => &Self::BT709_FULL,
+        }
+    }
+
+    /// One half, in this fixed-point format. Added before shifting off the
+    /// fraction to make the rightshift round correctly.
+    pub(crate) const fn half(&self) -> i32 {
+        1 << (self.fraction_bits - 1)
+    }
+}
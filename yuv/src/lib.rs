@@ -1,3 +1,9 @@
-//! Pure-rust BT.601 YUV color space support
+//! Pure-rust BT.601 and BT.709 YUV color space support
 
 pub mod bt601;
+pub mod bt709;
+pub mod color;
+pub mod upsample;
+
+pub use bt601::yuv420_to_rgba_with_matrix;
+pub use color::{ColorMatrix, YuvRange};
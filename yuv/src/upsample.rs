@@ -0,0 +1,323 @@
+//! Chroma upsampling
+
+#[cfg(feature = "simd")]
+use wide::{u16x8, u8x16};
+
+/// Where chroma samples sit relative to the luma samples they belong to.
+///
+/// Each chroma sample of a 4:2:0 picture covers a 2x2 area of luma samples,
+/// but different standards put the chroma sample itself at different places
+/// within that area. Interpolating with the wrong siting shifts the chroma
+/// by a quarter of a chroma sample, which blurs colored edges.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ChromaSiting {
+    /// Chroma is sited in the middle of the 2x2 luma samples, both
+    /// horizontally and vertically.
+    ///
+    /// This is the siting specified by H.263 (and MPEG-1, and JPEG).
+    #[default]
+    Center,
+
+    /// Chroma is co-sited with the left column of luma samples, and sited
+    /// halfway between the two rows vertically.
+    ///
+    /// This is the siting used by MPEG-2 and most later standards.
+    Left,
+}
+
+/// Horizontally upsample a single row of left-sited chroma samples to
+/// `out.len()` samples.
+///
+/// Even output samples coincide with a chroma sample, and odd ones are
+/// halfway between two of them. As with `upsample_row_scalar`, the output is
+/// left at 4 times the scale of the input.
+#[inline]
+fn upsample_row_left(row: &[u8], out: &mut [u16]) {
+    let last = row.len() - 1;
+
+    for (x, out) in out.iter_mut().enumerate() {
+        let i = x / 2;
+        *out = if x % 2 == 0 {
+            4 * row[i] as u16
+        } else {
+            2 * row[i] as u16 + 2 * row[(i + 1).min(last)] as u16
+        };
+    }
+}
+
+/// Horizontally upsample a single row of chroma samples to `out.len()`
+/// samples, with the `[3/4, 1/4]` and `[1/4, 3/4]` filter pair.
+///
+/// The output is left at 4 times the scale of the input (the filter taps are
+/// not divided out), so that the vertical pass can do the single rounding.
+#[inline]
+fn upsample_row_scalar(row: &[u8], out: &mut [u16], first: usize) {
+    let last = row.len() - 1;
+
+    for (x, out) in out.iter_mut().enumerate().skip(first * 2) {
+        let i = x / 2;
+        let near = row[i] as u16;
+        let far = if x % 2 == 0 {
+            row[i.saturating_sub(1)]
+        } else {
+            row[(i + 1).min(last)]
+        } as u16;
+
+        *out = 3 * near + far;
+    }
+}
+
+/// Loads exactly 16 samples into a SIMD vector.
+#[cfg(feature = "simd")]
+#[inline]
+fn load_u8x16(samples: &[u8]) -> u8x16 {
+    let mut array = [0u8; 16];
+    array.copy_from_slice(samples);
+    u8x16::from(array)
+}
+
+/// Same as `upsample_row_scalar`, but doing the bulk of the row 16 chroma
+/// samples at a time, leaving the edges to the scalar implementation.
+#[cfg(feature = "simd")]
+#[inline]
+fn upsample_row(row: &[u8], out: &mut [u16]) {
+    let mut i = 0;
+
+    // The first sample needs to clamp its left neighbour, and the chunks need
+    // one extra sample on both sides, so SIMD starts at the second sample.
+    if row.len() > 17 {
+        out[0] = 4 * row[0] as u16;
+        if out.len() > 1 {
+            out[1] = 3 * row[0] as u16 + row[1] as u16;
+        }
+
+        i = 1;
+        while i + 17 <= row.len() && (i + 16) * 2 <= out.len() {
+            let left = load_u8x16(&row[i - 1..i + 15]);
+            let near = load_u8x16(&row[i..i + 16]);
+            let right = load_u8x16(&row[i + 1..i + 17]);
+
+            for (half, start) in [(false, 0), (true, 8)] {
+                let widen = |v: u8x16| {
+                    if half {
+                        u16x8::from_u8x16_high(v)
+                    } else {
+                        u16x8::from_u8x16_low(v)
+                    }
+                };
+
+                let near3 = widen(near) * 3;
+                let even = (near3 + widen(left)).to_array();
+                let odd = (near3 + widen(right)).to_array();
+
+                let out = &mut out[(i + start) * 2..(i + start + 8) * 2];
+                for lane in 0..8 {
+                    out[lane * 2] = even[lane];
+                    out[lane * 2 + 1] = odd[lane];
+                }
+            }
+
+            i += 16;
+        }
+    }
+
+    upsample_row_scalar(row, out, i);
+}
+
+#[cfg(not(feature = "simd"))]
+#[inline]
+fn upsample_row(row: &[u8], out: &mut [u16]) {
+    upsample_row_scalar(row, out, 0);
+}
+
+/// Upsample a single 4:2:0 chroma plane into a `width` by `height` plane.
+fn chroma_plane_420_to_444_bilinear(
+    plane: &[u8],
+    width: usize,
+    height: usize,
+    siting: ChromaSiting,
+) -> Vec<u8> {
+    let chroma_width = width.div_ceil(2);
+    let chroma_height = height.div_ceil(2);
+
+    debug_assert_eq!(plane.len(), chroma_width * chroma_height);
+
+    // Horizontal pass, at 4 times the scale of the input.
+    let mut horizontal = vec![0u16; width * chroma_height];
+    for (row, out) in plane
+        .chunks_exact(chroma_width)
+        .zip(horizontal.chunks_exact_mut(width))
+    {
+        match siting {
+            ChromaSiting::Center => upsample_row(row, out),
+            ChromaSiting::Left => upsample_row_left(row, out),
+        }
+    }
+
+    // Vertical pass, undoing both scale factors at once, with rounding.
+    let mut result = vec![0u8; width * height];
+    for (y, out) in result.chunks_exact_mut(width).enumerate() {
+        let j = y / 2;
+        let far_j = if y % 2 == 0 {
+            j.saturating_sub(1)
+        } else {
+            (j + 1).min(chroma_height - 1)
+        };
+
+        let near = &horizontal[j * width..(j + 1) * width];
+        let far = &horizontal[far_j * width..(far_j + 1) * width];
+
+        for ((out, near), far) in out.iter_mut().zip(near).zip(far) {
+            *out = ((3 * *near as u32 + *far as u32 + 8) >> 4) as u8;
+        }
+    }
+
+    result
+}
+
+/// Convert the chroma planes of a YUV 4:2:0 picture into 4:4:4, using
+/// bilinear interpolation.
+///
+/// `width` and `height` are the dimensions of the luma plane, which is also
+/// the size of both returned planes. The chroma planes must be half as wide
+/// and half as tall, rounded up.
+///
+/// Each chroma sample is assumed to be sited in the middle of the 2x2 luma
+/// samples it belongs to, as H.263 specifies, so every output sample is
+/// interpolated from the nearest chroma sample with a weight of 3/4, and the
+/// next nearest one in each direction with a weight of 1/4. Samples on the
+/// edges of the picture are extended outwards.
+///
+/// See `chroma_420_to_444_bilinear_sited` for chroma sited elsewhere.
+pub fn chroma_420_to_444_bilinear(
+    cb: &[u8],
+    cr: &[u8],
+    width: usize,
+    height: usize,
+) -> (Vec<u8>, Vec<u8>) {
+    chroma_420_to_444_bilinear_sited(cb, cr, width, height, ChromaSiting::default())
+}
+
+/// Convert the chroma planes of a YUV 4:2:0 picture into 4:4:4, using
+/// bilinear interpolation matched to a given chroma `siting`.
+///
+/// This is the same as `chroma_420_to_444_bilinear`, except that with
+/// `ChromaSiting::Left`, each row is interpolated horizontally with weights
+/// of 1 and 0 for the columns that coincide with a chroma sample, and 1/2
+/// and 1/2 for the columns in between.
+pub fn chroma_420_to_444_bilinear_sited(
+    cb: &[u8],
+    cr: &[u8],
+    width: usize,
+    height: usize,
+    siting: ChromaSiting,
+) -> (Vec<u8>, Vec<u8>) {
+    if width == 0 || height == 0 {
+        return (vec![], vec![]);
+    }
+
+    (
+        chroma_plane_420_to_444_bilinear(cb, width, height, siting),
+        chroma_plane_420_to_444_bilinear(cr, width, height, siting),
+    )
+}
+
+#[test]
+fn test_upsample_constant() {
+    for (width, height) in [
+        (1usize, 1usize),
+        (2, 2),
+        (3, 5),
+        (16, 16),
+        (50, 7),
+        (176, 144),
+    ] {
+        let chroma_len = width.div_ceil(2) * height.div_ceil(2);
+        let (cb, cr) = chroma_420_to_444_bilinear(
+            &vec![90; chroma_len],
+            &vec![240; chroma_len],
+            width,
+            height,
+        );
+
+        assert_eq!(cb, vec![90; width * height]);
+        assert_eq!(cr, vec![240; width * height]);
+    }
+}
+
+#[test]
+fn test_upsample_tiny() {
+    assert_eq!(chroma_420_to_444_bilinear(&[], &[], 0, 0), (vec![], vec![]));
+
+    // A single chroma sample covers a 2x2 area by itself
+    assert_eq!(
+        chroma_420_to_444_bilinear(&[10], &[20], 2, 2),
+        (vec![10; 4], vec![20; 4])
+    );
+
+    // Two chroma samples side by side: the middle two columns are blended
+    #[rustfmt::skip]
+    assert_eq!(
+        chroma_420_to_444_bilinear(&[0, 64], &[64, 0], 4, 2),
+        (
+            vec![0, 16, 48, 64,
+                 0, 16, 48, 64],
+            vec![64, 48, 16, 0,
+                 64, 48, 16, 0]
+        )
+    );
+
+    // The same vertically
+    #[rustfmt::skip]
+    assert_eq!(
+        chroma_420_to_444_bilinear(&[0, 64], &[0, 64], 1, 4).0,
+        vec![0, 16, 48, 64]
+    );
+}
+
+#[test]
+fn test_upsample_left_siting() {
+    assert_eq!(ChromaSiting::default(), ChromaSiting::Center);
+
+    // Two chroma samples side by side: every other column coincides with
+    // one, and the ones in between are halfway
+    #[rustfmt::skip]
+    assert_eq!(
+        chroma_420_to_444_bilinear_sited(&[0, 64], &[64, 0], 4, 2, ChromaSiting::Left),
+        (
+            vec![0, 32, 64, 64,
+                 0, 32, 64, 64],
+            vec![64, 32, 0, 0,
+                 64, 32, 0, 0]
+        )
+    );
+
+    // Vertically, left siting is still centered
+    assert_eq!(
+        chroma_420_to_444_bilinear_sited(&[0, 64], &[0, 64], 1, 4, ChromaSiting::Left).0,
+        vec![0, 16, 48, 64]
+    );
+
+    // Constant planes stay constant
+    let (cb, cr) =
+        chroma_420_to_444_bilinear_sited(&[90; 12], &[240; 12], 7, 5, ChromaSiting::Left);
+    assert_eq!(cb, vec![90; 35]);
+    assert_eq!(cr, vec![240; 35]);
+}
+
+#[test]
+fn test_upsample_wide_matches_scalar() {
+    // Wide enough to go through the SIMD path (if enabled), with an odd width
+    // so that the remainder is handled too.
+    let width: usize = 75;
+    let chroma_width = width.div_ceil(2);
+    let row: Vec<u8> = (0..chroma_width).map(|i| (i * 37 % 256) as u8).collect();
+
+    let mut expected = vec![0u16; width];
+    upsample_row_scalar(&row, &mut expected, 0);
+
+    let mut actual = vec![0u16; width];
+    upsample_row(&row, &mut actual);
+
+    assert_eq!(actual, expected);
+}